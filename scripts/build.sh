@@ -1,6 +1,10 @@
 #!/bin/sh
 
 WORK_DIR="${PWD}"
+# conda-build puts the source into a folder of its own
+if test "${CONDA_BUILD}" = "1" && test -d "${WORK_DIR}/src"; then
+    WORK_DIR="${WORK_DIR}/src"
+fi
 
 SRC="${PKG_NAME}-${PKG_VERSION}-${target_platform}"
 
@@ -19,9 +23,17 @@ elif test -f "${SRC}.xz"; then
 elif test -f "${SRC}.zst"; then
     ( cd "$PREFIX" && cat "${WORK_DIR}/${SRC}.zst" | unzstd > "${PREFIX}/${PKG_NAME}" )
     chmod 755 "${PREFIX}/${PKG_NAME}"
+elif test -f "${SRC}.wasm"; then
+    cp "${WORK_DIR}/${SRC}.wasm" "${PREFIX}/${PKG_NAME}.wasm"
+elif ls "${WORK_DIR}/${SRC}".*js > /dev/null 2>&1; then
+    # node bundle, keep its extension
+    cp "${WORK_DIR}/${SRC}".*js "${PREFIX}/"
 elif test -f "${WORK_DIR}/${SRC}"; then
     cp "${WORK_DIR}/${SRC}" "${PREFIX}/${PKG_NAME}"
     chmod 755 "${PREFIX}/${PKG_NAME}"
+elif test "${CONDA_BUILD}" = "1"; then
+    # conda-build unpacked the archive already
+    cp -R "${WORK_DIR}/." "$PREFIX"
 else
     echo "${SRC} not found, not a file, not a zip, not a tarball, not compressed"
     echo "Work directory contents is:"
@@ -38,6 +50,9 @@ while [ $(find . -mindepth 1 -maxdepth 1 -type d -not -name conda-meta | wc -l)
     if test -d "bin"; then
         echo "Found only a bin subdir, this looks good"
         break
+    elif test -f "package.json"; then
+        echo "Found a node package, keeping its layout"
+        break
     else
         # move everything up a level
         SUBDIR=$(find . -mindepth 1 -maxdepth 1 -type d -not -name conda-meta)
@@ -47,45 +62,218 @@ while [ $(find . -mindepth 1 -maxdepth 1 -type d -not -name conda-meta | wc -l)
     fi
 done
 
-# Move all executable files into bin
-mkdir -p bin
-mkdir -p extras
+KIND="binary"
+if test -f "${RECIPE_DIR}/kind.txt"; then
+    KIND="$(cat "${RECIPE_DIR}/kind.txt")"
+fi
 
-for f in *; do
-    if test -f "${f}"; then
-        if file "${f}" | grep "executable"; then
-            chmod 755 "${f}"
-        fi
+case "${KIND}" in
+data)
+    # Data packages: Move everything into the configured directory
+    INSTALL_TO="$(cat "${RECIPE_DIR}/install_to.txt")"
+    mkdir .octoconda-data
+    for f in *; do
+        case "${f}" in
+        conda-meta|.octoconda-data)
+            ;;
+        *)
+            mv "${f}" .octoconda-data
+        esac
+    done
+    mkdir -p "$(dirname "${INSTALL_TO}")"
+    mv .octoconda-data "${INSTALL_TO}"
+    ;;
+library)
+    # Library packages: Shared libraries into lib, headers into include
+    mkdir -p lib
+    mkdir -p include
+    mkdir -p extras
 
-        if test -x "${f}"; then
-            mv "${f}" bin
-        else
-            case "$f" in
-            *.exe|*.bat|*.com)
-                mv "${f}" bin
+    for f in *; do
+        if test -f "${f}"; then
+            case "${f}" in
+            *.so|*.so.*|*.dylib|*.dll|*.lib|*.a)
+                mv "${f}" lib
+                ;;
+            *.h|*.hh|*.hpp|*.hxx)
+                mv "${f}" include
                 ;;
             *)
                 mv "${f}" extras
                 ;;
             esac
+        elif test -d "${f}"; then
+            case "${f}" in
+            conda-meta|bin|include|lib|share|extras)
+                ;;
+            *)
+                mv "${f}" extras
+            esac
         fi
-    elif test -d "${f}"; then
+    done
+
+    # DLLs are looked up next to executables on Windows
+    case "${target_platform}" in
+    win-*)
+        mkdir -p Library/bin
+        find lib -maxdepth 1 -name "*.dll" -exec mv {} Library/bin \;
+        for d in bin include lib; do
+            if test -d "${d}"; then
+                mkdir -p "Library/${d}"
+                mv "${d}"/* "Library/${d}" || true
+                rmdir "${d}"
+            fi
+        done
+        ;;
+    esac
+    ;;
+node)
+    # Node packages: The payload goes into lib/node_modules, shims into bin
+    NODE_DIR="lib/node_modules/${PKG_NAME}"
+    mkdir .octoconda-node
+    for f in *; do
         case "${f}" in
-        conda-meta|bin|etc|include|lib|man|share|ssl|extras)
+        conda-meta|.octoconda-node)
             ;;
         *)
-            mv "${f}" extras
+            mv "${f}" .octoconda-node
         esac
+    done
+    mkdir -p lib/node_modules
+    mv .octoconda-node "${NODE_DIR}"
+
+    if test -f "${NODE_DIR}/package.json"; then
+        # npm tarball: Commands are listed in package.json
+        ( cd "${NODE_DIR}" && node -e '
+            const p = require("./package.json");
+            const bin = typeof p.bin === "string" ? { [p.name.split("/").pop()]: p.bin } : (p.bin || {});
+            for (const [name, path] of Object.entries(bin)) console.log(`${name}\t${path}`);
+            const deps = Object.keys(p.dependencies || {}).length;
+            process.exit(deps > 0 && !require("fs").existsSync("node_modules") ? 3 : 0);
+        ' > "${PREFIX}/.octoconda-bins" )
+        if test $? -eq 3; then
+            ( cd "${NODE_DIR}" && npm install --omit=dev --ignore-scripts --no-audit --no-fund ) || exit 4
+        fi
+    else
+        # Bundled script: The package name is the command
+        for f in "${NODE_DIR}"/*; do
+            printf '%s\t%s\n' "${PKG_NAME}" "$(basename "${f}")" >> "${PREFIX}/.octoconda-bins"
+            break
+        done
     fi
-done
 
-cd "${PREFIX}/bin"
+    mkdir -p bin Library/bin
+    while IFS="$(printf '\t')" read -r name path; do
+        path="${path#./}"
+        printf '#!/bin/sh\nexec node "$(dirname "$0")/../%s/%s" "$@"\n' "${NODE_DIR}" "${path}" > "bin/${name}"
+        chmod 755 "bin/${name}"
+        printf '@node "%%~dp0..\\..\\%s\\%s" %%*\r\n' "$(echo "${NODE_DIR}" | tr / '\\')" "$(echo "${path}" | tr / '\\')" > "Library/bin/${name}.cmd"
+    done < "${PREFIX}/.octoconda-bins"
+    rm -f "${PREFIX}/.octoconda-bins"
+    ;;
+*)
+    # Move all executable files into bin
+    mkdir -p bin
+    mkdir -p extras
+
+    for f in *; do
+        if test -f "${f}"; then
+            if file "${f}" | grep "executable"; then
+                chmod 755 "${f}"
+            fi
 
-for f in *; do
-    if [[ "$f" == *"-${PKG_VERSION}"* ]]; then
-        short="${f%%-*}"
-        mv "${f}" "${short}"
+            if test -x "${f}"; then
+                mv "${f}" bin
+            else
+                case "$f" in
+                *.exe|*.bat|*.com|*.wasm)
+                    mv "${f}" bin
+                    ;;
+                *)
+                    mv "${f}" extras
+                    ;;
+                esac
+            fi
+        elif test -d "${f}"; then
+            case "${f}" in
+            conda-meta|bin|etc|include|lib|man|share|ssl|extras)
+                ;;
+            *)
+                mv "${f}" extras
+            esac
+        fi
+    done
+    ;;
+esac
+
+# Drop debug symbols and other files the package should not contain
+if test -f "${RECIPE_DIR}/exclude_files.txt"; then
+    while IFS= read -r pattern; do
+        if test -n "${pattern}"; then
+            find . -path ./conda-meta -prune -o -name "${pattern}" -prune -exec rm -rf {} +
+        fi
+    done < "${RECIPE_DIR}/exclude_files.txt"
+fi
+
+# Set environment variables when conda activates the environment
+case "${target_platform}" in
+win-*)
+    SCRIPT_SUFFIX="bat"
+    ;;
+*)
+    SCRIPT_SUFFIX="sh"
+    ;;
+esac
+for action in activate deactivate; do
+    if test -f "${RECIPE_DIR}/${action}.${SCRIPT_SUFFIX}"; then
+        mkdir -p "${PREFIX}/etc/conda/${action}.d"
+        cp "${RECIPE_DIR}/${action}.${SCRIPT_SUFFIX}" "${PREFIX}/etc/conda/${action}.d/${PKG_NAME}_${action}.${SCRIPT_SUFFIX}"
     fi
 done
 
+# Only binary packages have executables to clean up
+if test "${KIND}" = "binary"; then
+    cd "${PREFIX}/bin"
+
+    for f in *; do
+        if [[ "$f" == *"-${PKG_VERSION}"* ]]; then
+            short="${f%%-*}"
+            mv "${f}" "${short}"
+        fi
+    done
+
+    # Remove self-updaters, conda takes care of updates. The executable
+    # named like the package and the only executable are the tool itself.
+    if test -f "${RECIPE_DIR}/exclude_binaries.txt" && test "$(ls -A | wc -l)" -gt 1; then
+        while IFS= read -r pattern; do
+            if test -n "${pattern}"; then
+                find . -maxdepth 1 \( -name "${pattern}" -o -name "${pattern}.exe" \) \
+                    ! -name "${PKG_NAME}" ! -name "${PKG_NAME}.exe" -exec rm -f {} +
+            fi
+        done < "${RECIPE_DIR}/exclude_binaries.txt"
+    fi
+
+    # Keep only the executables the package asks for on the PATH
+    if test -f "${RECIPE_DIR}/binaries.txt"; then
+        for f in *; do
+            if ! grep -qxF -e "${f}" -e "${f%.*}" "${RECIPE_DIR}/binaries.txt"; then
+                mv "${f}" "${PREFIX}/extras"
+            fi
+        done
+    fi
+fi
+
 shopt -u dotglob
+
+# Scripts conda runs after installing and before removing the package
+if test "${SCRIPT_SUFFIX}" = "bat"; then
+    SCRIPT_DIR="${PREFIX}/Scripts"
+else
+    SCRIPT_DIR="${PREFIX}/bin"
+fi
+for action in post-link pre-unlink; do
+    if test -f "${RECIPE_DIR}/${action}.${SCRIPT_SUFFIX}"; then
+        mkdir -p "${SCRIPT_DIR}"
+        cp "${RECIPE_DIR}/${action}.${SCRIPT_SUFFIX}" "${SCRIPT_DIR}/.${PKG_NAME}-${action}.${SCRIPT_SUFFIX}"
+    fi
+done
@@ -2,13 +2,19 @@
 
 set -e
 
-# Reads TARGET_CHANNEL from env.sh
+# Reads TARGET_CHANNEL and QUARANTINE_CHANNEL from env.sh
 test -f "./env.sh" && source "./env.sh"
 
 test -f "build.sh" || exit 1
 
 CURRENT="${PWD}"
 
+# Packages are built into one directory per destination channel, only the
+# ones passing `octoconda install-test` get uploaded
+OUTPUT="${CURRENT}/output"
+OCTOCONDA="${OCTOCONDA:-octoconda}"
+rm -rf "${OUTPUT}"
+
 echo "Build and upload all conda recipes in ${CURRENT}"
 
 RECIPE_COUNT=$(find . -type f -name recipe.yaml | wc -l) 
@@ -25,6 +31,7 @@ for platform in "${CURRENT}/"*/; do
   if test -d "$platform"; then
     PLATFORM_DIR="${platform}"
     platform=$(basename "${PLATFORM_DIR}")
+    test "${platform}" = "output" && continue
     echo "*** Processing ${platform} in ${PLATFORM_DIR}"
 
     for package in "${PLATFORM_DIR}/"*/; do
@@ -34,18 +41,36 @@ for platform in "${CURRENT}/"*/; do
       if test -d "$PACKAGE_DIR"; then
         echo "    * ${package} (${count}/${RECIPE_COUNT})"
         if test -f "${PACKAGE_DIR}/recipe.yaml"; then
-          if ( cd "${PACKAGE_DIR}" \
-              && rattler-build publish \
-                  --to "https://prefix.dev/${TARGET_CHANNEL}" \
-                  --generate-attestation \
-                  --target-platform="${platform}" 2>&1 \
-                      | grep -v 'Ignoring prefix-detection for file:' \
-                      | grep -v '├─ extras/' \
-             ); then
-            SUCCESS_PACKAGES=$((SUCCESS_PACKAGES + 1))
+          if test "${platform}" = "multi"; then
+            # Multi-platform recipes select their sources per target platform
+            TARGETS=$(grep -o 'target_platform == "[^"]*"' "${PACKAGE_DIR}/recipe.yaml" | cut -d'"' -f2)
           else
-            FAILED_PACKAGES=$((FAILED_PACKAGES + 1))
+            TARGETS="${platform}"
+          fi
+          # Quarantined packages go to the quarantine channel, if there is
+          # one, and are only built otherwise
+          DESTINATION="target"
+          if test -f "${PACKAGE_DIR}/quarantine.txt"; then
+            if test -n "${QUARANTINE_CHANNEL}"; then
+              DESTINATION="quarantine"
+            else
+              echo "        QUARANTINED, BUILDING WITHOUT UPLOADING"
+              DESTINATION="none"
+            fi
           fi
+          for target in ${TARGETS}; do
+            if ( cd "${PACKAGE_DIR}" \
+                && rattler-build build \
+                    --output-dir="${OUTPUT}/${DESTINATION}" \
+                    --target-platform="${target}" 2>&1 \
+                        | grep -v 'Ignoring prefix-detection for file:' \
+                        | grep -v '├─ extras/' \
+               ); then
+              SUCCESS_PACKAGES=$((SUCCESS_PACKAGES + 1))
+            else
+              FAILED_PACKAGES=$((FAILED_PACKAGES + 1))
+            fi
+          done
           count=$((count + 1))
         else
           echo "        NO RECIPE FOUND, SKIPPING"
@@ -63,3 +88,42 @@ done
 } >> report.txt
 
 shopt -u dotglob
+
+test -d "${OUTPUT}" || exit 0
+
+# rattler-build keeps downloaded sources next to the packages
+PACKAGES=$(find "${OUTPUT}" -mindepth 3 -maxdepth 3 -type f \
+  \( -name '*.conda' -o -name '*.tar.bz2' \) -not -path '*/src_cache/*')
+test -n "${PACKAGES}" || exit 0
+echo >> report.txt
+# Failures are listed in the report, the packages passing get uploaded
+# shellcheck disable=SC2086
+"${OCTOCONDA}" install-test --passed "${CURRENT}/passed.txt" ${PACKAGES} >> report.txt || true
+
+UPLOADED_PACKAGES=0
+UPLOAD_FAILED_PACKAGES=0
+
+upload() {
+  CHANNEL="$1"
+  DIR="$2"
+  test -n "${CHANNEL}" || return 0
+  while read -r package_file; do
+    if rattler-build upload prefix --channel "${CHANNEL}" --generate-attestation "${package_file}"; then
+      UPLOADED_PACKAGES=$((UPLOADED_PACKAGES + 1))
+    else
+      UPLOAD_FAILED_PACKAGES=$((UPLOAD_FAILED_PACKAGES + 1))
+    fi
+  done < <(grep "^${DIR}/" "${CURRENT}/passed.txt")
+}
+
+if test -f "${CURRENT}/passed.txt"; then
+  upload "${TARGET_CHANNEL}" "${OUTPUT}/target"
+  upload "${QUARANTINE_CHANNEL}" "${OUTPUT}/quarantine"
+fi
+
+{ \
+  echo ; \
+  echo "## Package upload" ; \
+  echo ; \
+  echo "Success: ${UPLOADED_PACKAGES}, Failed: ${UPLOAD_FAILED_PACKAGES}"; \
+} >> report.txt
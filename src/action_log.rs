@@ -0,0 +1,153 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! An append-only log of the decisions of each run
+//!
+//! Every line is a JSON object, so that the provenance of published packages
+//! can be audited later: Which asset with which digest went into a package,
+//! and why versions or platforms were skipped.
+
+use std::{io::Write as _, path::Path};
+
+use anyhow::Context as _;
+use rattler_conda_types::Platform;
+
+use crate::{Report, package_generation::ChosenAsset};
+
+/// One decision about a package
+#[derive(Debug, serde::Serialize)]
+pub struct Action<'a> {
+    pub time: chrono::DateTime<chrono::Utc>,
+    pub package: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    pub status: &'static str,
+    pub message: &'a str,
+    #[serde(skip_serializing_if = "no_assets")]
+    pub assets: &'a [ChosenAsset],
+}
+
+fn no_assets(assets: &&[ChosenAsset]) -> bool {
+    assets.is_empty()
+}
+
+/// All decisions in `report`, made at `time`, ordered by package
+pub fn actions(report: &Report, time: chrono::DateTime<chrono::Utc>) -> Vec<Action<'_>> {
+    let mut packages = report.packages.iter().collect::<Vec<_>>();
+    packages.sort_by_key(|(name, _)| *name);
+
+    packages
+        .into_iter()
+        .flat_map(|(package, versions)| {
+            versions.iter().flat_map(move |v| {
+                v.status.iter().map(move |s| Action {
+                    time,
+                    package,
+                    version: v.version.as_deref(),
+                    platform: (s.platform != Platform::Unknown).then(|| s.platform.to_string()),
+                    status: s.status.name(),
+                    message: &s.message,
+                    assets: &s.assets,
+                })
+            })
+        })
+        .collect()
+}
+
+/// Append the decisions in `report` to the log at `path`
+pub fn append(
+    path: &Path,
+    report: &Report,
+    time: chrono::DateTime<chrono::Utc>,
+) -> anyhow::Result<()> {
+    let mut lines = String::new();
+    for action in actions(report, time) {
+        lines.push_str(&serde_json::to_string(&action)?);
+        lines.push('\n');
+    }
+
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .context(format!(
+            "Failed to append to the action log {}",
+            path.display()
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package_generation::{PackagingStatus, VersionPackagingStatus};
+
+    #[test]
+    fn test_append() {
+        let mut report = Report::default();
+        report.insert(
+            "foo".to_string(),
+            vec![VersionPackagingStatus {
+                version: Some("1.0.0".to_string()),
+                found_platforms: vec![Platform::Linux64],
+                status: vec![
+                    PackagingStatus::success(Platform::Linux64).with_assets(vec![ChosenAsset {
+                        name: "foo-linux-x86_64.tar.gz".to_string(),
+                        url: "https://github.com/foo/foo/releases/download/v1.0.0/foo-linux-x86_64.tar.gz"
+                            .to_string(),
+                        digest: Some("sha256:abcd".to_string()),
+                        digest_source: Some(crate::attestation::DIGEST_SOURCE),
+                        node_id: "RA_1".to_string(),
+                        updated_at: "2025-01-01T00:00:00Z".parse().unwrap(),
+                    }]),
+                    PackagingStatus::missing_platform(Platform::OsxArm64),
+                ],
+            }],
+        );
+        report.insert(
+            "bar".to_string(),
+            vec![VersionPackagingStatus {
+                version: Some("2.0.0".to_string()),
+                found_platforms: vec![],
+                status: vec![PackagingStatus::rejected_tag(
+                    "\"nightly\" is a pre-release",
+                )],
+            }],
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("actions.jsonl");
+        let time = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        append(&log, &report, time).unwrap();
+        append(&log, &report, time).unwrap();
+
+        let content = std::fs::read_to_string(&log).unwrap();
+        let lines = content
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 6);
+
+        assert_eq!(lines[0]["package"], "bar");
+        assert_eq!(lines[0]["status"], "skipped");
+        assert!(lines[0].get("platform").is_none());
+        assert!(lines[0].get("assets").is_none());
+
+        assert_eq!(lines[1]["package"], "foo");
+        assert_eq!(lines[1]["version"], "1.0.0");
+        assert_eq!(lines[1]["platform"], "linux-64");
+        assert_eq!(lines[1]["status"], "succeeded");
+        assert_eq!(lines[1]["assets"][0]["digest"], "sha256:abcd");
+        assert_eq!(
+            lines[1]["assets"][0]["digest_source"],
+            "attested, unverified"
+        );
+        assert_eq!(lines[1]["time"], "2023-11-14T22:13:20Z");
+
+        assert_eq!(lines[2]["platform"], "osx-arm64");
+        assert_eq!(lines[2]["message"], "platform file not found");
+        assert_eq!(lines[3], lines[0]);
+    }
+}
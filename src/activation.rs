@@ -0,0 +1,125 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Scripts setting environment variables when conda activates an
+//! environment with a package, and restoring them on deactivation
+
+use std::collections::BTreeMap;
+
+/// Replaced by the prefix of the activated environment in values
+pub const PREFIX_PLACEHOLDER: &str = "{prefix}";
+
+/// The files next to `build.sh` the scripts are written to
+pub const SCRIPT_FILES: &[&str] = &[
+    "activate.sh",
+    "deactivate.sh",
+    "activate.bat",
+    "deactivate.bat",
+];
+
+/// Make sure the build script can embed `env` into shell and batch scripts
+pub fn check_env(env: &BTreeMap<String, String>) -> anyhow::Result<()> {
+    for (name, value) in env {
+        let mut chars = name.chars();
+        let valid_name = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(anyhow::anyhow!(
+                "Invalid environment variable name {name:?}"
+            ));
+        }
+        if let Some(c) = value
+            .chars()
+            .find(|c| matches!(c, '"' | '$' | '%' | '`' | '\n' | '\r'))
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid character {c:?} in the value of {name}, use {PREFIX_PLACEHOLDER} to refer to the environment"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The activation and deactivation scripts for `env`, as file name next to
+/// `build.sh` and content
+///
+/// Returns nothing if `env` is empty.
+pub fn scripts(env: &BTreeMap<String, String>) -> Vec<(&'static str, String)> {
+    if env.is_empty() {
+        return vec![];
+    }
+
+    let mut activate_sh = String::new();
+    let mut deactivate_sh = String::new();
+    let mut activate_bat = String::new();
+    let mut deactivate_bat = String::new();
+    for (name, value) in env {
+        let backup = format!("CONDA_BACKUP_{name}");
+
+        let value_sh = value.replace(PREFIX_PLACEHOLDER, "${CONDA_PREFIX}");
+        activate_sh.push_str(&format!(
+            "if [ -n \"${{{name}+x}}\" ]; then export {backup}=\"${{{name}}}\"; fi\nexport {name}=\"{value_sh}\"\n"
+        ));
+        deactivate_sh.push_str(&format!(
+            "if [ -n \"${{{backup}+x}}\" ]; then export {name}=\"${{{backup}}}\"; unset {backup}; else unset {name}; fi\n"
+        ));
+
+        let value_bat = value.replace(PREFIX_PLACEHOLDER, "%CONDA_PREFIX%");
+        activate_bat.push_str(&format!(
+            "@if defined {name} set \"{backup}=%{name}%\"\n@set \"{name}={value_bat}\"\n"
+        ));
+        deactivate_bat.push_str(&format!(
+            "@if defined {backup} (set \"{name}=%{backup}%\" & set {backup}=) else (set {name}=)\n"
+        ));
+    }
+
+    SCRIPT_FILES
+        .iter()
+        .copied()
+        .zip([activate_sh, deactivate_sh, activate_bat, deactivate_bat])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts() {
+        assert!(scripts(&BTreeMap::new()).is_empty());
+
+        let env = BTreeMap::from([("MANPATH".to_string(), "{prefix}/share/man".to_string())]);
+        check_env(&env).unwrap();
+        let scripts = scripts(&env).into_iter().collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            scripts["activate.sh"],
+            "if [ -n \"${MANPATH+x}\" ]; then export CONDA_BACKUP_MANPATH=\"${MANPATH}\"; fi\nexport MANPATH=\"${CONDA_PREFIX}/share/man\"\n"
+        );
+        assert_eq!(
+            scripts["deactivate.sh"],
+            "if [ -n \"${CONDA_BACKUP_MANPATH+x}\" ]; then export MANPATH=\"${CONDA_BACKUP_MANPATH}\"; unset CONDA_BACKUP_MANPATH; else unset MANPATH; fi\n"
+        );
+        assert_eq!(
+            scripts["activate.bat"],
+            "@if defined MANPATH set \"CONDA_BACKUP_MANPATH=%MANPATH%\"\n@set \"MANPATH=%CONDA_PREFIX%/share/man\"\n"
+        );
+        assert_eq!(
+            scripts["deactivate.bat"],
+            "@if defined CONDA_BACKUP_MANPATH (set \"MANPATH=%CONDA_BACKUP_MANPATH%\" & set CONDA_BACKUP_MANPATH=) else (set MANPATH=)\n"
+        );
+    }
+
+    #[test]
+    fn test_check_env() {
+        let check = |name: &str, value: &str| {
+            check_env(&BTreeMap::from([(name.to_string(), value.to_string())]))
+        };
+        assert!(check("TOOL_HOME", "{prefix}\\share\\tool").is_ok());
+        assert!(check("1TOOL", "x").is_err());
+        assert!(check("TOOL-HOME", "x").is_err());
+        assert!(check("TOOL_HOME", "$HOME/.tool").is_err());
+        assert!(check("TOOL_HOME", "\"quoted\"").is_err());
+    }
+}
@@ -0,0 +1,172 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! `[[packages]]` entries for new repositories
+
+use std::collections::BTreeMap;
+
+use octocrab::models::repos::Asset;
+use rattler_conda_types::Platform;
+
+use crate::{
+    config_file::Package,
+    package_generation::{AssetFilter, match_platform},
+};
+
+/// The asset the default patterns pick for each platform of `package`, as
+/// index into `assets`
+pub fn default_choices(package: &Package, assets: &[Asset]) -> BTreeMap<Platform, Option<usize>> {
+    package
+        .platforms
+        .iter()
+        .map(|(platform, patterns)| {
+            let patterns = patterns.iter().map(|p| p.regex.clone()).collect::<Vec<_>>();
+            let chosen = match_platform(
+                &patterns,
+                &AssetFilter::default(),
+                &package.asset_preference,
+                assets,
+            )
+            .and_then(|chosen| assets.iter().position(|a| std::ptr::eq(a, chosen)));
+            (*platform, chosen)
+        })
+        .collect()
+}
+
+/// A glob matching `asset_name` in releases of other versions as well
+///
+/// `version` is replaced by `*`, glob special characters are escaped.
+pub fn asset_glob(asset_name: &str, version: &str) -> String {
+    let escape = |part: &str| {
+        part.chars()
+            .map(|c| match c {
+                '*' | '?' | '[' => format!("[{c}]"),
+                c => c.to_string(),
+            })
+            .collect::<String>()
+    };
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let parts = if version.is_empty() {
+        vec![asset_name]
+    } else {
+        asset_name.split(version).collect()
+    };
+    let glob = parts.into_iter().map(escape).collect::<Vec<_>>().join("*");
+    format!("glob:{glob}")
+}
+
+/// What to configure for a platform the defaults get wrong
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Override {
+    /// Do not package the platform
+    Disable,
+    /// Pick assets matching this pattern
+    Pattern(String),
+}
+
+/// The overrides needed to get `chosen` instead of the `defaults`
+///
+/// Both map platforms to an index into `assets`, `tag` is the tag of the
+/// release the assets belong to.
+pub fn overrides(
+    defaults: &BTreeMap<Platform, Option<usize>>,
+    chosen: &BTreeMap<Platform, Option<usize>>,
+    assets: &[Asset],
+    tag: &str,
+) -> BTreeMap<Platform, Override> {
+    chosen
+        .iter()
+        .filter(|(platform, choice)| defaults.get(platform).copied().flatten() != **choice)
+        .map(|(platform, choice)| {
+            let value = match choice {
+                Some(index) => Override::Pattern(asset_glob(&assets[*index].name, tag)),
+                None => Override::Disable,
+            };
+            (*platform, value)
+        })
+        .collect()
+}
+
+/// A `[[packages]]` entry for `repository` with `overrides`
+pub fn package_entry(repository: &str, overrides: &BTreeMap<Platform, Override>) -> String {
+    let mut result = format!("\n[[packages]]\nrepository = {}\n", toml_string(repository));
+    if !overrides.is_empty() {
+        let platforms = overrides
+            .iter()
+            .map(|(platform, value)| {
+                let value = match value {
+                    Override::Disable => toml_string("null"),
+                    Override::Pattern(pattern) => toml_string(pattern),
+                };
+                format!("{platform} = {value}")
+            })
+            .collect::<Vec<_>>();
+        result.push_str(&format!("platforms = {{ {} }}\n", platforms.join(", ")));
+    }
+    result
+}
+
+fn toml_string(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config_file::TomlPackage, package_generation::tests::asset_fixture};
+
+    #[test]
+    fn test_asset_glob() {
+        assert_eq!(
+            asset_glob("tool-1.2.3-x86_64-linux.tar.gz", "v1.2.3"),
+            "glob:tool-*-x86_64-linux.tar.gz"
+        );
+        assert_eq!(
+            asset_glob("tool_1.2.3_1.2.3.zip", "1.2.3"),
+            "glob:tool_*_*.zip"
+        );
+        assert_eq!(asset_glob("tool[linux]", "v2"), "glob:tool[[]linux]");
+    }
+
+    #[test]
+    fn test_overrides() {
+        let assets = [
+            "tool-1.0.0-x86_64-unknown-linux-musl.tar.gz",
+            "tool-1.0.0-x86_64-pc-windows-msvc.zip",
+            "tool-1.0.0-linux-x64-static.bin",
+        ]
+        .map(|name| asset_fixture(name, None));
+        let package = Package::with_defaults("foo/tool").unwrap();
+
+        let defaults = default_choices(&package, &assets);
+        assert_eq!(defaults[&Platform::Linux64], Some(0));
+        assert_eq!(defaults[&Platform::Win64], Some(1));
+        assert_eq!(defaults[&Platform::OsxArm64], None);
+
+        let mut chosen = defaults.clone();
+        assert!(overrides(&defaults, &chosen, &assets, "v1.0.0").is_empty());
+
+        chosen.insert(Platform::Linux64, Some(2));
+        chosen.insert(Platform::Win64, None);
+        let overrides = overrides(&defaults, &chosen, &assets, "v1.0.0");
+        assert_eq!(
+            overrides,
+            BTreeMap::from([
+                (
+                    Platform::Linux64,
+                    Override::Pattern("glob:tool-*-linux-x64-static.bin".to_string())
+                ),
+                (Platform::Win64, Override::Disable),
+            ])
+        );
+
+        let entry = package_entry("foo/tool", &overrides);
+        let toml: TomlPackage = toml::from_str(&entry.replace("[[packages]]", "")).unwrap();
+        let package = Package::try_from(toml).unwrap();
+        assert!(!package.platforms.contains_key(&Platform::Win64));
+        assert_eq!(
+            default_choices(&package, &assets)[&Platform::Linux64],
+            Some(2)
+        );
+    }
+}
@@ -0,0 +1,310 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Asset digests from the in-toto attestations some projects publish with
+//! their releases
+//!
+//! GitHub only reports digests for assets uploaded recently. The attestation
+//! API of GitHub is looked up by digest, so it can not help to find one, but
+//! SLSA provenance (`*.intoto.jsonl`) and sigstore bundles (`*.sigstore.json`)
+//! attached to a release list the digests of all assets they cover.
+//!
+//! Whoever can replace an asset can replace those files as well, and their
+//! signatures are not checked here. So digests are only used if GitHub keeps
+//! an attestation of the repository for them, and are marked as
+//! [`DIGEST_SOURCE`] in the action log.
+
+use std::collections::HashMap;
+
+use anyhow::Context as _;
+use base64::{
+    Engine as _, alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+};
+
+/// The `digest_source` of assets with digests from attestations
+pub const DIGEST_SOURCE: &str = "attested, unverified";
+
+/// Name endings of release assets holding attestations
+const ATTESTATION_SUFFIXES: &[&str] = &[".intoto.jsonl", ".sigstore.json"];
+
+/// Whether the release asset `name` holds attestations
+pub fn is_attestation(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ATTESTATION_SUFFIXES.iter().any(|s| name.ends_with(s))
+}
+
+const PADDING_OPTIONAL: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+const STANDARD: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, PADDING_OPTIONAL);
+const URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, PADDING_OPTIONAL);
+
+/// Decode standard or URL-safe base64, padded or not
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    STANDARD
+        .decode(text)
+        .or_else(|_| URL_SAFE.decode(text))
+        .ok()
+}
+
+/// The in-toto statement in the DSSE envelope `envelope`, which may be
+/// wrapped in a sigstore bundle
+fn statement(envelope: &serde_json::Value) -> anyhow::Result<serde_json::Value> {
+    let envelope = envelope.get("dsseEnvelope").unwrap_or(envelope);
+    let payload = envelope
+        .get("payload")
+        .and_then(|p| p.as_str())
+        .context("The attestation has no DSSE payload")?;
+    let payload = base64_decode(payload).context("The DSSE payload is no base64")?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+/// The digests of the subjects of the attestations in `content`, keyed by
+/// subject name, as `algorithm:hex` like GitHub reports them
+pub fn subject_digests(content: &[u8]) -> anyhow::Result<HashMap<String, String>> {
+    let content = std::str::from_utf8(content)?;
+    let mut result = HashMap::new();
+    for line in content.lines().filter(|l| !l.trim().is_empty()) {
+        let statement = statement(&serde_json::from_str(line)?)?;
+        let subjects = statement
+            .get("subject")
+            .and_then(|s| s.as_array())
+            .context("The attestation has no subjects")?;
+        for subject in subjects {
+            let Some(name) = subject.get("name").and_then(|n| n.as_str()) else {
+                continue;
+            };
+            let digest = ["sha256", "sha512"].iter().find_map(|algorithm| {
+                subject
+                    .get("digest")
+                    .and_then(|d| d.get(algorithm))
+                    .and_then(|d| d.as_str())
+                    .map(|value| format!("{algorithm}:{value}"))
+            });
+            if let Some(digest) = digest {
+                result.insert(name.to_string(), digest);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Digests of the assets of `releases` that GitHub has no digest for, keyed
+/// by download URL
+///
+/// Only attestations in `inspections` are used, i.e. those of releases that
+/// get recipes. Failures are logged and otherwise ignored.
+pub fn attested_digests(
+    releases: &crate::github::Releases,
+    inspections: &crate::inspect::Inspections,
+) -> HashMap<String, String> {
+    let mut result = HashMap::new();
+    for (release, _) in releases {
+        if release.assets.iter().all(|a| a.digest.is_some()) {
+            continue;
+        }
+        for attestation in release.assets.iter().filter(|a| is_attestation(&a.name)) {
+            let digests = match inspections.subject_digests(attestation) {
+                Some(Ok(digests)) => digests,
+                Some(Err(e)) => {
+                    tracing::warn!("Ignoring attestation {}: {e:#}", attestation.name);
+                    continue;
+                }
+                None => continue,
+            };
+            for asset in release.assets.iter().filter(|a| a.digest.is_none()) {
+                if let Some(digest) = digests.get(&asset.name) {
+                    result.insert(asset.browser_download_url.to_string(), digest.clone());
+                }
+            }
+        }
+    }
+    result
+}
+
+/// The `digests` GitHub keeps an attestation of `repository` for
+///
+/// Failures are logged and the digest is dropped.
+pub async fn confirmed_digests(
+    provider: &impl crate::ReleaseProvider,
+    repository: &crate::types::Repository,
+    digests: HashMap<String, String>,
+) -> HashMap<String, String> {
+    let mut result = HashMap::new();
+    for (url, digest) in digests {
+        match provider.has_attestation(repository, &digest).await {
+            Ok(true) => {
+                result.insert(url, digest);
+            }
+            Ok(false) => tracing::warn!("Ignoring {digest} of {url}: GitHub has no attestation"),
+            Err(e) => tracing::warn!("Ignoring {digest} of {url}: {e}"),
+        }
+    }
+    result
+}
+
+/// Mark the assets in `status` whose digest is one of the attested `digests`
+pub fn mark_attested(
+    status: &mut [crate::package_generation::VersionPackagingStatus],
+    digests: &HashMap<String, String>,
+) {
+    let assets = status
+        .iter_mut()
+        .flat_map(|v| v.status.iter_mut())
+        .flat_map(|s| s.assets.iter_mut());
+    for asset in assets {
+        if asset.digest.is_some() && asset.digest.as_ref() == digests.get(&asset.url) {
+            asset.digest_source = Some(DIGEST_SOURCE);
+        }
+    }
+}
+
+/// Fill in the `digests` of assets of `releases` GitHub has no digest for
+pub fn apply_digests(releases: &mut crate::github::Releases, digests: &HashMap<String, String>) {
+    for (release, _) in releases {
+        for asset in release.assets.iter_mut().filter(|a| a.digest.is_none()) {
+            if let Some(digest) = digests.get(asset.browser_download_url.as_str()) {
+                tracing::debug!("Using attested digest of {}", asset.name);
+                asset.digest = Some(digest.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package_generation::tests::{asset_fixture, release_fixture};
+
+    /// An in-toto statement about `tool-linux.tar.gz` with a SHA256 of `abab…`
+    const PAYLOAD: &str = "eyJfdHlwZSI6Imh0dHBzOi8vaW4tdG90by5pby9TdGF0ZW1lbnQvdjEiLCJzdWJqZWN0IjpbeyJuYW1lIjoidG9vbC1saW51eC50YXIuZ3oiLCJkaWdlc3QiOnsic2hhMjU2IjoiYWJhYmFiYWJhYmFiYWJhYmFiYWJhYmFiYWJhYmFiYWJhYmFiYWJhYmFiYWJhYmFiYWJhYmFiYWJhYmFiYWJhYiJ9fV19";
+
+    #[test]
+    fn test_base64_decode() {
+        assert_eq!(base64_decode("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(base64_decode("aGVsbG8").unwrap(), b"hello");
+        assert_eq!(base64_decode("-_8=").unwrap(), [0xfb, 0xff]);
+        assert!(base64_decode("not base64!").is_none());
+        // Leftover bits must be zero
+        assert!(base64_decode("QR==").is_none());
+    }
+
+    #[test]
+    fn test_subject_digests() {
+        let sha256 = format!("sha256:{}", "ab".repeat(32));
+
+        let intoto = format!(
+            "{{\"payloadType\":\"application/vnd.in-toto+json\",\"payload\":\"{PAYLOAD}\"}}\n\n"
+        );
+        let digests = subject_digests(intoto.as_bytes()).unwrap();
+        assert_eq!(
+            digests,
+            HashMap::from([("tool-linux.tar.gz".to_string(), sha256.clone())])
+        );
+
+        let bundle = format!("{{\"dsseEnvelope\":{{\"payload\":\"{PAYLOAD}\"}}}}");
+        let digests = subject_digests(bundle.as_bytes()).unwrap();
+        assert_eq!(digests["tool-linux.tar.gz"], sha256);
+
+        assert!(subject_digests(b"{\"payload\":\"e30=\"}").is_err());
+        assert!(subject_digests(b"not json").is_err());
+    }
+
+    /// Keeps attestations for `sha256:abcd` only
+    struct Attestations;
+
+    impl crate::ReleaseProvider for Attestations {
+        async fn query_releases(
+            &self,
+            _package: &crate::config_file::Package,
+        ) -> crate::Result<(octocrab::models::Repository, crate::github::Releases)> {
+            unreachable!()
+        }
+
+        async fn has_attestation(
+            &self,
+            _repository: &crate::types::Repository,
+            digest: &str,
+        ) -> crate::Result<bool> {
+            Ok(digest == "sha256:abcd")
+        }
+    }
+
+    #[test]
+    fn test_confirmed_digests() {
+        use crate::package_generation::{PackagingStatus, VersionPackagingStatus};
+        use rattler_conda_types::Platform;
+
+        let repository = crate::types::Repository::try_from("foo/tool").unwrap();
+        let digests = HashMap::from([
+            (
+                "https://example.org/a".to_string(),
+                "sha256:abcd".to_string(),
+            ),
+            (
+                "https://example.org/b".to_string(),
+                "sha256:ef01".to_string(),
+            ),
+        ]);
+        let confirmed = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(confirmed_digests(&Attestations, &repository, digests));
+        assert_eq!(
+            confirmed,
+            HashMap::from([(
+                "https://example.org/a".to_string(),
+                "sha256:abcd".to_string()
+            )])
+        );
+
+        let asset = |name: &str, digest: Option<&str>| {
+            let mut asset =
+                crate::package_generation::ChosenAsset::from(&asset_fixture(name, digest));
+            asset.url = format!("https://example.org/{name}");
+            asset
+        };
+        let mut status = vec![VersionPackagingStatus {
+            version: Some("1.0.0-0".to_string()),
+            found_platforms: vec![Platform::Linux64, Platform::Win64],
+            status: vec![
+                PackagingStatus::success(Platform::Linux64)
+                    .with_assets(vec![asset("a", Some("sha256:abcd"))]),
+                PackagingStatus::success(Platform::Win64)
+                    .with_assets(vec![asset("b", Some("sha256:1234"))]),
+            ],
+        }];
+        mark_attested(&mut status, &confirmed);
+        assert_eq!(
+            status[0].status[0].assets[0].digest_source,
+            Some(DIGEST_SOURCE)
+        );
+        assert_eq!(status[0].status[1].assets[0].digest_source, None);
+    }
+
+    #[test]
+    fn test_apply_digests() {
+        assert!(is_attestation("tool.intoto.jsonl"));
+        assert!(is_attestation("tool-linux.tar.gz.sigstore.json"));
+        assert!(!is_attestation("tool-linux.tar.gz"));
+
+        let mut release = release_fixture("v1.0.0", &[]);
+        release.assets = vec![
+            asset_fixture("tool-linux.tar.gz", None),
+            asset_fixture("tool-windows.zip", Some("sha256:1234")),
+        ];
+        let mut releases = vec![(release, Ok(("1.0.0".to_string(), 0)))];
+        let url = releases[0].0.assets[0].browser_download_url.to_string();
+        apply_digests(
+            &mut releases,
+            &HashMap::from([(url, "sha256:abcd".to_string())]),
+        );
+        let digests = releases[0]
+            .0
+            .assets
+            .iter()
+            .map(|a| a.digest.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(digests, vec![Some("sha256:abcd"), Some("sha256:1234")]);
+    }
+}
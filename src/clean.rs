@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Find and remove recipes in permanent work directories
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr as _,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context as _;
+use rattler_conda_types::{Platform, VersionWithSource};
+
+use crate::{
+    conda::ChannelPackage,
+    package_generation::{RecipeFormat, RecipeLayout},
+};
+
+/// A recipe a previous run generated
+#[derive(Clone, Debug)]
+pub struct GeneratedRecipe {
+    /// The directory containing the recipe file
+    pub path: PathBuf,
+    pub format: RecipeFormat,
+    /// More than one for multi-platform recipes
+    pub platforms: Vec<Platform>,
+    pub name: String,
+    pub version: String,
+    pub build_number: u64,
+    /// When the recipe file was last written
+    pub modified: SystemTime,
+}
+
+impl GeneratedRecipe {
+    fn is_in(&self, records: &[ChannelPackage]) -> bool {
+        let Ok(version) = VersionWithSource::from_str(&self.version) else {
+            return false;
+        };
+        self.platforms.iter().all(|platform| {
+            let subdir = platform.to_string();
+            records.iter().any(|record| {
+                record.subdir == subdir
+                    && record.name == self.name
+                    && record.version == version
+                    && record.build_number == self.build_number
+            })
+        })
+    }
+}
+
+fn read_dir(dir: &Path) -> anyhow::Result<impl Iterator<Item = PathBuf>> {
+    Ok(std::fs::read_dir(dir)
+        .context(format!("Failed to read directory {}", dir.display()))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir()))
+}
+
+/// The directories `depth` levels below `dir` that contain a recipe file
+fn recipe_dirs(
+    dir: &Path,
+    depth: usize,
+    result: &mut Vec<(PathBuf, RecipeFormat)>,
+) -> anyhow::Result<()> {
+    if depth == 0 {
+        if let Some(format) = RecipeFormat::ALL
+            .into_iter()
+            .find(|f| dir.join(f.file_name()).is_file())
+        {
+            result.push((dir.to_path_buf(), format));
+        }
+        return Ok(());
+    }
+    for sub_dir in read_dir(dir)? {
+        recipe_dirs(&sub_dir, depth - 1, result)?;
+    }
+    Ok(())
+}
+
+/// All recipes `layout` put into `work_dir`
+///
+/// Anything that does not look like a generated recipe is ignored.
+pub fn find_recipes(work_dir: &Path, layout: RecipeLayout) -> anyhow::Result<Vec<GeneratedRecipe>> {
+    let mut paths = Vec::new();
+    recipe_dirs(work_dir, layout.depth(), &mut paths)?;
+
+    let mut result = Vec::new();
+    for (path, format) in paths {
+        let recipe_file = path.join(format.file_name());
+        let Ok(content) = std::fs::read_to_string(&recipe_file) else {
+            continue;
+        };
+        let Ok((name, version, build_number)) = crate::recipe::identify(&content) else {
+            tracing::warn!("Ignoring unparsable recipe {}", recipe_file.display());
+            continue;
+        };
+        let platforms = match layout.platform_of(path.strip_prefix(work_dir).unwrap_or(&path)) {
+            Some(platform) => vec![platform],
+            None => crate::recipe::selected_platforms(&content)?
+                .iter()
+                .filter_map(|p| Platform::from_str(p).ok())
+                .collect(),
+        };
+        if platforms.is_empty() {
+            continue;
+        }
+        let modified = std::fs::metadata(&recipe_file)
+            .and_then(|m| m.modified())
+            .context(format!("Failed to stat {}", recipe_file.display()))?;
+        result.push(GeneratedRecipe {
+            path,
+            format,
+            platforms,
+            name,
+            version,
+            build_number,
+            modified,
+        });
+    }
+    result.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(result)
+}
+
+/// The `recipes` that are older than `older_than` and, if `in_channel` is
+/// given, were published to the channel already
+pub fn select<'a>(
+    recipes: &'a [GeneratedRecipe],
+    older_than: Option<Duration>,
+    in_channel: Option<&[ChannelPackage]>,
+) -> Vec<&'a GeneratedRecipe> {
+    let now = SystemTime::now();
+    recipes
+        .iter()
+        .filter(|r| {
+            older_than.is_none_or(|age| now.duration_since(r.modified).unwrap_or_default() >= age)
+        })
+        .filter(|r| in_channel.is_none_or(|records| r.is_in(records)))
+        .collect()
+}
+
+pub fn remove(recipe: &GeneratedRecipe) -> anyhow::Result<()> {
+    std::fs::remove_dir_all(&recipe.path)
+        .context(format!("Failed to remove {}", recipe.path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_and_select_recipes() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let work_dir = work_dir.path();
+
+        let recipe_dir = work_dir.join("linux-64").join("bar-1.0.0-0");
+        std::fs::create_dir_all(&recipe_dir).unwrap();
+        std::fs::write(
+            recipe_dir.join("recipe.yaml"),
+            "package:\n  name: bar\n  version: 1.0.0\nbuild:\n  number: 0\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(work_dir.join("linux-64").join("empty")).unwrap();
+        std::fs::create_dir_all(work_dir.join("not-a-platform").join("bar")).unwrap();
+        std::fs::write(work_dir.join("build.sh"), "").unwrap();
+
+        let recipes = find_recipes(work_dir, RecipeLayout::PerPlatform).unwrap();
+        assert_eq!(recipes.len(), 1);
+        assert_eq!(recipes[0].path, recipe_dir);
+        assert_eq!(recipes[0].platforms, vec![Platform::Linux64]);
+        assert_eq!(recipes[0].name, "bar");
+        assert_eq!(recipes[0].version, "1.0.0");
+
+        assert_eq!(select(&recipes, None, None).len(), 1);
+        assert_eq!(select(&recipes, Some(Duration::ZERO), None).len(), 1);
+        assert!(select(&recipes, Some(Duration::from_secs(86400)), None).is_empty());
+        assert!(select(&recipes, None, Some(&[])).is_empty());
+
+        remove(&recipes[0]).unwrap();
+        assert!(
+            find_recipes(work_dir, RecipeLayout::PerPlatform)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
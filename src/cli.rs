@@ -2,18 +2,306 @@
 // © Tobias Hunger <tobias.hunger@gmail.com>
 
 use anyhow::Context;
-use clap::Parser;
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 use std::path::{Path, PathBuf};
 
 #[derive(Clone, Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Log more, can be given several times
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+    /// Log less, can be given several times
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub quiet: u8,
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, global = true)]
+    pub log_format: LogFormat,
+    /// Colorize the report
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto, global = true)]
+    pub color: ColorChoice,
     #[arg(long, default_value = "./config.toml")]
     pub config_file: PathBuf,
+    /// Merge `<config-file-stem>.<ENV>.toml` over the configuration file
+    #[arg(long)]
+    pub env: Option<String>,
     #[arg(long)]
     pub work_dir: Option<PathBuf>,
     #[arg(long, default_value = "false")]
     pub keep_temporary_data: bool,
+    /// File to keep information in between runs
+    #[arg(long)]
+    pub state_file: Option<PathBuf>,
+    /// Only process packages in this group (can be given several times)
+    #[arg(long)]
+    pub group: Vec<String>,
+    /// Give up on a package if retrieving its releases takes longer than
+    /// this many seconds
+    #[arg(long, value_name = "SECONDS")]
+    pub timeout_per_package: Option<u64>,
+    /// Report missing required platforms as failures
+    #[arg(long, default_value = "false")]
+    pub strict: bool,
+    /// Generate recipes even for packages that are in the channel already
+    #[arg(long, visible_alias = "force-regenerate", default_value = "false")]
+    pub force: bool,
+    /// Generate recipes with a bumped build number for packages in the
+    /// channel whose release asset was uploaded again since `--state-file`
+    /// recorded it
+    #[arg(long, default_value = "false")]
+    pub rebuild_reuploads: bool,
+    /// Do not query the channel at all, generate recipes for everything
+    #[arg(long, default_value = "false")]
+    pub ignore_channel: bool,
+    /// Ask the channel for its repodata even if the cached copy is younger
+    /// than `cache_ttl`, and update the cache
+    #[arg(long, default_value = "false")]
+    pub refresh_channel: bool,
+    /// Generate recipes even if the channel can not be checked for existing
+    /// packages, instead of failing all packages
+    #[arg(long, default_value = "false")]
+    pub ignore_channel_errors: bool,
+    /// What to do with recipes a previous run left in `--work-dir`
+    #[arg(long, value_enum, default_value_t = ExistingRecipes::Overwrite)]
+    pub existing_recipes: ExistingRecipes,
+    /// How to arrange recipe directories in the work directory
+    #[arg(long, value_enum, default_value_t = RecipeLayout::PerPlatform)]
+    pub layout: RecipeLayout,
+    /// Generate one recipe per version that selects the source for each
+    /// platform, instead of one recipe per platform
+    #[arg(long, default_value = "false")]
+    pub multi_platform_recipes: bool,
+    /// Which tool the generated recipes are for
+    #[arg(long, value_enum, default_value_t = RecipeFormat::Rattler)]
+    pub recipe_format: RecipeFormat,
+    /// minijinja recipe template for packages that do not set `recipe_template`
+    #[arg(long)]
+    pub recipe_template: Option<PathBuf>,
+    /// Only look at releases published since this date (`YYYY-MM-DD` or
+    /// RFC 3339), or since the last successful run (`last-run`, needs
+    /// `--state-file`)
+    #[arg(long, value_name = "DATE")]
+    pub since: Option<octoconda::config_file::Since>,
+    /// Report how long the stages of the run took, also written to
+    /// `timings.json` in the work directory
+    #[arg(long, default_value = "false")]
+    pub timings: bool,
+    /// Write the responses of GitHub and the channels into this directory
+    #[arg(long, value_name = "DIR", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+    /// Use responses recorded with `--record` instead of asking GitHub and
+    /// the channels
+    #[arg(long, value_name = "DIR")]
+    pub replay: Option<PathBuf>,
+    /// Stop after the first package that failed
+    #[arg(long, default_value = "false")]
+    pub fail_fast: bool,
+    /// How often to retry failed requests for the releases of a package
+    #[arg(long, default_value = "3")]
+    pub github_retries: u32,
+    /// Milliseconds to wait before the first retry, doubled for every
+    /// further retry
+    #[arg(long, default_value = "1000", value_name = "MILLISECONDS")]
+    pub github_retry_delay: u64,
+    /// Milliseconds to leave between two requests to the GitHub API
+    #[arg(long, default_value = "100", value_name = "MILLISECONDS")]
+    pub github_request_interval: u64,
+    /// Keep the releases of repositories in this directory, and only
+    /// download them again when GitHub lists them differently
+    #[arg(long, value_name = "DIR")]
+    pub release_cache: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable log lines
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ColorChoice {
+    /// Colorize when writing to a terminal and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ExistingRecipes {
+    /// Replace them
+    Overwrite,
+    /// Keep them and skip generating the recipe
+    Skip,
+    /// Fail generating the recipe
+    Fail,
+}
+
+impl From<ExistingRecipes> for octoconda::package_generation::ExistingRecipes {
+    fn from(value: ExistingRecipes) -> Self {
+        match value {
+            ExistingRecipes::Overwrite => Self::Overwrite,
+            ExistingRecipes::Skip => Self::Skip,
+            ExistingRecipes::Fail => Self::Fail,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum RecipeLayout {
+    /// `<platform>/<name>-<version>-<build>/`
+    PerPlatform,
+    /// `<name>/<version>-<build>/<platform>/`
+    PerPackage,
+    /// `<name>-<version>-<build>-<platform>/`
+    Flat,
+}
+
+impl From<RecipeLayout> for octoconda::package_generation::RecipeLayout {
+    fn from(value: RecipeLayout) -> Self {
+        match value {
+            RecipeLayout::PerPlatform => Self::PerPlatform,
+            RecipeLayout::PerPackage => Self::PerPackage,
+            RecipeLayout::Flat => Self::Flat,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum RecipeFormat {
+    /// rattler-build `recipe.yaml`
+    Rattler,
+    /// conda-build `meta.yaml`
+    CondaBuild,
+}
+
+impl From<RecipeFormat> for octoconda::package_generation::RecipeFormat {
+    fn from(value: RecipeFormat) -> Self {
+        match value {
+            RecipeFormat::Rattler => Self::Rattler,
+            RecipeFormat::CondaBuild => Self::CondaBuild,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Write a commented starter configuration to `--config-file`
+    Init {
+        /// Channel the packages get uploaded to, a prefix.dev channel name or
+        /// URL
+        #[arg(long)]
+        channel: String,
+        /// Replace an existing configuration file
+        #[arg(long, default_value = "false")]
+        force: bool,
+    },
+    /// Add a `[[packages]]` entry for a repository to `--config-file`
+    Add {
+        /// GitHub repository as `owner/repo`
+        repository: String,
+        /// Pick the asset of each platform from the latest release and only
+        /// configure platforms where the default patterns pick another one
+        #[arg(short, long, default_value = "false")]
+        interactive: bool,
+    },
+    /// Remove the `[[packages]]` entry of a package from `--config-file`
+    Remove {
+        /// Name of the package
+        name: String,
+        /// Also delete all versions of the package from the channel
+        #[arg(long, default_value = "false")]
+        purge_channel: bool,
+        /// Delete from the channel without asking for confirmation
+        #[arg(long, default_value = "false")]
+        yes: bool,
+    },
+    /// Release a quarantined package: Copy it from the quarantine channel
+    /// into the channel and remove its `quarantine` setting
+    Approve {
+        /// Name of the package
+        name: String,
+    },
+    /// Suggest packages for repositories with binary releases
+    Discover {
+        /// Github user whose starred repositories to look at
+        #[arg(long)]
+        starred: String,
+    },
+    /// Remove generated recipes from `--work-dir`
+    Clean {
+        /// Only remove recipes of packages that are in the channel already
+        #[arg(long, default_value = "false")]
+        in_channel: bool,
+        /// Only remove recipes generated more than this many days ago
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<u64>,
+        /// List the recipes that would be removed without removing them
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+    },
+    /// Keep running and generate recipes for new releases periodically,
+    /// needs a `--state-file`
+    Watch {
+        /// Time between the starts of two runs, e.g. `30m`, `6h` or `1d`
+        #[arg(long, default_value = "6h", value_parser = octoconda::config_file::parse_duration)]
+        interval: std::time::Duration,
+        /// Shell command to run in the work directory after runs that
+        /// generated recipes, e.g. to build and upload them
+        #[arg(long)]
+        build_command: Option<String>,
+    },
+    /// Listen for GitHub `release` webhooks and generate recipes for just
+    /// the published release, needs `OCTOCONDA_WEBHOOK_SECRET`
+    Webhook {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: std::net::SocketAddr,
+        /// Shell command to run in the work directory after runs that
+        /// generated recipes, e.g. to build and upload them
+        #[arg(long)]
+        build_command: Option<String>,
+    },
+    /// List the downloads of the packages in the channel, most downloaded
+    /// first
+    Stats,
+    /// Copy packages from one channel to a prefix.dev channel, e.g. from
+    /// staging to production
+    Sync {
+        /// Channel to copy from
+        #[arg(long)]
+        from: String,
+        /// prefix.dev channel to copy to
+        #[arg(long)]
+        to: String,
+        /// Only copy this package (can be given several times), defaults to
+        /// all configured packages
+        #[arg(long)]
+        package: Vec<String>,
+        /// Only copy this version (can be given several times)
+        #[arg(long)]
+        version: Vec<String>,
+        /// List the packages that would be copied without copying them
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+    },
+    /// Install built packages into throwaway environments and run them
+    InstallTest {
+        /// Package files or directories to search for them, e.g. the
+        /// rattler-build output directory
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Channel to install run dependencies from (can be given several
+        /// times)
+        #[arg(long, default_value = "conda-forge")]
+        channel: Vec<String>,
+        /// Write the packages that did not fail to this file, one path per
+        /// line, e.g. to only upload those
+        #[arg(long)]
+        passed: Option<PathBuf>,
+    },
 }
 
 pub struct WorkDir(WorkDirInner);
@@ -37,6 +325,68 @@ impl WorkDir {
 }
 
 impl Cli {
+    fn log_level(&self) -> tracing::Level {
+        match 2 + i16::from(self.verbose) - i16::from(self.quiet) {
+            ..=0 => tracing::Level::ERROR,
+            1 => tracing::Level::WARN,
+            2 => tracing::Level::INFO,
+            3 => tracing::Level::DEBUG,
+            _ => tracing::Level::TRACE,
+        }
+    }
+
+    pub fn run_options(&self) -> octoconda::RunOptions {
+        octoconda::RunOptions {
+            timeout_per_package: self.timeout_per_package.map(std::time::Duration::from_secs),
+            fail_fast: self.fail_fast,
+            retry_policy: octoconda::RetryPolicy {
+                max_retries: self.github_retries,
+                initial_delay: std::time::Duration::from_millis(self.github_retry_delay),
+            },
+            ignore_channel: self.ignore_channel,
+            refresh_channel: self.refresh_channel,
+            ignore_channel_errors: self.ignore_channel_errors,
+            since: None,
+            timings: self.timings,
+            tag: None,
+            fixtures: self.fixtures(),
+        }
+    }
+
+    pub fn github_request_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.github_request_interval)
+    }
+
+    pub fn fixtures(&self) -> Option<octoconda::fixtures::Mode> {
+        use octoconda::fixtures::Mode;
+        self.record
+            .clone()
+            .map(Mode::Record)
+            .or_else(|| self.replay.clone().map(Mode::Replay))
+    }
+
+    pub fn use_color(&self) -> bool {
+        match self.color {
+            ColorChoice::Auto => {
+                use std::io::IsTerminal as _;
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && std::io::stderr().is_terminal()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+
+    pub fn init_logging(&self) {
+        let builder = tracing_subscriber::fmt()
+            .with_max_level(self.log_level())
+            .with_writer(std::io::stderr);
+        match self.log_format {
+            LogFormat::Text => builder.init(),
+            LogFormat::Json => builder.json().init(),
+        }
+    }
+
     pub fn work_directory(&self) -> anyhow::Result<WorkDir> {
         if let Some(path) = &self.work_dir {
             let path = std::env::current_dir()
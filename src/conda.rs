@@ -1,33 +1,477 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // © Tobias Hunger <tobias.hunger@gmail.com>
 
-use rattler_conda_types::{Channel, ChannelConfig, PackageName, Platform, RepoDataRecord};
-use rattler_repodata_gateway::Gateway;
+use rattler_conda_types::{
+    Channel, ChannelConfig, PackageName, Platform, RepoDataRecord, VersionWithSource,
+};
+use rattler_networking::{
+    AuthenticationMiddleware, AuthenticationStorage,
+    authentication_storage::{StorageBackend as _, backends::memory::MemoryStorage},
+};
+use rattler_repodata_gateway::{Gateway, SourceConfig, fetch::CacheAction};
 
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-pub async fn get_conda_package_versions(
+use crate::{Config, Error, Result};
+
+/// A gateway that authenticates with the configured tokens of channel hosts
+/// first and with the credentials rattler knows about otherwise
+///
+/// Repodata is cached, the channel is asked whether the cache is current.
+pub fn gateway(config: &Config) -> Result<Gateway> {
+    gateway_with_cache_action(config, CacheAction::CacheOrFetch)
+}
+
+fn gateway_with_cache_action(config: &Config, cache_action: CacheAction) -> Result<Gateway> {
+    let memory = MemoryStorage::default();
+    for (host, credential) in &config.credentials {
+        if *host == config.github_host {
+            continue;
+        }
+        let token = crate::credentials::token(host, credential).map_err(Error::Config)?;
+        memory
+            .store(
+                host,
+                &rattler_networking::Authentication::BearerToken(token),
+            )
+            .map_err(|e| Error::Config(e.into()))?;
+    }
+
+    let mut storage = AuthenticationStorage::empty();
+    storage.add_backend(Arc::new(memory));
+    for backend in AuthenticationStorage::from_env_and_defaults()
+        .map_err(|e| Error::Config(e.into()))?
+        .backends
+    {
+        storage.add_backend(backend);
+    }
+
+    let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+        .with(AuthenticationMiddleware::from_auth_storage(storage))
+        .build();
+    let mut builder = Gateway::builder().with_client(client).with_channel_config(
+        rattler_repodata_gateway::ChannelConfig {
+            default: SourceConfig {
+                cache_action,
+                ..SourceConfig::default()
+            },
+            ..rattler_repodata_gateway::ChannelConfig::default()
+        },
+    );
+    if let Some(dir) = cache_dir(config) {
+        builder = builder.with_cache_dir(dir);
+    }
+    Ok(builder.finish())
+}
+
+/// The configured `cache_dir`, or the repodata cache of rattler
+fn cache_dir(config: &Config) -> Option<PathBuf> {
+    config.conda.cache_dir.clone().or_else(|| {
+        rattler::default_cache_dir()
+            .ok()
+            .map(|d| d.join("repodata"))
+    })
+}
+
+/// The file remembering when the repodata of `channel` was last fetched
+fn stamp_file(cache_dir: &Path, channel: &str) -> PathBuf {
+    let name = channel
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    cache_dir.join("octoconda").join(format!("{name}.fetched"))
+}
+
+/// What was fetched from a channel at which time
+///
+/// The cache only holds the subdirs and packages that were queried, so a
+/// stamp only covers queries for (some of) those.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Stamp {
+    fetched: chrono::DateTime<chrono::Utc>,
+    subdirs: BTreeSet<String>,
+    packages: BTreeSet<String>,
+}
+
+impl Stamp {
+    fn new(
+        fetched: chrono::DateTime<chrono::Utc>,
+        platforms: &[Platform],
+        packages: &[&str],
+    ) -> Self {
+        Self {
+            fetched,
+            subdirs: platforms.iter().map(|p| p.to_string()).collect(),
+            packages: packages.iter().map(|p| p.to_ascii_lowercase()).collect(),
+        }
+    }
+
+    fn covers(&self, other: &Stamp) -> bool {
+        other.subdirs.is_subset(&self.subdirs) && other.packages.is_subset(&self.packages)
+    }
+}
+
+/// Whether `stamp` says the repodata of all `platforms` and `packages` was
+/// fetched less than `ttl` before `now`
+fn is_fresh(
+    stamp: &Path,
+    ttl: std::time::Duration,
+    now: chrono::DateTime<chrono::Utc>,
+    platforms: &[Platform],
+    packages: &[&str],
+) -> bool {
+    let ttl = chrono::TimeDelta::from_std(ttl).unwrap_or(chrono::TimeDelta::MAX);
+    let queried = Stamp::new(now, platforms, packages);
+    std::fs::read_to_string(stamp)
+        .ok()
+        .and_then(|s| serde_json::from_str::<Stamp>(&s).ok())
+        .is_some_and(|stamp| {
+            now.signed_duration_since(stamp.fetched) < ttl && stamp.covers(&queried)
+        })
+}
+
+/// Uses the cached repodata of channels without asking them for the
+/// configured `cache_ttl` after fetching it
+pub struct RepodataCache<'a> {
+    config: &'a Config,
+    /// Ask the channel for current repodata even if the cache is fresh, and
+    /// update the cache with it
+    refresh: bool,
+}
+
+impl<'a> RepodataCache<'a> {
+    pub fn new(config: &'a Config, refresh: bool) -> Self {
+        Self { config, refresh }
+    }
+
+    fn cache_action(
+        &self,
+        channel: &str,
+        platforms: &[Platform],
+        packages: &[&str],
+    ) -> CacheAction {
+        if self.refresh {
+            return CacheAction::CacheOrFetch;
+        }
+        match (self.config.conda.cache_ttl, cache_dir(self.config)) {
+            (Some(ttl), Some(dir))
+                if is_fresh(
+                    &stamp_file(&dir, channel),
+                    ttl,
+                    chrono::Utc::now(),
+                    platforms,
+                    packages,
+                ) =>
+            {
+                tracing::debug!("Conda: Using cached repodata of {channel}");
+                CacheAction::ForceCacheOnly
+            }
+            _ => CacheAction::CacheOrFetch,
+        }
+    }
+
+    /// A gateway to query `packages` for `platforms` in `channel` with
+    pub fn gateway(
+        &self,
+        channel: &str,
+        platforms: &[Platform],
+        packages: &[&str],
+    ) -> Result<Gateway> {
+        gateway_with_cache_action(self.config, self.cache_action(channel, platforms, packages))
+    }
+
+    /// Remember that the repodata of `packages` for `platforms` in `channel`
+    /// was just fetched with a gateway from [`Self::gateway`]
+    pub fn fetched(&self, channel: &str, platforms: &[Platform], packages: &[&str]) {
+        let (Some(_), Some(dir)) = (self.config.conda.cache_ttl, cache_dir(self.config)) else {
+            return;
+        };
+        if !matches!(
+            self.cache_action(channel, platforms, packages),
+            CacheAction::CacheOrFetch
+        ) {
+            return;
+        }
+        let stamp = stamp_file(&dir, channel);
+        let written = stamp
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| {
+                let contents =
+                    serde_json::to_string(&Stamp::new(chrono::Utc::now(), platforms, packages))
+                        .map_err(std::io::Error::other)?;
+                std::fs::write(&stamp, contents)
+            });
+        if let Err(e) = written {
+            tracing::warn!("Failed to write {}: {e}", stamp.display());
+        }
+    }
+}
+
+/// What octoconda needs to know about a package in a channel
+///
+/// Much smaller than a full `RepoDataRecord`, which big channels have
+/// thousands of.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ChannelPackage {
+    pub name: String,
+    pub subdir: String,
+    pub version: VersionWithSource,
+    pub build: String,
+    pub build_number: u64,
+}
+
+impl From<&RepoDataRecord> for ChannelPackage {
+    fn from(record: &RepoDataRecord) -> Self {
+        let record = &record.package_record;
+        Self {
+            name: record.name.as_normalized().to_string(),
+            subdir: record.subdir.clone(),
+            version: record.version.clone(),
+            build: record.build.clone(),
+            build_number: record.build_number,
+        }
+    }
+}
+
+async fn query(
+    gateway: &Gateway,
     channel: &str,
     platforms: impl Iterator<Item = Platform> + Clone,
-
     packages: impl Iterator<Item = &str>,
-) -> Result<Vec<RepoDataRecord>, anyhow::Error> {
+) -> Result<Vec<rattler_repodata_gateway::RepoData>> {
     let channel = Channel::from_str(
         channel,
         &ChannelConfig::default_with_root_dir(PathBuf::from(".")),
-    )?;
+    )
+    .map_err(|e| Error::Channel(e.into()))?;
 
-    let specs = packages.map(|p| PackageName::try_from(p).expect("Invalid package name"));
+    let specs = packages
+        .map(PackageName::try_from)
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| Error::Config(e.into()))?;
 
-    let repo_data = Gateway::new()
+    gateway
         .query(std::iter::once(channel), platforms, specs)
-        .await?;
+        .await
+        .map_err(|e| Error::Channel(e.into()))
+}
 
+/// The full records of all `packages` in `channel` for the `platforms`
+pub async fn get_conda_package_versions(
+    gateway: &Gateway,
+    channel: &str,
+    platforms: impl Iterator<Item = Platform> + Clone,
+    packages: impl Iterator<Item = &str>,
+) -> Result<Vec<RepoDataRecord>> {
     let mut result = Vec::new();
-    for rd in repo_data {
-        for rdi in rd.iter() {
-            result.push(rdi.clone())
-        }
+    for rd in query(gateway, channel, platforms, packages).await? {
+        result.extend(rd.iter().cloned());
     }
     Ok(result)
 }
+
+/// The compact index of all `packages` in `channel` for the `platforms`
+///
+/// The full records are dropped one subdir at a time while building the
+/// index.
+pub async fn get_channel_index(
+    gateway: &Gateway,
+    channel: &str,
+    platforms: impl Iterator<Item = Platform> + Clone,
+    packages: impl Iterator<Item = &str>,
+) -> Result<Vec<ChannelPackage>> {
+    let mut result = Vec::new();
+    for rd in query(gateway, channel, platforms, packages).await? {
+        result.extend(rd.iter().map(ChannelPackage::from));
+    }
+    Ok(result)
+}
+
+/// The names of all `packages` that exist in `channel` for any of the
+/// `platforms`.
+pub async fn get_conda_package_names(
+    gateway: &Gateway,
+    channel: &str,
+    platforms: impl Iterator<Item = Platform> + Clone,
+    packages: impl Iterator<Item = &str>,
+) -> Result<HashSet<String>> {
+    let mut result = HashSet::new();
+    for rd in query(gateway, channel, platforms, packages).await? {
+        result.extend(
+            rd.iter()
+                .map(|r| r.package_record.name.as_normalized().to_string()),
+        );
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::str::FromStr as _;
+
+    use rattler_conda_types::PackageRecord;
+
+    use super::*;
+
+    pub fn record_fixture(name: &str, version: &str, subdir: &str) -> RepoDataRecord {
+        let mut package_record = PackageRecord::new(
+            PackageName::try_from(name).unwrap(),
+            VersionWithSource::from_str(version).unwrap(),
+            "h0_0".to_string(),
+        );
+        package_record.subdir = subdir.to_string();
+        let file_name = format!("{}-{version}-h0_0.conda", name.to_ascii_lowercase());
+        RepoDataRecord {
+            package_record,
+            url: url::Url::parse(&format!("https://prefix.dev/test/{subdir}/{file_name}")).unwrap(),
+            file_name,
+            channel: None,
+        }
+    }
+
+    #[test]
+    fn test_channel_package() {
+        let mut record = record_fixture("Tool", "1.2.0", "linux-64");
+        record.package_record.build_number = 3;
+
+        let package = ChannelPackage::from(&record);
+        assert_eq!(
+            package,
+            ChannelPackage {
+                name: "tool".to_string(),
+                subdir: "linux-64".to_string(),
+                version: VersionWithSource::from_str("1.2.0").unwrap(),
+                build: "h0_0".to_string(),
+                build_number: 3,
+            }
+        );
+        let json = serde_json::to_string(&package).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ChannelPackage>(&json).unwrap(),
+            package
+        );
+    }
+
+    #[test]
+    fn test_is_fresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let stamp = stamp_file(dir.path(), "https://prefix.dev/github-releases");
+        assert!(stamp.ends_with("octoconda/https___prefix_dev_github_releases.fetched"));
+
+        let now = chrono::Utc::now();
+        let ttl = std::time::Duration::from_secs(60 * 60);
+        let platforms = [Platform::Linux64, Platform::OsxArm64];
+        let packages = ["tool", "other"];
+        assert!(!is_fresh(&stamp, ttl, now, &platforms, &packages));
+
+        std::fs::create_dir_all(stamp.parent().unwrap()).unwrap();
+        std::fs::write(
+            &stamp,
+            serde_json::to_string(&Stamp::new(now, &platforms, &packages)).unwrap(),
+        )
+        .unwrap();
+        let later = now + chrono::TimeDelta::minutes(59);
+        assert!(is_fresh(&stamp, ttl, later, &platforms, &packages));
+        assert!(is_fresh(
+            &stamp,
+            ttl,
+            later,
+            &[Platform::Linux64],
+            &["Tool"]
+        ));
+        assert!(!is_fresh(
+            &stamp,
+            ttl,
+            now + chrono::TimeDelta::minutes(61),
+            &platforms,
+            &packages
+        ));
+
+        // Stamps of an older octoconda only hold the time
+        std::fs::write(&stamp, now.to_rfc3339()).unwrap();
+        assert!(!is_fresh(&stamp, ttl, later, &platforms, &packages));
+    }
+
+    #[test]
+    fn test_refresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml = format!(
+            "[conda]\nchannel = \"test\"\ncache_dir = {:?}\ncache_ttl = \"1h\"\n",
+            dir.path().display().to_string()
+        );
+        let config: Config = toml::from_str::<crate::config_file::TomlConfig>(&toml)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let channel = "https://prefix.dev/test";
+        let platforms = [Platform::Linux64];
+        let packages = ["tool"];
+
+        let cache = RepodataCache::new(&config, false);
+        assert!(matches!(
+            cache.cache_action(channel, &platforms, &packages),
+            CacheAction::CacheOrFetch
+        ));
+        cache.fetched(channel, &platforms, &packages);
+        assert!(matches!(
+            cache.cache_action(channel, &platforms, &packages),
+            CacheAction::ForceCacheOnly
+        ));
+
+        // A refresh asks the channel, updates the cache and restarts the TTL
+        let refresh = RepodataCache::new(&config, true);
+        assert!(matches!(
+            refresh.cache_action(channel, &platforms, &packages),
+            CacheAction::CacheOrFetch
+        ));
+        let stamp = stamp_file(dir.path(), channel);
+        std::fs::write(&stamp, "2000-01-01T00:00:00Z").unwrap();
+        refresh.fetched(channel, &platforms, &packages);
+        assert!(is_fresh(
+            &stamp,
+            std::time::Duration::from_secs(60),
+            chrono::Utc::now(),
+            &platforms,
+            &packages
+        ));
+    }
+
+    #[test]
+    fn test_fresh_cache_misses() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml = format!(
+            "[conda]\nchannel = \"test\"\ncache_dir = {:?}\ncache_ttl = \"1h\"\n",
+            dir.path().display().to_string()
+        );
+        let config: Config = toml::from_str::<crate::config_file::TomlConfig>(&toml)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let channel = "https://prefix.dev/test";
+
+        let cache = RepodataCache::new(&config, false);
+        cache.fetched(channel, &[Platform::Linux64], &["tool"]);
+
+        // A package or platform that was not fetched yet is not in the cache
+        assert!(matches!(
+            cache.cache_action(channel, &[Platform::Linux64], &["tool", "new-tool"]),
+            CacheAction::CacheOrFetch
+        ));
+        assert!(matches!(
+            cache.cache_action(channel, &[Platform::Linux64, Platform::Win64], &["tool"]),
+            CacheAction::CacheOrFetch
+        ));
+
+        // Fetching them stamps the new query
+        cache.fetched(channel, &[Platform::Linux64], &["tool", "new-tool"]);
+        assert!(matches!(
+            cache.cache_action(channel, &[Platform::Linux64], &["tool", "new-tool"]),
+            CacheAction::ForceCacheOnly
+        ));
+    }
+}
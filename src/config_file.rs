@@ -2,36 +2,526 @@
 // © Tobias Hunger <tobias.hunger@gmail.com>
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     convert::TryFrom,
-    path::Path,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::LazyLock,
 };
 
 use anyhow::Context;
-use rattler_conda_types::Platform;
+use rattler_conda_types::{MatchSpec, ParseStrictness, Platform, Version};
 use serde::Deserialize;
 
 use crate::types::Repository;
 
+/// A platform pattern that only applies to some versions
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScopedPattern {
+    pub pattern: String,
+    /// First version the pattern applies to
+    pub since: Option<String>,
+    /// First version the pattern does not apply to anymore
+    pub until: Option<String>,
+}
+
+impl From<String> for ScopedPattern {
+    fn from(pattern: String) -> Self {
+        Self {
+            pattern,
+            since: None,
+            until: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum PatternItem {
+    Pattern(String),
+    Scoped(ScopedPattern),
+}
+
+impl From<PatternItem> for ScopedPattern {
+    fn from(item: PatternItem) -> Self {
+        match item {
+            PatternItem::Pattern(pattern) => pattern.into(),
+            PatternItem::Scoped(scoped) => scoped,
+        }
+    }
+}
+
+/// Changes to the built-in patterns of a platform
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PatternChanges {
+    /// Patterns to try before the built-in ones
+    #[serde(default)]
+    pub prepend: Vec<PatternItem>,
+    /// Patterns to try after the built-in ones
+    #[serde(default)]
+    pub append: Vec<PatternItem>,
+    /// Patterns to use instead of the built-in ones
+    pub replace: Option<Vec<PatternItem>>,
+}
+
 #[derive(Deserialize)]
 #[serde(untagged)]
-pub enum StringOrList {
+pub enum PlatformPatterns {
     String(String),
-    List(Vec<String>),
+    List(Vec<PatternItem>),
+    Changes(PatternChanges),
+}
+
+/// A compiled platform pattern
+#[derive(Clone, Debug)]
+pub struct PlatformPattern {
+    pub regex: regex::Regex,
+    pub since: Option<Version>,
+    pub until: Option<Version>,
+}
+
+impl PlatformPattern {
+    /// Whether the pattern should be used for releases of `version`
+    pub fn applies_to(&self, version: &Version) -> bool {
+        self.since.as_ref().is_none_or(|since| version >= since)
+            && self.until.as_ref().is_none_or(|until| version < until)
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.regex.is_match(haystack)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.regex.as_str()
+    }
+}
+
+/// Which asset size to prefer when several assets match a platform
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SizePreference {
+    /// Keep the order of the release assets
+    #[default]
+    Ignore,
+    Smallest,
+    Largest,
+}
+
+/// How to pick one of several assets matched by the same platform pattern
+#[derive(Clone, Debug, Default)]
+pub struct AssetPreference {
+    /// Substrings of asset names, earlier ones win
+    pub prefer: Vec<String>,
+    /// Breaks ties left after looking at `prefer`
+    pub size: SizePreference,
+}
+
+/// How release tags map to conda versions
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum VersionScheme {
+    /// Dotted numbers with an optional `-<build number>`
+    #[default]
+    Standard,
+    /// Dates like `2024.10.2`, `2024-10-01` or `20241001`
+    Calver,
+}
+
+/// Where the default package name comes from
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NameFrom {
+    /// The repository name
+    #[default]
+    Repo,
+    /// The owner and repository name, joined by `-`
+    OwnerRepo,
+}
+
+/// What the release assets contain
+#[derive(Clone, Copy, Debug, Default, Deserialize, serde::Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PackageKind {
+    /// Executables, installed into `bin`
+    #[default]
+    Binary,
+    /// Fonts, themes, datasets and the like, installed into `install_to`
+    Data,
+    /// Shared libraries and headers, installed into `lib` and `include`
+    Library,
+    /// A pure-python wheel, repackaged as noarch python package
+    PythonWheel,
+    /// An npm tarball or node bundle, installed into `lib/node_modules`
+    Node,
+}
+
+impl PackageKind {
+    /// Whether packages of this kind are built for `noarch` only
+    pub fn is_noarch(self) -> bool {
+        matches!(self, PackageKind::PythonWheel | PackageKind::Node)
+    }
+}
+
+/// A size in bytes, or a string with a unit like `"500MB"` or `"2 GiB"`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum TomlSize {
+    Bytes(u64),
+    Text(String),
+}
+
+impl TryFrom<&TomlSize> for u64 {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &TomlSize) -> Result<Self, Self::Error> {
+        let text = match value {
+            TomlSize::Bytes(bytes) => return Ok(*bytes),
+            TomlSize::Text(text) => text.trim(),
+        };
+        let split = text
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+        let number = number
+            .parse::<f64>()
+            .context(format!("{text:?} does not start with a number"))?;
+        let factor = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "kb" => 1000,
+            "mb" => 1000 * 1000,
+            "gb" => 1000 * 1000 * 1000,
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            _ => return Err(anyhow::anyhow!("Unknown unit in size {text:?}")),
+        };
+        Ok((number * factor as f64) as u64)
+    }
+}
+
+/// Environment set up when conda activates an environment with the package
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlActivation {
+    pub env: Option<BTreeMap<String, String>>,
+}
+
+/// A script conda runs when linking or unlinking the package
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlLinkScript {
+    pub sh: Option<String>,
+    pub bat: Option<String>,
+}
+
+/// Script contents for unix and Windows, each is optional
+#[derive(Clone, Debug, Default)]
+pub struct LinkScript {
+    pub sh: Option<String>,
+    pub bat: Option<String>,
+}
+
+impl From<TomlLinkScript> for LinkScript {
+    fn from(value: TomlLinkScript) -> Self {
+        let non_empty = |s: Option<String>| s.filter(|s| !s.trim().is_empty());
+        Self {
+            sh: non_empty(value.sh),
+            bat: non_empty(value.bat),
+        }
+    }
+}
+
+/// Another place to get releases from
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlSource {
+    pub repository: Option<String>,
+    pub recorded: Option<String>,
+    /// Where to download the assets of recorded releases from, with `{tag}`
+    /// and `{name}` placeholders
+    pub assets: Option<String>,
+}
+
+/// Where to get the releases of a package from when its repository fails
+#[derive(Clone, Debug)]
+pub enum Source {
+    /// Another GitHub repository, e.g. a mirror
+    Github(Repository),
+    /// A URL serving the releases file `--record` wrote for the package
+    ///
+    /// The assets are downloaded from `assets` if given, from the URLs
+    /// GitHub listed for them otherwise.
+    Recorded {
+        releases: url::Url,
+        assets: Option<AssetUrls>,
+    },
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Github(repository) => write!(f, "{}/{}", repository.owner, repository.repo),
+            Source::Recorded { releases, .. } => write!(f, "{releases}"),
+        }
+    }
+}
+
+/// Placeholders of asset URL templates
+const ASSET_URL_PLACEHOLDERS: &[&str] = &["tag", "name"];
+
+/// A template for the download URLs of release assets, e.g.
+/// `https://mirror.example.com/tool/{tag}/{name}`
+#[derive(Clone, Debug)]
+pub struct AssetUrls(String);
+
+impl AssetUrls {
+    pub fn new(template: &str) -> anyhow::Result<Self> {
+        static PLACEHOLDER: LazyLock<regex::Regex> =
+            LazyLock::new(|| regex::Regex::new(r"\{([^}]*)\}").expect("valid regex"));
+        for p in PLACEHOLDER.captures_iter(template) {
+            if !ASSET_URL_PLACEHOLDERS.contains(&&p[1]) {
+                return Err(anyhow::anyhow!(
+                    "Unknown placeholder {{{}}} in asset URL {template:?}",
+                    &p[1]
+                ));
+            }
+        }
+        if !template.contains("{name}") {
+            return Err(anyhow::anyhow!(
+                "The asset URL {template:?} has no {{name}} placeholder"
+            ));
+        }
+        let result = Self(template.to_string());
+        result.url("v1.0.0", "tool.tar.gz")?;
+        Ok(result)
+    }
+
+    /// The download URL of the asset `name` of the release tagged `tag`
+    pub fn url(&self, tag: &str, name: &str) -> anyhow::Result<url::Url> {
+        http_url(&self.0.replace("{tag}", tag).replace("{name}", name))
+    }
 }
 
+/// Rewrites a release tag into a conda version
 #[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlVersionRewrite {
+    pub pattern: String,
+    pub replace: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct VersionRewrite {
+    pub regex: regex::Regex,
+    /// Replacement with `$1`-style references to groups of `regex`
+    pub replace: String,
+}
+
+#[derive(Default, Deserialize)]
 pub struct TomlPackage {
     pub name: Option<String>,
+    pub name_from: Option<NameFrom>,
+    /// Package name with `{owner}` and `{repo}` placeholders
+    pub name_template: Option<String>,
     pub repository: String,
-    pub platforms: Option<HashMap<Platform, StringOrList>>,
+    pub platforms: Option<HashMap<Platform, PlatformPatterns>>,
+    pub groups: Option<Vec<String>>,
+    pub require_platforms: Option<Vec<Platform>>,
+    pub recipe_template: Option<PathBuf>,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub documentation: Option<String>,
+    pub homepage: Option<String>,
+    pub doi: Option<String>,
+    pub citation: Option<String>,
+    pub maintainers: Option<Vec<String>>,
+    pub notify: Option<Vec<String>>,
+    pub disabled: Option<bool>,
+    pub quarantine: Option<bool>,
+    pub build_string: Option<String>,
+    pub anchor: Option<bool>,
+    pub asset_prefix: Option<String>,
+    pub case_insensitive: Option<bool>,
+    pub exclude_assets: Option<HashMap<Platform, Vec<String>>>,
+    pub prefer_assets: Option<Vec<String>>,
+    pub prefer_size: Option<SizePreference>,
+    pub version_scheme: Option<VersionScheme>,
+    pub version_map: Option<Vec<TomlVersionRewrite>>,
+    pub prereleases: Option<bool>,
+    pub keep_versions: Option<usize>,
+    pub max_releases: Option<usize>,
+    pub pin: Option<String>,
+    pub max_asset_size: Option<TomlSize>,
+    pub text_limits: Option<TextLimits>,
+    pub policy: Option<Policy>,
+    pub exclude_files: Option<Vec<String>>,
+    pub binaries: Option<Vec<String>>,
+    pub keep_updaters: Option<bool>,
+    pub activation: Option<TomlActivation>,
+    pub post_link: Option<TomlLinkScript>,
+    pub pre_unlink: Option<TomlLinkScript>,
+    pub source_code_assets: Option<bool>,
+    pub unsupported_oses: Option<Vec<String>>,
+    pub platforms_since: Option<HashMap<Platform, String>>,
+    pub variants: Option<Vec<String>>,
+    pub binary_relocation: Option<bool>,
+    pub prefix_detection: Option<bool>,
+    pub run_exports: Option<Vec<String>>,
+    pub run_constraints: Option<Vec<String>>,
+    pub kind: Option<PackageKind>,
+    pub install_to: Option<String>,
+    pub sources: Option<Vec<TomlSource>>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Package {
     pub name: String,
     pub repository: Repository,
-    pub platforms: HashMap<Platform, Vec<regex::Regex>>,
+    pub platforms: HashMap<Platform, Vec<PlatformPattern>>,
+    /// Assets matching any of these are never picked for the platform
+    pub exclude_assets: HashMap<Platform, Vec<regex::Regex>>,
+    pub asset_preference: AssetPreference,
+    pub groups: Vec<String>,
+    /// Platforms that must be found in every release in strict mode
+    pub required_platforms: HashSet<Platform>,
+    /// minijinja template to use instead of the default recipe
+    pub recipe_template: Option<PathBuf>,
+    /// Replaces the repository description as package summary
+    pub summary: Option<String>,
+    /// Replaces the repository description in the package description
+    pub description: Option<String>,
+    /// Documentation URL, guessed from the homepage if unset
+    pub documentation: Option<String>,
+    /// Replaces the homepage of the repository
+    pub homepage: Option<String>,
+    /// DOI to cite the packaged software with, without `https://doi.org/`
+    pub doi: Option<String>,
+    /// How to cite the packaged software
+    pub citation: Option<String>,
+    /// Who to contact when the package breaks
+    pub maintainers: Vec<String>,
+    /// Handles like `@alice` to ping in the report when the package fails
+    pub notify: Vec<String>,
+    /// Keep the package configured, but do not process it
+    pub disabled: bool,
+    /// Do not upload the package to the channel until it is approved
+    pub quarantine: bool,
+    /// Build string with `{placeholder}`s, see [`BUILD_STRING_PLACEHOLDERS`]
+    pub build_string: Option<String>,
+    pub version_scheme: VersionScheme,
+    /// Tried in order before `version_scheme`, the first match wins
+    pub version_map: Vec<VersionRewrite>,
+    /// Package releases marked as pre-release, or with pre-release tags
+    pub prereleases: bool,
+    /// Only generate recipes for this many of the newest versions
+    pub keep_versions: Option<usize>,
+    /// Only retrieve this many of the newest releases from GitHub
+    pub max_releases: Option<usize>,
+    /// Only generate recipes for this upstream version
+    pub pin: Option<Version>,
+    /// Refuse to package assets larger than this many bytes
+    pub max_asset_size: Option<u64>,
+    pub text_limits: TextLimits,
+    /// Constraints on the releases and assets that get packaged
+    pub policy: Policy,
+    /// Globs of file names to remove from the package, e.g. debug symbols
+    pub exclude_files: Vec<String>,
+    /// Executables to install when the archive contains several, all are
+    /// installed if empty
+    pub binaries: Vec<String>,
+    /// Install self-updater executables like `tool-update` instead of
+    /// removing them
+    pub keep_updaters: bool,
+    /// Environment variables set on activation, `{prefix}` in values is the
+    /// environment prefix
+    pub activation_env: BTreeMap<String, String>,
+    /// Run once after the package is installed into an environment
+    pub post_link: LinkScript,
+    /// Run before the package is removed from an environment
+    pub pre_unlink: LinkScript,
+    /// Let platform patterns match GitHub's "Source code" archives
+    pub source_code_assets: bool,
+    /// Replaces the operating systems whose assets never match
+    pub unsupported_oses: Option<Vec<String>>,
+    /// First version each platform is expected in, detected if unset
+    pub platforms_since: HashMap<Platform, Version>,
+    /// Asset name parts of builds published side by side, e.g. `musl` and
+    /// `gnu`
+    pub variants: Vec<String>,
+    /// Let rattler-build relocate binaries, detected from the asset if unset
+    pub binary_relocation: Option<bool>,
+    /// Let rattler-build replace the build prefix in files, detected from
+    /// the asset if unset
+    pub prefix_detection: Option<bool>,
+    /// Match specs added to the requirements of packages built against
+    /// this one
+    pub run_exports: Vec<String>,
+    /// Match specs constraining packages installed next to this one
+    pub run_constraints: Vec<String>,
+    pub kind: PackageKind,
+    /// Directory relative to the environment prefix the contents of data
+    /// packages are installed into
+    pub install_to: Option<String>,
+    /// Fallbacks tried in order when the repository fails or has no assets
+    pub sources: Vec<Source>,
+}
+
+impl Package {
+    /// A package for `repository` with all settings at their defaults
+    pub fn with_defaults(repository: &str) -> anyhow::Result<Self> {
+        TomlPackage {
+            repository: repository.to_string(),
+            ..Default::default()
+        }
+        .try_into()
+    }
+
+    /// The patterns of each platform that apply to releases of `version`
+    pub fn patterns_for(&self, version: &Version) -> HashMap<Platform, Vec<regex::Regex>> {
+        self.platforms
+            .iter()
+            .map(|(platform, patterns)| {
+                let patterns = patterns
+                    .iter()
+                    .filter(|p| p.applies_to(version))
+                    .map(|p| p.regex.clone())
+                    .collect();
+                (*platform, patterns)
+            })
+            .collect()
+    }
+}
+
+/// Files left out of packages unless `exclude_files` says otherwise: Debug
+/// symbols bloat packages without being useful to users
+pub const DEFAULT_EXCLUDE_FILES: &[&str] = &["*.pdb", "*.debug", "*.dSYM"];
+
+/// Placeholders available in build strings
+pub const BUILD_STRING_PLACEHOLDERS: &[&str] = &["build_number", "libc", "shortsha"];
+
+fn check_build_string(build_string: &str) -> anyhow::Result<()> {
+    static PLACEHOLDER: LazyLock<regex::Regex> =
+        LazyLock::new(|| regex::Regex::new(r"\{([^}]*)\}").expect("valid regex"));
+    for p in PLACEHOLDER.captures_iter(build_string) {
+        if !BUILD_STRING_PLACEHOLDERS.contains(&&p[1]) {
+            return Err(anyhow::anyhow!(
+                "Unknown placeholder {{{}}} in build string {build_string:?}",
+                &p[1]
+            ));
+        }
+    }
+    let literal = PLACEHOLDER.replace_all(build_string, "");
+    if let Some(c) = literal
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && !matches!(c, '_' | '.' | '+'))
+    {
+        return Err(anyhow::anyhow!(
+            "Invalid character {c:?} in build string {build_string:?}"
+        ));
+    }
+    Ok(())
 }
 
 fn default_platforms() -> HashMap<Platform, Vec<String>> {
@@ -73,6 +563,35 @@ fn default_platforms() -> HashMap<Platform, Vec<String>> {
                     .to_string(),
             ],
         ),
+        (
+            Platform::LinuxArmV7l,
+            // ARMv6 binaries run on ARMv7, so use those if there is nothing better
+            vec![
+                "(^|[\\._-])armv7l?[\\._-](unknown[\\._-])?linux[\\._-]musleabihf(\\.gz|\\.xz|\\.zst|\\.tar\\.gz|\\.tar\\.xz|\\.tgz|\\.txz|\\.zip)?$"
+                    .to_string(),
+                "(^|[\\._-])armv7l?[\\._-](unknown[\\._-])?linux([\\._-]gnueabihf)?(\\.gz|\\.xz|\\.zst|\\.tar\\.gz|\\.tar\\.xz|\\.tgz|\\.txz|\\.zip)?$"
+                    .to_string(),
+                "(^|[\\._-])linux[\\._-](armv7l?|armhf)([\\._-]unknown)?([\\._-]gnueabihf|[\\._-]musleabihf)?(\\.gz|\\.xz|\\.zst|\\.tar\\.gz|\\.tar\\.xz|\\.tgz|\\.txz|\\.zip)?$"
+                    .to_string(),
+                "(^|[\\._-])arm(v6)?[\\._-](unknown[\\._-])?linux[\\._-]musleabihf(\\.gz|\\.xz|\\.zst|\\.tar\\.gz|\\.tar\\.xz|\\.tgz|\\.txz|\\.zip)?$"
+                    .to_string(),
+                "(^|[\\._-])arm(v6)?[\\._-](unknown[\\._-])?linux[\\._-]gnueabihf(\\.gz|\\.xz|\\.zst|\\.tar\\.gz|\\.tar\\.xz|\\.tgz|\\.txz|\\.zip)?$"
+                    .to_string(),
+                "(^|[\\._-])linux[\\._-]armv6(hf)?(\\.gz|\\.xz|\\.zst|\\.tar\\.gz|\\.tar\\.xz|\\.tgz|\\.txz|\\.zip)?$"
+                    .to_string(),
+            ],
+        ),
+        (
+            Platform::LinuxRiscv64,
+            vec![
+                "(^|[\\._-])riscv64(gc)?[\\._-](unknown[\\._-])?linux[\\._-]musl(\\.gz|\\.xz|\\.zst|\\.tar\\.gz|\\.tar\\.xz|\\.tgz|\\.txz|\\.zip)?$"
+                    .to_string(),
+                "(^|[\\._-])riscv64(gc)?[\\._-](unknown[\\._-])?linux([\\._-]gnu)?(\\.gz|\\.xz|\\.zst|\\.tar\\.gz|\\.tar\\.xz|\\.tgz|\\.txz|\\.zip)?$"
+                    .to_string(),
+                "(^|[\\._-])linux[\\._-]riscv64(gc)?([\\._-]unknown)?([\\._-]gnu|[\\._-]musl)?(\\.gz|\\.xz|\\.zst|\\.tar\\.gz|\\.tar\\.xz|\\.tgz|\\.txz|\\.zip)?$"
+                    .to_string(),
+            ],
+        ),
         (
             Platform::Osx64,
             vec![
@@ -119,28 +638,231 @@ fn default_platforms() -> HashMap<Platform, Vec<String>> {
     ])
 }
 
+/// Marks a platform pattern as glob instead of a regex
+const GLOB_PREFIX: &str = "glob:";
+
+/// Translate a glob matching a whole asset name into a regex
+///
+/// Supports `*`, `?` and `[...]` character classes (`[!...]` negates).
+fn glob_to_regex(glob: &str) -> String {
+    let mut result = String::from("^");
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => result.push_str(".*"),
+            '?' => result.push('.'),
+            '[' => {
+                let rest = chars.as_str();
+                match rest.find(']') {
+                    Some(end) if end > 0 => {
+                        let class = &rest[..end];
+                        result.push('[');
+                        let class = match class.strip_prefix('!') {
+                            Some(negated) => {
+                                result.push('^');
+                                negated
+                            }
+                            None => class,
+                        };
+                        result.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+                        result.push(']');
+                        chars = rest[end + 1..].chars();
+                    }
+                    _ => result.push_str("\\["),
+                }
+            }
+            c => result.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    result.push('$');
+    result
+}
+
+/// Built-in patterns of python wheel packages: Pure-python wheels only
+fn wheel_platforms() -> HashMap<Platform, Vec<String>> {
+    HashMap::from([(
+        Platform::NoArch,
+        vec!["(^|[\\._-])(py3|py2\\.py3)-none-any\\.whl$".to_string()],
+    )])
+}
+
+/// Built-in patterns of node packages: npm tarballs and bundled scripts
+fn node_platforms() -> HashMap<Platform, Vec<String>> {
+    HashMap::from([(
+        Platform::NoArch,
+        vec!["\\.tgz$".to_string(), "\\.(js|mjs|cjs)$".to_string()],
+    )])
+}
+
+/// Built-in patterns for platforms packages need to opt into
+fn optional_platforms() -> HashMap<Platform, Vec<String>> {
+    HashMap::from([
+        (
+        Platform::LinuxPpc64le,
+        vec![
+            "(^|[\\._-])(powerpc64le|ppc64le)[\\._-](unknown[\\._-])?linux[\\._-]musl(\\.gz|\\.xz|\\.zst|\\.tar\\.gz|\\.tar\\.xz|\\.tgz|\\.txz|\\.zip)?$"
+                .to_string(),
+            "(^|[\\._-])(powerpc64le|ppc64le)[\\._-](unknown[\\._-])?linux([\\._-]gnu)?(\\.gz|\\.xz|\\.zst|\\.tar\\.gz|\\.tar\\.xz|\\.tgz|\\.txz|\\.zip)?$"
+                .to_string(),
+            "(^|[\\._-])linux[\\._-](powerpc64le|ppc64le)([\\._-]unknown)?([\\._-]gnu|[\\._-]musl)?(\\.gz|\\.xz|\\.zst|\\.tar\\.gz|\\.tar\\.xz|\\.tgz|\\.txz|\\.zip)?$"
+                .to_string(),
+        ],
+        ),
+        (
+            Platform::WasiWasm32,
+            vec![
+                "(^|[\\._-])wasm32[\\._-](unknown[\\._-])?wasi(p1|p2)?(\\.wasm|\\.gz|\\.xz|\\.zst|\\.tar\\.gz|\\.tar\\.xz|\\.tgz|\\.txz|\\.zip)?$"
+                    .to_string(),
+                "(^|[\\._-])wasi(p1|p2)?[\\._-]wasm32(\\.wasm|\\.gz|\\.xz|\\.zst|\\.tar\\.gz|\\.tar\\.xz|\\.tgz|\\.txz|\\.zip)?$"
+                    .to_string(),
+                "\\.wasm$".to_string(),
+            ],
+        ),
+    ])
+}
+
+/// `url` if it is a http or https URL with a host
+pub fn http_url(url: &str) -> anyhow::Result<url::Url> {
+    let parsed = url::Url::parse(url).context(format!("{url:?} is not a URL"))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none_or(str::is_empty) {
+        return Err(anyhow::anyhow!("{url:?} is not a http or https URL"));
+    }
+    Ok(parsed)
+}
+
+/// The DOI `doi`, which may be given as `https://doi.org/` URL
+fn parse_doi(doi: &str) -> anyhow::Result<String> {
+    let doi = doi.trim();
+    let bare = ["https://doi.org/", "http://doi.org/", "doi:"]
+        .iter()
+        .find_map(|prefix| doi.strip_prefix(prefix))
+        .unwrap_or(doi);
+    match bare.split_once('/') {
+        Some((registrant, suffix))
+            if registrant.starts_with("10.") && !suffix.is_empty() && !bare.contains(' ') =>
+        {
+            Ok(bare.to_string())
+        }
+        _ => Err(anyhow::anyhow!(
+            "{doi:?} is not a DOI like \"10.1234/example\""
+        )),
+    }
+}
+
+/// `name` as conda package name: lower case, made of letters, digits, `-`,
+/// `_` and `.`
+pub fn package_name(name: &str) -> anyhow::Result<String> {
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("Package names must not be empty"));
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+    {
+        return Err(anyhow::anyhow!(
+            "Invalid character {c:?} in package name {name:?}, only letters, digits, '-', '_' and '.' are allowed"
+        ));
+    }
+    Ok(name.to_ascii_lowercase())
+}
+
 impl TryFrom<TomlPackage> for Package {
     type Error = anyhow::Error;
 
     fn try_from(value: TomlPackage) -> Result<Self, Self::Error> {
         let repository = Repository::try_from(value.repository.as_str())?;
-        let name = value
-            .name
-            .clone()
-            .unwrap_or_else(|| repository.repo.clone());
+        let name = match (&value.name, &value.name_template, value.name_from) {
+            (Some(name), None, None) => name.clone(),
+            (None, Some(template), None) => template
+                .replace("{owner}", &repository.owner)
+                .replace("{repo}", &repository.repo),
+            (None, None, name_from) => match name_from.unwrap_or_default() {
+                NameFrom::Repo => repository.repo.clone(),
+                NameFrom::OwnerRepo => format!("{}-{}", repository.owner, repository.repo),
+            },
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Only one of name, name_template and name_from may be set for {}",
+                    value.repository
+                ));
+            }
+        };
+        let name = package_name(&name).context(format!(
+            "failed to derive a package name for {}",
+            value.repository
+        ))?;
+
+        // Anchor patterns at the asset prefix or package name, unless told
+        // otherwise
+        let n = &if value.anchor.unwrap_or(true) {
+            value
+                .asset_prefix
+                .as_deref()
+                .map(regex::escape)
+                .or_else(|| value.name.clone())
+        } else {
+            None
+        };
+
+        let case_insensitive = value.case_insensitive.unwrap_or(true);
+        let compile = |k: &Platform, pattern: &str| {
+            regex::RegexBuilder::new(pattern)
+                .case_insensitive(case_insensitive)
+                .build()
+                .context(format!("failed to parse regex for platform {k}"))
+        };
 
-        let n = &value.name;
+        let scoped = |patterns: Vec<String>| {
+            patterns
+                .into_iter()
+                .map(ScopedPattern::from)
+                .collect::<Vec<_>>()
+        };
+        let parse_version = |k: &Platform, version: &Option<String>| {
+            version
+                .as_deref()
+                .map(|v| {
+                    Version::from_str(v).context(format!(
+                        "failed to parse pattern version {v:?} for platform {k}"
+                    ))
+                })
+                .transpose()
+        };
 
+        let kind = value.kind.unwrap_or_default();
         let platforms = {
-            let mut result = default_platforms();
+            let defaults = match kind {
+                PackageKind::PythonWheel => wheel_platforms(),
+                PackageKind::Node => node_platforms(),
+                _ => default_platforms(),
+            };
+            let mut result = defaults
+                .into_iter()
+                .map(|(k, v)| (k, scoped(v)))
+                .collect::<HashMap<_, _>>();
             for (k, v) in value.platforms.unwrap_or_default().drain() {
                 let strings = match v {
-                    StringOrList::String(s) => {
+                    PlatformPatterns::String(s) => {
                         if s == "null" {
                             result.remove(&k);
                             continue;
                         }
 
+                        if s == "default" {
+                            let Some(patterns) = optional_platforms().remove(&k) else {
+                                return Err(anyhow::anyhow!(
+                                    "There are no built-in patterns for platform {k}"
+                                ));
+                            };
+                            result.insert(k, scoped(patterns));
+                            continue;
+                        }
+
+                        if s.starts_with(GLOB_PREFIX) {
+                            result.insert(k, vec![s.into()]);
+                            continue;
+                        }
+
                         if let Some(n) = n.as_ref() {
                             let Some(current) = result.get(&k) else {
                                 return Err(anyhow::anyhow!(format!(
@@ -153,17 +875,37 @@ impl TryFrom<TomlPackage> for Package {
                                     .iter()
                                     .map(|c| {
                                         let mut r = n.to_string();
-                                        r.push_str(&format!(".*{c}"));
-                                        r
+                                        r.push_str(&format!(".*{}", c.pattern));
+                                        r.into()
                                     })
                                     .collect::<Vec<_>>(),
                             );
                             continue;
                         }
 
-                        vec![s]
+                        vec![s.into()]
+                    }
+                    PlatformPatterns::List(items) => items.into_iter().map(Into::into).collect(),
+                    PlatformPatterns::Changes(changes) => {
+                        let base = match changes.replace {
+                            Some(replace) => replace.into_iter().map(Into::into).collect(),
+                            None => result
+                                .remove(&k)
+                                .or_else(|| optional_platforms().remove(&k).map(scoped))
+                                .unwrap_or_default(),
+                        };
+                        let patterns = changes
+                            .prepend
+                            .into_iter()
+                            .map(Into::into)
+                            .chain(base)
+                            .chain(changes.append.into_iter().map(Into::into))
+                            .collect::<Vec<ScopedPattern>>();
+                        if patterns.is_empty() {
+                            return Err(anyhow::anyhow!("No patterns left for platform {k}"));
+                        }
+                        patterns
                     }
-                    StringOrList::List(items) => items,
                 };
                 result.insert(k, strings);
             }
@@ -173,14 +915,21 @@ impl TryFrom<TomlPackage> for Package {
                 .map(|(k, v)| {
                     let re = v
                         .iter()
-                        .map(|r| {
-                            let pattern = if let Some(n) = n {
+                        .map(|p| {
+                            let r = &p.pattern;
+                            let pattern = if let Some(glob) = r.strip_prefix(GLOB_PREFIX) {
+                                // Globs match the whole name, no need to anchor
+                                glob_to_regex(glob)
+                            } else if let Some(n) = n {
                                 format!("^{n}.*{r}")
                             } else {
                                 r.to_string()
                             };
-                            regex::Regex::new(&pattern)
-                                .context(format!("failed to parse regex for platform {k}"))
+                            Ok(PlatformPattern {
+                                regex: compile(&k, &pattern)?,
+                                since: parse_version(&k, &p.since)?,
+                                until: parse_version(&k, &p.until)?,
+                            })
                         })
                         .collect::<anyhow::Result<Vec<_>>>()?;
                     Ok((k, re))
@@ -188,20 +937,370 @@ impl TryFrom<TomlPackage> for Package {
                 .collect::<anyhow::Result<HashMap<_, _>>>()?
         };
 
+        let exclude_assets = value
+            .exclude_assets
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(k, v)| {
+                let re = v
+                    .iter()
+                    .map(|r| match r.strip_prefix(GLOB_PREFIX) {
+                        Some(glob) => compile(&k, &glob_to_regex(glob)),
+                        None => compile(&k, r),
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                Ok((k, re))
+            })
+            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+
+        let platforms_since = value
+            .platforms_since
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(k, v)| {
+                let since = Version::from_str(&v).context(format!(
+                    "failed to parse platforms_since version {v:?} for platform {k}"
+                ))?;
+                Ok((k, since))
+            })
+            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+
+        let version_map = value
+            .version_map
+            .unwrap_or_default()
+            .into_iter()
+            .map(|r| {
+                Ok(VersionRewrite {
+                    regex: regex::Regex::new(&r.pattern).context(format!(
+                        "failed to parse version_map pattern {:?}",
+                        r.pattern
+                    ))?,
+                    replace: r.replace,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let required_platforms = value
+            .require_platforms
+            .unwrap_or_default()
+            .into_iter()
+            .collect::<HashSet<_>>();
+        if let Some(p) = required_platforms
+            .iter()
+            .find(|p| !platforms.contains_key(*p))
+        {
+            return Err(anyhow::anyhow!(
+                "Required platform {p} is not configured for {name}"
+            ));
+        }
+
+        if let Some(build_string) = &value.build_string {
+            check_build_string(build_string)?;
+        }
+        let notify = value.notify.unwrap_or_default();
+        if let Some(handle) = notify.iter().find(|h| {
+            h.len() < 2
+                || !h.starts_with('@')
+                || h[1..]
+                    .chars()
+                    .any(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '/')))
+        }) {
+            return Err(anyhow::anyhow!(
+                "Invalid notify handle {handle:?} of {name}, expected something like \"@alice\""
+            ));
+        }
+        let variants = value.variants.unwrap_or_default();
+        if let Some(v) = variants.iter().find(|v| {
+            v.is_empty()
+                || !v
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+        }) {
+            return Err(anyhow::anyhow!("Invalid variant {v:?} of {name}"));
+        }
+        if !variants.is_empty() && value.build_string.is_some() {
+            return Err(anyhow::anyhow!(
+                "{name} can not have both variants and a build_string, variants need the build string rattler-build generates"
+            ));
+        }
+        if value.keep_versions == Some(0) {
+            return Err(anyhow::anyhow!(
+                "keep_versions of {name} must be at least 1"
+            ));
+        }
+        if value.max_releases == Some(0) {
+            return Err(anyhow::anyhow!("max_releases of {name} must be at least 1"));
+        }
+        let exclude_files = value.exclude_files.unwrap_or_else(|| {
+            DEFAULT_EXCLUDE_FILES
+                .iter()
+                .map(|f| f.to_string())
+                .collect()
+        });
+        if let Some(f) = exclude_files
+            .iter()
+            .find(|f| f.is_empty() || f.contains(['/', '\n', '"']))
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid exclude_files entry {f:?} of {name}, expected a file name glob like \"*.pdb\""
+            ));
+        }
+        let binaries = value.binaries.unwrap_or_default();
+        if let Some(b) = binaries
+            .iter()
+            .find(|b| b.is_empty() || b.contains(['/', '\\', '\n']))
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid binaries entry {b:?} of {name}, expected the name of an executable"
+            ));
+        }
+        let activation_env = value.activation.and_then(|a| a.env).unwrap_or_default();
+        crate::activation::check_env(&activation_env)
+            .context(format!("Invalid activation.env of {name}"))?;
+        let match_specs = |key: &str, specs: Option<Vec<String>>| {
+            let specs = specs.unwrap_or_default();
+            for spec in &specs {
+                MatchSpec::from_str(spec, ParseStrictness::Strict)
+                    .context(format!("Invalid {key} entry {spec:?} of {name}"))?;
+            }
+            anyhow::Ok(specs)
+        };
+        let run_exports = match_specs("run_exports", value.run_exports)?;
+        let run_constraints = match_specs("run_constraints", value.run_constraints)?;
+        let install_to = match (kind, value.install_to) {
+            (PackageKind::Data, Some(dir)) => {
+                let dir = dir.trim_matches('/').to_string();
+                if dir.is_empty()
+                    || dir.contains(['\\', '\n', '"'])
+                    || dir
+                        .split('/')
+                        .any(|c| c.is_empty() || c == "." || c == "..")
+                {
+                    return Err(anyhow::anyhow!(
+                        "Invalid install_to {dir:?} of {name}, expected a directory in the environment like \"share/fonts/{name}\""
+                    ));
+                }
+                Some(dir)
+            }
+            (PackageKind::Data, None) => {
+                return Err(anyhow::anyhow!(
+                    "{name} is a data package, but has no install_to"
+                ));
+            }
+            (_, Some(_)) => {
+                return Err(anyhow::anyhow!(
+                    "install_to of {name} is only used for data packages"
+                ));
+            }
+            (_, None) => None,
+        };
+        let documentation = value
+            .documentation
+            .map(|url| {
+                http_url(&url).context(format!("Invalid documentation URL of {name}"))?;
+                anyhow::Ok(url)
+            })
+            .transpose()?;
+        let homepage = value
+            .homepage
+            .map(|url| {
+                http_url(&url).context(format!("Invalid homepage URL of {name}"))?;
+                anyhow::Ok(url)
+            })
+            .transpose()?;
+        let doi = value
+            .doi
+            .as_deref()
+            .map(|d| parse_doi(d).context(format!("Invalid doi of {name}")))
+            .transpose()?;
+        let sources = value
+            .sources
+            .unwrap_or_default()
+            .into_iter()
+            .map(|source| match (source.repository, source.recorded) {
+                (Some(repository), None) if source.assets.is_none() => {
+                    Ok(Source::Github(Repository::try_from(repository.as_str())?))
+                }
+                (Some(_), None) => Err(anyhow::anyhow!(
+                    "Repository sources of {name} can not have assets, they come from the repository"
+                )),
+                (None, Some(url)) => Ok(Source::Recorded {
+                    releases: http_url(&url)
+                        .context(format!("Invalid recorded source URL of {name}"))?,
+                    assets: source
+                        .assets
+                        .as_deref()
+                        .map(AssetUrls::new)
+                        .transpose()
+                        .context(format!("Invalid asset URL of {name}"))?,
+                }),
+                _ => Err(anyhow::anyhow!(
+                    "Sources of {name} need either a repository or a recorded URL"
+                )),
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let pin = value
+            .pin
+            .as_deref()
+            .map(|v| Version::from_str(v).context(format!("failed to parse pin {v:?} of {name}")))
+            .transpose()?;
+        let max_asset_size = value
+            .max_asset_size
+            .as_ref()
+            .map(u64::try_from)
+            .transpose()
+            .context(format!("failed to parse max_asset_size of {name}"))?;
+
         Ok(Package {
             name,
             repository,
             platforms,
+            exclude_assets,
+            asset_preference: AssetPreference {
+                prefer: value.prefer_assets.unwrap_or_default(),
+                size: value.prefer_size.unwrap_or_default(),
+            },
+            groups: value.groups.unwrap_or_default(),
+            required_platforms,
+            recipe_template: value.recipe_template,
+            summary: value.summary,
+            description: value.description,
+            documentation,
+            homepage,
+            doi,
+            citation: value
+                .citation
+                .map(|c| c.trim().to_string())
+                .filter(|c| !c.is_empty()),
+            maintainers: value.maintainers.unwrap_or_default(),
+            notify,
+            disabled: value.disabled.unwrap_or(false),
+            quarantine: value.quarantine.unwrap_or(false),
+            build_string: value.build_string,
+            version_scheme: value.version_scheme.unwrap_or_default(),
+            version_map,
+            prereleases: value.prereleases.unwrap_or(false),
+            keep_versions: value.keep_versions,
+            max_releases: value.max_releases,
+            pin,
+            max_asset_size,
+            text_limits: value.text_limits.unwrap_or_default(),
+            policy: value.policy.unwrap_or_default(),
+            exclude_files,
+            binaries,
+            keep_updaters: value.keep_updaters.unwrap_or(false),
+            activation_env,
+            post_link: value.post_link.map(LinkScript::from).unwrap_or_default(),
+            pre_unlink: value.pre_unlink.map(LinkScript::from).unwrap_or_default(),
+            source_code_assets: value.source_code_assets.unwrap_or(false),
+            unsupported_oses: value.unsupported_oses,
+            platforms_since,
+            variants,
+            binary_relocation: value.binary_relocation,
+            prefix_detection: value.prefix_detection,
+            run_exports,
+            run_constraints,
+            kind,
+            install_to,
+            sources,
         })
     }
 }
 
+/// Only look at releases published after this
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Since {
+    Date(chrono::DateTime<chrono::Utc>),
+    /// The start of the last successful run recorded in the state file
+    LastRun,
+}
+
+impl FromStr for Since {
+    type Err = anyhow::Error;
+
+    /// Parses `last-run`, RFC 3339 timestamps and `YYYY-MM-DD` dates
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "last-run" {
+            return Ok(Self::LastRun);
+        }
+        if let Ok(date) = chrono::DateTime::parse_from_rfc3339(s) {
+            return Ok(Self::Date(date.to_utc()));
+        }
+        let date = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").context(format!(
+            "{s:?} is neither \"last-run\", a date nor a RFC 3339 timestamp"
+        ))?;
+        Ok(Self::Date(date.and_time(chrono::NaiveTime::MIN).and_utc()))
+    }
+}
+
+/// What to do with packages that are also available on conda-forge
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CondaForgePolicy {
+    /// Do not check conda-forge at all
+    #[default]
+    Ignore,
+    /// Package as usual, but mention the conda-forge package in the report
+    Report,
+    /// Do not package anything available on conda-forge
+    Skip,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Conda {
     pub channel: String,
+    #[serde(default)]
+    pub conda_forge_check: CondaForgePolicy,
+    /// Where to cache repodata, rattler's cache directory by default
+    pub cache_dir: Option<PathBuf>,
+    /// Use cached repodata without asking the channel for this long
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub cache_ttl: Option<std::time::Duration>,
+    /// Channel the packages of quarantined packages get uploaded to, they
+    /// are not uploaded at all if unset
+    pub quarantine_channel: Option<String>,
+}
+
+/// `<number><unit>` with a unit of `s`, `m`, `h` or `d`
+pub fn parse_duration(value: &str) -> Result<std::time::Duration, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("{value:?} needs a unit (s, m, h or d)"))?;
+    let (number, unit) = value.split_at(split);
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("{value:?} does not start with a number"))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("{unit:?} is not a unit (s, m, h or d)")),
+    };
+    if number == 0 {
+        return Err("The duration must not be zero".to_string());
+    }
+    Ok(std::time::Duration::from_secs(number * seconds))
+}
+
+fn deserialize_duration<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<std::time::Duration>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| parse_duration(&value).map_err(serde::de::Error::custom))
+        .transpose()
 }
 
 impl Conda {
+    /// The settings for the quarantine channel, if there is one
+    pub fn quarantine(&self) -> Option<Conda> {
+        self.quarantine_channel.as_ref().map(|channel| Conda {
+            channel: channel.clone(),
+            quarantine_channel: None,
+            ..self.clone()
+        })
+    }
+
     pub fn short_channel(&self) -> anyhow::Result<String> {
         if let Ok(channel_url) = url::Url::parse(&self.channel) {
             if channel_url.host_str() != Some("prefix.dev") {
@@ -221,23 +1320,171 @@ impl Conda {
     }
 }
 
+/// Package all repositories with releases in a Github organization
+#[derive(Clone, Debug, Deserialize)]
+pub struct Discover {
+    pub org: String,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+/// Where to export metrics of a run to
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Telemetry {
+    /// Prometheus text file for the node_exporter textfile collector
+    pub textfile: Option<PathBuf>,
+}
+
+/// How much text taken from GitHub ends up in recipes, in characters
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TextLimits {
+    /// Maximum length of the package summary
+    pub summary: usize,
+    /// Maximum length of the release notes in the package description
+    pub release_notes: usize,
+}
+
+impl Default for TextLimits {
+    fn default() -> Self {
+        Self {
+            summary: 200,
+            release_notes: 2000,
+        }
+    }
+}
+
+/// Constraints on where the packaged release assets come from
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// GitHub logins allowed to upload release assets, `{owner}` is the
+    /// owner of the repository, anyone may upload if empty
+    pub uploaders: Vec<String>,
+    /// How long a release needs to be published before it gets packaged
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub min_release_age: Option<std::time::Duration>,
+}
+
+/// Where to find the token for a host
+///
+/// Exactly one of the sources needs to be set.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Credential {
+    /// Environment variable holding the token
+    pub env: Option<String>,
+    /// File holding the token
+    pub file: Option<PathBuf>,
+    /// Read the token from the OS keyring
+    #[serde(default)]
+    pub keyring: bool,
+}
+
+impl Credential {
+    fn validate(&self, host: &str) -> anyhow::Result<()> {
+        let sources = [self.env.is_some(), self.file.is_some(), self.keyring];
+        match sources.iter().filter(|s| **s).count() {
+            1 => Ok(()),
+            0 => Err(anyhow::anyhow!(
+                "Credentials for {host} need one of env, file or keyring"
+            )),
+            _ => Err(anyhow::anyhow!(
+                "Credentials for {host} can only use one of env, file or keyring"
+            )),
+        }
+    }
+}
+
 #[derive(serde::Deserialize)]
 pub struct TomlConfig {
+    #[serde(default)]
     pub packages: Vec<TomlPackage>,
     pub conda: Conda,
+    #[serde(default)]
+    pub discover: Vec<Discover>,
+    #[serde(default)]
+    pub maintainers: Vec<String>,
+    pub max_asset_size: Option<TomlSize>,
+    pub text_limits: Option<TextLimits>,
+    pub policy: Option<Policy>,
+    pub since: Option<String>,
+    #[serde(default)]
+    pub telemetry: Telemetry,
+    pub github_host: Option<String>,
+    #[serde(default)]
+    pub credentials: BTreeMap<String, Credential>,
+}
+
+/// Make sure no two `packages` end up with the same conda package name
+fn check_name_collisions(packages: &[Package]) -> anyhow::Result<()> {
+    let mut seen = HashMap::new();
+    for package in packages {
+        let repository = &package.repository;
+        if let Some(other) = seen.insert(package.name.as_str(), repository) {
+            return Err(anyhow::anyhow!(
+                "{}/{} and {}/{} are both packaged as {}, set a different `name` for one of them",
+                other.owner,
+                other.repo,
+                repository.owner,
+                repository.repo,
+                package.name
+            ));
+        }
+    }
+    Ok(())
 }
 
 impl TryFrom<TomlConfig> for Config {
     type Error = anyhow::Error;
 
     fn try_from(mut value: TomlConfig) -> Result<Self, Self::Error> {
+        for (host, credential) in &value.credentials {
+            credential.validate(host)?;
+        }
+
+        let packages = value
+            .packages
+            .drain(..)
+            .map(|mut tp| {
+                tp.maintainers
+                    .get_or_insert_with(|| value.maintainers.clone());
+                if tp.max_asset_size.is_none() {
+                    tp.max_asset_size = value.max_asset_size.clone();
+                }
+                if tp.text_limits.is_none() {
+                    tp.text_limits = value.text_limits;
+                }
+                if tp.policy.is_none() {
+                    tp.policy = value.policy.clone();
+                }
+                tp.try_into()
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        check_name_collisions(&packages)?;
+
         Ok(Config {
-            packages: value
-                .packages
-                .drain(..)
-                .map(|tp| tp.try_into())
-                .collect::<anyhow::Result<Vec<_>>>()?,
+            packages,
             conda: value.conda,
+            discover: value.discover,
+            maintainers: value.maintainers,
+            max_asset_size: value
+                .max_asset_size
+                .as_ref()
+                .map(u64::try_from)
+                .transpose()
+                .context("failed to parse max_asset_size")?,
+            text_limits: value.text_limits.unwrap_or_default(),
+            policy: value.policy.unwrap_or_default(),
+            since: value.since.as_deref().map(Since::from_str).transpose()?,
+            telemetry: value.telemetry,
+            github_host: value
+                .github_host
+                .unwrap_or_else(|| crate::github::GITHUB_HOST.to_string()),
+            credentials: value.credentials,
         })
     }
 }
@@ -246,24 +1493,135 @@ impl TryFrom<TomlConfig> for Config {
 pub struct Config {
     pub packages: Vec<Package>,
     pub conda: Conda,
+    pub discover: Vec<Discover>,
+    /// Maintainers of packages that do not list their own
+    pub maintainers: Vec<String>,
+    /// Default `max_asset_size` of packages that do not set their own
+    pub max_asset_size: Option<u64>,
+    /// Default `text_limits` of packages that do not set their own
+    pub text_limits: TextLimits,
+    /// Default `policy` of packages that do not set their own
+    pub policy: Policy,
+    /// Default for `--since`
+    pub since: Option<Since>,
+    pub telemetry: Telemetry,
+    /// The GitHub (Enterprise) host the repositories are on
+    pub github_host: String,
+    /// Where to find the tokens of hosts, keyed by host name
+    pub credentials: BTreeMap<String, Credential>,
 }
 
 impl Config {
-    pub fn all_platforms(&self) -> HashSet<Platform> {
+    /// Drop all packages that are not in any of the `groups`.
+    ///
+    /// An empty `groups` list keeps all packages.
+    pub fn retain_groups(&mut self, groups: &[String]) {
+        if groups.is_empty() {
+            return;
+        }
         self.packages
-            .iter()
-            .flat_map(|p| p.platforms.keys())
-            .copied()
-            .collect()
+            .retain(|p| p.groups.iter().any(|g| groups.contains(g)));
     }
-}
 
-pub fn parse_config(path: &Path) -> anyhow::Result<Config> {
-    let contents = std::fs::read_to_string(path).context(format!(
+    /// Add a package with default settings for each of the `repositories`
+    /// found in the organization of `discover`.
+    ///
+    /// Excluded repositories and repositories that are already configured
+    /// explicitly are ignored.
+    pub fn add_discovered_packages(
+        &mut self,
+        discover: &Discover,
+        repositories: impl Iterator<Item = String>,
+    ) -> anyhow::Result<()> {
+        for repo in repositories {
+            if discover
+                .exclude
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(&repo))
+                || self.packages.iter().any(|p| {
+                    p.repository.owner.eq_ignore_ascii_case(&discover.org)
+                        && p.repository.repo.eq_ignore_ascii_case(&repo)
+                })
+            {
+                continue;
+            }
+
+            let mut package = Package::with_defaults(&format!("{}/{repo}", discover.org))?;
+            if let Some(other) = self.packages.iter().find(|p| p.name == package.name) {
+                tracing::warn!(
+                    "Not adding discovered {}/{repo}: {}/{} is packaged as {} already",
+                    discover.org,
+                    other.repository.owner,
+                    other.repository.repo,
+                    package.name
+                );
+                continue;
+            }
+            package.groups = discover.groups.clone();
+            package.maintainers = self.maintainers.clone();
+            package.max_asset_size = self.max_asset_size;
+            package.text_limits = self.text_limits;
+            package.policy = self.policy.clone();
+            self.packages.push(package);
+        }
+        Ok(())
+    }
+
+    pub fn all_platforms(&self) -> HashSet<Platform> {
+        self.packages
+            .iter()
+            .flat_map(|p| p.platforms.keys())
+            .copied()
+            .collect()
+    }
+}
+
+fn read_toml_table(path: &Path) -> anyhow::Result<toml::Table> {
+    let contents = std::fs::read_to_string(path).context(format!(
         "Failed to read configuration file {}",
         path.display()
     ))?;
-    let config: TomlConfig = toml::from_str(&contents).context(format!(
+    toml::from_str(&contents).context(format!(
+        "Failed to parse configuration file {}",
+        path.display()
+    ))
+}
+
+/// Merge `overlay` into `base`: Tables are merged recursively, all other
+/// values (including arrays) in `overlay` replace those in `base`.
+fn merge_toml_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_toml_tables(base_table, overlay_table)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// The path of the overlay for `environment`: `config.toml` turns into
+/// `config.<environment>.toml`.
+fn environment_config_path(path: &Path, environment: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{stem}.{environment}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.{environment}"),
+    };
+    path.with_file_name(file_name)
+}
+
+fn read_config(path: &Path, environment: Option<&str>) -> anyhow::Result<Config> {
+    let mut table = read_toml_table(path)?;
+
+    if let Some(environment) = environment {
+        let overlay_path = environment_config_path(path, environment);
+        merge_toml_tables(&mut table, read_toml_table(&overlay_path)?);
+    }
+
+    let config: TomlConfig = toml::Value::Table(table).try_into().context(format!(
         "Failed to parse configuration file {}",
         path.display()
     ))?;
@@ -271,17 +1629,1614 @@ pub fn parse_config(path: &Path) -> anyhow::Result<Config> {
     config.try_into()
 }
 
+pub fn parse_config(path: &Path, environment: Option<&str>) -> crate::Result<Config> {
+    read_config(path, environment).map_err(crate::Error::Config)
+}
+
+const STARTER_CONFIG: &str = r#"# Configuration of octoconda, see the README for all settings.
+
+# Default maintainers of all packages, written into the recipes.
+# maintainers = ["your-github-handle"]
+
+[conda]
+# The prefix.dev channel the packages get uploaded to: A channel name or a
+# https://prefix.dev/<name> URL. Versions already in the channel are skipped.
+channel = "{channel}"
+# Check whether packages are on conda-forge already: "ignore", "report" or
+# "skip".
+# conda_forge_check = "report"
+
+# One [[packages]] table per GitHub repository with binary releases.
+[[packages]]
+repository = "BurntSushi/ripgrep"
+# The package name defaults to the repository name.
+# name = "ripgrep"
+#
+# Built-in patterns pick the release asset of each platform. Override them
+# per platform with:
+#   "null"                   to not package the platform at all
+#   ["regex", ...]           to replace the built-in patterns
+#   { prepend = ["regex"] }  to try patterns before the built-in ones
+#   { append = ["regex"] }   to try patterns after the built-in ones
+#   "glob:*-linux.tar.gz"    to use a glob instead of a regex
+# platforms = { win-64 = "null", linux-64 = ["ripgrep-.*-x86_64-unknown-linux-musl"] }
+"#;
+
+/// A commented configuration file to start from, publishing to `channel`
+pub fn starter_config(channel: &str) -> anyhow::Result<String> {
+    let short_channel = Conda {
+        channel: channel.to_string(),
+        conda_forge_check: CondaForgePolicy::default(),
+        cache_dir: None,
+        cache_ttl: None,
+        quarantine_channel: None,
+    }
+    .short_channel()?;
+    let name = short_channel.trim_matches('/');
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+    {
+        return Err(anyhow::anyhow!(
+            "{channel:?} is neither a channel name nor a https://prefix.dev/<name> URL"
+        ));
+    }
+    Ok(STARTER_CONFIG.replace("{channel}", name))
+}
+
+/// The name of the package the `[[packages]]` table in `block` configures
+fn block_package_name(block: &str) -> anyhow::Result<String> {
+    let mut table: toml::Table = toml::from_str(block)?;
+    let package = match table.remove("packages") {
+        Some(toml::Value::Array(mut packages)) if packages.len() == 1 => packages.remove(0),
+        _ => return Err(anyhow::anyhow!("Not a [[packages]] table")),
+    };
+    let package: TomlPackage = package.try_into()?;
+    Ok(Package::try_from(package)?.name)
+}
+
+/// The lines of `config`, each with whether it starts inside a value
+///
+/// Lines of multi-line strings and arrays may look like table headers or
+/// comments, the TOML parser knows better.
+fn toml_lines(config: &str) -> anyhow::Result<Vec<(&str, bool)>> {
+    fn collect(
+        value: &toml::Spanned<toml::de::DeValue<'_>>,
+        spans: &mut Vec<std::ops::Range<usize>>,
+    ) {
+        spans.push(value.span());
+        match value.get_ref() {
+            toml::de::DeValue::Table(table) => {
+                table.values().for_each(|v| collect(v, spans));
+            }
+            toml::de::DeValue::Array(array) => array.iter().for_each(|v| collect(v, spans)),
+            _ => {}
+        }
+    }
+
+    let table = toml::de::DeTable::parse(config)?;
+    let mut spans = Vec::new();
+    table
+        .get_ref()
+        .values()
+        .for_each(|v| collect(v, &mut spans));
+
+    let mut start = 0;
+    Ok(config
+        .split_inclusive('\n')
+        .map(|line| {
+            let offset = start + (line.len() - line.trim_start().len());
+            start += line.len();
+            let inside = spans.iter().any(|s| s.start < offset && offset < s.end);
+            (line, inside)
+        })
+        .collect())
+}
+
+/// `config` without the `[[packages]]` table of the package called `name`
+///
+/// Works on the text, so that comments and formatting of everything else
+/// are kept. Comment lines right above the table are removed with it.
+pub fn remove_package(config: &str, name: &str) -> anyhow::Result<String> {
+    edit_package(config, name, |_| Ok(None))
+}
+
+/// `config` with the `quarantine` setting of the package called `name`
+/// removed
+pub fn approve_package(config: &str, name: &str) -> anyhow::Result<String> {
+    edit_package(config, name, |block| {
+        let mut in_sub_table = false;
+        let mut found = false;
+        let mut result = String::new();
+        for (index, (line, inside)) in toml_lines(block)?.into_iter().enumerate() {
+            in_sub_table |= index > 0 && !inside && line.trim_start().starts_with('[');
+            let key = line
+                .split_once('=')
+                .filter(|_| !inside)
+                .map(|(key, _)| key.trim());
+            if !in_sub_table && key == Some("quarantine") {
+                found = true;
+            } else {
+                result.push_str(line);
+            }
+        }
+        if !found {
+            return Err(anyhow::anyhow!("{name} is not quarantined"));
+        }
+        Ok(Some(result))
+    })
+}
+
+/// `config` with the `[[packages]]` table of the package called `name`
+/// replaced by what `edit` makes of it, or removed if that is `None`
+fn edit_package(
+    config: &str,
+    name: &str,
+    edit: impl Fn(&str) -> anyhow::Result<Option<String>>,
+) -> anyhow::Result<String> {
+    let name = package_name(name)?;
+    let lines = toml_lines(config)?;
+    let is_comment = |(line, inside): (&str, bool)| !inside && line.trim_start().starts_with('#');
+    let is_header = |(line, inside): (&str, bool)| !inside && line.trim_start().starts_with('[');
+    let is_package_header = |(line, inside): (&str, bool)| !inside && line.trim() == "[[packages]]";
+    let is_package_sub_table = |(line, _): (&str, bool)| {
+        let line = line.trim_start().trim_start_matches('[');
+        line.starts_with("packages.")
+    };
+
+    let mut result = String::new();
+    let mut comments = String::new();
+    let mut found = false;
+    let mut index = 0;
+    while index < lines.len() {
+        let (line, _) = lines[index];
+        if is_comment(lines[index]) {
+            comments.push_str(line);
+            index += 1;
+            continue;
+        }
+        if !is_package_header(lines[index]) {
+            result.push_str(&std::mem::take(&mut comments));
+            result.push_str(line);
+            index += 1;
+            continue;
+        }
+
+        let mut end = (index + 1..lines.len())
+            .find(|&i| {
+                is_header(lines[i])
+                    && (is_package_header(lines[i]) || !is_package_sub_table(lines[i]))
+            })
+            .unwrap_or(lines.len());
+        // Comments above the next table belong to that
+        while end > index + 1 && is_comment(lines[end - 1]) {
+            end -= 1;
+        }
+        let block = lines[index..end]
+            .iter()
+            .map(|(line, _)| *line)
+            .collect::<String>();
+        let block_name = block_package_name(&block).context(format!(
+            "Failed to parse the [[packages]] table at line {}",
+            index + 1
+        ))?;
+        if block_name == name {
+            found = true;
+            match edit(&block)? {
+                Some(block) => {
+                    result.push_str(&std::mem::take(&mut comments));
+                    result.push_str(&block);
+                }
+                None => comments.clear(),
+            }
+        } else {
+            result.push_str(&std::mem::take(&mut comments));
+            result.push_str(&block);
+        }
+        index = end;
+    }
+    result.push_str(&comments);
+
+    if !found {
+        return Err(anyhow::anyhow!("There is no package called {name}"));
+    }
+    Ok(result)
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
 
+    fn parse_package(toml: &str) -> anyhow::Result<Package> {
+        let toml: TomlPackage = toml::from_str(toml)?;
+        toml.try_into()
+    }
+
     pub fn get_default_patterns() -> HashMap<Platform, Vec<regex::Regex>> {
-        let toml = TomlPackage {
-            name: None,
-            repository: "foo/bar".to_string(),
-            platforms: None,
+        Package::with_defaults("foo/bar")
+            .unwrap()
+            .platforms
+            .into_iter()
+            .map(|(k, v)| (k, v.into_iter().map(|p| p.regex).collect()))
+            .collect()
+    }
+
+    pub fn get_optional_patterns(platform: Platform) -> Vec<regex::Regex> {
+        optional_platforms()
+            .remove(&platform)
+            .unwrap()
+            .iter()
+            .map(|p| {
+                regex::RegexBuilder::new(p)
+                    .case_insensitive(true)
+                    .build()
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    fn config_from_str(toml: &str) -> Config {
+        let toml: TomlConfig = toml::from_str(toml).unwrap();
+        toml.try_into().unwrap()
+    }
+
+    #[test]
+    fn test_retain_groups() {
+        let mut config = config_from_str(
+            r#"
+[conda]
+channel = "test"
+
+[[packages]]
+repository = "foo/editor"
+groups = ["editors"]
+
+[[packages]]
+repository = "foo/lsp"
+groups = ["editors", "language-servers"]
+
+[[packages]]
+repository = "foo/other"
+"#,
+        );
+
+        config.retain_groups(&[]);
+        assert_eq!(config.packages.len(), 3);
+
+        config.retain_groups(&["language-servers".to_string()]);
+        assert_eq!(
+            config
+                .packages
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["lsp"]
+        );
+    }
+
+    #[test]
+    fn test_environment_config_path() {
+        assert_eq!(
+            environment_config_path(Path::new("./config.toml"), "staging"),
+            PathBuf::from("./config.staging.toml")
+        );
+        assert_eq!(
+            environment_config_path(Path::new("conf/octoconda"), "prod"),
+            PathBuf::from("conf/octoconda.prod")
+        );
+    }
+
+    #[test]
+    fn test_merge_toml_tables() {
+        let mut base: toml::Table = toml::from_str(
+            r#"
+[conda]
+channel = "github-releases"
+other = "kept"
+
+[[packages]]
+repository = "foo/bar"
+"#,
+        )
+        .unwrap();
+        let overlay: toml::Table = toml::from_str(
+            r#"
+[conda]
+channel = "github-releases-staging"
+"#,
+        )
+        .unwrap();
+
+        merge_toml_tables(&mut base, overlay);
+
+        let config: TomlConfig = toml::Value::Table(base).try_into().unwrap();
+        assert_eq!(config.conda.channel, "github-releases-staging");
+        assert_eq!(config.packages.len(), 1);
+    }
+
+    #[test]
+    fn test_cache_ttl() {
+        let config = config_from_str(
+            r#"
+[conda]
+channel = "test"
+cache_ttl = "2h"
+"#,
+        );
+        assert_eq!(
+            config.conda.cache_ttl,
+            Some(std::time::Duration::from_secs(2 * 60 * 60))
+        );
+        assert_eq!(config.conda.cache_dir, None);
+
+        assert!(parse_duration("90s").is_ok());
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("1w").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(
+            toml::from_str::<TomlConfig>("[conda]\nchannel = \"test\"\ncache_ttl = \"soon\"\n")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_add_discovered_packages() {
+        let mut config = config_from_str(
+            r#"
+[conda]
+channel = "test"
+
+[[packages]]
+repository = "my-org/configured"
+name = "renamed"
+
+[[discover]]
+org = "my-org"
+exclude = ["excluded", "mytool"]
+groups = ["org"]
+"#,
+        );
+
+        let discover = config.discover[0].clone();
+        config
+            .add_discovered_packages(
+                &discover,
+                ["configured", "excluded", "tool", "Renamed", "MyTool"]
+                    .into_iter()
+                    .map(|r| r.to_string()),
+            )
+            .unwrap();
+
+        assert_eq!(
+            config
+                .packages
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["renamed", "tool"]
+        );
+        assert_eq!(config.packages[1].groups, vec!["org".to_string()]);
+    }
+
+    #[test]
+    fn test_name_collisions() {
+        let toml = |second: &str| {
+            format!(
+                "[conda]\nchannel = \"test\"\n\n[[packages]]\nrepository = \"Foo/tool\"\n\n[[packages]]\n{second}\n"
+            )
         };
-        let package: super::Package = toml.try_into().unwrap();
-        package.platforms
+        let parse = |toml: &str| Config::try_from(toml::from_str::<TomlConfig>(toml).unwrap());
+
+        let error = parse(&toml("repository = \"Bar/Tool\"")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Foo/tool and Bar/Tool are both packaged as tool, set a different `name` for one of them"
+        );
+        assert!(parse(&toml("repository = \"Baz/other\"\nname = \"TOOL\"")).is_err());
+        assert!(parse(&toml("repository = \"Bar/tool\"\nname = \"bar-tool\"")).is_ok());
+    }
+
+    #[test]
+    fn test_maintainers() {
+        let mut config = config_from_str(
+            r#"
+maintainers = ["hunger"]
+
+[conda]
+channel = "test"
+
+[[packages]]
+repository = "foo/default"
+
+[[packages]]
+repository = "foo/own"
+maintainers = ["someone", "else"]
+
+[[packages]]
+repository = "foo/none"
+maintainers = []
+"#,
+        );
+        config
+            .add_discovered_packages(
+                &Discover {
+                    org: "foo".to_string(),
+                    exclude: vec![],
+                    groups: vec![],
+                },
+                std::iter::once("discovered".to_string()),
+            )
+            .unwrap();
+
+        assert_eq!(
+            config
+                .packages
+                .iter()
+                .map(|p| p.maintainers.join(","))
+                .collect::<Vec<_>>(),
+            vec!["hunger", "someone,else", "", "hunger"]
+        );
+    }
+
+    #[test]
+    fn test_starter_config() {
+        for channel in ["my-tools", "https://prefix.dev/my-tools"] {
+            let config = config_from_str(&starter_config(channel).unwrap());
+            assert_eq!(config.conda.channel, "my-tools");
+            assert_eq!(
+                config.conda.full_channel().unwrap(),
+                "https://prefix.dev/my-tools"
+            );
+            assert_eq!(config.packages.len(), 1);
+            assert_eq!(config.packages[0].name, "ripgrep");
+        }
+
+        assert!(starter_config("https://example.com/my-tools").is_err());
+        assert!(starter_config("https://prefix.dev/").is_err());
+        assert!(starter_config("my tools").is_err());
+        assert!(starter_config("").is_err());
+    }
+
+    #[test]
+    fn test_remove_package() {
+        let config = r#"maintainers = ["hunger"]
+
+[conda]
+channel = "test"
+
+[[packages]]
+repository = "foo/one"
+
+# Renamed, as "two" is taken
+[[packages]]
+repository = "foo/two"
+name = "other-two"
+
+[packages.platforms.linux-64]
+append = ["two-linux"]
+
+[[packages]]
+repository = "foo/three"
+
+[[discover]]
+org = "bar"
+"#;
+        let removed = remove_package(config, "other-two").unwrap();
+        assert_eq!(
+            removed,
+            r#"maintainers = ["hunger"]
+
+[conda]
+channel = "test"
+
+[[packages]]
+repository = "foo/one"
+
+[[packages]]
+repository = "foo/three"
+
+[[discover]]
+org = "bar"
+"#
+        );
+        assert_eq!(
+            config_from_str(&remove_package(&removed, "three").unwrap())
+                .packages
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["one"]
+        );
+        assert!(remove_package(config, "two").is_err());
+
+        // Names are matched the way they are derived from the configuration
+        assert_eq!(remove_package(config, "Other-Two").unwrap(), removed);
+        assert!(remove_package(config, "other two").is_err());
+    }
+
+    #[test]
+    fn test_edit_package_with_scripts() {
+        let config = r#"[conda]
+channel = "test"
+quarantine_channel = "test-quarantine"
+
+[[packages]]
+repository = "foo/one"
+post_link.sh = """
+[ -d "$PREFIX/etc" ] && echo "etc exists" >> "$PREFIX/.messages.txt"
+# not a comment
+[[packages]]
+quarantine = true
+"""
+quarantine = true
+
+[[packages]]
+repository = "foo/two"
+"#;
+        assert_eq!(config_from_str(config).packages.len(), 2);
+
+        let removed = remove_package(config, "two").unwrap();
+        assert!(removed.ends_with("\"\"\"\nquarantine = true\n\n"));
+        assert_eq!(config_from_str(&removed).packages.len(), 1);
+
+        let approved = approve_package(config, "one").unwrap();
+        assert!(approved.contains("[[packages]]\nquarantine = true\n\"\"\"\n\n"));
+        assert!(!config_from_str(&approved).packages[0].quarantine);
+
+        assert_eq!(
+            remove_package(config, "one").unwrap(),
+            r#"[conda]
+channel = "test"
+quarantine_channel = "test-quarantine"
+
+[[packages]]
+repository = "foo/two"
+"#
+        );
+    }
+
+    #[test]
+    fn test_approve_package() {
+        let config = r#"[conda]
+channel = "test"
+quarantine_channel = "test-quarantine"
+
+[[packages]]
+repository = "foo/one"
+quarantine = true
+
+[packages.activation.env]
+quarantine = "{prefix}"
+
+[[packages]]
+repository = "foo/two"
+quarantine = true
+"#;
+        let parsed = config_from_str(config);
+        assert_eq!(
+            parsed.conda.quarantine().unwrap().full_channel().unwrap(),
+            "https://prefix.dev/test-quarantine"
+        );
+        assert!(parsed.packages.iter().all(|p| p.quarantine));
+
+        let approved = approve_package(config, "one").unwrap();
+        assert_eq!(
+            approved,
+            r#"[conda]
+channel = "test"
+quarantine_channel = "test-quarantine"
+
+[[packages]]
+repository = "foo/one"
+
+[packages.activation.env]
+quarantine = "{prefix}"
+
+[[packages]]
+repository = "foo/two"
+quarantine = true
+"#
+        );
+        assert!(approve_package(&approved, "one").is_err());
+        assert!(approve_package(config, "three").is_err());
+    }
+
+    #[test]
+    fn test_text_limits() {
+        let config = config_from_str(
+            r#"
+[text_limits]
+summary = 80
+
+[conda]
+channel = "test"
+
+[[packages]]
+repository = "foo/default"
+
+[[packages]]
+repository = "foo/own"
+text_limits = { release_notes = 500 }
+"#,
+        );
+        assert_eq!(
+            config
+                .packages
+                .iter()
+                .map(|p| (p.text_limits.summary, p.text_limits.release_notes))
+                .collect::<Vec<_>>(),
+            vec![(80, 2000), (200, 500)]
+        );
+        assert!(parse_package("repository = \"foo/bar\"\ntext_limits = { title = 10 }").is_err());
+    }
+
+    #[test]
+    fn test_policy() {
+        let config = config_from_str(
+            r#"
+[policy]
+uploaders = ["{owner}", "github-actions[bot]"]
+min_release_age = "24h"
+
+[conda]
+channel = "test"
+
+[[packages]]
+repository = "foo/default"
+
+[[packages]]
+repository = "foo/own"
+policy = { min_release_age = "1h" }
+"#,
+        );
+        assert_eq!(
+            config.packages[0].policy,
+            Policy {
+                uploaders: vec!["{owner}".to_string(), "github-actions[bot]".to_string()],
+                min_release_age: Some(std::time::Duration::from_secs(24 * 60 * 60)),
+            }
+        );
+        assert_eq!(
+            config.packages[1].policy,
+            Policy {
+                uploaders: vec![],
+                min_release_age: Some(std::time::Duration::from_secs(60 * 60)),
+            }
+        );
+        assert!(
+            parse_package("repository = \"foo/bar\"\npolicy = { min_release_age = \"1y\" }")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_max_asset_size() {
+        let config = config_from_str(
+            r#"
+max_asset_size = "500MB"
+
+[conda]
+channel = "test"
+
+[[packages]]
+repository = "foo/default"
+
+[[packages]]
+repository = "foo/own"
+max_asset_size = "1.5 GiB"
+
+[[packages]]
+repository = "foo/bytes"
+max_asset_size = 1024
+"#,
+        );
+        assert_eq!(config.max_asset_size, Some(500_000_000));
+        assert_eq!(
+            config
+                .packages
+                .iter()
+                .map(|p| p.max_asset_size)
+                .collect::<Vec<_>>(),
+            vec![Some(500_000_000), Some(3 << 29), Some(1024)]
+        );
+
+        assert!(parse_package("repository = \"foo/bar\"\nmax_asset_size = \"5 parsecs\"").is_err());
+        assert!(parse_package("repository = \"foo/bar\"\nmax_asset_size = \"MB\"").is_err());
+        assert_eq!(
+            parse_package("repository = \"foo/bar\"")
+                .unwrap()
+                .max_asset_size,
+            None
+        );
+    }
+
+    #[test]
+    fn test_exclude_files() {
+        assert_eq!(
+            parse_package("repository = \"foo/bar\"")
+                .unwrap()
+                .exclude_files,
+            DEFAULT_EXCLUDE_FILES
+        );
+        assert_eq!(
+            parse_package("repository = \"foo/bar\"\nexclude_files = [\"*.map\"]")
+                .unwrap()
+                .exclude_files,
+            vec!["*.map"]
+        );
+        assert!(
+            parse_package("repository = \"foo/bar\"\nexclude_files = []")
+                .unwrap()
+                .exclude_files
+                .is_empty()
+        );
+        assert!(parse_package("repository = \"foo/bar\"\nexclude_files = [\"lib/*.a\"]").is_err());
+    }
+
+    #[test]
+    fn test_binaries() {
+        assert!(
+            parse_package("repository = \"foo/bar\"")
+                .unwrap()
+                .binaries
+                .is_empty()
+        );
+        assert_eq!(
+            parse_package("repository = \"foo/bar\"\nbinaries = [\"bar\", \"bar-helper\"]")
+                .unwrap()
+                .binaries,
+            vec!["bar", "bar-helper"]
+        );
+        assert!(parse_package("repository = \"foo/bar\"\nbinaries = [\"bin/bar\"]").is_err());
+    }
+
+    #[test]
+    fn test_activation() {
+        let package = parse_package(
+            "repository = \"foo/bar\"\nactivation.env = { MANPATH = \"{prefix}/share/man\" }",
+        )
+        .unwrap();
+        assert_eq!(package.activation_env["MANPATH"], "{prefix}/share/man");
+        assert!(
+            parse_package("repository = \"foo/bar\"\nactivation.env = { \"BAD NAME\" = \"x\" }")
+                .is_err()
+        );
+        assert!(parse_package("repository = \"foo/bar\"\nactivation.path = \"x\"").is_err());
+    }
+
+    #[test]
+    fn test_link_scripts() {
+        let package = parse_package(
+            "repository = \"foo/bar\"\n[post_link]\nsh = \"\"\"\nbar --init\n\"\"\"\nbat = \"  \"",
+        )
+        .unwrap();
+        assert_eq!(package.post_link.sh.as_deref(), Some("bar --init\n"));
+        assert!(package.post_link.bat.is_none());
+        assert!(package.pre_unlink.sh.is_none());
+        assert!(parse_package("repository = \"foo/bar\"\n[pre_unlink]\nps1 = \"x\"").is_err());
+    }
+
+    #[test]
+    fn test_run_requirements() {
+        let package = parse_package(
+            "repository = \"foo/bar\"\nrun_exports = [\"bar >=1.2,<2\"]\nrun_constraints = [\"bar-plugins >=1\"]",
+        )
+        .unwrap();
+        assert_eq!(package.run_exports, vec!["bar >=1.2,<2"]);
+        assert_eq!(package.run_constraints, vec!["bar-plugins >=1"]);
+        assert!(parse_package("repository = \"foo/bar\"\nrun_exports = [\"bar >=>1\"]").is_err());
+    }
+
+    #[test]
+    fn test_data_kind() {
+        let package = parse_package("repository = \"foo/bar\"").unwrap();
+        assert_eq!(package.kind, PackageKind::Binary);
+        assert!(package.install_to.is_none());
+
+        let package = parse_package(
+            "repository = \"foo/bar\"\nkind = \"data\"\ninstall_to = \"share/fonts/bar/\"",
+        )
+        .unwrap();
+        assert_eq!(package.kind, PackageKind::Data);
+        assert_eq!(package.install_to.as_deref(), Some("share/fonts/bar"));
+
+        assert!(parse_package("repository = \"foo/bar\"\nkind = \"data\"").is_err());
+        assert!(
+            parse_package("repository = \"foo/bar\"\nkind = \"data\"\ninstall_to = \"../etc\"")
+                .is_err()
+        );
+        assert!(parse_package("repository = \"foo/bar\"\ninstall_to = \"share/bar\"").is_err());
+        assert_eq!(
+            parse_package("repository = \"foo/bar\"\nkind = \"library\"")
+                .unwrap()
+                .kind,
+            PackageKind::Library
+        );
+
+        let package = parse_package("repository = \"foo/bar\"\nkind = \"python-wheel\"").unwrap();
+        assert_eq!(package.kind, PackageKind::PythonWheel);
+        assert_eq!(
+            package.platforms.keys().collect::<Vec<_>>(),
+            vec![&Platform::NoArch]
+        );
+        let pattern = &package.platforms[&Platform::NoArch][0].regex;
+        assert!(pattern.is_match("bar-1.0.0-py3-none-any.whl"));
+        assert!(pattern.is_match("bar-1.0.0-py2.py3-none-any.whl"));
+        assert!(!pattern.is_match("bar-1.0.0-cp312-cp312-manylinux_2_17_x86_64.whl"));
+
+        let package = parse_package("repository = \"foo/bar\"\nkind = \"node\"").unwrap();
+        assert_eq!(
+            package.platforms.keys().collect::<Vec<_>>(),
+            vec![&Platform::NoArch]
+        );
+        let patterns = &package.platforms[&Platform::NoArch];
+        assert!(patterns[0].regex.is_match("bar-1.0.0.tgz"));
+        assert!(patterns[1].regex.is_match("bar.mjs"));
+        assert!(
+            !patterns
+                .iter()
+                .any(|p| p.regex.is_match("bar-linux-x64.tar.gz"))
+        );
+    }
+
+    #[test]
+    fn test_package_name() {
+        let package = parse_package("repository = \"BurntSushi/RipGrep\"").unwrap();
+        assert_eq!(package.name, "ripgrep");
+        let package = parse_package("repository = \"foo/bar\"\nname = \"Bar_Tool.cli\"").unwrap();
+        assert_eq!(package.name, "bar_tool.cli");
+
+        let error = parse_package("repository = \"foo/bar\"\nname = \"bar tool\"").unwrap_err();
+        assert!(format!("{error:#}").contains("foo/bar"));
+        assert!(format!("{error:#}").contains("' '"));
+        assert!(parse_package("repository = \"foo/bar\"\nname = \"bär\"").is_err());
+        assert!(parse_package("repository = \"foo/bar\"\nname = \"\"").is_err());
+    }
+
+    #[test]
+    fn test_name_from() {
+        let name = |toml: &str| parse_package(toml).map(|p| p.name);
+        assert_eq!(
+            name("repository = \"Civo/cli\"\nname_from = \"owner-repo\"").unwrap(),
+            "civo-cli"
+        );
+        assert_eq!(
+            name("repository = \"civo/cli\"\nname_from = \"repo\"").unwrap(),
+            "cli"
+        );
+        assert_eq!(
+            name("repository = \"civo/cli\"\nname_template = \"{repo}-{owner}\"").unwrap(),
+            "cli-civo"
+        );
+        assert!(name("repository = \"civo/cli\"\nname_template = \"{org}-{repo}\"").is_err());
+        assert!(name("repository = \"civo/cli\"\nname = \"civo\"\nname_from = \"repo\"").is_err());
+        assert!(name("repository = \"civo/cli\"\nname_from = \"owner\"").is_err());
+    }
+
+    #[test]
+    fn test_urls() {
+        let package = parse_package(
+            "repository = \"foo/bar\"\nhomepage = \"https://bar.example.org\"\ndocumentation = \"https://docs.rs/bar\"",
+        )
+        .unwrap();
+        assert_eq!(package.homepage.as_deref(), Some("https://bar.example.org"));
+        assert_eq!(
+            package.documentation.as_deref(),
+            Some("https://docs.rs/bar")
+        );
+
+        assert!(parse_package("repository = \"foo/bar\"\nhomepage = \"TBD\"").is_err());
+        assert!(
+            parse_package("repository = \"foo/bar\"\ndocumentation = \"file:///usr/share/doc\"")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_citation() {
+        let package = parse_package(
+            r#"
+repository = "NREL/EnergyPlus"
+doi = "https://doi.org/10.11578/dc.20171025.1983"
+citation = "EnergyPlus, U.S. Department of Energy. "
+"#,
+        )
+        .unwrap();
+        assert_eq!(package.doi.as_deref(), Some("10.11578/dc.20171025.1983"));
+        assert_eq!(
+            package.citation.as_deref(),
+            Some("EnergyPlus, U.S. Department of Energy.")
+        );
+
+        assert_eq!(
+            parse_doi("doi:10.5281/zenodo.1234").unwrap(),
+            "10.5281/zenodo.1234"
+        );
+        assert!(parse_doi("zenodo.1234").is_err());
+        assert!(parse_doi("10.5281/").is_err());
+        assert!(parse_package("repository = \"foo/bar\"\ndoi = \"TBD\"").is_err());
+    }
+
+    #[test]
+    fn test_max_releases() {
+        let package = parse_package("repository = \"foo/bar\"\nmax_releases = 20").unwrap();
+        assert_eq!(package.max_releases, Some(20));
+        assert!(parse_package("repository = \"foo/bar\"\nmax_releases = 0").is_err());
+    }
+
+    #[test]
+    fn test_sources() {
+        assert!(
+            parse_package("repository = \"foo/bar\"")
+                .unwrap()
+                .sources
+                .is_empty()
+        );
+
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+sources = [
+    { repository = "mirror/bar" },
+    { recorded = "https://mirror.example.com/bar.json" },
+]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            package
+                .sources
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>(),
+            vec!["mirror/bar", "https://mirror.example.com/bar.json"]
+        );
+
+        assert!(parse_package("repository = \"foo/bar\"\nsources = [{}]").is_err());
+        assert!(
+            parse_package(
+                "repository = \"foo/bar\"\nsources = [{ repository = \"a/b\", recorded = \"https://x\" }]"
+            )
+            .is_err()
+        );
+        assert!(
+            parse_package(
+                "repository = \"foo/bar\"\nsources = [{ recorded = \"file:///bar.json\" }]"
+            )
+            .is_err()
+        );
+        assert!(
+            parse_package("repository = \"foo/bar\"\nsources = [{ url = \"https://x\" }]").is_err()
+        );
+
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+sources = [
+    { recorded = "https://mirror.example.com/bar.json", assets = "https://mirror.example.com/bar/{tag}/{name}" },
+]
+"#,
+        )
+        .unwrap();
+        let Source::Recorded {
+            assets: Some(assets),
+            ..
+        } = &package.sources[0]
+        else {
+            panic!("no asset URLs");
+        };
+        assert_eq!(
+            assets.url("v1.0.0", "bar-linux.tar.gz").unwrap().as_str(),
+            "https://mirror.example.com/bar/v1.0.0/bar-linux.tar.gz"
+        );
+        for assets in [
+            "https://mirror.example.com/bar/{tag}",
+            "https://mirror.example.com/{version}/{name}",
+            "/bar/{name}",
+        ] {
+            assert!(
+                parse_package(&format!(
+                    "repository = \"foo/bar\"\nsources = [{{ recorded = \"https://x\", assets = \"{assets}\" }}]"
+                ))
+                .is_err()
+            );
+        }
+        assert!(
+            parse_package(
+                "repository = \"foo/bar\"\nsources = [{ repository = \"a/b\", assets = \"https://x/{name}\" }]"
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_pin() {
+        let package = parse_package("repository = \"foo/bar\"\npin = \"1.2.3\"").unwrap();
+        assert_eq!(package.pin, Some(Version::from_str("1.2.3").unwrap()));
+        assert_eq!(parse_package("repository = \"foo/bar\"").unwrap().pin, None);
+        assert!(parse_package("repository = \"foo/bar\"\npin = \"1..2\"").is_err());
+    }
+
+    #[test]
+    fn test_disabled() {
+        assert!(!parse_package("repository = \"foo/bar\"").unwrap().disabled);
+        assert!(
+            parse_package("repository = \"foo/bar\"\ndisabled = true")
+                .unwrap()
+                .disabled
+        );
+    }
+
+    #[test]
+    fn test_notify() {
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+notify = ["@alice", "@my-org/team"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(package.notify, vec!["@alice", "@my-org/team"]);
+
+        assert!(parse_package("repository = \"foo/bar\"\nnotify = [\"alice\"]").is_err());
+        assert!(parse_package("repository = \"foo/bar\"\nnotify = [\"@\"]").is_err());
+        assert!(parse_package("repository = \"foo/bar\"\nnotify = [\"@a b\"]").is_err());
+    }
+
+    #[test]
+    fn test_build_string() {
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+build_string = "gh_{shortsha}_{libc}_{build_number}"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            package.build_string.as_deref(),
+            Some("gh_{shortsha}_{libc}_{build_number}")
+        );
+
+        assert!(
+            parse_package(
+                r#"
+repository = "foo/bar"
+build_string = "gh_{commit}"
+"#
+            )
+            .is_err()
+        );
+        assert!(
+            parse_package(
+                r#"
+repository = "foo/bar"
+build_string = "gh-{libc}"
+"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_pattern_changes() {
+        let defaults = get_default_patterns()
+            .remove(&Platform::Linux64)
+            .unwrap()
+            .iter()
+            .map(|r| r.as_str().to_string())
+            .collect::<Vec<_>>();
+
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+
+[platforms.linux-64]
+prepend = ["first"]
+append = ["last"]
+
+[platforms.osx-64]
+replace = ["mac"]
+append = ["other-mac"]
+
+[platforms.linux-ppc64le]
+append = ["power"]
+"#,
+        )
+        .unwrap();
+        let patterns = |p| {
+            package.platforms[&p]
+                .iter()
+                .map(|r| r.as_str().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let linux = patterns(Platform::Linux64);
+        assert_eq!(linux.len(), defaults.len() + 2);
+        assert_eq!(linux.first().map(String::as_str), Some("first"));
+        assert_eq!(&linux[1..=defaults.len()], &defaults[..]);
+        assert_eq!(linux.last().map(String::as_str), Some("last"));
+
+        assert_eq!(patterns(Platform::Osx64), vec!["mac", "other-mac"]);
+        assert_eq!(
+            patterns(Platform::LinuxPpc64le).last().map(String::as_str),
+            Some("power")
+        );
+
+        assert!(
+            parse_package(
+                r#"
+repository = "foo/bar"
+platforms = { linux-64 = { replace = [] } }
+"#
+            )
+            .is_err()
+        );
+        assert!(
+            parse_package(
+                r#"
+repository = "foo/bar"
+platforms = { linux-64 = { add = ["typo"] } }
+"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_anchor() {
+        let pattern_strings = |toml: &str| {
+            let mut package = parse_package(toml).unwrap();
+            package
+                .platforms
+                .remove(&Platform::Linux64)
+                .unwrap()
+                .iter()
+                .map(|r| r.as_str().to_string())
+                .collect::<Vec<_>>()
+        };
+        let defaults = pattern_strings(r#"repository = "foo/bar""#);
+
+        let anchored = pattern_strings(
+            r#"
+repository = "foo/bar"
+name = "baz"
+"#,
+        );
+        assert!(anchored.iter().all(|p| p.starts_with("^baz.*")));
+
+        let unanchored = pattern_strings(
+            r#"
+repository = "foo/bar"
+name = "baz"
+anchor = false
+"#,
+        );
+        assert_eq!(unanchored, defaults);
+    }
+
+    #[test]
+    fn test_asset_prefix() {
+        let package = parse_package(
+            r#"
+repository = "ClementTsang/bottom"
+name = "btm"
+asset_prefix = "bottom"
+"#,
+        )
+        .unwrap();
+        assert_eq!(package.name, "btm");
+        assert!(
+            package.platforms[&Platform::Linux64]
+                .iter()
+                .all(|r| r.as_str().starts_with("^bottom.*"))
+        );
+
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+asset_prefix = "bar.v2"
+"#,
+        )
+        .unwrap();
+        assert!(
+            package.platforms[&Platform::Linux64]
+                .iter()
+                .all(|r| r.as_str().starts_with("^bar\\.v2.*"))
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let package = parse_package(
+            r#"
+repository = "foo/mytool"
+name = "MyTool"
+"#,
+        )
+        .unwrap();
+        let linux = &package.platforms[&Platform::Linux64];
+        assert!(linux.iter().any(|r| r.is_match("MyTool-Linux-X86_64.zip")));
+        assert!(linux.iter().any(|r| r.is_match("mytool-linux-x86_64.zip")));
+
+        let package = parse_package(
+            r#"
+repository = "foo/mytool"
+name = "MyTool"
+case_insensitive = false
+"#,
+        )
+        .unwrap();
+        let linux = &package.platforms[&Platform::Linux64];
+        assert!(!linux.iter().any(|r| r.is_match("mytool-linux-x86_64.zip")));
+    }
+
+    #[test]
+    fn test_glob_to_regex() {
+        assert_eq!(
+            glob_to_regex("*-linux-x64.tar.gz"),
+            "^.*\\-linux\\-x64\\.tar\\.gz$"
+        );
+        assert_eq!(glob_to_regex("tool-v?.zip"), "^tool\\-v.\\.zip$");
+        assert_eq!(glob_to_regex("tool-[!a-c]x"), "^tool\\-[^a-c]x$");
+        assert_eq!(glob_to_regex("tool-[x"), "^tool\\-\\[x$");
+
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+name = "bar"
+platforms = { linux-64 = "glob:*-linux-x64.tar.gz", osx-64 = ["glob:bar-mac-*.zip"] }
+"#,
+        )
+        .unwrap();
+        let matches = |p, name| package.platforms[&p].iter().any(|r| r.is_match(name));
+        assert!(matches(Platform::Linux64, "other-linux-x64.tar.gz"));
+        assert!(!matches(Platform::Linux64, "other-linux-x64.tar.gz.sha256"));
+        assert!(matches(Platform::Osx64, "bar-mac-intel.zip"));
+        assert!(!matches(Platform::Osx64, "foo-bar-mac-intel.zip"));
+    }
+
+    #[test]
+    fn test_exclude_assets() {
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+exclude_assets = { win-64 = ["\\.full\\.zip$", "glob:*-update*"] }
+"#,
+        )
+        .unwrap();
+        let excludes = &package.exclude_assets[&Platform::Win64];
+        assert!(excludes.iter().any(|r| r.is_match("bar-windows.FULL.zip")));
+        assert!(
+            excludes
+                .iter()
+                .any(|r| r.is_match("bar-update-windows.zip"))
+        );
+        assert!(!excludes.iter().any(|r| r.is_match("bar-windows.zip")));
+        assert!(!package.exclude_assets.contains_key(&Platform::Linux64));
+
+        assert!(
+            parse_package(
+                r#"
+repository = "foo/bar"
+exclude_assets = { win-64 = ["("] }
+"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_asset_preference() {
+        let package = Package::with_defaults("foo/bar").unwrap();
+        assert!(package.asset_preference.prefer.is_empty());
+        assert_eq!(package.asset_preference.size, SizePreference::Ignore);
+
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+prefer_assets = ["minimal", "musl"]
+prefer_size = "smallest"
+"#,
+        )
+        .unwrap();
+        assert_eq!(package.asset_preference.prefer, vec!["minimal", "musl"]);
+        assert_eq!(package.asset_preference.size, SizePreference::Smallest);
+
+        assert!(
+            parse_package(
+                r#"
+repository = "foo/bar"
+prefer_size = "tiny"
+"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_version_scoped_patterns() {
+        let package = parse_package(
+            r#"
+repository = "neovim/neovim"
+anchor = false
+platforms = { linux-64 = [
+    { until = "0.10.4", pattern = "nvim-linux64\\.tar\\.gz$" },
+    { since = "0.10.4", pattern = "nvim-linux-x86_64\\.tar\\.gz$" },
+    "nvim\\.appimage$",
+] }
+"#,
+        )
+        .unwrap();
+        let matches = |version: &str, name| {
+            let version = Version::from_str(version).unwrap();
+            package.patterns_for(&version)[&Platform::Linux64]
+                .iter()
+                .any(|r| r.is_match(name))
+        };
+        assert!(matches("0.9.5", "nvim-linux64.tar.gz"));
+        assert!(!matches("0.9.5", "nvim-linux-x86_64.tar.gz"));
+        assert!(!matches("0.10.4", "nvim-linux64.tar.gz"));
+        assert!(matches("0.10.4", "nvim-linux-x86_64.tar.gz"));
+        assert!(matches("0.9.5", "nvim.appimage"));
+        assert!(matches("0.11.0", "nvim.appimage"));
+
+        let package = parse_package(
+            r#"
+repository = "neovim/neovim"
+
+[platforms.linux-64]
+prepend = [{ since = "1.0", pattern = "new-name" }]
+"#,
+        )
+        .unwrap();
+        let patterns = &package.platforms[&Platform::Linux64];
+        assert_eq!(patterns[0].since, Some(Version::from_str("1.0").unwrap()));
+        assert!(patterns[1..].iter().all(|p| p.since.is_none()));
+
+        for toml in [
+            r#"
+repository = "foo/bar"
+platforms = { linux-64 = [{ until = "a!1.0", pattern = "x" }] }
+"#,
+            r#"
+repository = "foo/bar"
+platforms = { linux-64 = [{ before = "1.0", pattern = "x" }] }
+"#,
+        ] {
+            assert!(parse_package(toml).is_err());
+        }
+    }
+
+    #[test]
+    fn test_version_map() {
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+version_map = [{ pattern = "^(.*)-patch(\\d+)$", replace = "$1.$2" }]
+"#,
+        )
+        .unwrap();
+        assert_eq!(package.version_map.len(), 1);
+        assert_eq!(package.version_map[0].replace, "$1.$2");
+
+        for toml in [
+            r#"
+repository = "foo/bar"
+version_map = [{ pattern = "(", replace = "" }]
+"#,
+            r#"
+repository = "foo/bar"
+version_map = [{ match = "x", replace = "" }]
+"#,
+        ] {
+            assert!(parse_package(toml).is_err());
+        }
+    }
+
+    #[test]
+    fn test_since() {
+        assert_eq!(Since::from_str("last-run").unwrap(), Since::LastRun);
+        let date = chrono::DateTime::parse_from_rfc3339("2024-10-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        assert_eq!(Since::from_str("2024-10-01").unwrap(), Since::Date(date));
+        assert_eq!(
+            Since::from_str("2024-10-01T02:00:00+02:00").unwrap(),
+            Since::Date(date)
+        );
+        assert!(Since::from_str("yesterday").is_err());
+
+        let config = config_from_str(
+            r#"
+since = "2024-10-01"
+
+[conda]
+channel = "test"
+"#,
+        );
+        assert_eq!(config.since, Some(Since::Date(date)));
+    }
+
+    #[test]
+    fn test_telemetry() {
+        let config = config_from_str(
+            r#"
+[conda]
+channel = "test"
+"#,
+        );
+        assert!(config.telemetry.textfile.is_none());
+
+        let config = config_from_str(
+            r#"
+[conda]
+channel = "test"
+
+[telemetry]
+textfile = "/var/lib/node_exporter/octoconda.prom"
+"#,
+        );
+        assert_eq!(
+            config.telemetry.textfile,
+            Some(PathBuf::from("/var/lib/node_exporter/octoconda.prom"))
+        );
+
+        let toml = r#"
+[conda]
+channel = "test"
+
+[telemetry]
+otlp = "http://localhost:4317"
+"#;
+        assert!(toml::from_str::<TomlConfig>(toml).is_err());
+    }
+
+    #[test]
+    fn test_credentials() {
+        let config = config_from_str(
+            r#"
+[conda]
+channel = "test"
+"#,
+        );
+        assert_eq!(config.github_host, "github.com");
+        assert!(config.credentials.is_empty());
+
+        let config = config_from_str(
+            r#"
+github_host = "github.example.com"
+
+[conda]
+channel = "test"
+
+[credentials."github.example.com"]
+env = "GHE_TOKEN"
+
+[credentials."prefix.dev"]
+keyring = true
+"#,
+        );
+        assert_eq!(config.github_host, "github.example.com");
+        assert_eq!(
+            config.credentials["github.example.com"].env.as_deref(),
+            Some("GHE_TOKEN")
+        );
+        assert!(config.credentials["prefix.dev"].keyring);
+
+        for credential in ["{}", r#"{ env = "TOKEN", file = "token.txt" }"#] {
+            let toml: TomlConfig = toml::from_str(&format!(
+                "[conda]\nchannel = \"test\"\n[credentials]\n\"github.com\" = {credential}\n"
+            ))
+            .unwrap();
+            assert!(Config::try_from(toml).is_err());
+        }
+    }
+
+    #[test]
+    fn test_platforms_since() {
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+platforms_since = { win-arm64 = "0.5.0" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            package.platforms_since[&Platform::WinArm64],
+            Version::from_str("0.5.0").unwrap()
+        );
+
+        assert!(
+            parse_package(
+                r#"
+repository = "foo/bar"
+platforms_since = { win-arm64 = "a!1.0" }
+"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_variants() {
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+variants = ["musl", "gnu"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(package.variants, vec!["musl", "gnu"]);
+
+        for invalid in [
+            r#"variants = [""]"#,
+            r#"variants = ["a b"]"#,
+            "variants = [\"musl\"]\nbuild_string = \"gh_{libc}\"",
+        ] {
+            assert!(parse_package(&format!("repository = \"foo/bar\"\n{invalid}\n")).is_err());
+        }
+    }
+
+    #[test]
+    fn test_optional_platforms() {
+        assert!(!get_default_patterns().contains_key(&Platform::LinuxPpc64le));
+
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+platforms = { linux-ppc64le = "default" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            package.platforms[&Platform::LinuxPpc64le].len(),
+            get_optional_patterns(Platform::LinuxPpc64le).len()
+        );
+        assert!(!get_default_patterns().contains_key(&Platform::WasiWasm32));
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+platforms = { wasi-wasm32 = "default" }
+"#,
+        )
+        .unwrap();
+        assert!(package.platforms.contains_key(&Platform::WasiWasm32));
+
+        assert!(
+            parse_package(
+                r#"
+repository = "foo/bar"
+platforms = { emscripten-wasm32 = "default" }
+"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_require_platforms() {
+        let package = parse_package(
+            r#"
+repository = "foo/bar"
+require_platforms = ["linux-64", "osx-arm64"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            package.required_platforms,
+            HashSet::from([Platform::Linux64, Platform::OsxArm64])
+        );
+
+        assert!(
+            parse_package(
+                r#"
+repository = "foo/bar"
+require_platforms = ["linux-64"]
+platforms = { linux-64 = "null" }
+"#,
+            )
+            .is_err()
+        );
     }
 }
@@ -0,0 +1,118 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Tokens for GitHub and conda channel hosts
+
+use anyhow::Context as _;
+use rattler_networking::{
+    Authentication,
+    authentication_storage::{
+        StorageBackend as _, backends::keyring::KeyringAuthenticationStorage,
+    },
+};
+
+use crate::config_file::{Config, Credential};
+
+fn keyring_token(host: &str) -> anyhow::Result<String> {
+    let authentication = KeyringAuthenticationStorage::default()
+        .get(host)
+        .context(format!(
+            "Failed to read the credentials of {host} from the keyring"
+        ))?;
+    match authentication {
+        Some(Authentication::BearerToken(token) | Authentication::CondaToken(token)) => Ok(token),
+        Some(Authentication::BasicHTTP { password, .. }) => Ok(password),
+        Some(_) => Err(anyhow::anyhow!(
+            "The keyring holds no token for {host}, but other credentials"
+        )),
+        None => Err(anyhow::anyhow!(
+            "The keyring holds no credentials for {host}"
+        )),
+    }
+}
+
+/// The token `credential` points to for `host`
+pub fn token(host: &str, credential: &Credential) -> anyhow::Result<String> {
+    let token = if let Some(variable) = &credential.env {
+        std::env::var(variable).context(format!(
+            "The token for {host} should be in ${variable}, which is not set"
+        ))?
+    } else if let Some(file) = &credential.file {
+        std::fs::read_to_string(file).context(format!(
+            "Failed to read the token for {host} from {}",
+            file.display()
+        ))?
+    } else {
+        keyring_token(host)?
+    };
+
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(anyhow::anyhow!("The token for {host} is empty"));
+    }
+    Ok(token.to_string())
+}
+
+/// The token the `gh` CLI stores for `host` after `gh auth login`, if any
+pub fn gh_cli_token(host: &str) -> Option<String> {
+    let output = std::process::Command::new("gh")
+        .args(["auth", "token", "--hostname", host])
+        .env("GH_PROMPT_DISABLED", "1")
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let token = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!token.is_empty()).then_some(token)
+}
+
+/// The configured token for the GitHub host of `config`, if any
+pub fn github_token(config: &Config) -> anyhow::Result<Option<String>> {
+    config
+        .credentials
+        .get(&config.github_host)
+        .map(|c| token(&config.github_host, c))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("token.txt");
+        std::fs::write(&file, "ghp_secret\n").unwrap();
+
+        let credential = Credential {
+            file: Some(file),
+            ..Default::default()
+        };
+        assert_eq!(token("github.com", &credential).unwrap(), "ghp_secret");
+
+        let credential = Credential {
+            file: Some(dir.path().join("missing.txt")),
+            ..Default::default()
+        };
+        assert!(token("github.com", &credential).is_err());
+
+        let credential = Credential {
+            env: Some("OCTOCONDA_TEST_TOKEN_THAT_IS_NOT_SET".to_string()),
+            ..Default::default()
+        };
+        let error = token("github.com", &credential).unwrap_err();
+        assert!(format!("{error:#}").contains("OCTOCONDA_TEST_TOKEN_THAT_IS_NOT_SET"));
+
+        let empty = dir.path().join("empty.txt");
+        std::fs::write(&empty, "\n").unwrap();
+        let credential = Credential {
+            file: Some(empty),
+            ..Default::default()
+        };
+        assert!(token("github.com", &credential).is_err());
+    }
+}
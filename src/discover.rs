@@ -0,0 +1,57 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+use crate::{
+    config_file::Package,
+    github::Github,
+    package_generation::{AssetFilter, match_platform},
+};
+
+/// Print `[[packages]]` entries for all repositories starred by `user` whose
+/// latest release has assets matching the default platform patterns.
+pub async fn print_starred_packages(gh: &Github, user: &str) -> anyhow::Result<()> {
+    let repositories = gh.query_starred_repositories(user).await?;
+
+    for repository in repositories {
+        let full_name = format!("{}/{}", repository.owner, repository.repo);
+
+        let release = match gh.query_latest_release(&repository).await {
+            Ok(release) => release,
+            Err(e) => {
+                tracing::warn!("Skipping {full_name}: {e}");
+                continue;
+            }
+        };
+
+        let package = Package::with_defaults(&full_name)?;
+
+        let mut platforms = package
+            .platforms
+            .iter()
+            .filter(|(_, patterns)| {
+                let patterns = patterns.iter().map(|p| p.regex.clone()).collect::<Vec<_>>();
+                match_platform(
+                    &patterns,
+                    &AssetFilter::default(),
+                    &package.asset_preference,
+                    &release.assets,
+                )
+                .is_some()
+            })
+            .map(|(platform, _)| platform.to_string())
+            .collect::<Vec<_>>();
+        if platforms.is_empty() {
+            tracing::info!("Skipping {full_name}: No assets match the default patterns");
+            continue;
+        }
+        platforms.sort();
+
+        println!(
+            "# {}: {}\n[[packages]]\nrepository = \"{full_name}\"\n",
+            release.tag_name,
+            platforms.join(", ")
+        );
+    }
+
+    Ok(())
+}
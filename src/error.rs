@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+/// Errors reported by the public API
+///
+/// The variants tell apart where things went wrong, so callers can decide
+/// whether to retry, to skip a package or to give up.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The configuration is invalid: Fix it and try again
+    #[error("configuration error: {0:#}")]
+    Config(anyhow::Error),
+    /// Talking to Github failed: Might work when retried later
+    #[error("Github error: {0:#}")]
+    Github(anyhow::Error),
+    /// Querying the conda channel failed: Might work when retried later
+    #[error("conda channel error: {0:#}")]
+    Channel(anyhow::Error),
+    /// Writing a recipe failed
+    #[error("recipe generation error: {0:#}")]
+    Recipe(anyhow::Error),
+    /// Reading or writing the state kept between runs failed
+    #[error("state file error: {0:#}")]
+    State(anyhow::Error),
+}
+
+impl Error {
+    /// Whether retrying the failed operation later might succeed
+    ///
+    /// Github errors are transient if GitHub answered with a server error or
+    /// did not answer at all. Missing repositories, authentication problems
+    /// and unexpected responses stay broken when retried.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Github(e) => e.chain().any(is_transient_cause),
+            Error::Channel(_) => true,
+            _ => false,
+        }
+    }
+}
+
+fn is_transient_cause(cause: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(e) = cause.downcast_ref::<crate::github::StatusError>() {
+        e.status >= 500
+    } else if let Some(e) = cause.downcast_ref::<octocrab::GitHubError>() {
+        e.status_code.is_server_error()
+    } else if let Some(e) = cause.downcast_ref::<octocrab::Error>() {
+        matches!(
+            e,
+            octocrab::Error::Service { .. } | octocrab::Error::Hyper { .. }
+        )
+    } else if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+        e.is_timeout() || e.is_connect() || e.status().is_some_and(|s| s.is_server_error())
+    } else {
+        false
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
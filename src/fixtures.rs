@@ -0,0 +1,304 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Record GitHub and channel responses to disk and replay them later
+//!
+//! Replaying makes runs deterministic and lets them work offline, e.g. for
+//! integration tests of the whole pipeline or for demos.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+
+use crate::{
+    Error, Result,
+    config_file::{AssetUrls, Package},
+    github::{ReleaseProvider, Releases},
+};
+
+/// What to do with recorded responses
+#[derive(Clone, Debug)]
+pub enum Mode {
+    /// Ask the real services and write their responses into the directory
+    Record(PathBuf),
+    /// Read responses from the directory instead of asking the services
+    Replay(PathBuf),
+}
+
+fn read<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let content = std::fs::read_to_string(path).context(format!(
+        "Failed to read recorded response {}",
+        path.display()
+    ))?;
+    serde_json::from_str(&content).context(format!(
+        "Failed to parse recorded response {}",
+        path.display()
+    ))
+}
+
+fn write<T: serde::Serialize>(path: &Path, value: &T) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .context(format!("Failed to create directory {}", parent.display()))?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(value)?)
+        .context(format!("Failed to record response {}", path.display()))
+}
+
+/// The result of `query`, recorded as `<name>.json` or replayed from there
+/// depending on `mode`
+///
+/// Problems with the recording are reported as `error`.
+pub async fn recorded<T: serde::Serialize + serde::de::DeserializeOwned>(
+    mode: Option<&Mode>,
+    name: &str,
+    error: fn(anyhow::Error) -> Error,
+    query: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match mode {
+        None => query.await,
+        Some(Mode::Replay(dir)) => read(&dir.join(format!("{name}.json"))).map_err(error),
+        Some(Mode::Record(dir)) => {
+            let value = query.await?;
+            write(&dir.join(format!("{name}.json")), &value).map_err(error)?;
+            Ok(value)
+        }
+    }
+}
+
+fn releases_file(dir: &Path, package: &Package) -> PathBuf {
+    dir.join("github")
+        .join(&package.repository.owner)
+        .join(format!("{}.json", package.repository.repo))
+}
+
+/// Records the releases another provider returns
+pub struct Recorder<'a, P> {
+    provider: &'a P,
+    dir: PathBuf,
+}
+
+impl<'a, P: ReleaseProvider> Recorder<'a, P> {
+    pub fn new(provider: &'a P, dir: &Path) -> Self {
+        Self {
+            provider,
+            dir: dir.to_path_buf(),
+        }
+    }
+}
+
+impl<P: ReleaseProvider> ReleaseProvider for Recorder<'_, P> {
+    async fn query_releases(
+        &self,
+        package: &Package,
+    ) -> Result<(octocrab::models::Repository, Releases)> {
+        let result = self.provider.query_releases(package).await?;
+        write(&releases_file(&self.dir, package), &result).map_err(Error::Github)?;
+        Ok(result)
+    }
+
+    async fn has_attestation(
+        &self,
+        repository: &crate::types::Repository,
+        digest: &str,
+    ) -> Result<bool> {
+        self.provider.has_attestation(repository, digest).await
+    }
+}
+
+/// Provides the releases a [`Recorder`] wrote before
+pub struct Replay {
+    dir: PathBuf,
+}
+
+impl Replay {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+}
+
+impl ReleaseProvider for Replay {
+    async fn query_releases(
+        &self,
+        package: &Package,
+    ) -> Result<(octocrab::models::Repository, Releases)> {
+        read(&releases_file(&self.dir, package)).map_err(Error::Github)
+    }
+}
+
+/// Releases recorded for `package`, with versions derived from the tags
+/// again: The configuration may have changed since they were recorded.
+fn rederive(
+    content: &[u8],
+    package: &Package,
+) -> anyhow::Result<(octocrab::models::Repository, Releases)> {
+    let (repository, releases): (
+        octocrab::models::Repository,
+        Vec<(octocrab::models::repos::Release, serde::de::IgnoredAny)>,
+    ) = serde_json::from_slice(content)?;
+    let releases = releases
+        .into_iter()
+        .filter_map(|(release, _)| {
+            let version = crate::github::release_version(&release, package)?;
+            Some((release, version))
+        })
+        .collect();
+    Ok((repository, releases))
+}
+
+/// Provides the releases a [`Recorder`] wrote before, served at a URL
+///
+/// This is a fallback source for packages whose repository is gone. Their
+/// assets can be served from elsewhere, too.
+pub struct Remote {
+    url: url::Url,
+    assets: Option<AssetUrls>,
+}
+
+impl Remote {
+    pub fn new(url: &url::Url, assets: Option<AssetUrls>) -> Self {
+        Self {
+            url: url.clone(),
+            assets,
+        }
+    }
+
+    async fn fetch(
+        &self,
+        package: &Package,
+    ) -> anyhow::Result<(octocrab::models::Repository, Releases)> {
+        tracing::debug!("Querying {}", self.url);
+        let content = reqwest::get(self.url.clone())
+            .await
+            .and_then(|r| r.error_for_status())
+            .context(format!("Failed to download {}", self.url))?
+            .bytes()
+            .await
+            .context(format!("Failed to download {}", self.url))?;
+        let (repository, mut releases) =
+            rederive(&content, package).context(format!("Failed to parse {}", self.url))?;
+        if let Some(assets) = &self.assets {
+            mirror_assets(&mut releases, assets)?;
+        }
+        Ok((repository, releases))
+    }
+}
+
+/// Point the download URLs of the assets of `releases` at `assets`
+fn mirror_assets(releases: &mut Releases, assets: &AssetUrls) -> anyhow::Result<()> {
+    for (release, _) in releases {
+        for asset in &mut release.assets {
+            asset.browser_download_url = assets.url(&release.tag_name, &asset.name)?;
+        }
+    }
+    Ok(())
+}
+
+impl ReleaseProvider for Remote {
+    async fn query_releases(
+        &self,
+        package: &Package,
+    ) -> Result<(octocrab::models::Repository, Releases)> {
+        self.fetch(package).await.map_err(Error::Github)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let record = Mode::Record(dir.path().to_path_buf());
+        let replay = Mode::Replay(dir.path().to_path_buf());
+
+        let names = vec!["bar".to_string(), "foo".to_string()];
+        let recorded_names = block_on(recorded(Some(&record), "channel", Error::Channel, async {
+            Ok(names.clone())
+        }))
+        .unwrap();
+        assert_eq!(recorded_names, names);
+        assert!(dir.path().join("channel.json").is_file());
+
+        let replayed_names: Vec<String> =
+            block_on(recorded(Some(&replay), "channel", Error::Channel, async {
+                Err(Error::Channel(anyhow::anyhow!("must not be queried")))
+            }))
+            .unwrap();
+        assert_eq!(replayed_names, names);
+
+        let missing = block_on(recorded::<Vec<String>>(
+            Some(&replay),
+            "conda-forge",
+            Error::Channel,
+            async { Ok(vec![]) },
+        ));
+        assert!(matches!(missing, Err(Error::Channel(_))));
+    }
+
+    #[test]
+    fn test_replay_without_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        let package = Package::with_defaults("foo/bar").unwrap();
+        let result = block_on(Replay::new(dir.path()).query_releases(&package));
+        let error = result.unwrap_err();
+        assert!(matches!(error, Error::Github(_)));
+        assert!(error.to_string().contains("bar.json"));
+    }
+
+    #[test]
+    fn test_rederive() {
+        use crate::package_generation::tests::{release_fixture, repository_fixture};
+
+        let repository = repository_fixture(serde_json::json!({
+            "id": 1,
+            "name": "bar",
+            "url": "https://api.github.com/repos/foo/bar",
+        }));
+        let mut draft = release_fixture("v3.0.0", &[]);
+        draft.draft = true;
+        let recorded: Releases = vec![
+            (draft, Ok(("3.0.0".to_string(), 0))),
+            (
+                release_fixture("v2.0.0", &["bar-linux-x86_64.tar.gz"]),
+                Err("rejected by an older configuration".to_string()),
+            ),
+        ];
+        let content = serde_json::to_vec(&(&repository, &recorded)).unwrap();
+
+        let package = Package::with_defaults("foo/bar").unwrap();
+        let (repository, releases) = rederive(&content, &package).unwrap();
+        assert_eq!(repository.name, "bar");
+        assert_eq!(releases.len(), 1);
+        assert_eq!(releases[0].0.tag_name, "v2.0.0");
+        assert_eq!(releases[0].1, Ok(("2.0.0".to_string(), 0)));
+
+        assert!(rederive(b"[]", &package).is_err());
+    }
+
+    #[test]
+    fn test_mirror_assets() {
+        use crate::package_generation::tests::release_fixture;
+
+        let mut releases: Releases = vec![(
+            release_fixture("v2.0.0", &["bar-linux-x86_64.tar.gz"]),
+            Ok(("2.0.0".to_string(), 0)),
+        )];
+        let assets = AssetUrls::new("https://mirror.example.com/bar/{tag}/{name}").unwrap();
+        mirror_assets(&mut releases, &assets).unwrap();
+        assert_eq!(
+            releases[0].0.assets[0].browser_download_url.as_str(),
+            "https://mirror.example.com/bar/v2.0.0/bar-linux-x86_64.tar.gz"
+        );
+    }
+}
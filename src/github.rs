@@ -3,94 +3,850 @@
 
 use anyhow::Context;
 
+/// Releases with the version and build number derived from their tag
+pub type Releases = Vec<(octocrab::models::repos::Release, crate::version::TagVersion)>;
+
+/// A source of releases to package
+pub trait ReleaseProvider {
+    /// Repository metadata and all packageable releases of `package`
+    fn query_releases(
+        &self,
+        package: &crate::config_file::Package,
+    ) -> impl Future<Output = crate::Result<(octocrab::models::Repository, Releases)>>;
+
+    /// Whether GitHub keeps an attestation `repository` made for the asset
+    /// with `digest`
+    ///
+    /// Providers that are not GitHub can not tell.
+    fn has_attestation(
+        &self,
+        _repository: &crate::types::Repository,
+        _digest: &str,
+    ) -> impl Future<Output = crate::Result<bool>> {
+        async { Ok(false) }
+    }
+}
+
+/// The host of public GitHub
+pub const GITHUB_HOST: &str = "github.com";
+
 pub struct Github {
     octocrab: octocrab::Octocrab,
+    pacer: Pacer,
+    /// Directory to keep the releases of repositories in between runs
+    release_cache: Option<std::path::PathBuf>,
+}
+
+/// The release pages of a repository, revalidated with their ETag
+///
+/// Pages after the first are only valid while the newest release is still
+/// `latest_release`.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+struct CachedReleases {
+    max_releases: Option<usize>,
+    #[serde(default)]
+    latest_release: Option<octocrab::models::ReleaseId>,
+    pages: Vec<CachedPage>,
+}
+
+/// One page of releases as GitHub listed it
+#[derive(Clone, Default, serde::Deserialize, serde::Serialize)]
+struct CachedPage {
+    etag: Option<String>,
+    releases: Vec<octocrab::models::repos::Release>,
+}
+
+fn cache_file(dir: &std::path::Path, repository: &crate::types::Repository) -> std::path::PathBuf {
+    dir.join(&repository.owner)
+        .join(format!("{}.json", repository.repo))
+}
+
+/// The releases cached in `file` for the same `max_releases`
+fn cached_releases(file: &std::path::Path, max_releases: Option<usize>) -> CachedReleases {
+    let Ok(content) = std::fs::read_to_string(file) else {
+        return CachedReleases::default();
+    };
+    match serde_json::from_str::<CachedReleases>(&content) {
+        Ok(cached) if cached.max_releases == max_releases => cached,
+        Ok(_) => CachedReleases::default(),
+        Err(e) => {
+            tracing::debug!("Ignoring release cache {}: {e}", file.display());
+            CachedReleases::default()
+        }
+    }
+}
+
+fn write_cache(file: &std::path::Path, cached: &CachedReleases) -> anyhow::Result<()> {
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent)
+            .context(format!("Failed to create directory {}", parent.display()))?;
+    }
+    std::fs::write(file, serde_json::to_string(cached)?)
+        .context(format!("Failed to write release cache {}", file.display()))
+}
+
+/// Time between two requests to GitHub unless configured otherwise
+pub const DEFAULT_REQUEST_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Releases GitHub returns per page at most
+const RELEASES_PER_PAGE: usize = 100;
+
+/// How often to repeat a request GitHub asked to wait with
+const RATE_LIMIT_RETRIES: u32 = 3;
+/// Waiting longer than this for a rate limit to reset fails the request
+const MAX_RATE_LIMIT_WAIT: std::time::Duration = std::time::Duration::from_secs(300);
+/// How long to wait after secondary rate limits without `retry-after`
+const SECONDARY_RATE_LIMIT_WAIT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Spreads requests over time, and holds all of them back while GitHub
+/// asks to wait
+pub struct Pacer {
+    interval: std::time::Duration,
+    next: std::sync::Mutex<tokio::time::Instant>,
+}
+
+impl Pacer {
+    pub fn new(interval: std::time::Duration) -> Self {
+        Self {
+            interval,
+            next: std::sync::Mutex::new(tokio::time::Instant::now()),
+        }
+    }
+
+    fn next(&self) -> std::sync::MutexGuard<'_, tokio::time::Instant> {
+        self.next
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Claim the next free slot for a request at or after `now`
+    fn reserve(&self, now: tokio::time::Instant) -> tokio::time::Instant {
+        let mut next = self.next();
+        let slot = (*next).max(now);
+        *next = slot + self.interval;
+        slot
+    }
+
+    /// Wait until the next request may be sent
+    pub async fn wait(&self) {
+        let slot = self.reserve(tokio::time::Instant::now());
+        tokio::time::sleep_until(slot).await;
+    }
+
+    /// Hold back all requests for `duration`
+    pub fn pause(&self, duration: std::time::Duration) {
+        let mut next = self.next();
+        *next = (*next).max(tokio::time::Instant::now() + duration);
+    }
+}
+
+/// The GitHub API rate limit of the current token
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quota {
+    pub limit: u64,
+    pub remaining: u64,
+    pub used: u64,
+}
+
+/// Requests per hour GitHub allows without authentication
+const UNAUTHENTICATED_LIMIT: u64 = 60;
+
+/// The rate limit before and after a run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuotaUsage {
+    pub before: Quota,
+    pub after: Quota,
+}
+
+impl QuotaUsage {
+    /// Requests made in between, the limit resets once an hour
+    pub fn used(&self) -> u64 {
+        if self.after.used >= self.before.used {
+            self.after.used - self.before.used
+        } else {
+            self.after.used
+        }
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.after.limit > UNAUTHENTICATED_LIMIT
+    }
+
+    /// Whether less than a tenth of the limit is left
+    pub fn is_low(&self) -> bool {
+        self.after.remaining < self.after.limit / 10
+    }
 }
 
 impl Github {
-    pub fn new() -> anyhow::Result<Self> {
-        let octocrab = if let Ok(token) = std::env::var("GITHUB_TOKEN") {
-            eprintln!("Github with personal token authentication");
-            octocrab::OctocrabBuilder::default()
+    pub fn new() -> crate::Result<Self> {
+        Self::for_host(GITHUB_HOST, None)
+    }
+
+    /// A client for `host`, a GitHub Enterprise host unless it is
+    /// [`GITHUB_HOST`]
+    ///
+    /// On [`GITHUB_HOST`] the token in the environment wins over the
+    /// configured `token`, other hosts never get it. The token of the `gh`
+    /// CLI is used when neither is available.
+    pub fn for_host(host: &str, token: Option<String>) -> crate::Result<Self> {
+        let mut builder = octocrab::OctocrabBuilder::default();
+        if host != GITHUB_HOST {
+            builder = builder
+                .base_uri(format!("https://{host}/api/v3"))
+                .context(format!("{host:?} is not a valid host"))
+                .map_err(crate::Error::Config)?;
+        }
+
+        // Tokens in the environment are for github.com, e.g. in CI
+        let env_token = |name| std::env::var(name).ok().filter(|_| host == GITHUB_HOST);
+        let octocrab = if let Some(token) = env_token("GITHUB_TOKEN") {
+            tracing::info!("Github with personal token authentication");
+            builder
                 .personal_token(token.clone())
                 .build()
-                .context("failed to set GITHUB_TOKEN")?
-        } else if let Ok(token) = std::env::var("GITHUB_ACCESS_TOKEN") {
-            eprintln!("Github with user access token authentication");
-            octocrab::OctocrabBuilder::default()
+                .context("failed to set GITHUB_TOKEN")
+        } else if let Some(token) = env_token("GITHUB_ACCESS_TOKEN") {
+            tracing::info!("Github with user access token authentication");
+            builder
                 .user_access_token(token.clone())
                 .build()
-                .context("failed to set GITHUB_TOKEN")?
+                .context("failed to set GITHUB_TOKEN")
+        } else if let Some(token) = token {
+            tracing::info!("Github with configured token for {host}");
+            builder
+                .personal_token(token)
+                .build()
+                .context(format!("failed to set the token for {host}"))
+        } else if let Some(token) = crate::credentials::gh_cli_token(host) {
+            tracing::info!("Github with the token of the gh CLI for {host}");
+            builder
+                .personal_token(token)
+                .build()
+                .context("failed to set the token of the gh CLI")
         } else {
-            eprintln!("Github without authentication");
-            octocrab::OctocrabBuilder::default()
+            tracing::info!("Github without authentication");
+            builder
                 .build()
-                .context("Failed to build without authentication")?
-        };
+                .context("Failed to build without authentication")
+        }
+        .map_err(crate::Error::Github)?;
 
-        Ok(Github { octocrab })
+        Ok(Github {
+            octocrab,
+            pacer: Pacer::new(DEFAULT_REQUEST_INTERVAL),
+            release_cache: None,
+        })
     }
 
-    pub async fn query_releases(
-        &self,
-        repository: &crate::types::Repository,
-        package_name: &str,
-    ) -> anyhow::Result<(
-        octocrab::models::Repository,
-        Vec<(octocrab::models::repos::Release, (String, u32))>,
-    )> {
-        use tokio_stream::StreamExt;
-
-        eprintln!("GH: querying {}/{}", repository.owner, repository.repo);
+    /// Keep the releases of repositories in `dir` between runs, and only
+    /// list them again when a repository has a new release
+    pub fn with_release_cache(mut self, dir: &std::path::Path) -> Self {
+        self.release_cache = Some(dir.to_path_buf());
+        self
+    }
 
-        let mut releases_result = Vec::new();
+    /// Leave at least `interval` between two requests
+    pub fn with_request_interval(mut self, interval: std::time::Duration) -> Self {
+        self.pacer = Pacer::new(interval);
+        self
+    }
 
-        let repo = self.octocrab.repos(&repository.owner, &repository.repo);
-        let repo_result = repo.get().await.context("Failed to get repository data")?;
+    /// Names of all repositories in `org` that are not archived and have
+    /// at least one release.
+    pub async fn query_organization_repositories(&self, org: &str) -> anyhow::Result<Vec<String>> {
+        tracing::info!("GH: discovering repositories in {org}");
 
-        let stream = repo
-            .releases()
-            .list()
-            .send()
+        let repositories = self
+            .get_all::<octocrab::models::Repository>(format!("/orgs/{org}/repos?per_page=100"))
             .await
-            .context("Failed to retrieve list of releases")?
-            .into_stream(&self.octocrab);
+            .context(format!("Failed to list repositories of {org}"))?;
+
+        let mut result = Vec::new();
 
-        tokio::pin!(stream);
-        while let Some(release) = stream.try_next().await? {
-            let tag = &release.tag_name;
-            if tag.contains("prerelease") || tag.contains("alpha") || tag.contains("beta") {
+        for repository in repositories {
+            if repository.archived.unwrap_or(false) {
                 continue;
             }
 
-            let tag = if let Some(t) = tag.strip_prefix(&format!("{package_name}_")) {
-                t.to_string()
-            } else {
-                tag.to_string()
-            };
-            let tag = if let Some(t) = tag.strip_prefix('v') {
-                t.to_string()
-            } else {
-                tag
+            let (releases, _) = self
+                .get_page::<Vec<octocrab::models::repos::Release>>(&format!(
+                    "/repos/{org}/{}/releases?per_page=1",
+                    repository.name
+                ))
+                .await
+                .context(format!(
+                    "Failed to list releases of {org}/{}",
+                    repository.name
+                ))?;
+            if releases.is_empty() {
+                continue;
+            }
+
+            result.push(repository.name);
+        }
+
+        Ok(result)
+    }
+
+    /// All repositories starred by `user`
+    pub async fn query_starred_repositories(
+        &self,
+        user: &str,
+    ) -> anyhow::Result<Vec<crate::types::Repository>> {
+        tracing::info!("GH: querying repositories starred by {user}");
+
+        let repositories = self
+            .get_all::<octocrab::models::Repository>(format!("/users/{user}/starred?per_page=100"))
+            .await
+            .context(format!("Failed to list repositories starred by {user}"))?;
+
+        let mut result = Vec::new();
+
+        for repository in repositories {
+            let Some(full_name) = &repository.full_name else {
+                continue;
             };
+            result.push(crate::types::Repository::try_from(full_name.as_str())?);
+        }
 
-            let (version, build) = if let Some((version, build)) = tag.split_once('-') {
-                (version.to_string(), build.to_string())
-            } else {
-                (tag, String::new())
+        Ok(result)
+    }
+
+    /// The current rate limit, querying it does not count against it
+    pub async fn quota(&self) -> anyhow::Result<Quota> {
+        let rate = self
+            .octocrab
+            .ratelimit()
+            .get()
+            .await
+            .context("Failed to query the GitHub rate limit")?
+            .rate;
+        Ok(Quota {
+            limit: rate.limit as u64,
+            remaining: rate.remaining as u64,
+            used: rate.used as u64,
+        })
+    }
+
+    pub async fn query_latest_release(
+        &self,
+        repository: &crate::types::Repository,
+    ) -> anyhow::Result<octocrab::models::repos::Release> {
+        let (release, _) = self
+            .get_page(&format!(
+                "/repos/{}/{}/releases/latest",
+                repository.owner, repository.repo
+            ))
+            .await
+            .context(format!(
+                "Failed to get latest release of {}/{}",
+                repository.owner, repository.repo
+            ))?;
+        Ok(release)
+    }
+
+    /// GET `uri` from the API, parsed from JSON, and the `link` header
+    /// pointing to other pages if there is one
+    ///
+    /// Goes through the pacer and repeats the request when GitHub asks to
+    /// wait.
+    async fn get_page<T: serde::de::DeserializeOwned>(
+        &self,
+        uri: &str,
+    ) -> anyhow::Result<(T, Option<String>)> {
+        let (value, link, _) = self
+            .get_page_if_changed(uri, None)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("GitHub answered 304 for {uri}"))?;
+        Ok((value, link))
+    }
+
+    /// The value at `uri` with its link and ETag headers, or `None` if it
+    /// still has the given `etag`
+    ///
+    /// GitHub does not count requests answered with "not modified" against
+    /// the rate limit.
+    async fn get_page_if_changed<T: serde::de::DeserializeOwned>(
+        &self,
+        uri: &str,
+        etag: Option<&str>,
+    ) -> anyhow::Result<Option<(T, Option<String>, Option<String>)>> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        if let Some(etag) = etag.and_then(|e| reqwest::header::HeaderValue::from_str(e).ok()) {
+            headers.insert(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        let mut retries = 0;
+        loop {
+            self.pacer.wait().await;
+            let response = self
+                .octocrab
+                ._get_with_headers(uri, Some(headers.clone()))
+                .await
+                .context(format!("Failed to get {uri}"))?;
+            let status = response.status().as_u16();
+            let headers = response.headers().clone();
+            let header = |name: &str| {
+                headers
+                    .get(name)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string)
             };
+            let link = header("link");
+            let body = self
+                .octocrab
+                .body_to_string(response)
+                .await
+                .context(format!("Failed to read the response for {uri}"))?;
+
+            match rate_limit_wait(status, header, &body, chrono::Utc::now()) {
+                Some(wait) if wait <= MAX_RATE_LIMIT_WAIT && retries < RATE_LIMIT_RETRIES => {
+                    retries += 1;
+                    tracing::warn!(
+                        "GH: Rate limited, waiting {}s before retrying ({retries}/{RATE_LIMIT_RETRIES})",
+                        wait.as_secs()
+                    );
+                    self.pacer.pause(wait);
+                    continue;
+                }
+                Some(wait) => {
+                    return Err(anyhow::anyhow!(
+                        "GitHub rate limit exceeded for {uri}, retry in {}s",
+                        wait.as_secs()
+                    ));
+                }
+                None => {}
+            }
+            if status == 304 && etag.is_some() {
+                return Ok(None);
+            }
+            if !(200..300).contains(&status) {
+                return Err(StatusError {
+                    status,
+                    uri: uri.to_string(),
+                    message: error_message(&body),
+                }
+                .into());
+            }
+            let value = serde_json::from_str(&body)
+                .context(format!("Failed to parse the response for {uri}"))?;
+            return Ok(Some((value, link, header("etag"))));
+        }
+    }
+
+    /// All items on all pages of the list at `uri`
+    async fn get_all<T: serde::de::DeserializeOwned>(&self, uri: String) -> anyhow::Result<Vec<T>> {
+        let mut result = Vec::new();
+        let mut next = Some(uri);
+        while let Some(uri) = next {
+            let (items, link) = self.get_page::<Vec<T>>(&uri).await?;
+            result.extend(items);
+            next = link.as_deref().and_then(|l| link_target(l, "next"));
+        }
+        Ok(result)
+    }
+}
+
+/// The URI of the page with the relation `rel` in a `link` header
+fn link_target(link: &str, rel: &str) -> Option<String> {
+    let rel = format!("rel=\"{rel}\"");
+    link.split(',').find_map(|part| {
+        let (uri, params) = part.split_once(';')?;
+        params.split(';').any(|p| p.trim() == rel).then(|| {
+            uri.trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        })
+    })
+}
+
+/// The number of pages according to a `link` header
+fn page_count(link: &str) -> Option<usize> {
+    let last = url::Url::parse(&link_target(link, "last")?).ok()?;
+    last.query_pairs()
+        .find_map(|(key, value)| (key == "page").then(|| value.parse().ok()).flatten())
+}
+
+/// GitHub answered a request with an error status
+#[derive(Debug, thiserror::Error)]
+#[error("GitHub answered {status} for {uri}: {message}")]
+pub struct StatusError {
+    pub status: u16,
+    pub uri: String,
+    pub message: String,
+}
+
+/// The `message` of a GitHub error response, or the whole body
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| body.to_string())
+}
+
+/// How long GitHub asks to wait before repeating a request that got a
+/// response with `status`, `header`s and `body`
+///
+/// Follows the advice of the GitHub documentation: Wait for `retry-after`
+/// seconds if given, until `x-ratelimit-reset` if no requests are left, and
+/// a minute for other secondary rate limits. Returns `None` for responses
+/// that are no rate limit errors.
+fn rate_limit_wait(
+    status: u16,
+    header: impl Fn(&str) -> Option<String>,
+    body: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Option<std::time::Duration> {
+    if status != 403 && status != 429 {
+        return None;
+    }
+    if let Some(seconds) = header("retry-after").and_then(|s| s.trim().parse::<u64>().ok()) {
+        return Some(std::time::Duration::from_secs(seconds));
+    }
+    if header("x-ratelimit-remaining").as_deref() == Some("0") {
+        let reset = header("x-ratelimit-reset").and_then(|s| s.trim().parse::<i64>().ok())?;
+        let seconds = (reset - now.timestamp()).max(1);
+        return Some(std::time::Duration::from_secs(seconds as u64));
+    }
+    body.to_ascii_lowercase()
+        .contains("secondary rate limit")
+        .then_some(SECONDARY_RATE_LIMIT_WAIT)
+}
 
-            if version.chars().all(|c| c.is_ascii_digit() || c == '.')
-                && (build.is_empty() || build.chars().any(|c| c.is_ascii_digit()))
+impl ReleaseProvider for Github {
+    async fn query_releases(
+        &self,
+        package: &crate::config_file::Package,
+    ) -> crate::Result<(octocrab::models::Repository, Releases)> {
+        self.fetch_releases(package)
+            .await
+            .map_err(crate::Error::Github)
+    }
+
+    async fn has_attestation(
+        &self,
+        repository: &crate::types::Repository,
+        digest: &str,
+    ) -> crate::Result<bool> {
+        #[derive(serde::Deserialize)]
+        struct Attestations {
+            attestations: Vec<serde::de::IgnoredAny>,
+        }
+
+        let uri = format!(
+            "/repos/{}/{}/attestations/{digest}",
+            repository.owner, repository.repo
+        );
+        match self.get_page::<Attestations>(&uri).await {
+            Ok((found, _)) => Ok(!found.attestations.is_empty()),
+            Err(e)
+                if e.downcast_ref::<StatusError>()
+                    .is_some_and(|e| e.status == 404) =>
             {
-                let build_number: u32 = build.parse().unwrap_or(0);
-                releases_result.push((release, (version, build_number)));
-            } else {
-                eprintln!("Invalid version when looking at {package_name}: {version} ({build})");
-                continue;
+                Ok(false)
             }
+            Err(e) => Err(crate::Error::Github(e)),
+        }
+    }
+}
+
+impl Github {
+    async fn fetch_releases(
+        &self,
+        package: &crate::config_file::Package,
+    ) -> anyhow::Result<(octocrab::models::Repository, Releases)> {
+        let repository = &package.repository;
+        let path = format!("/repos/{}/{}", repository.owner, repository.repo);
+
+        tracing::debug!("GH: querying {}/{}", repository.owner, repository.repo);
+
+        let ((repo_result, _), mut releases) = tokio::try_join!(
+            async {
+                self.get_page::<octocrab::models::Repository>(&path)
+                    .await
+                    .context("Failed to get repository data")
+            },
+            self.list_releases(package, &path),
+        )?;
+        if package
+            .build_string
+            .as_deref()
+            .is_some_and(|b| b.contains("{shortsha}"))
+        {
+            self.resolve_tag_commits(&path, &mut releases).await?;
         }
 
+        let releases_result = releases
+            .into_iter()
+            .filter_map(|release| {
+                let version = release_version(&release, package)?;
+                Some((release, version))
+            })
+            .collect();
+
         Ok((repo_result, releases_result))
     }
+
+    /// Point `target_commitish` of `releases` at the commit their tag is on
+    ///
+    /// GitHub fills it with the branch the release was created from, build
+    /// strings with `{shortsha}` need the commit.
+    async fn resolve_tag_commits(
+        &self,
+        path: &str,
+        releases: &mut [octocrab::models::repos::Release],
+    ) -> anyhow::Result<()> {
+        let commits = self
+            .get_all::<octocrab::models::repos::Tag>(format!("{path}/tags?per_page=100"))
+            .await
+            .context("Failed to retrieve list of tags")?
+            .into_iter()
+            .map(|tag| (tag.name, tag.commit.sha))
+            .collect::<std::collections::HashMap<_, _>>();
+        for release in releases {
+            if let Some(sha) = commits.get(&release.tag_name) {
+                release.target_commitish = sha.clone();
+            }
+        }
+        Ok(())
+    }
+
+    /// All releases of `package`, up to its `max_releases`
+    ///
+    /// With a release cache, pages that did not change since the last run
+    /// come from there.
+    async fn list_releases(
+        &self,
+        package: &crate::config_file::Package,
+        path: &str,
+    ) -> anyhow::Result<Vec<octocrab::models::repos::Release>> {
+        let file = self
+            .release_cache
+            .as_deref()
+            .map(|dir| cache_file(dir, &package.repository));
+        let cached = file
+            .as_deref()
+            .map(|f| cached_releases(f, package.max_releases))
+            .unwrap_or_default();
+
+        let per_page = package
+            .max_releases
+            .map_or(RELEASES_PER_PAGE, |max| max.min(RELEASES_PER_PAGE));
+        // A page and how many pages there are, `None` for cached pages
+        let releases_page = |page: usize| {
+            let uri = format!("{path}/releases?per_page={per_page}&page={page}");
+            let cached = cached.pages.get(page - 1);
+            async move {
+                let etag = cached.and_then(|c| c.etag.as_deref());
+                match self
+                    .get_page_if_changed::<Vec<octocrab::models::repos::Release>>(&uri, etag)
+                    .await
+                    .context("Failed to retrieve list of releases")?
+                {
+                    Some((releases, link, etag)) => Ok((
+                        CachedPage { etag, releases },
+                        Some(link.as_deref().and_then(page_count).unwrap_or(1)),
+                    )),
+                    None => Ok::<_, anyhow::Error>((cached.cloned().unwrap_or_default(), None)),
+                }
+            }
+        };
+
+        let releases = |pages: &[CachedPage]| {
+            let mut releases = pages
+                .iter()
+                .flat_map(|p| p.releases.iter().cloned())
+                .collect::<Vec<_>>();
+            if let Some(max) = package.max_releases {
+                releases.truncate(max);
+            }
+            releases
+        };
+
+        // An unchanged first page means there is no new release, so the
+        // cached pages are all still current.
+        let (first, pages) = releases_page(1).await?;
+        let Some(mut pages) = pages else {
+            tracing::debug!("GH: {path} has no new release");
+            return Ok(releases(&cached.pages));
+        };
+        if let Some(max) = package.max_releases {
+            pages = pages.min(max.div_ceil(per_page));
+        }
+
+        // The first page tells how many there are. Without a new release
+        // the others are taken from the cache, otherwise they are fetched
+        // all at once.
+        let latest_release = first.releases.first().map(|r| r.id);
+        let mut all_pages = vec![first];
+        if latest_release.is_some()
+            && latest_release == cached.latest_release
+            && cached.pages.len() == pages
+        {
+            all_pages.extend(cached.pages[1..].iter().cloned());
+        } else {
+            for (page, _) in futures::future::try_join_all((2..=pages).map(releases_page)).await? {
+                all_pages.push(page);
+            }
+        }
+
+        let releases = releases(&all_pages);
+        if let Some(file) = file {
+            let cached = CachedReleases {
+                max_releases: package.max_releases,
+                latest_release,
+                pages: all_pages,
+            };
+            if let Err(e) = write_cache(&file, &cached) {
+                tracing::warn!("{e:#}");
+            }
+        }
+        Ok(releases)
+    }
+}
+
+/// The version `release` gets packaged as, `None` for drafts and for
+/// pre-releases `package` does not ask for
+pub fn release_version(
+    release: &octocrab::models::repos::Release,
+    package: &crate::config_file::Package,
+) -> Option<crate::version::TagVersion> {
+    let package_name = &package.name;
+    let tag = &release.tag_name;
+    if release.draft {
+        tracing::debug!("Skipping draft release {tag} of {package_name}");
+        return None;
+    }
+    if release.prerelease && !package.prereleases {
+        tracing::debug!("Skipping pre-release {tag} of {package_name}");
+        return None;
+    }
+
+    let version = crate::version::version_from_tag(tag, package);
+    if let Err(reason) = &version {
+        tracing::debug!("Rejected tag of {package_name}: {reason}");
+    }
+    Some(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit_wait() {
+        let now = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let headers = |pairs: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                pairs
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        let secs = std::time::Duration::from_secs;
+
+        assert_eq!(
+            rate_limit_wait(403, headers(&[("retry-after", "30")]), "", now),
+            Some(secs(30))
+        );
+        assert_eq!(
+            rate_limit_wait(
+                403,
+                headers(&[
+                    ("x-ratelimit-remaining", "0"),
+                    ("x-ratelimit-reset", "1700000120")
+                ]),
+                "",
+                now
+            ),
+            Some(secs(120))
+        );
+        assert_eq!(
+            rate_limit_wait(
+                403,
+                headers(&[]),
+                r#"{"message": "You have exceeded a secondary rate limit."}"#,
+                now
+            ),
+            Some(SECONDARY_RATE_LIMIT_WAIT)
+        );
+        assert_eq!(
+            rate_limit_wait(403, headers(&[]), r#"{"message": "Forbidden"}"#, now),
+            None
+        );
+        assert_eq!(
+            rate_limit_wait(404, headers(&[("retry-after", "30")]), "", now),
+            None
+        );
+    }
+
+    #[test]
+    fn test_link() {
+        let link = "<https://api.github.com/repositories/1/releases?per_page=100&page=2>; rel=\"next\", <https://api.github.com/repositories/1/releases?per_page=100&page=5>; rel=\"last\"";
+        assert_eq!(
+            link_target(link, "next").as_deref(),
+            Some("https://api.github.com/repositories/1/releases?per_page=100&page=2")
+        );
+        assert_eq!(page_count(link), Some(5));
+
+        let link = "<https://api.github.com/repositories/1/releases?page=1>; rel=\"first\"";
+        assert_eq!(link_target(link, "next"), None);
+        assert_eq!(page_count(link), None);
+    }
+
+    #[test]
+    fn test_cached_pages() {
+        use crate::package_generation::tests::release_fixture;
+
+        let dir = tempfile::tempdir().unwrap();
+        let repository = crate::types::Repository::try_from("foo/bar").unwrap();
+        let file = cache_file(dir.path(), &repository);
+        assert!(cached_releases(&file, None).pages.is_empty());
+
+        write_cache(
+            &file,
+            &CachedReleases {
+                max_releases: None,
+                latest_release: Some(octocrab::models::ReleaseId(1)),
+                pages: vec![CachedPage {
+                    etag: Some("\"abc\"".to_string()),
+                    releases: vec![
+                        release_fixture("v2.0.0", &[]),
+                        release_fixture("v1.0.0", &["bar-linux-x86_64.tar.gz"]),
+                    ],
+                }],
+            },
+        )
+        .unwrap();
+
+        let cached = cached_releases(&file, None);
+        assert_eq!(cached.latest_release, Some(octocrab::models::ReleaseId(1)));
+        let pages = cached.pages;
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].etag.as_deref(), Some("\"abc\""));
+        assert_eq!(pages[0].releases.len(), 2);
+        assert_eq!(pages[0].releases[1].assets.len(), 1);
+
+        // Pages listed for another `max_releases` have another size
+        assert!(cached_releases(&file, Some(1)).pages.is_empty());
+
+        std::fs::write(&file, "not json").unwrap();
+        assert!(cached_releases(&file, None).pages.is_empty());
+    }
+
+    #[test]
+    fn test_pacer() {
+        let interval = std::time::Duration::from_millis(100);
+        let pacer = Pacer::new(interval);
+        let now = tokio::time::Instant::now();
+
+        assert_eq!(pacer.reserve(now), now);
+        assert_eq!(pacer.reserve(now), now + interval);
+        assert_eq!(pacer.reserve(now + interval * 5), now + interval * 5);
+
+        pacer.pause(std::time::Duration::from_secs(60));
+        assert!(pacer.reserve(now) >= now + std::time::Duration::from_secs(60));
+    }
 }
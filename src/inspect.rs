@@ -0,0 +1,378 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Look into release archives before generating recipes for them
+
+use std::{collections::HashMap, io::Read};
+
+use anyhow::Context as _;
+
+/// File name suffixes of executables on Windows, in lower case
+const EXECUTABLE_SUFFIXES: &[&str] = &[".exe", ".bat", ".com"];
+
+/// Name endings of self-updater executables, which must not end up in conda
+/// packages: conda updates them, not the tools themselves
+const UPDATER_SUFFIXES: &[&str] = &["-update", "-updater", "-upd"];
+/// Names of self-updater executables
+const UPDATER_NAMES: &[&str] = &["updater"];
+
+/// Whether the executable `name` looks like a self-updater
+pub fn is_updater(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    let name = name.strip_suffix(".exe").unwrap_or(&name);
+    UPDATER_NAMES.contains(&name) || UPDATER_SUFFIXES.iter().any(|s| name.ends_with(s))
+}
+
+/// The `executables` of the package `name` that are self-updaters to remove
+///
+/// The executable named like the package and the only executable are the
+/// tool itself, whatever their name says, e.g. `topgrade-update` in a
+/// `topgrade-update` package.
+pub fn updaters<'a>(executables: &'a [String], name: &str) -> Vec<&'a String> {
+    if executables.len() < 2 {
+        return vec![];
+    }
+    executables
+        .iter()
+        .filter(|e| {
+            let stem = e.to_ascii_lowercase();
+            let stem = stem.strip_suffix(".exe").unwrap_or(&stem);
+            stem != name.to_ascii_lowercase() && is_updater(e)
+        })
+        .collect()
+}
+
+/// Globs matching the names `is_updater` detects, without `.exe` suffix
+pub fn updater_globs() -> Vec<String> {
+    UPDATER_SUFFIXES
+        .iter()
+        .map(|s| format!("*{s}"))
+        .chain(UPDATER_NAMES.iter().map(|n| n.to_string()))
+        .collect()
+}
+
+/// Whether the build script would put the file at `path` into `bin`
+fn is_executable(path: &[&str], mode: Option<u32>) -> bool {
+    let in_bin = match path {
+        [_] => true,
+        [dir, _] => *dir == "bin",
+        _ => false,
+    };
+    let name = path.last().unwrap_or(&"").to_ascii_lowercase();
+    in_bin
+        && (mode.is_some_and(|m| m & 0o111 != 0)
+            || EXECUTABLE_SUFFIXES.iter().any(|s| name.ends_with(s)))
+}
+
+/// The names of the executables among `files`, given as path and mode
+///
+/// Leading directories shared by all files are dropped first, like the
+/// build script does.
+fn find_executables(files: &[(String, Option<u32>)]) -> Vec<String> {
+    let paths = files
+        .iter()
+        .map(|(path, mode)| {
+            let components = path
+                .split('/')
+                .filter(|c| !c.is_empty() && *c != ".")
+                .collect::<Vec<_>>();
+            (components, *mode)
+        })
+        .collect::<Vec<_>>();
+
+    let mut strip = 0;
+    while let Some((first, _)) = paths.first() {
+        let Some(dir) = first.get(strip) else {
+            break;
+        };
+        if *dir == "bin"
+            || !paths
+                .iter()
+                .all(|(p, _)| p.len() > strip + 1 && p[strip] == *dir)
+        {
+            break;
+        }
+        strip += 1;
+    }
+
+    let mut result = paths
+        .iter()
+        .filter(|(p, mode)| is_executable(&p[strip..], *mode))
+        .filter_map(|(p, _)| p.last().map(|n| n.to_string()))
+        .collect::<Vec<_>>();
+    result.sort();
+    result.dedup();
+    result
+}
+
+fn tar_files(archive: impl Read) -> anyhow::Result<Vec<(String, Option<u32>)>> {
+    let mut archive = tar::Archive::new(archive);
+    let mut result = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_file() {
+            let path = entry.path()?.to_string_lossy().to_string();
+            result.push((path, entry.header().mode().ok()));
+        }
+    }
+    Ok(result)
+}
+
+fn zip_files(content: &[u8]) -> anyhow::Result<Vec<(String, Option<u32>)>> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(content))?;
+    let mut result = Vec::new();
+    for index in 0..archive.len() {
+        let file = archive.by_index(index)?;
+        if file.is_file() {
+            result.push((file.name().to_string(), file.unix_mode()));
+        }
+    }
+    Ok(result)
+}
+
+/// The executables in the archive `asset_name` with `content`
+///
+/// Returns `None` for assets that are no archive octoconda can look into.
+pub fn executables(asset_name: &str, content: &[u8]) -> anyhow::Result<Option<Vec<String>>> {
+    let name = asset_name.to_ascii_lowercase();
+    let files = if name.ends_with(".zip") {
+        zip_files(content)?
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        tar_files(flate2::read::GzDecoder::new(content))?
+    } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+        tar_files(xz2::read::XzDecoder::new(content))?
+    } else if name.ends_with(".tar") {
+        tar_files(content)?
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(find_executables(&files)))
+}
+
+/// Largest asset looked into for packages without a `max_asset_size`
+pub const MAX_INSPECTED_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Download the file at `url`, failing once it gets larger than `max_size`
+/// bytes
+pub async fn download_limited(url: &url::Url, max_size: u64) -> anyhow::Result<Vec<u8>> {
+    let mut response = reqwest::get(url.clone())
+        .await
+        .and_then(|r| r.error_for_status())
+        .context(format!("Failed to download {url}"))?;
+    let too_large = || anyhow::anyhow!("{url} is larger than {max_size} bytes");
+    if response.content_length().is_some_and(|l| l > max_size) {
+        return Err(too_large());
+    }
+    let mut content = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .context(format!("Failed to download {url}"))?
+    {
+        if (content.len() + chunk.len()) as u64 > max_size {
+            return Err(too_large());
+        }
+        content.extend_from_slice(&chunk);
+    }
+    Ok(content)
+}
+
+/// What to find out about an asset before generating its recipe
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Inspection {
+    /// The executables in an archive
+    Executables,
+    /// The metadata of a python wheel
+    Wheel,
+    /// The asset digests listed in an attestation
+    Attestation,
+}
+
+/// What looking into release assets found, keyed by download URL
+///
+/// Failures are kept as message, to be reported for each recipe.
+#[derive(Debug, Default)]
+pub struct Inspections {
+    executables: HashMap<String, Result<Option<Vec<String>>, String>>,
+    wheels: HashMap<String, Result<crate::wheel::WheelMetadata, String>>,
+    attestations: HashMap<String, Result<HashMap<String, String>, String>>,
+}
+
+impl Inspections {
+    /// Download and look into `assets`, reading at most `max_size` bytes of
+    /// each
+    pub async fn new(
+        assets: &[(Inspection, octocrab::models::repos::Asset)],
+        max_size: u64,
+    ) -> Self {
+        let mut result = Self::default();
+        for (inspection, asset) in assets {
+            let url = asset.browser_download_url.to_string();
+            if result.executables.contains_key(&url)
+                || result.wheels.contains_key(&url)
+                || result.attestations.contains_key(&url)
+            {
+                continue;
+            }
+            tracing::debug!("Inspecting {}", asset.name);
+            let content = download_limited(&asset.browser_download_url, max_size).await;
+            let context = || format!("Failed to look into {}", asset.name);
+            match inspection {
+                Inspection::Executables => {
+                    let found = content
+                        .and_then(|c| executables(&asset.name, &c))
+                        .context(context())
+                        .map_err(|e| format!("{e:#}"));
+                    result.executables.insert(url, found);
+                }
+                Inspection::Wheel => {
+                    let found = content
+                        .and_then(|c| crate::wheel::metadata(&c))
+                        .context(context())
+                        .map_err(|e| format!("{e:#}"));
+                    result.wheels.insert(url, found);
+                }
+                Inspection::Attestation => {
+                    let found = content
+                        .and_then(|c| crate::attestation::subject_digests(&c))
+                        .context(context())
+                        .map_err(|e| format!("{e:#}"));
+                    result.attestations.insert(url, found);
+                }
+            }
+        }
+        result
+    }
+
+    /// The executables in `asset`, `None` if it is no archive
+    pub fn executables(
+        &self,
+        asset: &octocrab::models::repos::Asset,
+    ) -> anyhow::Result<Option<&[String]>> {
+        match self.executables.get(asset.browser_download_url.as_str()) {
+            Some(Ok(found)) => Ok(found.as_deref()),
+            Some(Err(e)) => Err(anyhow::anyhow!("{e}")),
+            None => Err(anyhow::anyhow!("{} was not inspected", asset.name)),
+        }
+    }
+
+    /// The metadata of the wheel `asset`
+    pub fn wheel(
+        &self,
+        asset: &octocrab::models::repos::Asset,
+    ) -> anyhow::Result<&crate::wheel::WheelMetadata> {
+        match self.wheels.get(asset.browser_download_url.as_str()) {
+            Some(Ok(wheel)) => Ok(wheel),
+            Some(Err(e)) => Err(anyhow::anyhow!("{e}")),
+            None => Err(anyhow::anyhow!("{} was not inspected", asset.name)),
+        }
+    }
+
+    /// The digests the attestation `asset` lists, keyed by subject name,
+    /// `None` if it was not looked into
+    pub fn subject_digests(
+        &self,
+        asset: &octocrab::models::repos::Asset,
+    ) -> Option<anyhow::Result<&HashMap<String, String>>> {
+        self.attestations
+            .get(asset.browser_download_url.as_str())
+            .map(|found| found.as_ref().map_err(|e| anyhow::anyhow!("{e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write as _;
+
+    use super::*;
+
+    fn tar_gz(files: &[(&str, u32)]) -> Vec<u8> {
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (path, mode) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            header.set_mode(*mode);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, &b"data"[..])
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn zip(files: &[(&str, u32)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (path, mode) in files {
+            let options = zip::write::SimpleFileOptions::default().unix_permissions(*mode);
+            writer.start_file(*path, options).unwrap();
+            writer.write_all(b"data").unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_tar_gz() {
+        let archive = tar_gz(&[
+            ("tool-1.0.0/tool", 0o755),
+            ("tool-1.0.0/tool-updater", 0o755),
+            ("tool-1.0.0/README.md", 0o644),
+            ("tool-1.0.0/completions/tool.bash", 0o755),
+        ]);
+        assert_eq!(
+            executables("tool-1.0.0-linux.tar.gz", &archive).unwrap(),
+            Some(vec!["tool".to_string(), "tool-updater".to_string()])
+        );
+
+        let archive = tar_gz(&[("bin/tool", 0o755), ("share/man/man1/tool.1", 0o644)]);
+        assert_eq!(
+            executables("tool.tgz", &archive).unwrap(),
+            Some(vec!["tool".to_string()])
+        );
+
+        assert_eq!(executables("tool-linux-x86_64", b"\x7fELF").unwrap(), None);
+        assert!(executables("tool.tar.gz", b"not an archive").is_err());
+    }
+
+    #[test]
+    fn test_is_updater() {
+        assert!(is_updater("atuin-update"));
+        assert!(is_updater("tool-updater.exe"));
+        assert!(is_updater("TOOL-UPD.EXE"));
+        assert!(is_updater("updater"));
+        assert!(!is_updater("atuin"));
+        assert!(!is_updater("update-notifier"));
+        assert!(!is_updater("tool-updates"));
+    }
+
+    #[test]
+    fn test_updaters() {
+        let executables = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            updaters(&executables(&["atuin", "atuin-update"]), "atuin"),
+            vec!["atuin-update"]
+        );
+        assert!(updaters(&executables(&["tool-update"]), "tool").is_empty());
+        assert!(updaters(&executables(&["updater"]), "updater").is_empty());
+        assert_eq!(
+            updaters(
+                &executables(&["pkg-update.exe", "pkg-updater.exe", "helper.exe"]),
+                "pkg-update"
+            ),
+            vec!["pkg-updater.exe"]
+        );
+    }
+
+    #[test]
+    fn test_zip() {
+        let archive = zip(&[
+            ("tool.exe", 0o644),
+            ("helper.exe", 0o644),
+            ("LICENSE", 0o644),
+        ]);
+        assert_eq!(
+            executables("tool-windows.zip", &archive).unwrap(),
+            Some(vec!["helper.exe".to_string(), "tool.exe".to_string()])
+        );
+    }
+}
@@ -0,0 +1,551 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Install built packages into throwaway environments and run them
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::{Path, PathBuf},
+    str::FromStr as _,
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use rattler_conda_types::{
+    Channel, ChannelConfig, MatchSpec, Matches as _, PackageName, PackageRecord, ParseStrictness,
+    Platform, PrefixRecord, RepoDataRecord, VersionWithSource,
+    package::{ArchiveIdentifier, IndexJson},
+};
+use rattler_repodata_gateway::Gateway;
+
+use crate::package_generation::Status;
+
+/// How long an executable may take to answer `--version` or `--help`
+const SMOKE_TEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn is_package_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.ends_with(".conda") || n.ends_with(".tar.bz2"))
+}
+
+fn collect_packages(path: &Path, result: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    if path.is_dir() {
+        let entries = std::fs::read_dir(path)
+            .context(format!("Failed to read directory {}", path.display()))?;
+        for entry in entries.filter_map(|e| e.ok()) {
+            collect_packages(&entry.path(), result)?;
+        }
+    } else if is_package_file(path) {
+        result.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+/// All package archives in `paths`, directories are searched recursively
+pub fn find_packages(paths: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut result = Vec::new();
+    for path in paths {
+        collect_packages(path, &mut result)?;
+    }
+    result.sort();
+    Ok(result)
+}
+
+/// A record to install `package_file` with
+///
+/// The subdir is taken from the directory the file is in, as rattler-build
+/// puts packages into `<output>/<platform>/`.
+fn record_for(package_file: &Path) -> anyhow::Result<RepoDataRecord> {
+    let file_name = package_file
+        .file_name()
+        .and_then(|n| n.to_str())
+        .context("Package file name is not valid UTF-8")?;
+    let identifier = ArchiveIdentifier::try_from_filename(file_name)
+        .context(format!("{file_name:?} is not a package file name"))?;
+    let subdir = package_file
+        .parent()
+        .and_then(|p| p.file_name())
+        .and_then(|n| n.to_str())
+        .and_then(|n| Platform::from_str(n).ok())
+        .unwrap_or(Platform::current());
+
+    let mut package_record = PackageRecord::new(
+        PackageName::try_from(identifier.name.as_str())?,
+        VersionWithSource::from_str(&identifier.version)?,
+        identifier.build_string,
+    );
+    package_record.subdir = subdir.to_string();
+
+    let path = std::path::absolute(package_file)?;
+    Ok(RepoDataRecord {
+        package_record,
+        file_name: file_name.to_string(),
+        url: url::Url::from_file_path(&path)
+            .map_err(|_| anyhow::anyhow!("Can not turn {} into a URL", path.display()))?,
+        channel: None,
+    })
+}
+
+/// `record` with the requirements and noarch type from the index of
+/// `package_file`
+fn with_index(mut record: RepoDataRecord, package_file: &Path) -> anyhow::Result<RepoDataRecord> {
+    let index: IndexJson = rattler_package_streaming::seek::read_package_file(package_file)
+        .context("Failed to read info/index.json of the package")?;
+    record.package_record.depends = index.depends;
+    record.package_record.noarch = index.noarch;
+    Ok(record)
+}
+
+/// Where the run dependencies of tested packages come from
+pub struct Dependencies {
+    gateway: Gateway,
+    channels: Vec<Channel>,
+}
+
+impl Dependencies {
+    pub fn new(channels: &[String]) -> anyhow::Result<Self> {
+        let config = ChannelConfig::default_with_root_dir(PathBuf::from("."));
+        let channels = channels
+            .iter()
+            .map(|c| Channel::from_str(c, &config).context(format!("Invalid channel {c:?}")))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self {
+            gateway: Gateway::new(),
+            channels,
+        })
+    }
+
+    /// The records to install next to `package`
+    async fn resolve(&self, package: &PackageRecord) -> anyhow::Result<Vec<RepoDataRecord>> {
+        let specs = requirements(&package.depends)?;
+        if specs.is_empty() {
+            return Ok(vec![]);
+        }
+        let candidates = self
+            .gateway
+            .query(
+                self.channels.clone(),
+                [Platform::current(), Platform::NoArch],
+                specs,
+            )
+            .recursive(true)
+            .await
+            .context("Failed to query the run dependencies")?
+            .iter()
+            .flat_map(|repo_data| repo_data.iter().cloned())
+            .collect::<Vec<_>>();
+        pick_dependencies(package, &candidates)
+    }
+}
+
+/// The match specs of `depends`, without virtual packages like `__glibc`,
+/// which are up to the host
+fn requirements(depends: &[String]) -> anyhow::Result<Vec<MatchSpec>> {
+    depends
+        .iter()
+        .map(|d| {
+            MatchSpec::from_str(d, ParseStrictness::Lenient)
+                .context(format!("Invalid requirement {d:?}"))
+        })
+        .filter(|spec| {
+            spec.as_ref().map_or(true, |s| {
+                s.name
+                    .as_ref()
+                    .is_some_and(|n| !n.as_normalized().starts_with("__"))
+            })
+        })
+        .collect()
+}
+
+/// The records satisfying the run requirements of `package`, recursively,
+/// picked from `candidates`
+///
+/// There is no solver: The newest candidate matching a requirement wins and
+/// requirements it conflicts with fail the test. That is good enough for the
+/// few dependencies of repackaged tools.
+fn pick_dependencies(
+    package: &PackageRecord,
+    candidates: &[RepoDataRecord],
+) -> anyhow::Result<Vec<RepoDataRecord>> {
+    let mut picked = BTreeMap::<String, &RepoDataRecord>::new();
+    let mut queue = VecDeque::from(requirements(&package.depends)?);
+    while let Some(spec) = queue.pop_front() {
+        let Some(name) = spec.name.as_ref().map(|n| n.as_normalized().to_string()) else {
+            continue;
+        };
+        if let Some(record) = picked.get(&name) {
+            if !spec.matches(*record) {
+                return Err(anyhow::anyhow!(
+                    "{name} {} does not satisfy {spec}",
+                    record.package_record.version
+                ));
+            }
+            continue;
+        }
+        let record = candidates
+            .iter()
+            .filter(|r| spec.matches(*r))
+            .max_by(|a, b| {
+                (&a.package_record.version, a.package_record.build_number)
+                    .cmp(&(&b.package_record.version, b.package_record.build_number))
+            })
+            .ok_or_else(|| anyhow::anyhow!("Nothing provides {spec}"))?;
+        queue.extend(requirements(&record.package_record.depends)?);
+        picked.insert(name, record);
+    }
+    Ok(picked.into_values().cloned().collect())
+}
+
+/// What the files a package installed can be checked with
+#[derive(Debug, PartialEq)]
+enum Check {
+    /// Run one of the executables in the [`executable_dirs`]
+    Executables,
+    /// Import these python modules
+    PythonModules(Vec<String>),
+    /// Nothing to run, the package installed this many files
+    Files(usize),
+}
+
+/// The directories packages for `platform` install executables into
+fn executable_dirs(platform: Platform) -> &'static [&'static str] {
+    if platform.is_windows() {
+        &["bin", "Library/bin", "Scripts"]
+    } else {
+        &["bin"]
+    }
+}
+
+fn check_for(files: &[PathBuf], platform: Platform) -> Check {
+    let dirs = executable_dirs(platform);
+    if files.iter().any(|f| dirs.iter().any(|d| f.starts_with(d))) {
+        return Check::Executables;
+    }
+
+    let mut modules = files
+        .iter()
+        .filter_map(|f| {
+            let mut components = f.components().map(|c| c.as_os_str().to_str());
+            components.find(|c| *c == Some("site-packages"))?;
+            let module = components.next()??;
+            // Files directly in site-packages are modules themselves
+            components
+                .next()
+                .is_some()
+                .then_some(module)
+                .or_else(|| module.strip_suffix(".py"))
+        })
+        .filter(|m| !m.starts_with('_') && !m.contains('.') && !m.contains('-'))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    modules.sort();
+    modules.dedup();
+    if modules.is_empty() {
+        Check::Files(files.len())
+    } else {
+        Check::PythonModules(modules)
+    }
+}
+
+/// Import the python `modules` with the interpreter in `prefix`
+async fn import_test(prefix: &Path, modules: &[String]) -> anyhow::Result<String> {
+    let python = if cfg!(windows) {
+        prefix.join("python.exe")
+    } else {
+        prefix.join("bin").join("python")
+    };
+    let statement = format!("import {}", modules.join(", "));
+    let run = tokio::process::Command::new(&python)
+        .arg("-c")
+        .arg(&statement)
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(SMOKE_TEST_TIMEOUT, run).await {
+        Ok(Ok(output)) if output.status.success() => Ok(format!("`{statement}` works")),
+        Ok(Ok(output)) => Err(anyhow::anyhow!(
+            "`{statement}` fails: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Ok(Err(e)) => Err(anyhow::anyhow!("Failed to run {}: {e}", python.display())),
+        Err(_) => Err(anyhow::anyhow!("`{statement}` timed out")),
+    }
+}
+
+/// The executables the package installed into the [`executable_dirs`] of
+/// `prefix`, the one called `name` first
+///
+/// `files` are the files the package installed. Executables of its
+/// dependencies are left out, they say nothing about the package.
+fn executables(prefix: &Path, files: &[PathBuf], name: &str, platform: Platform) -> Vec<PathBuf> {
+    let dirs = executable_dirs(platform);
+    let mut result = files
+        .iter()
+        .filter(|f| {
+            f.parent()
+                .is_some_and(|p| dirs.iter().any(|d| p == Path::new(d)))
+        })
+        .map(|f| prefix.join(f))
+        .filter(|p| p.is_file())
+        .collect::<Vec<_>>();
+    result.sort_by_key(|p| {
+        let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+        (stem != name, p.clone())
+    });
+    result
+}
+
+/// Run the executables of `name` in `prefix` with `--version` or `--help`
+/// until one of them succeeds
+async fn smoke_test(
+    prefix: &Path,
+    files: &[PathBuf],
+    name: &str,
+    platform: Platform,
+) -> anyhow::Result<String> {
+    let dirs = executable_dirs(platform).join(", ");
+    let executables = executables(prefix, files, name, platform);
+    if executables.is_empty() {
+        return Err(anyhow::anyhow!("No executables installed into {dirs}"));
+    }
+
+    for executable in &executables {
+        for argument in ["--version", "--help"] {
+            let run = tokio::process::Command::new(executable)
+                .arg(argument)
+                .stdin(std::process::Stdio::null())
+                .kill_on_drop(true)
+                .output();
+            match tokio::time::timeout(SMOKE_TEST_TIMEOUT, run).await {
+                Ok(Ok(output)) if output.status.success() => {
+                    let executable = executable.file_name().unwrap_or_default().to_string_lossy();
+                    return Ok(format!("`{executable} {argument}` works"));
+                }
+                Ok(Ok(_)) | Err(_) => {}
+                Ok(Err(e)) => {
+                    tracing::debug!("Failed to run {}: {e}", executable.display());
+                }
+            }
+        }
+    }
+    Err(anyhow::anyhow!(
+        "None of the {} executables in {dirs} runs with --version or --help",
+        executables.len()
+    ))
+}
+
+async fn install_and_run(
+    record: RepoDataRecord,
+    dependencies: &Dependencies,
+) -> anyhow::Result<String> {
+    let prefix = tempfile::Builder::new()
+        .prefix("octoconda-install-test.")
+        .tempdir()
+        .context("Failed to create a temporary environment")?;
+    let name = record.package_record.name.as_normalized().to_string();
+
+    let mut records = dependencies.resolve(&record.package_record).await?;
+    records.push(record);
+    rattler::install::Installer::new()
+        .with_target_platform(Platform::current())
+        .install(prefix.path(), records)
+        .await
+        .context("Failed to install the package")?;
+
+    let files = PrefixRecord::collect_from_prefix::<PrefixRecord>(prefix.path())
+        .context("Failed to read the installed packages")?
+        .into_iter()
+        .find(|r| r.repodata_record.package_record.name.as_normalized() == name)
+        .map(|r| r.files)
+        .unwrap_or_default();
+    match check_for(&files, Platform::current()) {
+        Check::Executables => smoke_test(prefix.path(), &files, &name, Platform::current()).await,
+        Check::PythonModules(modules) => import_test(prefix.path(), &modules).await,
+        Check::Files(0) => Err(anyhow::anyhow!("The package installed no files")),
+        Check::Files(count) => Ok(format!("installed {count} files, nothing to run")),
+    }
+}
+
+/// Install `package_file` with its run dependencies into a throwaway
+/// environment and check it
+///
+/// Packages with executables in `bin/` (or `Library/bin/` and `Scripts/` on
+/// Windows) get one of them run, python packages
+/// get their modules imported. Packages for other platforms than the host
+/// are skipped.
+pub async fn install_test(package_file: &Path, dependencies: &Dependencies) -> (Status, String) {
+    let record = match record_for(package_file).and_then(|r| with_index(r, package_file)) {
+        Ok(record) => record,
+        Err(e) => return (Status::Failed, format!("{e:#}")),
+    };
+    let host = Platform::current().to_string();
+    if record.package_record.subdir != host && record.package_record.subdir != "noarch" {
+        return (
+            Status::Skipped,
+            format!("built for {}, not {host}", record.package_record.subdir),
+        );
+    }
+
+    match install_and_run(record, dependencies).await {
+        Ok(message) => (Status::Succeeded, message),
+        Err(e) => (Status::Failed, format!("{e:#}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::conda::tests::record_fixture;
+
+    #[test]
+    fn test_find_packages() {
+        let output = tempfile::tempdir().unwrap();
+        let platform_dir = output.path().join("linux-64");
+        std::fs::create_dir_all(&platform_dir).unwrap();
+        for file in ["bar-1.0.0-h1234_0.conda", "baz-2.0-0.tar.bz2", "notes.txt"] {
+            std::fs::write(platform_dir.join(file), "").unwrap();
+        }
+
+        let packages = find_packages(&[output.path().to_path_buf()]).unwrap();
+        assert_eq!(
+            packages,
+            vec![
+                platform_dir.join("bar-1.0.0-h1234_0.conda"),
+                platform_dir.join("baz-2.0-0.tar.bz2"),
+            ]
+        );
+
+        let record = record_for(&packages[0]).unwrap();
+        assert_eq!(record.package_record.name.as_normalized(), "bar");
+        assert_eq!(record.package_record.version.to_string(), "1.0.0");
+        assert_eq!(record.package_record.build, "h1234_0");
+        assert_eq!(record.package_record.subdir, "linux-64");
+        assert_eq!(record.url.scheme(), "file");
+    }
+
+    fn record(name: &str, version: &str, depends: &[&str]) -> RepoDataRecord {
+        let mut record = record_fixture(name, version, "linux-64");
+        record.package_record.depends = depends.iter().map(|d| d.to_string()).collect();
+        record
+    }
+
+    #[test]
+    fn test_pick_dependencies() {
+        let candidates = [
+            record("python", "3.12.1", &["libzlib >=1.3"]),
+            record("python", "3.13.0", &["libzlib >=1.3"]),
+            record("libzlib", "1.3.1", &[]),
+            record("nodejs", "22.0.0", &[]),
+        ];
+        let versions = |package: &RepoDataRecord| {
+            pick_dependencies(&package.package_record, &candidates).map(|records| {
+                records
+                    .iter()
+                    .map(|r| format!("{}", r.package_record.version))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(
+            versions(&record(
+                "tool",
+                "1.0",
+                &["python >=3.10,<3.13", "__glibc >=2.17"]
+            ))
+            .unwrap(),
+            vec!["1.3.1", "3.12.1"]
+        );
+        assert_eq!(
+            versions(&record("tool", "1.0", &["python", "nodejs"])).unwrap(),
+            vec!["1.3.1", "22.0.0", "3.13.0"]
+        );
+        assert!(versions(&record("tool", "1.0", &["python", "python <3.13"])).is_err());
+        assert!(versions(&record("tool", "1.0", &["ruby"])).is_err());
+        assert!(versions(&record("tool", "1.0", &[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_check_for() {
+        let files = |files: &[&str]| files.iter().map(PathBuf::from).collect::<Vec<_>>();
+        assert_eq!(
+            check_for(
+                &files(&["bin/tool", "share/man/man1/tool.1"]),
+                Platform::Linux64
+            ),
+            Check::Executables
+        );
+        assert_eq!(
+            check_for(
+                &files(&[
+                    "lib/python3.12/site-packages/foo/__init__.py",
+                    "lib/python3.12/site-packages/foo/bar.py",
+                    "lib/python3.12/site-packages/foo-1.0.dist-info/METADATA",
+                    "lib/python3.12/site-packages/single.py",
+                    "lib/python3.12/site-packages/__pycache__/single.cpython-312.pyc",
+                ],),
+                Platform::Linux64
+            ),
+            Check::PythonModules(vec!["foo".to_string(), "single".to_string()])
+        );
+        assert_eq!(
+            check_for(
+                &files(&["share/fonts/font.ttf", "etc/config"]),
+                Platform::Linux64
+            ),
+            Check::Files(2)
+        );
+        assert_eq!(check_for(&[], Platform::Linux64), Check::Files(0));
+
+        let windows = files(&["Library/bin/tool.exe", "Scripts/tool-script.py"]);
+        assert_eq!(check_for(&windows, Platform::Win64), Check::Executables);
+        assert_eq!(check_for(&windows, Platform::Linux64), Check::Files(2));
+    }
+
+    #[test]
+    fn test_executables() {
+        let prefix = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(prefix.path().join("bin")).unwrap();
+        // `node` comes from a dependency
+        for file in ["another", "bar", "zzz", "node"] {
+            std::fs::write(prefix.path().join("bin").join(file), "").unwrap();
+        }
+        let files = [
+            "bin/another",
+            "bin/bar",
+            "bin/zzz",
+            "bin/missing",
+            "lib/bar.so",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+        let names = |files: &[PathBuf], platform| {
+            executables(prefix.path(), files, "bar", platform)
+                .iter()
+                .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&files, Platform::Linux64),
+            vec!["bar", "another", "zzz"]
+        );
+        assert!(names(&[], Platform::Linux64).is_empty());
+
+        for dir in ["Library/bin", "Scripts"] {
+            std::fs::create_dir_all(prefix.path().join(dir)).unwrap();
+        }
+        std::fs::write(prefix.path().join("Library/bin/bar.exe"), "").unwrap();
+        std::fs::write(prefix.path().join("Scripts/bar-cli.exe"), "").unwrap();
+        let files = ["Library/bin/bar.exe", "Scripts/bar-cli.exe", "bin/zzz"]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names(&files, Platform::Win64),
+            vec!["bar.exe", "bar-cli.exe", "zzz"]
+        );
+        assert_eq!(names(&files, Platform::Linux64), vec!["zzz"]);
+    }
+}
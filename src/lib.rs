@@ -0,0 +1,820 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Generate conda recipes from binaries attached to Github releases.
+//!
+//! [`run`] drives the whole pipeline: It checks the conda channel for
+//! existing packages, asks a [`ReleaseProvider`] for releases and lets a
+//! [`RecipeGenerator`] write recipes for everything that is missing. The
+//! outcome is collected in a [`Report`].
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::Instant,
+};
+
+use tracing::Instrument as _;
+
+pub mod action_log;
+pub mod activation;
+pub mod add;
+pub mod attestation;
+pub mod clean;
+pub mod conda;
+pub mod config_file;
+pub mod credentials;
+pub mod discover;
+pub mod error;
+pub mod fixtures;
+pub mod github;
+pub mod inspect;
+pub mod install_test;
+pub mod metrics;
+pub mod package_generation;
+pub mod policy;
+pub mod recipe;
+pub mod report;
+pub mod state;
+pub mod stats;
+pub mod sync;
+pub mod types;
+pub mod version;
+pub mod webhook;
+pub mod wheel;
+
+pub use config_file::Config;
+pub use error::{Error, Result};
+pub use github::ReleaseProvider;
+pub use package_generation::RecipeGenerator;
+pub use report::Report;
+
+use package_generation::{PackagingStatus, Status, VersionPackagingStatus};
+
+/// Maximum number of recipes generated in one run
+pub const PACKAGE_GENERATION_LIMIT: usize = 500;
+
+/// How often to retry transient failures to retrieve releases
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt failed
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every further retry
+    pub initial_delay: std::time::Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_delay: std::time::Duration::from_secs(1),
+        }
+    }
+}
+
+/// Settings for [`run`]
+#[derive(Clone, Debug, Default)]
+pub struct RunOptions {
+    /// Give up on a package, or on one of its sources, if retrieving its
+    /// releases takes longer
+    pub timeout_per_package: Option<std::time::Duration>,
+    /// Stop processing packages after the first failed one
+    pub fail_fast: bool,
+    pub retry_policy: RetryPolicy,
+    /// Do not query the channel for existing packages
+    pub ignore_channel: bool,
+    /// Fetch channel repodata even if the cached copy is still fresh
+    pub refresh_channel: bool,
+    /// Generate recipes even if the channel could not be checked for
+    /// existing packages
+    pub ignore_channel_errors: bool,
+    /// Ignore releases published before this
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    /// Record how long the stages of the run take in the report
+    pub timings: bool,
+    /// Only look at the release with this tag
+    pub tag: Option<String>,
+    /// Record the channel responses, or replay recorded ones
+    pub fixtures: Option<fixtures::Mode>,
+}
+
+async fn query_releases_with_retries(
+    releases: &impl ReleaseProvider,
+    package: &config_file::Package,
+    policy: &RetryPolicy,
+) -> Result<(octocrab::models::Repository, github::Releases)> {
+    let mut delay = policy.initial_delay;
+    let mut retries = 0;
+    loop {
+        match releases.query_releases(package).await {
+            Ok(result) => return Ok(result),
+            Err(e) if e.is_transient() && retries < policy.max_retries => {
+                retries += 1;
+                tracing::warn!(
+                    "{e}: Retrying in {}ms ({retries}/{})",
+                    delay.as_millis(),
+                    policy.max_retries
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The repository metadata and releases of a package
+type SourceReleases = (octocrab::models::Repository, github::Releases);
+
+async fn query_source(
+    releases: &impl ReleaseProvider,
+    package: &config_file::Package,
+    source: Option<&config_file::Source>,
+    index: usize,
+    options: &RunOptions,
+) -> Result<SourceReleases> {
+    let policy = &options.retry_policy;
+    match source {
+        None => query_releases_with_retries(releases, package, policy).await,
+        Some(config_file::Source::Github(repository)) => {
+            let mirror = config_file::Package {
+                repository: repository.clone(),
+                ..package.clone()
+            };
+            query_releases_with_retries(releases, &mirror, policy).await
+        }
+        Some(config_file::Source::Recorded { releases, assets }) => {
+            let remote = fixtures::Remote::new(releases, assets.clone());
+            fixtures::recorded(
+                options.fixtures.as_ref(),
+                &format!("sources/{}-{index}", package.name),
+                Error::Github,
+                query_releases_with_retries(&remote, package, policy),
+            )
+            .await
+        }
+    }
+}
+
+/// The releases of `package` from its repository, or from the first of its
+/// `sources` that has any assets if the repository fails
+///
+/// Returns the fallback source that was used, if any. The releases are
+/// `None` if the last source tried timed out.
+async fn query_sources<'a>(
+    releases: &impl ReleaseProvider,
+    package: &'a config_file::Package,
+    options: &RunOptions,
+) -> (
+    Option<&'a config_file::Source>,
+    Option<Result<SourceReleases>>,
+) {
+    let sources = std::iter::once(None)
+        .chain(package.sources.iter().map(Some))
+        .collect::<Vec<_>>();
+    let mut last = (None, None);
+    for (index, source) in sources.iter().copied().enumerate() {
+        let query = query_source(releases, package, source, index, options);
+        let result = match options.timeout_per_package {
+            Some(timeout) => tokio::time::timeout(timeout, query).await.ok(),
+            None => Some(query.await),
+        };
+        let name = source.map_or_else(|| "its repository".to_string(), |s| s.to_string());
+        match &result {
+            Some(Ok((_, r))) if r.iter().any(|(r, _)| !r.assets.is_empty()) => {
+                return (source, result);
+            }
+            _ if sources.len() == 1 => {}
+            Some(Ok(_)) => tracing::warn!("{}: No assets in {name}", package.name),
+            Some(Err(e)) => tracing::warn!("{}: {name} failed: {e}", package.name),
+            None => tracing::warn!("{}: Timed out querying {name}", package.name),
+        }
+        // Releases without assets beat errors of later sources
+        if !matches!(last, (_, Some(Ok(_)))) {
+            last = (source, result);
+        }
+    }
+    last
+}
+
+fn has_failures(status: &[VersionPackagingStatus]) -> bool {
+    status
+        .iter()
+        .flat_map(|v| v.status.iter())
+        .any(|s| s.status == Status::Failed)
+}
+
+/// Generate recipes for all packages in `config`
+pub async fn run(
+    config: &Config,
+    releases: &impl ReleaseProvider,
+    generator: &RecipeGenerator,
+    options: &RunOptions,
+) -> Result<Report> {
+    let mut result = Report::default();
+    if options.timings {
+        result.timings = Some(vec![]);
+    }
+    result.notify = config
+        .packages
+        .iter()
+        .filter(|p| !p.notify.is_empty())
+        .map(|p| (p.name.clone(), p.notify.clone()))
+        .collect();
+
+    let repodata_cache = conda::RepodataCache::new(config, options.refresh_channel);
+    let mut repo_packages = if options.ignore_channel {
+        tracing::info!("Conda: Ignoring channel information");
+        vec![]
+    } else {
+        let start = Instant::now();
+        let channel = config.conda.full_channel().map_err(Error::Config)?;
+        let platforms = config.all_platforms().into_iter().collect::<Vec<_>>();
+        let names = config
+            .packages
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect::<Vec<_>>();
+        let packages = match fixtures::recorded(
+            options.fixtures.as_ref(),
+            "channel",
+            Error::Channel,
+            async {
+                let packages = conda::get_channel_index(
+                    &repodata_cache.gateway(&channel, &platforms, &names)?,
+                    &channel,
+                    platforms.iter().copied(),
+                    names.iter().copied(),
+                )
+                .await?;
+                repodata_cache.fetched(&channel, &platforms, &names);
+                Ok(packages)
+            },
+        )
+        .await
+        {
+            Ok(packages) => packages,
+            Err(e) => {
+                tracing::error!("Conda: Failed to check {channel}: {e}");
+                result.channel_errors.push(format!("{channel}: {e}"));
+                vec![]
+            }
+        };
+        tracing::info!("Conda: Channel information collected");
+        result.record_timing("conda channel", None, start.elapsed());
+        packages
+    };
+
+    // Quarantined packages in the quarantine channel are built already
+    let quarantined = config
+        .packages
+        .iter()
+        .filter(|p| p.quarantine)
+        .map(|p| p.name.as_str())
+        .collect::<Vec<_>>();
+    let quarantine = config
+        .conda
+        .quarantine()
+        .filter(|_| !options.ignore_channel && !quarantined.is_empty());
+    if let Some(quarantine) = quarantine {
+        let channel = quarantine.full_channel().map_err(Error::Config)?;
+        let platforms = config.all_platforms().into_iter().collect::<Vec<_>>();
+        match fixtures::recorded(
+            options.fixtures.as_ref(),
+            "quarantine-channel",
+            Error::Channel,
+            async {
+                let packages = conda::get_channel_index(
+                    &repodata_cache.gateway(&channel, &platforms, &quarantined)?,
+                    &channel,
+                    platforms.iter().copied(),
+                    quarantined.iter().copied(),
+                )
+                .await?;
+                repodata_cache.fetched(&channel, &platforms, &quarantined);
+                Ok(packages)
+            },
+        )
+        .await
+        {
+            Ok(packages) => repo_packages.extend(packages),
+            Err(e) => {
+                tracing::error!("Conda: Failed to check {channel}: {e}");
+                result.channel_errors.push(format!("{channel}: {e}"));
+            }
+        }
+    }
+
+    let conda_forge_packages =
+        if config.conda.conda_forge_check == config_file::CondaForgePolicy::Ignore {
+            HashSet::new()
+        } else {
+            let start = Instant::now();
+            let platforms = config
+                .all_platforms()
+                .into_iter()
+                .chain(std::iter::once(rattler_conda_types::Platform::NoArch))
+                .collect::<Vec<_>>();
+            let names = config
+                .packages
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>();
+            let packages = match fixtures::recorded(
+                options.fixtures.as_ref(),
+                "conda-forge",
+                Error::Channel,
+                async {
+                    let packages = conda::get_conda_package_names(
+                        &repodata_cache.gateway("conda-forge", &platforms, &names)?,
+                        "conda-forge",
+                        platforms.iter().copied(),
+                        names.iter().copied(),
+                    )
+                    .await?;
+                    repodata_cache.fetched("conda-forge", &platforms, &names);
+                    Ok(packages)
+                },
+            )
+            .await
+            {
+                Ok(packages) => packages,
+                Err(e) => {
+                    tracing::error!("Conda: Failed to check conda-forge: {e}");
+                    result.channel_errors.push(format!("conda-forge: {e}"));
+                    HashSet::new()
+                }
+            };
+            tracing::info!("Conda: conda-forge information collected");
+            result.record_timing("conda-forge", None, start.elapsed());
+            packages
+        };
+
+    if !result.channel_errors.is_empty() && !options.ignore_channel_errors {
+        // Without knowing what is in the channel already, recipes for
+        // everything would be generated
+        let error = result.channel_errors.join(", ");
+        for package in &config.packages {
+            let status = if package.disabled {
+                vec![PackagingStatus::disabled()]
+            } else {
+                PackagingStatus::channel_unavailable(&error)
+            };
+            result.insert(
+                package.name.clone(),
+                vec![VersionPackagingStatus {
+                    version: None,
+                    found_platforms: vec![],
+                    status,
+                }],
+            );
+        }
+        return Ok(result);
+    }
+
+    let mut package_count = 0;
+
+    for package in &config.packages {
+        let repo_packages = &repo_packages;
+        let span = tracing::info_span!("package", name = %package.name);
+
+        if package.disabled {
+            tracing::info!("{}: Disabled", package.name);
+            result.insert(
+                package.name.clone(),
+                vec![VersionPackagingStatus {
+                    version: None,
+                    found_platforms: vec![],
+                    status: vec![PackagingStatus::disabled()],
+                }],
+            );
+            continue;
+        }
+
+        let on_conda_forge = conda_forge_packages.contains(&package.name);
+        if on_conda_forge && config.conda.conda_forge_check == config_file::CondaForgePolicy::Skip {
+            result.insert(
+                package.name.clone(),
+                vec![VersionPackagingStatus {
+                    version: None,
+                    found_platforms: vec![],
+                    status: vec![PackagingStatus::on_conda_forge(true)],
+                }],
+            );
+            continue;
+        }
+
+        let start = Instant::now();
+        let (source, query_result) = query_sources(releases, package, options)
+            .instrument(span.clone())
+            .await;
+        result.record_timing("github", Some(&package.name), start.elapsed());
+        if let Some(source) = source {
+            tracing::info!("{}: Using releases from {source}", package.name);
+            result
+                .sources
+                .insert(package.name.clone(), source.to_string());
+        }
+
+        let (repository, mut package_releases) = match query_result {
+            Some(Ok((repository, releases))) => (repository, releases),
+            Some(Err(e)) => {
+                tracing::error!("{e}");
+                result.insert(
+                    package.name.clone(),
+                    vec![VersionPackagingStatus {
+                        version: None,
+                        found_platforms: vec![],
+                        status: PackagingStatus::github_failed(&e.to_string()),
+                    }],
+                );
+                if options.fail_fast {
+                    break;
+                }
+                continue;
+            }
+            None => {
+                tracing::error!("{}: Timed out retrieving releases", package.name);
+                result.insert(
+                    package.name.clone(),
+                    vec![VersionPackagingStatus {
+                        version: None,
+                        found_platforms: vec![],
+                        status: PackagingStatus::timed_out(),
+                    }],
+                );
+                if options.fail_fast {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        if let Some(since) = options.since {
+            let since = policy::since(&package.policy, since);
+            package_releases.retain(|(r, _)| r.published_at.is_some_and(|p| p >= since));
+        }
+        if let Some(tag) = &options.tag {
+            package_releases.retain(|(r, _)| &r.tag_name == tag);
+        }
+
+        let start = Instant::now();
+        let planned = span.in_scope(|| {
+            generator.planned_inspections(
+                package,
+                &repository,
+                &package_releases,
+                repo_packages,
+                PACKAGE_GENERATION_LIMIT - package_count,
+            )
+        })?;
+        let max_size = package
+            .max_asset_size
+            .unwrap_or(inspect::MAX_INSPECTED_SIZE);
+        let (attestations, planned): (Vec<_>, Vec<_>) = planned
+            .into_iter()
+            .partition(|(inspection, _)| *inspection == inspect::Inspection::Attestation);
+        let attested = if attestations.is_empty() {
+            HashMap::new()
+        } else {
+            let digests = fixtures::recorded(
+                options.fixtures.as_ref(),
+                &format!("attestations/{}", package.name),
+                Error::Github,
+                async {
+                    let inspections = inspect::Inspections::new(&attestations, max_size).await;
+                    let digests = attestation::attested_digests(&package_releases, &inspections);
+                    Ok(
+                        attestation::confirmed_digests(releases, &package.repository, digests)
+                            .await,
+                    )
+                },
+            )
+            .instrument(span.clone())
+            .await;
+            match digests {
+                Ok(digests) => {
+                    attestation::apply_digests(&mut package_releases, &digests);
+                    digests
+                }
+                Err(e) => {
+                    tracing::warn!("{}: No attested digests: {e}", package.name);
+                    HashMap::new()
+                }
+            }
+        };
+        let inspections = inspect::Inspections::new(&planned, max_size)
+            .instrument(span.clone())
+            .await;
+        let generated = span.in_scope(|| {
+            generator.generate(
+                package,
+                &repository,
+                &package_releases,
+                repo_packages,
+                PACKAGE_GENERATION_LIMIT - package_count,
+                &inspections,
+            )
+        })?;
+        result.record_timing("recipes", Some(&package.name), start.elapsed());
+        package_count += generated.count;
+        result.recipes += generated.written;
+
+        let mut packages = generated.status;
+        attestation::mark_attested(&mut packages, &attested);
+        if on_conda_forge {
+            packages.push(VersionPackagingStatus {
+                version: None,
+                found_platforms: vec![],
+                status: vec![PackagingStatus::on_conda_forge(false)],
+            });
+        }
+
+        let failed = has_failures(&packages);
+        result.insert(package.name.clone(), packages);
+        if failed && options.fail_fast {
+            tracing::warn!("{} failed: Stopping early", package.name);
+            break;
+        }
+        if package_count >= PACKAGE_GENERATION_LIMIT {
+            tracing::warn!(
+                "Package limit reached after {} packages: SKIPPING package generation",
+                result.len()
+            );
+            break;
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FailingProvider {
+        attempts: std::cell::Cell<u32>,
+        status: u16,
+    }
+
+    impl ReleaseProvider for FailingProvider {
+        async fn query_releases(
+            &self,
+            _package: &config_file::Package,
+        ) -> Result<(octocrab::models::Repository, github::Releases)> {
+            self.attempts.set(self.attempts.get() + 1);
+            Err(Error::Github(
+                anyhow::Error::from(github::StatusError {
+                    status: self.status,
+                    uri: "/repos/foo/bar".to_string(),
+                    message: "Something went wrong".to_string(),
+                })
+                .context("Failed to get repository data"),
+            ))
+        }
+    }
+
+    #[test]
+    fn test_retries_are_exhausted() {
+        let provider = FailingProvider {
+            attempts: std::cell::Cell::new(0),
+            status: 502,
+        };
+        let package = config_file::Package::with_defaults("foo/bar").unwrap();
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_delay: std::time::Duration::ZERO,
+        };
+
+        let result = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(query_releases_with_retries(&provider, &package, &policy));
+
+        let error = result.unwrap_err();
+        assert!(error.to_string().contains("answered 502"));
+        assert_eq!(provider.attempts.get(), 3);
+    }
+
+    #[test]
+    fn test_client_errors_are_not_retried() {
+        let provider = FailingProvider {
+            attempts: std::cell::Cell::new(0),
+            status: 404,
+        };
+        let package = config_file::Package::with_defaults("foo/bar").unwrap();
+        let policy = RetryPolicy {
+            max_retries: 2,
+            initial_delay: std::time::Duration::ZERO,
+        };
+
+        let result = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(query_releases_with_retries(&provider, &package, &policy));
+
+        assert!(!result.unwrap_err().is_transient());
+        assert_eq!(provider.attempts.get(), 1);
+    }
+
+    /// Has releases with assets in `mirror/*` repositories only
+    struct MirrorProvider;
+
+    impl ReleaseProvider for MirrorProvider {
+        async fn query_releases(
+            &self,
+            package: &config_file::Package,
+        ) -> Result<(octocrab::models::Repository, github::Releases)> {
+            use package_generation::tests::{release_fixture, repository_fixture};
+
+            let repository = &package.repository;
+            if repository.owner == "gone" {
+                return Err(Error::Github(anyhow::anyhow!("404 Not Found")));
+            }
+            let assets: &[&str] = if repository.owner == "mirror" {
+                &["bar-linux-x86_64.tar.gz"]
+            } else {
+                &[]
+            };
+            Ok((
+                repository_fixture(serde_json::json!({
+                    "id": 1,
+                    "name": repository.repo,
+                    "url": format!("https://api.github.com/repos/{}/{}", repository.owner, repository.repo),
+                })),
+                vec![(
+                    release_fixture("v1.0.0", assets),
+                    Ok(("1.0.0".to_string(), 0)),
+                )],
+            ))
+        }
+    }
+
+    #[test]
+    fn test_fallback_sources() {
+        let options = RunOptions {
+            retry_policy: RetryPolicy {
+                max_retries: 0,
+                initial_delay: std::time::Duration::ZERO,
+            },
+            ..Default::default()
+        };
+        let query = |toml: &str| {
+            let package = config_file::Package::try_from(
+                toml::from_str::<config_file::TomlPackage>(toml).unwrap(),
+            )
+            .unwrap();
+            let (source, result) = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(query_sources(&MirrorProvider, &package, &options));
+            (source.map(|s| s.to_string()), result.unwrap())
+        };
+
+        let (source, result) = query("repository = \"mirror/bar\"");
+        assert_eq!(source, None);
+        assert!(result.is_ok());
+
+        let (source, result) = query(
+            "repository = \"gone/bar\"\nsources = [{ repository = \"empty/bar\" }, { repository = \"mirror/bar\" }]",
+        );
+        assert_eq!(source.as_deref(), Some("mirror/bar"));
+        assert_eq!(result.unwrap().1[0].0.assets.len(), 1);
+
+        let (source, result) =
+            query("repository = \"empty/bar\"\nsources = [{ repository = \"gone/bar\" }]");
+        assert_eq!(source, None);
+        assert!(result.unwrap().1[0].0.assets.is_empty());
+
+        let (source, result) = query("repository = \"gone/bar\"");
+        assert_eq!(source, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_channel_errors() {
+        let config: Config = toml::from_str::<config_file::TomlConfig>(
+            "[conda]\nchannel = \"test\"\n\n[[packages]]\nrepository = \"foo/bar\"\n",
+        )
+        .unwrap()
+        .try_into()
+        .unwrap();
+        let work_dir = tempfile::tempdir().unwrap();
+        let generator = RecipeGenerator::new(work_dir.path(), &config).unwrap();
+        // Nothing was recorded, so querying the channel fails
+        let fixture_dir = tempfile::tempdir().unwrap();
+        let check = |ignore_channel_errors| {
+            let options = RunOptions {
+                retry_policy: RetryPolicy {
+                    max_retries: 0,
+                    initial_delay: std::time::Duration::ZERO,
+                },
+                ignore_channel_errors,
+                fixtures: Some(fixtures::Mode::Replay(fixture_dir.path().to_path_buf())),
+                ..Default::default()
+            };
+            let provider = FailingProvider {
+                attempts: std::cell::Cell::new(0),
+                status: 502,
+            };
+            let report = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(run(&config, &provider, &generator, &options))
+                .unwrap();
+            (report, provider.attempts.get())
+        };
+
+        let (report, attempts) = check(false);
+        assert_eq!(attempts, 0);
+        assert_eq!(report.channel_errors.len(), 1);
+        assert_eq!(report.count(Status::Failed), 1);
+        assert!(report.render(false).contains("could not check the channel"));
+
+        let (report, attempts) = check(true);
+        assert_eq!(attempts, 1);
+        assert_eq!(report.channel_errors.len(), 1);
+        assert!(
+            report
+                .render(false)
+                .contains("Channel check unavailable:\n")
+        );
+    }
+
+    /// Run `config` against releases of [`MirrorProvider`], with bar 1.0.0
+    /// for linux-64 in the channel if `published`
+    fn run_mirror(config: &Config, published: bool) -> Report {
+        let channel = if published {
+            vec![conda::ChannelPackage {
+                name: "bar".to_string(),
+                subdir: "linux-64".to_string(),
+                version: "1.0.0".parse().unwrap(),
+                build: "h0_0".to_string(),
+                build_number: 0,
+            }]
+        } else {
+            vec![]
+        };
+        let fixture_dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            fixture_dir.path().join("channel.json"),
+            serde_json::to_string(&channel).unwrap(),
+        )
+        .unwrap();
+        let work_dir = tempfile::tempdir().unwrap();
+        let generator = RecipeGenerator::new(work_dir.path(), config).unwrap();
+        let options = RunOptions {
+            fixtures: Some(fixtures::Mode::Replay(fixture_dir.path().to_path_buf())),
+            ..Default::default()
+        };
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(run(config, &MirrorProvider, &generator, &options))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_recipes_written() {
+        let config: Config = toml::from_str::<config_file::TomlConfig>(
+            "[conda]\nchannel = \"test\"\n\n[[packages]]\nrepository = \"mirror/bar\"\n",
+        )
+        .unwrap()
+        .try_into()
+        .unwrap();
+
+        let report = run_mirror(&config, false);
+        assert_eq!(report.recipes, 1);
+
+        // Everything is in the channel already: Nothing to build
+        let report = run_mirror(&config, true);
+        assert_eq!(report.count(Status::Succeeded), 1);
+        assert_eq!(report.recipes, 0);
+    }
+
+    #[test]
+    fn test_too_large_asset_in_channel() {
+        let config: Config = toml::from_str::<config_file::TomlConfig>(
+            "[conda]\nchannel = \"test\"\n\n[[packages]]\nrepository = \"mirror/bar\"\nmax_asset_size = 100\n",
+        )
+        .unwrap()
+        .try_into()
+        .unwrap();
+
+        let report = run_mirror(&config, false);
+        assert_eq!(report.count(Status::Failed), 1);
+        assert!(
+            report
+                .render(false)
+                .contains("more than the max_asset_size of 100")
+        );
+
+        let report = run_mirror(&config, true);
+        assert_eq!(report.count(Status::Failed), 0);
+        assert!(report.render(false).contains("already in conda"));
+    }
+}
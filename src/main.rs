@@ -1,113 +1,769 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // © Tobias Hunger <tobias.hunger@gmail.com>
 
-use std::collections::HashMap;
-
-use crate::package_generation::VersionPackagingStatus;
+use anyhow::Context as _;
+use octoconda::{
+    RecipeGenerator, Report, clean, conda,
+    config_file::{self, Since},
+    discover, fixtures, github,
+    package_generation::Status,
+    report::Timing,
+    state::State,
+    webhook,
+};
 
 mod cli;
-mod conda;
-mod config_file;
-mod github;
-mod package_generation;
-mod types;
-
-const PACKAGE_GENERATION_LIMIT: usize = 500;
 
 fn report_status(
     temporary_directory: &cli::WorkDir,
-    result: &HashMap<String, Vec<VersionPackagingStatus>>,
+    report: &Report,
+    color: bool,
 ) -> anyhow::Result<()> {
-    let report = package_generation::report_results(result);
-    eprintln!("{report}");
+    eprintln!("{}", report.render(color));
+
+    std::fs::write(
+        temporary_directory.status_file(),
+        report.to_markdown().as_bytes(),
+    )?;
+
+    Ok(())
+}
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    let cli = cli::parse_cli();
+    cli.init_logging();
+
+    match &cli.command {
+        Some(cli::Command::Init { channel, force }) => init(&cli, channel, *force),
+        Some(cli::Command::Add {
+            repository,
+            interactive,
+        }) => runtime().block_on(add(&cli, repository, *interactive)),
+        Some(cli::Command::Remove {
+            name,
+            purge_channel,
+            yes,
+        }) => runtime().block_on(remove(&cli, name, *purge_channel, *yes)),
+        Some(cli::Command::Approve { name }) => runtime().block_on(approve(&cli, name)),
+        Some(cli::Command::Discover { starred }) => runtime().block_on(async {
+            let config = config_file::parse_config(&cli.config_file, cli.env.as_deref())?;
+            let gh = github::Github::for_host(
+                &config.github_host,
+                octoconda::credentials::github_token(&config)?,
+            )?
+            .with_request_interval(cli.github_request_interval());
+            discover::print_starred_packages(&gh, starred).await
+        }),
+        Some(cli::Command::Clean {
+            in_channel,
+            older_than,
+            dry_run,
+        }) => clean_work_dir(&cli, *in_channel, *older_than, *dry_run),
+        Some(cli::Command::Stats) => runtime().block_on(print_stats(&cli)),
+        Some(cli::Command::Sync {
+            from,
+            to,
+            package,
+            version,
+            dry_run,
+        }) => runtime().block_on(sync_channels(&cli, from, to, package, version, *dry_run)),
+        Some(cli::Command::InstallTest {
+            paths,
+            channel,
+            passed,
+        }) => runtime().block_on(install_test(paths, channel, passed.as_deref())),
+        Some(cli::Command::Watch {
+            interval,
+            build_command,
+        }) => watch(&cli, *interval, build_command.as_deref()),
+        Some(cli::Command::Webhook {
+            listen,
+            build_command,
+        }) => webhook(&cli, *listen, build_command.as_deref()),
+        None => generate_packages(&cli, &RunScope::default()).map(|_| ()),
+    }
+}
+
+fn init(cli: &cli::Cli, channel: &str, force: bool) -> anyhow::Result<()> {
+    let config = config_file::starter_config(channel)?;
+    if cli.config_file.exists() && !force {
+        return Err(anyhow::anyhow!(
+            "{} exists already, use --force to replace it",
+            cli.config_file.display()
+        ));
+    }
+    std::fs::write(&cli.config_file, config)
+        .context(format!("Failed to write {}", cli.config_file.display()))?;
+    println!("Wrote {}", cli.config_file.display());
+    Ok(())
+}
 
-    let report = format!(
-        r#"## Status
+async fn add(cli: &cli::Cli, repository: &str, interactive: bool) -> anyhow::Result<()> {
+    let config = config_file::parse_config(&cli.config_file, cli.env.as_deref())?;
+    let package = config_file::Package::with_defaults(repository)?;
+    if config.packages.iter().any(|p| {
+        p.repository
+            .owner
+            .eq_ignore_ascii_case(&package.repository.owner)
+            && p.repository
+                .repo
+                .eq_ignore_ascii_case(&package.repository.repo)
+    }) {
+        return Err(anyhow::anyhow!("{repository} is configured already"));
+    }
 
-```
-{report}
-```
+    let overrides = if interactive {
+        let gh = github::Github::for_host(
+            &config.github_host,
+            octoconda::credentials::github_token(&config)?,
+        )?
+        .with_request_interval(cli.github_request_interval());
+        let release = gh.query_latest_release(&package.repository).await?;
+        let assets = &release.assets;
+        if assets.is_empty() {
+            return Err(anyhow::anyhow!(
+                "The latest release of {repository} ({}) has no assets",
+                release.tag_name
+            ));
+        }
+        println!("Latest release of {repository}: {}", release.tag_name);
 
-"#
+        let defaults = octoconda::add::default_choices(&package, assets);
+        let mut items = vec!["(do not package)".to_string()];
+        items.extend(assets.iter().map(|a| a.name.clone()));
+        let mut chosen = defaults.clone();
+        for (platform, choice) in &mut chosen {
+            let selection = dialoguer::Select::new()
+                .with_prompt(format!("Asset for {platform}"))
+                .items(&items)
+                .default(choice.map_or(0, |index| index + 1))
+                .interact()?;
+            *choice = selection.checked_sub(1);
+        }
+        octoconda::add::overrides(&defaults, &chosen, assets, &release.tag_name)
+    } else {
+        Default::default()
+    };
+
+    let entry = octoconda::add::package_entry(repository, &overrides);
+    if interactive {
+        print!("{entry}");
+        let confirmed = dialoguer::Confirm::new()
+            .with_prompt(format!("Add this to {}?", cli.config_file.display()))
+            .default(true)
+            .interact()?;
+        if !confirmed {
+            return Ok(());
+        }
+    }
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&cli.config_file)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, entry.as_bytes()))
+        .context(format!("Failed to write {}", cli.config_file.display()))?;
+    println!("Added {repository} to {}", cli.config_file.display());
+    Ok(())
+}
+
+const PREFIX_HOST: &str = "prefix.dev";
+
+/// The token for uploading to and deleting from prefix.dev channels
+fn prefix_token(config: &config_file::Config, action: &str) -> anyhow::Result<String> {
+    config
+        .credentials
+        .get(PREFIX_HOST)
+        .context(format!("{action} needs credentials for prefix.dev"))
+        .and_then(|c| octoconda::credentials::token(PREFIX_HOST, c))
+}
+
+async fn remove(cli: &cli::Cli, name: &str, purge_channel: bool, yes: bool) -> anyhow::Result<()> {
+    let name = config_file::package_name(name)?;
+    let name = name.as_str();
+    let content = std::fs::read_to_string(&cli.config_file)
+        .context(format!("Failed to read {}", cli.config_file.display()))?;
+    let remaining = config_file::remove_package(&content, name)?;
+
+    if purge_channel {
+        let config = config_file::parse_config(&cli.config_file, cli.env.as_deref())?;
+        let token = prefix_token(&config, "--purge-channel")?;
+        let channel = config.conda.short_channel()?;
+        let platforms = config
+            .all_platforms()
+            .into_iter()
+            .chain(std::iter::once(rattler_conda_types::Platform::NoArch))
+            .collect::<Vec<_>>();
+        let records = conda::get_conda_package_versions(
+            &conda::gateway(&config)?,
+            &config.conda.full_channel()?,
+            platforms.iter().copied(),
+            std::iter::once(name),
+        )
+        .await?;
+
+        // Deleting from the channel can not be undone
+        for record in &records {
+            println!("{}/{}", record.package_record.subdir, record.file_name);
+        }
+        if !records.is_empty() && !yes {
+            let confirmed = dialoguer::Confirm::new()
+                .with_prompt(format!(
+                    "Delete these {} files from {channel}?",
+                    records.len()
+                ))
+                .default(false)
+                .interact()?;
+            if !confirmed {
+                println!("Nothing removed");
+                return Ok(());
+            }
+        }
+
+        let client = reqwest::Client::new();
+        for record in &records {
+            octoconda::sync::delete(&client, record, &channel, &token).await?;
+            println!(
+                "Deleted {}/{} from {channel}",
+                record.package_record.subdir, record.file_name
+            );
+        }
+    }
+
+    std::fs::write(&cli.config_file, remaining)
+        .context(format!("Failed to write {}", cli.config_file.display()))?;
+    println!("Removed {name} from {}", cli.config_file.display());
+    Ok(())
+}
+
+async fn approve(cli: &cli::Cli, name: &str) -> anyhow::Result<()> {
+    let name = config_file::package_name(name)?;
+    let name = name.as_str();
+    let content = std::fs::read_to_string(&cli.config_file)
+        .context(format!("Failed to read {}", cli.config_file.display()))?;
+    let approved = config_file::approve_package(&content, name)?;
+
+    let config = config_file::parse_config(&cli.config_file, cli.env.as_deref())?;
+    if let Some(quarantine) = config.conda.quarantine() {
+        sync_channels(
+            cli,
+            &quarantine.full_channel()?,
+            &config.conda.short_channel()?,
+            &[name.to_string()],
+            &[],
+            false,
+        )
+        .await?;
+    }
+
+    std::fs::write(&cli.config_file, approved)
+        .context(format!("Failed to write {}", cli.config_file.display()))?;
+    println!("Approved {name} in {}", cli.config_file.display());
+    Ok(())
+}
+
+async fn sync_channels(
+    cli: &cli::Cli,
+    from: &str,
+    to: &str,
+    packages: &[String],
+    versions: &[String],
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let config = config_file::parse_config(&cli.config_file, cli.env.as_deref())?;
+    let channel = |channel: &str| config_file::Conda {
+        channel: channel.to_string(),
+        ..config.conda.clone()
+    };
+    let source_url = if url::Url::parse(from).is_ok() {
+        from.to_string()
+    } else {
+        channel(from).full_channel()?
+    };
+    let target = channel(to).short_channel()?;
+    let target_url = channel(to).full_channel()?;
+
+    let names = if packages.is_empty() {
+        config.packages.iter().map(|p| p.name.clone()).collect()
+    } else {
+        packages.to_vec()
+    };
+    let platforms = config
+        .all_platforms()
+        .into_iter()
+        .chain(std::iter::once(rattler_conda_types::Platform::NoArch))
+        .collect::<Vec<_>>();
+    let gateway = conda::gateway(&config)?;
+    let query = |channel| {
+        conda::get_conda_package_versions(
+            &gateway,
+            channel,
+            platforms.iter().copied(),
+            names.iter().map(String::as_str),
+        )
+    };
+    let source_records = query(&source_url).await?;
+    let target_records = query(&target_url).await?;
+
+    let selected = octoconda::sync::select(&source_records, &target_records, packages, versions);
+    if dry_run {
+        for record in &selected {
+            println!(
+                "Would copy {}/{}",
+                record.package_record.subdir, record.file_name
+            );
+        }
+        return Ok(());
+    }
+
+    let token = prefix_token(&config, "sync")?;
+    let client = reqwest::Client::new();
+    for record in selected {
+        octoconda::sync::copy(&client, record, &target, &token).await?;
+        println!(
+            "Copied {}/{}",
+            record.package_record.subdir, record.file_name
+        );
+    }
+    Ok(())
+}
+
+async fn print_stats(cli: &cli::Cli) -> anyhow::Result<()> {
+    let config = config_file::parse_config(&cli.config_file, cli.env.as_deref())?;
+    let channel = config.conda.short_channel()?;
+    let token = config
+        .credentials
+        .get(PREFIX_HOST)
+        .map(|c| octoconda::credentials::token(PREFIX_HOST, c))
+        .transpose()?;
+
+    let downloads = octoconda::stats::query_downloads(&channel, token.as_deref()).await?;
+    println!("## Downloads in {channel}\n");
+    print!(
+        "{}",
+        octoconda::stats::to_markdown(&octoconda::stats::rank(&downloads))
     );
+    Ok(())
+}
+
+async fn install_test(
+    paths: &[std::path::PathBuf],
+    channels: &[String],
+    passed_file: Option<&std::path::Path>,
+) -> anyhow::Result<()> {
+    let packages = octoconda::install_test::find_packages(paths)?;
+    let dependencies = octoconda::install_test::Dependencies::new(channels)?;
 
-    std::fs::write(temporary_directory.status_file(), report.as_bytes())?;
+    println!("## Install test\n");
+    println!("| | Package | Result |");
+    println!("|---|---|---|");
+    let mut failures = 0;
+    let mut passed = String::new();
+    for package in &packages {
+        let (status, message) = octoconda::install_test::install_test(package, &dependencies).await;
+        if status == Status::Failed {
+            failures += 1;
+        } else {
+            passed.push_str(&format!("{}\n", package.display()));
+        }
+        let file_name = package.file_name().unwrap_or_default().to_string_lossy();
+        println!(
+            "| {status} | {file_name} | {} |",
+            message.replace('|', "\\|")
+        );
+    }
 
+    if let Some(passed_file) = passed_file {
+        std::fs::write(passed_file, passed)
+            .context(format!("Failed to write {}", passed_file.display()))?;
+    }
+    if failures > 0 {
+        return Err(anyhow::anyhow!(
+            "{failures} of {} packages failed the install test",
+            packages.len()
+        ));
+    }
     Ok(())
 }
 
-fn main() -> Result<(), anyhow::Error> {
-    let cli = cli::parse_cli();
+fn clean_work_dir(
+    cli: &cli::Cli,
+    in_channel: bool,
+    older_than: Option<u64>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let Some(work_dir) = &cli.work_dir else {
+        return Err(anyhow::anyhow!("clean needs a --work-dir"));
+    };
+    let recipes = clean::find_recipes(work_dir, cli.layout.into())?;
 
-    let config = config_file::parse_config(&cli.config_file)?;
+    let records = if in_channel && !recipes.is_empty() {
+        let config = config_file::parse_config(&cli.config_file, cli.env.as_deref())?;
+        let mut platforms = recipes
+            .iter()
+            .flat_map(|r| r.platforms.iter().copied())
+            .collect::<Vec<_>>();
+        platforms.sort();
+        platforms.dedup();
+        let mut names = recipes.iter().map(|r| r.name.as_str()).collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        Some(runtime().block_on(conda::get_channel_index(
+            &conda::gateway(&config)?,
+            &config.conda.full_channel()?,
+            platforms.into_iter(),
+            names.into_iter(),
+        ))?)
+    } else {
+        None
+    };
+
+    let older_than = older_than.map(|days| std::time::Duration::from_secs(days * 24 * 60 * 60));
+    for recipe in clean::select(&recipes, older_than, records.as_deref()) {
+        if dry_run {
+            println!("Would remove {}", recipe.path.display());
+        } else {
+            clean::remove(recipe)?;
+            println!("Removed {}", recipe.path.display());
+        }
+    }
+    Ok(())
+}
+
+/// The GitHub rate limit, the report just goes without it when it is
+/// unavailable
+async fn quota(gh: &github::Github) -> Option<github::Quota> {
+    gh.quota()
+        .await
+        .inspect_err(|e| tracing::warn!("{e:#}"))
+        .ok()
+}
+
+/// What a run looks at, beyond the command line and configuration file
+#[derive(Default)]
+struct RunScope {
+    /// Applies when neither the command line nor the configuration file set
+    /// `since`
+    default_since: Option<Since>,
+    /// Only package this release
+    release: Option<webhook::Release>,
+}
+
+/// Generate recipes for everything in `scope` that is missing in the channel
+fn generate_packages(cli: &cli::Cli, scope: &RunScope) -> anyhow::Result<(cli::WorkDir, Report)> {
+    let started = std::time::Instant::now();
+    let mut config = config_file::parse_config(&cli.config_file, cli.env.as_deref())?;
+    let config_duration = started.elapsed();
 
     let temporary_directory = cli.work_directory()?;
-    eprintln!("temporary dir: {}", temporary_directory.path().display());
+    tracing::info!("temporary dir: {}", temporary_directory.path().display());
 
-    package_generation::generate_build_script(temporary_directory.path())?;
-    package_generation::generate_env_file(temporary_directory.path(), &config)?;
-    eprintln!("Workdir is set up");
+    let mut generator = RecipeGenerator::new(temporary_directory.path(), &config)?;
+    generator.strict = cli.strict;
+    generator.force = cli.force;
+    generator.existing_recipes = cli.existing_recipes.into();
+    generator.layout = cli.layout.into();
+    generator.multi_platform = cli.multi_platform_recipes;
+    generator.recipe_format = cli.recipe_format.into();
+    generator.recipe_template = cli.recipe_template.clone();
+    generator.inspect_archives = cli.replay.is_none();
+    tracing::debug!("Workdir is set up");
 
-    tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .unwrap()
-        .block_on(async {
-            let repo_packages = conda::get_conda_package_versions(
-                &config.conda.full_channel()?,
-                config.all_platforms().iter().copied(),
-                config.packages.iter().map(|p| p.name.as_str()),
+    let github_token = octoconda::credentials::github_token(&config)?;
+
+    let report = runtime().block_on(async {
+        let mut gh = github::Github::for_host(&config.github_host, github_token)?
+            .with_request_interval(cli.github_request_interval());
+        if let Some(dir) = &cli.release_cache {
+            gh = gh.with_release_cache(dir);
+        }
+        let fixtures = cli.fixtures();
+        let replay = matches!(fixtures, Some(fixtures::Mode::Replay(_)));
+        let textfile = config.telemetry.textfile.clone();
+        let quota_before = if replay { None } else { quota(&gh).await };
+
+        let start = std::time::Instant::now();
+        for discover in config.discover.clone() {
+            let repositories = fixtures::recorded(
+                fixtures.as_ref(),
+                &format!("discover/{}", discover.org),
+                octoconda::Error::Github,
+                async {
+                    gh.query_organization_repositories(&discover.org)
+                        .await
+                        .map_err(octoconda::Error::Github)
+                },
             )
             .await?;
+            config.add_discovered_packages(&discover, repositories.into_iter())?;
+        }
+        let discover_duration = start.elapsed();
+        config.retain_groups(&cli.group);
+        if let Some(release) = &scope.release {
+            config.packages.retain(|p| release.is_for(p));
+        }
+
+        let mut state = cli.state_file.as_deref().map(State::load).transpose()?;
+        if let Some(state) = &state {
+            generator.known_assets = state.assets.clone();
+        }
+        generator.rebuild_reuploads = cli.rebuild_reuploads;
+        let run_start = chrono::Utc::now();
+
+        let mut options = cli.run_options();
+        options.timings |= textfile.is_some();
+        options.tag = scope.release.as_ref().map(|r| r.tag.clone());
+        options.since = match cli.since.or(config.since).or(scope.default_since) {
+            Some(Since::Date(date)) => Some(date),
+            Some(Since::LastRun) => {
+                let Some(state) = &state else {
+                    return Err(anyhow::anyhow!("--since last-run needs a --state-file"));
+                };
+                state.last_run
+            }
+            None => None,
+        };
+
+        let mut report = match &fixtures {
+            None => octoconda::run(&config, &gh, &generator, &options).await?,
+            Some(fixtures::Mode::Record(dir)) => {
+                let recorder = fixtures::Recorder::new(&gh, dir);
+                octoconda::run(&config, &recorder, &generator, &options).await?
+            }
+            Some(fixtures::Mode::Replay(dir)) => {
+                let replay = fixtures::Replay::new(dir);
+                octoconda::run(&config, &replay, &generator, &options).await?
+            }
+        };
+        if let Some(timings) = &mut report.timings {
+            timings.splice(
+                0..0,
+                [
+                    Timing {
+                        stage: "config",
+                        package: None,
+                        duration: config_duration,
+                    },
+                    Timing {
+                        stage: "discover",
+                        package: None,
+                        duration: discover_duration,
+                    },
+                ],
+            );
+        }
+        if cli.timings {
+            std::fs::write(
+                temporary_directory.path().join("timings.json"),
+                report.timings_json()?,
+            )?;
+        }
+
+        if let (Some(state_file), Some(state)) = (&cli.state_file, &mut state) {
+            report.regressions = state.coverage_regressions(&report);
+            report.reuploads = state.reuploads(&report);
+            state.update_coverage(&report);
+            state.update_assets(&report);
+            if report.count(Status::Failed) == 0 && scope.release.is_none() {
+                state.last_run = Some(run_start);
+            }
+            state.save(state_file)?;
+        }
 
-            eprintln!("Conda: Channel information collected");
-
-            let gh = github::Github::new()?;
-
-            let mut result = HashMap::new();
-            let mut package_count = 0;
-
-            for package in &config.packages {
-                let repo_packages = &repo_packages;
-
-                let (repository, releases) =
-                    match gh.query_releases(&package.repository, &package.name).await {
-                        Ok((repository, releases)) => (repository, releases),
-                        Err(e) => {
-                            eprintln!("Error: {e}");
-                            result.insert(
-                                package.name.clone(),
-                                vec![VersionPackagingStatus {
-                                    version: None,
-                                    status: package_generation::PackagingStatus::github_failed(),
-                                }],
-                            );
-                            continue;
-                        }
-                    };
-
-                let (packages, generated_count) = package_generation::generate_packaging_data(
-                    package,
-                    &repository,
-                    &releases,
-                    repo_packages,
-                    temporary_directory.path(),
-                    PACKAGE_GENERATION_LIMIT - package_count,
-                )?;
-                package_count += generated_count;
-
-                result.insert(package.name.clone(), packages);
-                if package_count >= PACKAGE_GENERATION_LIMIT {
-                    eprintln!(
-                        "Package limit reached after {} packages: SKIPPING package generation",
-                        result.len()
-                    );
-                    break;
+        octoconda::action_log::append(
+            &temporary_directory.path().join("actions.jsonl"),
+            &report,
+            run_start,
+        )?;
+
+        if let Some(before) = quota_before {
+            report.github_quota = quota(&gh)
+                .await
+                .map(|after| github::QuotaUsage { before, after });
+        }
+
+        if let Some(textfile) = &textfile {
+            let metrics = octoconda::metrics::RunMetrics {
+                report: &report,
+                duration: started.elapsed(),
+                finished: chrono::Utc::now(),
+            };
+            octoconda::metrics::write_textfile(textfile, &metrics.render())?;
+        }
+        if !cli.timings {
+            report.timings = None;
+        }
+
+        generator.write_index()?;
+        report_status(&temporary_directory, &report, cli.use_color())?;
+
+        Ok(report)
+    })?;
+
+    Ok((temporary_directory, report))
+}
+
+/// Generate recipes every `interval` and run `build_command` in the work
+/// directory when new recipes were generated
+///
+/// Runs only look at releases since the last run without failures, so
+/// releases are not handled twice.
+fn watch(
+    cli: &cli::Cli,
+    interval: std::time::Duration,
+    build_command: Option<&str>,
+) -> anyhow::Result<()> {
+    if cli.state_file.is_none() {
+        return Err(anyhow::anyhow!(
+            "watch needs a --state-file to remember what it handled already"
+        ));
+    }
+
+    let scope = RunScope {
+        default_since: Some(Since::LastRun),
+        release: None,
+    };
+    loop {
+        let started = std::time::Instant::now();
+        generate_and_build(cli, &scope, build_command);
+
+        let wait = interval.saturating_sub(started.elapsed());
+        tracing::info!("Next run in {}s", wait.as_secs());
+        std::thread::sleep(wait);
+    }
+}
+
+/// Webhook connections handled at the same time, more are dropped
+const MAX_WEBHOOK_CONNECTIONS: usize = 16;
+
+/// Time a client gets to send its whole webhook request
+const WEBHOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Listen for release webhooks on `listen` and package each published release
+///
+/// Requests are answered right away, the releases are packaged one after the
+/// other.
+fn webhook(
+    cli: &cli::Cli,
+    listen: std::net::SocketAddr,
+    build_command: Option<&str>,
+) -> anyhow::Result<()> {
+    // Anyone can sign requests with an empty secret
+    let secret = std::env::var("OCTOCONDA_WEBHOOK_SECRET")
+        .ok()
+        .filter(|secret| !secret.trim().is_empty())
+        .ok_or_else(|| {
+            anyhow::anyhow!("webhook needs a non-empty OCTOCONDA_WEBHOOK_SECRET to verify requests")
+        })?;
+    let listener =
+        std::net::TcpListener::bind(listen).context(format!("Failed to listen on {listen}"))?;
+    tracing::info!("Listening for webhooks on {listen}");
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let secret = std::sync::Arc::new(secret);
+    let connections = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::warn!("Failed to accept a connection: {e}");
+                    continue;
                 }
+            };
+            if connections.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+                >= MAX_WEBHOOK_CONNECTIONS
+            {
+                connections.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                tracing::warn!("Too many open webhook connections, dropping one");
+                continue;
             }
+            let secret = secret.clone();
+            let sender = sender.clone();
+            let connections = connections.clone();
+            std::thread::spawn(move || {
+                respond(stream, secret.as_bytes(), &sender);
+                connections.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+            });
+        }
+    });
+
+    for release in receiver {
+        tracing::info!(
+            "Packaging {}/{} {}",
+            release.repository.owner,
+            release.repository.repo,
+            release.tag
+        );
+        let scope = RunScope {
+            default_since: None,
+            release: Some(release),
+        };
+        generate_and_build(cli, &scope, build_command);
+    }
+    Ok(())
+}
 
-            report_status(&temporary_directory, &result)?;
+fn respond(
+    stream: std::net::TcpStream,
+    secret: &[u8],
+    releases: &std::sync::mpsc::Sender<webhook::Release>,
+) {
+    use std::io::Write as _;
+
+    let _ = stream.set_write_timeout(Some(WEBHOOK_TIMEOUT));
+    let request = webhook::read_request(&mut std::io::BufReader::new(
+        webhook::DeadlineReader::new(&stream, WEBHOOK_TIMEOUT),
+    ));
+    let (status, message) = match request.and_then(|r| webhook::published_release(&r, secret)) {
+        Ok(Some(release)) => {
+            let _ = releases.send(release);
+            ("202 Accepted", "queued")
+        }
+        Ok(None) => ("200 OK", "ignored"),
+        Err(e) => {
+            tracing::warn!("Rejected webhook: {e:#}");
+            ("400 Bad Request", "rejected")
+        }
+    };
+    let _ = write!(
+        &stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{message}",
+        message.len()
+    );
+}
+
+/// Generate recipes and run `build_command` in the work directory when new
+/// recipes were generated
+///
+/// Failures are logged only, so that the next run can retry.
+fn generate_and_build(cli: &cli::Cli, scope: &RunScope, build_command: Option<&str>) {
+    match generate_packages(cli, scope) {
+        Ok((work_dir, report)) if report.recipes > 0 => {
+            if let Some(command) = build_command {
+                build(command, work_dir.path());
+            }
+        }
+        Ok(_) => {}
+        Err(e) => tracing::error!("Run failed: {e:#}"),
+    }
+}
 
-            Ok(())
-        })
+fn build(command: &str, work_dir: &std::path::Path) {
+    tracing::info!("Running {command:?} in {}", work_dir.display());
+    match std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(work_dir)
+        .status()
+    {
+        Ok(status) if status.success() => {}
+        Ok(status) => tracing::error!("{command:?} failed: {status}"),
+        Err(e) => tracing::error!("Failed to run {command:?}: {e}"),
+    }
 }
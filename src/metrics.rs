@@ -0,0 +1,218 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Export metrics of a run in the Prometheus text format
+
+use std::{collections::BTreeMap, fmt::Write as _, path::Path, time::Duration};
+
+use anyhow::Context as _;
+
+use crate::{Report, package_generation::Status};
+
+/// What is known about a finished run
+pub struct RunMetrics<'a> {
+    pub report: &'a Report,
+    /// How long the whole run took
+    pub duration: Duration,
+    pub finished: chrono::DateTime<chrono::Utc>,
+}
+
+fn metric(output: &mut String, name: &str, help: &str, values: &[(String, f64)]) {
+    let _ = writeln!(output, "# HELP octoconda_{name} {help}");
+    let _ = writeln!(output, "# TYPE octoconda_{name} gauge");
+    for (labels, value) in values {
+        let _ = writeln!(output, "octoconda_{name}{labels} {value}");
+    }
+}
+
+impl RunMetrics<'_> {
+    pub fn render(&self) -> String {
+        let statuses = [
+            Status::Succeeded,
+            Status::Failed,
+            Status::Skipped,
+            Status::Disabled,
+        ];
+        let mut result = String::new();
+
+        metric(
+            &mut result,
+            "packages",
+            "Packages processed in the last run by overall status",
+            &statuses.map(|s| {
+                (
+                    format!("{{status=\"{}\"}}", s.name()),
+                    self.report.count(s) as f64,
+                )
+            }),
+        );
+        metric(
+            &mut result,
+            "recipes",
+            "Platforms of package versions handled in the last run by status",
+            &statuses.map(|s| {
+                let count = self
+                    .report
+                    .packages
+                    .values()
+                    .flatten()
+                    .flat_map(|v| v.status.iter())
+                    .filter(|p| p.status == s)
+                    .count();
+                (format!("{{status=\"{}\"}}", s.name()), count as f64)
+            }),
+        );
+        metric(
+            &mut result,
+            "run_duration_seconds",
+            "Duration of the last run",
+            &[(String::new(), self.duration.as_secs_f64())],
+        );
+        metric(
+            &mut result,
+            "last_run_timestamp_seconds",
+            "When the last run finished",
+            &[(String::new(), self.finished.timestamp() as f64)],
+        );
+
+        if let Some(quota) = &self.report.github_quota {
+            metric(
+                &mut result,
+                "github_quota_used",
+                "GitHub API requests used by the last run",
+                &[(String::new(), quota.used() as f64)],
+            );
+            metric(
+                &mut result,
+                "github_quota_remaining",
+                "GitHub API requests left after the last run",
+                &[(String::new(), quota.after.remaining as f64)],
+            );
+        }
+
+        if let Some(timings) = &self.report.timings {
+            let mut stages = BTreeMap::new();
+            for timing in timings {
+                *stages.entry(timing.stage).or_insert(Duration::ZERO) += timing.duration;
+            }
+            metric(
+                &mut result,
+                "stage_duration_seconds",
+                "Time spent in each stage of the last run",
+                &stages
+                    .into_iter()
+                    .map(|(stage, d)| (format!("{{stage=\"{stage}\"}}"), d.as_secs_f64()))
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        result
+    }
+}
+
+/// Write `content` to `path` without the textfile collector ever seeing a
+/// partially written file
+pub fn write_textfile(path: &Path, content: &str) -> anyhow::Result<()> {
+    let temporary = path.with_extension("prom.tmp");
+    std::fs::write(&temporary, content).context(format!(
+        "Failed to write metrics to {}",
+        temporary.display()
+    ))?;
+    std::fs::rename(&temporary, path)
+        .context(format!("Failed to write metrics to {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::{Quota, QuotaUsage};
+    use crate::package_generation::{PackagingStatus, VersionPackagingStatus};
+    use crate::report::Timing;
+    use rattler_conda_types::Platform;
+
+    #[test]
+    fn test_render() {
+        let mut report = Report {
+            timings: Some(vec![]),
+            github_quota: Some(QuotaUsage {
+                before: Quota {
+                    limit: 5000,
+                    remaining: 4990,
+                    used: 10,
+                },
+                after: Quota {
+                    limit: 5000,
+                    remaining: 4950,
+                    used: 50,
+                },
+            }),
+            ..Default::default()
+        };
+        report.insert(
+            "foo".to_string(),
+            vec![VersionPackagingStatus {
+                version: Some("1.0.0".to_string()),
+                found_platforms: vec![Platform::Linux64, Platform::OsxArm64],
+                status: vec![
+                    PackagingStatus::success(Platform::Linux64),
+                    PackagingStatus::success(Platform::OsxArm64),
+                ],
+            }],
+        );
+        report.insert(
+            "bar".to_string(),
+            PackagingStatus::github_failed("boom")
+                .into_iter()
+                .map(|s| VersionPackagingStatus {
+                    version: None,
+                    found_platforms: vec![],
+                    status: vec![s],
+                })
+                .collect(),
+        );
+        for package in ["foo", "bar"] {
+            report.record_timing("github", Some(package), Duration::from_millis(750));
+        }
+        report.timings.as_mut().unwrap().push(Timing {
+            stage: "config",
+            package: None,
+            duration: Duration::from_millis(20),
+        });
+
+        let metrics = RunMetrics {
+            report: &report,
+            duration: Duration::from_secs(3),
+            finished: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+        }
+        .render();
+
+        for line in [
+            "# TYPE octoconda_packages gauge",
+            "octoconda_packages{status=\"succeeded\"} 1",
+            "octoconda_packages{status=\"failed\"} 1",
+            "octoconda_packages{status=\"skipped\"} 0",
+            "octoconda_recipes{status=\"succeeded\"} 2",
+            "octoconda_recipes{status=\"failed\"} 1",
+            "octoconda_run_duration_seconds 3",
+            "octoconda_last_run_timestamp_seconds 1700000000",
+            "octoconda_github_quota_used 40",
+            "octoconda_github_quota_remaining 4950",
+            "octoconda_stage_duration_seconds{stage=\"config\"} 0.02",
+            "octoconda_stage_duration_seconds{stage=\"github\"} 1.5",
+        ] {
+            assert!(metrics.lines().any(|l| l == line), "{line} in\n{metrics}");
+        }
+    }
+
+    #[test]
+    fn test_write_textfile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("octoconda.prom");
+        write_textfile(&path, "octoconda_run_duration_seconds 3\n").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "octoconda_run_duration_seconds 3\n"
+        );
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -2,22 +2,40 @@
 // © Tobias Hunger <tobias.hunger@gmail.com>
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     io::Write as _,
     path::{Path, PathBuf},
     str::FromStr,
+    sync::LazyLock,
 };
 
 use anyhow::Context as _;
 use rattler_conda_types::{Platform, VersionWithSource};
 
-use crate::config_file::Package;
+use crate::{
+    config_file::{AssetPreference, Package, PackageKind, SizePreference},
+    inspect::Inspection,
+    recipe::{self, MetaYaml, Recipe, SelectorRecipe},
+};
 
-#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Status {
     Failed,
     Succeeded,
     Skipped,
+    /// Not processed, as the configuration disables the package
+    Disabled,
+}
+
+impl Status {
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Failed => "failed",
+            Status::Succeeded => "succeeded",
+            Status::Skipped => "skipped",
+            Status::Disabled => "disabled",
+        }
+    }
 }
 
 impl std::fmt::Display for Status {
@@ -26,71 +44,479 @@ impl std::fmt::Display for Status {
             Status::Failed => "❌",
             Status::Succeeded => "✔ ",
             Status::Skipped => "❓",
+            Status::Disabled => "⏸ ",
         };
         write!(f, "{output}")
     }
 }
 
-pub fn generate_build_script(work_dir: &Path) -> anyhow::Result<()> {
+/// What to do about recipes left over in the work directory by earlier runs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExistingRecipes {
+    /// Replace them with freshly generated ones
+    #[default]
+    Overwrite,
+    /// Keep them and report the recipe as skipped
+    Skip,
+    /// Report the recipe as failed
+    Fail,
+}
+
+/// The kind of recipe to generate
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecipeFormat {
+    /// rattler-build `recipe.yaml`
+    #[default]
+    Rattler,
+    /// conda-build `meta.yaml`
+    CondaBuild,
+}
+
+impl RecipeFormat {
+    pub const ALL: [RecipeFormat; 2] = [RecipeFormat::Rattler, RecipeFormat::CondaBuild];
+
+    /// Name of the recipe file in the recipe directory
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            RecipeFormat::Rattler => "recipe.yaml",
+            RecipeFormat::CondaBuild => "meta.yaml",
+        }
+    }
+}
+
+/// Takes the place of the platform in the directory of multi-platform recipes
+pub const MULTI_PLATFORM_DIR: &str = "multi";
+
+/// How recipe directories are arranged in the work directory
+///
+/// `<platform>` is [`MULTI_PLATFORM_DIR`] for multi-platform recipes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecipeLayout {
+    /// `<platform>/<name>-<version>-<build>/`
+    #[default]
+    PerPlatform,
+    /// `<name>/<version>-<build>/<platform>/`
+    PerPackage,
+    /// `<name>-<version>-<build>-<platform>/`
+    Flat,
+}
+
+impl RecipeLayout {
+    /// The directory of a recipe, relative to the work directory
+    pub fn recipe_dir(
+        &self,
+        name: &str,
+        version: &str,
+        build_number: u32,
+        platform: &str,
+    ) -> PathBuf {
+        match self {
+            RecipeLayout::PerPlatform => {
+                Path::new(platform).join(format!("{name}-{version}-{build_number}"))
+            }
+            RecipeLayout::PerPackage => Path::new(name)
+                .join(format!("{version}-{build_number}"))
+                .join(platform),
+            RecipeLayout::Flat => format!("{name}-{version}-{build_number}-{platform}").into(),
+        }
+    }
+
+    /// Number of directory levels between the work directory and a recipe
+    pub fn depth(&self) -> usize {
+        match self {
+            RecipeLayout::PerPlatform => 2,
+            RecipeLayout::PerPackage => 3,
+            RecipeLayout::Flat => 1,
+        }
+    }
+
+    /// The target platform of the recipe in `recipe_dir`, relative to the
+    /// work directory, `None` for multi-platform recipes
+    pub fn platform_of(&self, recipe_dir: &Path) -> Option<Platform> {
+        let component = match self {
+            RecipeLayout::PerPlatform => recipe_dir.components().next()?.as_os_str(),
+            RecipeLayout::PerPackage | RecipeLayout::Flat => recipe_dir.file_name()?,
+        }
+        .to_str()?;
+        match self {
+            RecipeLayout::Flat => {
+                Platform::all().find(|p| component.ends_with(&format!("-{}", p.as_str())))
+            }
+            _ => Platform::from_str(component).ok(),
+        }
+    }
+}
+
+/// An entry of the index of all recipes in the work directory
+#[derive(Debug, serde::Serialize)]
+struct IndexEntry<'a> {
+    name: &'a str,
+    version: &'a str,
+    build_number: u64,
+    platforms: &'a [Platform],
+    /// Relative to the work directory
+    path: &'a Path,
+}
+
+fn generate_build_script(work_dir: &Path) -> anyhow::Result<()> {
     let build_script = work_dir.join("build.sh");
-    let mut file =
-        std::fs::File::create_new(build_script).context("Failed to create the build script")?;
     let content = include_str!("../scripts/build.sh");
-    file.write_all(content.as_bytes())
-        .context("Failed to write build script")?;
+    std::fs::write(build_script, content.as_bytes()).context("Failed to write build script")?;
     Ok(())
 }
 
-pub fn generate_env_file(
-    work_dir: &Path,
-    config: &crate::config_file::Config,
-) -> anyhow::Result<()> {
+fn generate_env_file(work_dir: &Path, config: &crate::config_file::Config) -> anyhow::Result<()> {
     let env_file = work_dir.join("env.sh");
-    let mut file = std::fs::File::create_new(env_file).context("Failed to create the env file")?;
-    let content = format!(
+    let mut content = format!(
         r#"
 TARGET_CHANNEL="{}"
 "#,
         config.conda.short_channel()?,
     );
-    file.write_all(content.as_bytes())
-        .context("Failed to write env.sh")?;
+    if let Some(quarantine) = config.conda.quarantine() {
+        content.push_str(&format!(
+            "QUARANTINE_CHANNEL=\"{}\"\n",
+            quarantine.short_channel()?
+        ));
+    }
+    std::fs::write(env_file, content.as_bytes()).context("Failed to write env.sh")?;
     Ok(())
 }
 
+/// Writes rattler-build recipes into a work directory
+pub struct RecipeGenerator {
+    work_dir: PathBuf,
+    /// The GitHub host repositories are on, for links to them
+    github_host: String,
+    /// Report missing required platforms as failures
+    pub strict: bool,
+    /// Recipe template for packages that do not configure their own
+    pub recipe_template: Option<PathBuf>,
+    /// Generate recipes even for packages that are in the channel already
+    pub force: bool,
+    pub existing_recipes: ExistingRecipes,
+    pub layout: RecipeLayout,
+    /// Generate one recipe for all platforms of a version, where possible
+    pub multi_platform: bool,
+    pub recipe_format: RecipeFormat,
+    /// Download archives to check that they contain only one executable,
+    /// unless the package picks its `binaries`
+    pub inspect_archives: bool,
+    /// The release assets packaged in earlier runs, keyed by download URL
+    pub known_assets: BTreeMap<String, crate::state::KnownAsset>,
+    /// Generate recipes with a bumped build number for packages in the
+    /// channel whose release asset was uploaded again since it was packaged
+    pub rebuild_reuploads: bool,
+}
+
+impl RecipeGenerator {
+    /// Set up `work_dir` for recipe generation
+    pub fn new(work_dir: &Path, config: &crate::config_file::Config) -> crate::Result<Self> {
+        generate_build_script(work_dir).map_err(crate::Error::Recipe)?;
+        generate_env_file(work_dir, config).map_err(crate::Error::Recipe)?;
+        Ok(Self {
+            work_dir: work_dir.to_path_buf(),
+            github_host: config.github_host.clone(),
+            strict: false,
+            recipe_template: None,
+            force: false,
+            existing_recipes: ExistingRecipes::default(),
+            layout: RecipeLayout::default(),
+            multi_platform: false,
+            recipe_format: RecipeFormat::default(),
+            inspect_archives: false,
+            known_assets: BTreeMap::new(),
+            rebuild_reuploads: false,
+        })
+    }
+
+    /// Whether `asset` changed since an earlier run packaged it
+    fn is_reupload(&self, asset: &octocrab::models::repos::Asset) -> bool {
+        self.known_assets
+            .get(asset.browser_download_url.as_str())
+            .is_some_and(|known| known.is_reupload(&asset.into()))
+    }
+
+    pub fn work_dir(&self) -> &Path {
+        &self.work_dir
+    }
+
+    /// Write `index.json` describing all recipes in the work directory,
+    /// including those left there by earlier runs
+    pub fn write_index(&self) -> crate::Result<PathBuf> {
+        let write = || -> anyhow::Result<PathBuf> {
+            let recipes = crate::clean::find_recipes(&self.work_dir, self.layout)?;
+            let entries = recipes
+                .iter()
+                .map(|r| IndexEntry {
+                    name: &r.name,
+                    version: &r.version,
+                    build_number: r.build_number,
+                    platforms: &r.platforms,
+                    path: r.path.strip_prefix(&self.work_dir).unwrap_or(&r.path),
+                })
+                .collect::<Vec<_>>();
+            let index_file = self.work_dir.join("index.json");
+            std::fs::write(&index_file, serde_json::to_string_pretty(&entries)?)
+                .context("Failed to write index.json")?;
+            Ok(index_file)
+        };
+        write().map_err(crate::Error::Recipe)
+    }
+
+    /// Generate recipes for all `releases` of `package` that are not in
+    /// `repo_packages` yet. At most `package_count_limit` recipes are
+    /// generated.
+    pub fn generate(
+        &self,
+        package: &Package,
+        repository: &octocrab::models::Repository,
+        releases: &crate::github::Releases,
+        repo_packages: &[crate::conda::ChannelPackage],
+        package_count_limit: usize,
+        inspections: &crate::inspect::Inspections,
+    ) -> crate::Result<Generated> {
+        self.generate_packaging_data(
+            package,
+            repository,
+            releases,
+            repo_packages,
+            package_count_limit,
+            Pass::Generate(inspections),
+        )
+        .map_err(crate::Error::Recipe)
+    }
+
+    /// The assets [`Self::generate`] needs looked into before it can
+    /// generate recipes for them
+    pub fn planned_inspections(
+        &self,
+        package: &Package,
+        repository: &octocrab::models::Repository,
+        releases: &crate::github::Releases,
+        repo_packages: &[crate::conda::ChannelPackage],
+        package_count_limit: usize,
+    ) -> crate::Result<Vec<(Inspection, octocrab::models::repos::Asset)>> {
+        let mut planned = vec![];
+        self.generate_packaging_data(
+            package,
+            repository,
+            releases,
+            repo_packages,
+            package_count_limit,
+            Pass::Plan(&mut planned),
+        )
+        .map_err(crate::Error::Recipe)?;
+        Ok(planned)
+    }
+}
+
+/// What [`RecipeGenerator::generate`] did for a package
+pub struct Generated {
+    pub status: Vec<VersionPackagingStatus>,
+    /// Recipes counted against the package count limit
+    pub count: usize,
+    /// Recipes written into the work directory
+    pub written: usize,
+}
+
+/// What a pass over the releases of a package does
+enum Pass<'a> {
+    /// Collect the assets to look into, without generating recipes
+    Plan(&'a mut Vec<(Inspection, octocrab::models::repos::Asset)>),
+    /// Generate recipes with what looking into assets found
+    Generate(&'a crate::inspect::Inspections),
+}
+
 pub struct PackagingStatus {
     pub platform: Platform,
     pub status: Status,
     pub message: String,
+    /// The release assets a recipe was generated from
+    pub assets: Vec<ChosenAsset>,
+    /// The policy keeps the release from being packaged
+    pub held_by_policy: bool,
+}
+
+/// A release asset octoconda generated a recipe from
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct ChosenAsset {
+    pub name: String,
+    pub url: String,
+    /// The `algorithm:value` digest put into the recipe, if GitHub reported
+    /// a usable one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// Where the `digest` came from, if not from GitHub's asset data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest_source: Option<&'static str>,
+    /// The GitHub node id of the asset, which changes when it is deleted and
+    /// uploaded again
+    pub node_id: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<&octocrab::models::repos::Asset> for ChosenAsset {
+    fn from(asset: &octocrab::models::repos::Asset) -> Self {
+        Self {
+            name: asset.name.clone(),
+            url: asset.browser_download_url.to_string(),
+            digest: extract_digest(asset).map(|d| format!("{}:{}", d.algorithm, d.value)),
+            digest_source: None,
+            node_id: asset.node_id.clone(),
+            updated_at: asset.updated_at,
+        }
+    }
 }
 
 pub struct VersionPackagingStatus {
     pub version: Option<String>,
+    /// Platforms with a matching asset in this version
+    pub found_platforms: Vec<Platform>,
     pub status: Vec<PackagingStatus>,
 }
 
 impl PackagingStatus {
-    pub fn github_failed() -> Vec<Self> {
+    pub fn github_failed(error: &str) -> Vec<Self> {
+        vec![Self {
+            platform: rattler_conda_types::Platform::Unknown,
+            status: Status::Failed,
+            message: format!("could not retrieve release information from Github: {error}"),
+            assets: vec![],
+            held_by_policy: false,
+        }]
+    }
+
+    pub fn channel_unavailable(error: &str) -> Vec<Self> {
         vec![Self {
             platform: rattler_conda_types::Platform::Unknown,
             status: Status::Failed,
-            message: "could not retrieve release information from Github".to_string(),
+            message: format!("could not check the channel for existing packages: {error}"),
+            assets: vec![],
+            held_by_policy: false,
         }]
     }
 
-    pub fn recipe_generation_failed(platform: Platform) -> Self {
+    pub fn timed_out() -> Vec<Self> {
+        vec![Self {
+            platform: rattler_conda_types::Platform::Unknown,
+            status: Status::Failed,
+            message: "timed out retrieving release information from Github".to_string(),
+            assets: vec![],
+            held_by_policy: false,
+        }]
+    }
+
+    pub fn recipe_generation_failed(platform: Platform, error: &str) -> Self {
         Self {
             platform,
             status: Status::Failed,
-            message: "could not generate package recipe".to_string(),
+            message: format!("could not generate package recipe: {error}"),
+            assets: vec![],
+            held_by_policy: false,
+        }
+    }
+
+    pub fn invalid_version(error: &str) -> Self {
+        Self {
+            platform: Platform::Unknown,
+            status: Status::Failed,
+            message: format!("could not parse version number from github release: {error}"),
+            assets: vec![],
+            held_by_policy: false,
         }
     }
 
-    pub fn invalid_version() -> Self {
+    pub fn rejected_tag(reason: &str) -> Self {
         Self {
             platform: Platform::Unknown,
+            status: Status::Skipped,
+            message: format!("ignored release tag: {reason}"),
+            assets: vec![],
+            held_by_policy: false,
+        }
+    }
+
+    pub fn duplicate_version(newer_tag: &str) -> Self {
+        Self {
+            platform: Platform::Unknown,
+            status: Status::Skipped,
+            message: format!("same version as the newer release {newer_tag}"),
+            assets: vec![],
+            held_by_policy: false,
+        }
+    }
+
+    pub fn older_versions_ignored(ignored: usize, keep: usize) -> Self {
+        Self {
+            platform: Platform::Unknown,
+            status: Status::Skipped,
+            message: format!("ignored {ignored} versions older than the newest {keep}"),
+            assets: vec![],
+            held_by_policy: false,
+        }
+    }
+
+    pub fn other_versions_than_pin(pin: &str, ignored: usize, found: bool) -> Self {
+        let message = if found {
+            format!("ignored {ignored} versions, the package is pinned to {pin}")
+        } else {
+            format!("no release of the pinned version {pin}, ignored {ignored} versions")
+        };
+        Self {
+            platform: Platform::Unknown,
+            status: Status::Skipped,
+            message,
+            assets: vec![],
+            held_by_policy: false,
+        }
+    }
+
+    pub fn asset_too_large(
+        platform: Platform,
+        asset: &octocrab::models::repos::Asset,
+        max_asset_size: u64,
+    ) -> Self {
+        Self {
+            platform,
+            status: Status::Failed,
+            message: format!(
+                "{} has {} bytes, more than the max_asset_size of {max_asset_size}",
+                asset.name, asset.size
+            ),
+            assets: vec![],
+            held_by_policy: false,
+        }
+    }
+
+    pub fn policy_violation(platform: Platform, violation: crate::policy::Violation) -> Self {
+        let (status, message) = match violation {
+            crate::policy::Violation::TooYoung(message) => (Status::Skipped, message),
+            crate::policy::Violation::Forbidden(message) => (Status::Failed, message),
+        };
+        Self {
+            platform,
+            status,
+            message: format!("policy violation: {message}"),
+            assets: vec![],
+            held_by_policy: true,
+        }
+    }
+
+    pub fn several_executables(
+        platform: Platform,
+        asset_name: &str,
+        executables: &[String],
+    ) -> Self {
+        Self {
+            platform,
             status: Status::Failed,
-            message: "could not parse version number from github release".to_string(),
+            message: format!(
+                "{asset_name} contains several executables ({}), pick some with `binaries`",
+                executables.join(", ")
+            ),
+            assets: vec![],
+            held_by_policy: false,
         }
     }
 
@@ -99,6 +525,8 @@ impl PackagingStatus {
             platform,
             status: Status::Succeeded,
             message: "already in conda".to_string(),
+            assets: vec![],
+            held_by_policy: false,
         }
     }
 
@@ -107,380 +535,2455 @@ impl PackagingStatus {
             platform,
             status: Status::Skipped,
             message: "platform file not found".to_string(),
+            assets: vec![],
+            held_by_policy: false,
         }
     }
 
+    pub fn disabled() -> Self {
+        Self {
+            platform: Platform::Unknown,
+            status: Status::Disabled,
+            message: "disabled in the configuration".to_string(),
+            assets: vec![],
+            held_by_policy: false,
+        }
+    }
+
+    pub fn on_conda_forge(skipped: bool) -> Self {
+        if skipped {
+            Self {
+                platform: Platform::Unknown,
+                status: Status::Skipped,
+                message: "available on conda-forge, not packaging".to_string(),
+                assets: vec![],
+                held_by_policy: false,
+            }
+        } else {
+            Self {
+                platform: Platform::Unknown,
+                status: Status::Succeeded,
+                message: "also available on conda-forge".to_string(),
+                assets: vec![],
+                held_by_policy: false,
+            }
+        }
+    }
+
+    pub fn missing_required_platform(platform: Platform) -> Self {
+        Self {
+            platform,
+            status: Status::Failed,
+            message: "required platform file not found".to_string(),
+            assets: vec![],
+            held_by_policy: false,
+        }
+    }
+
+    pub fn unsupported_os(oses: &[String]) -> Self {
+        Self {
+            platform: Platform::Unknown,
+            status: Status::Skipped,
+            message: format!("ignored assets for unsupported OS: {}", oses.join(", ")),
+            assets: vec![],
+            held_by_policy: false,
+        }
+    }
+
+    pub fn existing_recipe(platform: Platform) -> Self {
+        Self {
+            platform,
+            status: Status::Skipped,
+            message: "kept the recipe of an earlier run".to_string(),
+            assets: vec![],
+            held_by_policy: false,
+        }
+    }
+
+    /// The assets the recipe was generated from
+    pub fn with_assets(mut self, assets: Vec<ChosenAsset>) -> Self {
+        self.assets = assets;
+        self
+    }
+
     pub fn success(platform: Platform) -> Self {
         Self {
             platform,
             status: Status::Succeeded,
             message: "ok".to_string(),
+            assets: vec![],
+            held_by_policy: false,
         }
     }
 }
 
-pub fn report_results(status: &HashMap<String, Vec<VersionPackagingStatus>>) -> String {
-    let mut result = String::new();
-    for (package, sub_status) in status {
-        let package_status = sub_status.iter().flat_map(|v| v.status.iter()).fold(
-            Status::Succeeded,
-            |acc, s| match (&s.status, acc) {
-                (&Status::Failed, _) => Status::Failed,
-                (&Status::Succeeded, Status::Failed) => Status::Failed,
-                (&Status::Succeeded, Status::Succeeded) => Status::Succeeded,
-                (&Status::Succeeded, Status::Skipped) => Status::Succeeded,
-                (&Status::Skipped, Status::Failed) => Status::Failed,
-                (&Status::Skipped, Status::Succeeded) => Status::Succeeded,
-                (&Status::Skipped, Status::Skipped) => Status::Skipped,
-            },
-        );
+/// Which assets may match platform patterns at all
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AssetFilter<'a> {
+    /// Assets matching any of these are never picked
+    pub excludes: &'a [regex::Regex],
+    /// Consider the "Source code" pseudo-assets GitHub lists for releases
+    pub source_code: bool,
+    /// Only assets with this in their name, ignoring case
+    pub variant: Option<&'a str>,
+    /// The package the assets are matched for, see [`unsupported_os`]
+    pub package: Option<&'a Package>,
+}
 
-        result.push_str(&format!(
-            "{package_status}: {} ({} packages)\n",
-            package,
-            sub_status.len()
-        ));
+impl AssetFilter<'_> {
+    fn allows(&self, asset_name: &str) -> bool {
+        unsupported_os(asset_name, self.package).is_none()
+            && (self.source_code || !is_source_code(asset_name))
+            && !self.excludes.iter().any(|e| e.is_match(asset_name))
+            && self
+                .variant
+                .is_none_or(|v| asset_name.to_lowercase().contains(&v.to_lowercase()))
+    }
+}
+
+/// Whether `asset_name` is one of the archives GitHub generates from the
+/// tagged sources
+///
+/// These contain no binaries, so they are only used when asked for.
+pub fn is_source_code(asset_name: &str) -> bool {
+    asset_name == "Source code"
+        || asset_name
+            .strip_prefix("Source code (")
+            .is_some_and(|rest| rest.ends_with(')'))
+}
+
+/// The filter for assets of `package` on `platform`
+fn asset_filter<'a>(package: &'a Package, platform: &Platform) -> AssetFilter<'a> {
+    AssetFilter {
+        excludes: package
+            .exclude_assets
+            .get(platform)
+            .map(Vec::as_slice)
+            .unwrap_or_default(),
+        source_code: package.source_code_assets,
+        variant: None,
+        package: Some(package),
+    }
+}
+
+pub fn match_platform<'a>(
+    patterns: &[regex::Regex],
+    filter: &AssetFilter,
+    preference: &AssetPreference,
+    assets: &'a [octocrab::models::repos::Asset],
+) -> Option<&'a octocrab::models::repos::Asset> {
+    let asset_names = assets.iter().map(|a| a.name.as_str()).collect::<Vec<_>>();
+    let sizes = assets.iter().map(|a| a.size).collect::<Vec<_>>();
+    let candidates = matching_assets(patterns, filter, &asset_names);
+    pick_asset(&candidates, &asset_names, &sizes, preference).map(|index| &assets[index])
+}
 
-        for vs in sub_status {
-            let mut version = vs.version.clone().unwrap_or_default();
+/// Operating systems there is no conda platform for
+const UNSUPPORTED_OSES: &[&str] = &[
+    "android",
+    "dragonfly",
+    "freebsd",
+    "illumos",
+    "netbsd",
+    "openbsd",
+    "solaris",
+];
+
+/// The unsupported OS an asset of `package` is built for, if any
+///
+/// Assets for these never match any platform pattern. Only the part of the
+/// asset name after the package or repository name is looked at, so that
+/// tools named after an OS still match. `package` can replace the list of
+/// unsupported OSes.
+pub fn unsupported_os(asset_name: &str, package: Option<&Package>) -> Option<String> {
+    let name = asset_name.to_ascii_lowercase();
+    let target = package
+        .into_iter()
+        .flat_map(|p| {
+            [
+                p.name.to_ascii_lowercase(),
+                p.repository.repo.to_ascii_lowercase(),
+            ]
+        })
+        .filter_map(|prefix| name.strip_prefix(prefix.as_str()))
+        .min_by_key(|rest| rest.len())
+        .unwrap_or(&name);
+    let oses = package
+        .and_then(|p| p.unsupported_oses.as_deref())
+        .map(|oses| oses.iter().map(String::as_str).collect::<Vec<_>>())
+        .unwrap_or_else(|| UNSUPPORTED_OSES.to_vec());
+    target
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .find_map(|part| {
+            oses.iter()
+                .find(|os| part.starts_with(&os.to_ascii_lowercase()))
+        })
+        .map(|os| os.to_string())
+}
+
+/// Indices of all assets matched by the first pattern that matches any
+fn matching_assets(patterns: &[regex::Regex], filter: &AssetFilter, assets: &[&str]) -> Vec<usize> {
+    for r in patterns {
+        let matches = assets
+            .iter()
+            .enumerate()
+            .filter(|(_, a)| filter.allows(a))
+            .filter(|(_, a)| r.is_match(a))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        if !matches.is_empty() {
+            return matches;
+        }
+    }
+    vec![]
+}
 
-            let skipped = {
-                let skipped = vs
-                    .status
+/// Pick one of the `candidates` according to `preference`
+///
+/// Falls back to the first candidate when nothing else decides.
+fn pick_asset(
+    candidates: &[usize],
+    names: &[&str],
+    sizes: &[i64],
+    preference: &AssetPreference,
+) -> Option<usize> {
+    candidates.iter().copied().min_by_key(|&index| {
+        let name = names[index].to_lowercase();
+        let preferred = preference
+            .prefer
+            .iter()
+            .position(|p| name.contains(&p.to_lowercase()))
+            .unwrap_or(preference.prefer.len());
+        let size = match preference.size {
+            SizePreference::Ignore => 0,
+            SizePreference::Smallest => sizes[index],
+            SizePreference::Largest => -sizes[index],
+        };
+        (preferred, size, index)
+    })
+}
+
+impl RecipeGenerator {
+    fn recipe_template(&self, package: &Package) -> anyhow::Result<Option<String>> {
+        package
+            .recipe_template
+            .as_ref()
+            .or(self.recipe_template.as_ref())
+            .map(|path| {
+                std::fs::read_to_string(path)
+                    .context(format!("Failed to read recipe template {path:?}"))
+            })
+            .transpose()
+    }
+
+    fn generate_packaging_data(
+        &self,
+        package: &Package,
+        repository: &octocrab::models::Repository,
+        releases: &crate::github::Releases,
+        repo_packages: &[crate::conda::ChannelPackage],
+        package_count_limit: usize,
+        mut pass: Pass,
+    ) -> anyhow::Result<Generated> {
+        let mut result = vec![];
+        let mut package_generation_count: usize = 0;
+        let mut written = 0;
+        let now = chrono::Utc::now();
+
+        let metadata = RepositoryMetadata::new(package, repository, &self.github_host);
+        let template = self.recipe_template(package)?;
+
+        let kept_versions = package.keep_versions.map(|keep| {
+            newest_versions(
+                releases
                     .iter()
-                    .filter_map(|s| (s.status == Status::Skipped).then_some(s.platform))
-                    .fold(String::new(), |acc, p| {
-                        if acc.is_empty() {
-                            format!("{p}")
-                        } else {
-                            format!("{acc}, {p}")
-                        }
+                    .filter_map(|(_, v)| v.as_ref().ok())
+                    .map(|(version, _)| version.as_str()),
+                keep,
+            )
+        });
+        let mut ignored_versions = HashSet::new();
+        let mut unpinned_versions = HashSet::new();
+        let mut pin_found = false;
+
+        let platforms_since = platforms_since(package, releases);
+        let newest_releases = newest_indices(releases.iter().enumerate().filter_map(
+            |(index, (r, tag_version))| {
+                let (version, build_number) = tag_version.as_ref().ok()?;
+                let version = rattler_conda_types::Version::from_str(version).ok()?;
+                let key = (version, *build_number);
+                Some((index, (key, r.published_at.or(r.created_at))))
+            },
+        ));
+
+        for (index, (r, tag_version)) in releases.iter().enumerate() {
+            let (version_string, build_number) = match tag_version {
+                Ok(version) => version,
+                Err(reason) => {
+                    result.push(VersionPackagingStatus {
+                        version: Some(r.tag_name.clone()),
+                        found_platforms: vec![],
+                        status: vec![PackagingStatus::rejected_tag(reason)],
                     });
-                if skipped.is_empty() {
-                    skipped
-                } else {
-                    format!(" skipped: {skipped}")
+                    continue;
                 }
             };
-
-            result.push_str(&format!("    {version}{skipped}\n"));
-
-            for s in &vs.status {
-                if s.status == Status::Skipped {
+            let version = match rattler_conda_types::Version::from_str(version_string) {
+                Ok(version) => version,
+                Err(e) => {
+                    result.push(VersionPackagingStatus {
+                        version: Some(version_string.clone()),
+                        found_platforms: vec![],
+                        status: vec![PackagingStatus::invalid_version(&e.to_string())],
+                    });
+                    continue;
+                }
+            };
+            let newest = newest_releases[&(version.clone(), *build_number)];
+            if newest != index {
+                result.push(VersionPackagingStatus {
+                    version: Some(r.tag_name.clone()),
+                    found_platforms: vec![],
+                    status: vec![PackagingStatus::duplicate_version(
+                        &releases[newest].0.tag_name,
+                    )],
+                });
+                continue;
+            }
+            if kept_versions
+                .as_ref()
+                .is_some_and(|kept| !kept.contains(&version))
+            {
+                ignored_versions.insert(version);
+                continue;
+            }
+            if let Some(pin) = &package.pin {
+                if *pin != version {
+                    unpinned_versions.insert(version);
                     continue;
                 }
-                result.push_str(&format!(
-                    "        {}: {} {}\n",
-                    s.status, s.platform, s.message
-                ));
-                version = version.chars().map(|_| ' ').collect()
+                pin_found = true;
+            }
+            let platform_patterns = package.patterns_for(&version);
+            // Platforms upstream did not build for yet
+            let unexpected_platforms = platforms_since
+                .iter()
+                .filter(|(_, since)| version < **since)
+                .map(|(platform, _)| *platform)
+                .collect::<HashSet<_>>();
+            let version = VersionWithSource::new(version, version_string);
+            let mut version_result = vec![];
+
+            let mut found_platforms = HashSet::new();
+            // Templates are per platform and variant, conda-build has no
+            // selectors octoconda could generate
+            let selectors = self.recipe_format == RecipeFormat::Rattler && template.is_none();
+            // Build strings are per platform as well
+            let multi_platform = self.multi_platform
+                && selectors
+                && package.build_string.is_none()
+                && package.variants.is_empty()
+                && !package.kind.is_noarch();
+            let mut multi_platform_inputs = vec![];
+
+            for (platform, pattern) in &platform_patterns {
+                let _span = tracing::debug_span!("platform", %platform, version = %version_string)
+                    .entered();
+                let filter = asset_filter(package, platform);
+                let variant_assets = package
+                    .variants
+                    .iter()
+                    .filter(|_| selectors)
+                    .filter_map(|variant| {
+                        let filter = AssetFilter {
+                            variant: Some(variant.as_str()),
+                            ..filter
+                        };
+                        match_platform(
+                            &pattern[..],
+                            &filter,
+                            &package.asset_preference,
+                            &r.assets[..],
+                        )
+                        .map(|asset| (variant.as_str(), asset))
+                    })
+                    .collect::<Vec<_>>();
+                let asset = variant_assets.first().map(|(_, a)| *a).or_else(|| {
+                    match_platform(
+                        &pattern[..],
+                        &filter,
+                        &package.asset_preference,
+                        &r.assets[..],
+                    )
+                });
+                if let Some(asset) = asset {
+                    found_platforms.insert(platform);
+
+                    if package_generation_count < package_count_limit {
+                        let mut base_input = RecipeInput {
+                            package,
+                            version: version_string,
+                            build_number: *build_number,
+                            platform,
+                            metadata: &metadata,
+                            release_notes: r.body.as_deref(),
+                            asset,
+                            wheel: None,
+                            commit: tag_commit(r),
+                        };
+                        if let Err(e) = base_input.build_string() {
+                            version_result.push(PackagingStatus::recipe_generation_failed(
+                                *platform,
+                                &format!("{e:#}"),
+                            ));
+                            continue;
+                        }
+                        if !self.force && base_input.is_in_channel(repo_packages, &version) {
+                            if !(self.rebuild_reuploads && self.is_reupload(asset)) {
+                                version_result.push(
+                                    PackagingStatus::skip_platform(*platform)
+                                        .with_assets(vec![asset.into()]),
+                                );
+                                continue;
+                            }
+                            if let Pass::Generate(_) = pass {
+                                tracing::warn!(
+                                    "{}: {} was uploaded again, rebuilding {version_string}",
+                                    package.name,
+                                    asset.name
+                                );
+                            }
+                            base_input.build_number =
+                                base_input.next_build_number(repo_packages, &version);
+                        }
+                        let too_large = std::iter::once(asset)
+                            .chain(variant_assets.iter().map(|(_, a)| *a))
+                            .find(|a| {
+                                package
+                                    .max_asset_size
+                                    .is_some_and(|max| a.size as u64 > max)
+                            });
+                        if let Some(asset) = too_large {
+                            version_result.push(PackagingStatus::asset_too_large(
+                                *platform,
+                                asset,
+                                package.max_asset_size.unwrap_or_default(),
+                            ));
+                            continue;
+                        }
+                        if let Err(violation) = crate::policy::check(
+                            &package.policy,
+                            &package.repository,
+                            r,
+                            asset,
+                            now,
+                        ) {
+                            version_result
+                                .push(PackagingStatus::policy_violation(*platform, violation));
+                            continue;
+                        }
+                        if let Pass::Plan(planned) = &mut pass {
+                            // Assets GitHub has no digest for get theirs from
+                            // the attestations of the release
+                            if std::iter::once(asset)
+                                .chain(variant_assets.iter().map(|(_, a)| *a))
+                                .any(|a| a.digest.is_none())
+                            {
+                                planned.extend(
+                                    r.assets
+                                        .iter()
+                                        .filter(|a| crate::attestation::is_attestation(&a.name))
+                                        .map(|a| (Inspection::Attestation, a.clone())),
+                                );
+                            }
+                        }
+                        if self.inspect_archives
+                            && package.kind == PackageKind::Binary
+                            && package.binaries.is_empty()
+                        {
+                            let assets = std::iter::once(asset)
+                                .chain(variant_assets.iter().map(|(_, a)| *a).filter(|a| {
+                                    a.browser_download_url != asset.browser_download_url
+                                }))
+                                .collect::<Vec<_>>();
+                            match &mut pass {
+                                Pass::Plan(planned) => planned.extend(
+                                    assets
+                                        .iter()
+                                        .map(|a| (Inspection::Executables, (*a).clone())),
+                                ),
+                                Pass::Generate(inspections) => {
+                                    // Self-updaters are removed by the build script
+                                    let several = assets.iter().find_map(|a| {
+                                        let executables = match inspections.executables(a) {
+                                            Ok(found) => {
+                                                let found = found.unwrap_or_default();
+                                                let updaters = if package.keep_updaters {
+                                                    vec![]
+                                                } else {
+                                                    crate::inspect::updaters(found, &package.name)
+                                                };
+                                                found
+                                                    .iter()
+                                                    .filter(|e| !updaters.contains(e))
+                                                    .cloned()
+                                                    .collect::<Vec<_>>()
+                                            }
+                                            Err(e) => {
+                                                tracing::warn!("{e:#}");
+                                                return None;
+                                            }
+                                        };
+                                        (executables.len() > 1).then_some((a, executables))
+                                    });
+                                    if let Some((a, executables)) = several {
+                                        version_result.push(PackagingStatus::several_executables(
+                                            *platform,
+                                            &a.name,
+                                            &executables,
+                                        ));
+                                        continue;
+                                    }
+                                }
+                            }
+                        }
+                        // Wheels declare their requirements in their metadata
+                        let wheel =
+                            if self.inspect_archives && package.kind == PackageKind::PythonWheel {
+                                match &mut pass {
+                                    Pass::Plan(planned) => {
+                                        planned.push((Inspection::Wheel, asset.clone()));
+                                        None
+                                    }
+                                    Pass::Generate(inspections) => match inspections.wheel(asset) {
+                                        Ok(wheel) => Some(wheel),
+                                        Err(e) => {
+                                            version_result.push(
+                                                PackagingStatus::recipe_generation_failed(
+                                                    *platform,
+                                                    &format!("{e:#}"),
+                                                ),
+                                            );
+                                            continue;
+                                        }
+                                    },
+                                }
+                            } else {
+                                None
+                            };
+                        if let Pass::Plan(_) = pass {
+                            package_generation_count += 1;
+                            continue;
+                        }
+                        let input = RecipeInput {
+                            wheel,
+                            ..base_input
+                        };
+
+                        let status = if variant_assets.len() > 1 {
+                            generate_variant_package(
+                                &self.work_dir,
+                                self.layout,
+                                &input,
+                                &variant_assets,
+                                self.existing_recipes,
+                            )
+                        } else if multi_platform {
+                            multi_platform_inputs.push(base_input);
+                            package_generation_count += 1;
+                            continue;
+                        } else {
+                            generate_package(
+                                &self.work_dir,
+                                self.layout,
+                                self.recipe_format,
+                                template.as_deref(),
+                                &input,
+                                self.existing_recipes,
+                            )
+                        };
+                        if status.status == Status::Succeeded {
+                            written += 1;
+                        }
+                        version_result.push(status);
+                        package_generation_count += 1;
+                    }
+                }
+            }
+            if !multi_platform_inputs.is_empty() {
+                let status = generate_multi_platform_package(
+                    &self.work_dir,
+                    self.layout,
+                    &multi_platform_inputs,
+                    self.existing_recipes,
+                );
+                // One recipe covers all platforms
+                if status.iter().any(|s| s.status == Status::Succeeded) {
+                    written += 1;
+                }
+                version_result.extend(status);
+            }
+
+            for platform in package.platforms.keys() {
+                if !found_platforms.contains(platform) {
+                    if self.strict && package.required_platforms.contains(platform) {
+                        version_result.push(PackagingStatus::missing_required_platform(*platform));
+                    } else if !unexpected_platforms.contains(platform) {
+                        version_result.push(PackagingStatus::missing_platform(*platform));
+                    }
+                }
+            }
+
+            let mut unsupported = r
+                .assets
+                .iter()
+                .filter_map(|a| unsupported_os(&a.name, Some(package)))
+                .collect::<Vec<_>>();
+            unsupported.sort();
+            unsupported.dedup();
+            if !unsupported.is_empty() {
+                version_result.push(PackagingStatus::unsupported_os(&unsupported));
+            }
+
+            result.push(VersionPackagingStatus {
+                version: Some(format!("{version_string}-{build_number}")),
+                found_platforms: found_platforms.into_iter().copied().collect(),
+                status: version_result,
+            });
+        }
+
+        if let Some(keep) = package
+            .keep_versions
+            .filter(|_| !ignored_versions.is_empty())
+        {
+            result.push(VersionPackagingStatus {
+                version: None,
+                found_platforms: vec![],
+                status: vec![PackagingStatus::older_versions_ignored(
+                    ignored_versions.len(),
+                    keep,
+                )],
+            });
+        }
+
+        if let Some(pin) = package
+            .pin
+            .as_ref()
+            .filter(|_| !pin_found || !unpinned_versions.is_empty())
+        {
+            result.push(VersionPackagingStatus {
+                version: None,
+                found_platforms: vec![],
+                status: vec![PackagingStatus::other_versions_than_pin(
+                    &pin.to_string(),
+                    unpinned_versions.len(),
+                    pin_found,
+                )],
+            });
+        }
+
+        Ok(Generated {
+            status: result,
+            count: package_generation_count,
+            written,
+        })
+    }
+}
+
+/// The first version each platform is expected in
+///
+/// That is the oldest version with a matching asset, unless the package
+/// configures something else. Platforms without any matching asset are
+/// expected everywhere, which points at broken patterns.
+fn platforms_since(
+    package: &Package,
+    releases: &crate::github::Releases,
+) -> HashMap<Platform, rattler_conda_types::Version> {
+    let mut result = HashMap::<Platform, rattler_conda_types::Version>::new();
+    for (r, tag_version) in releases {
+        let Some(version) = tag_version
+            .as_ref()
+            .ok()
+            .and_then(|(v, _)| rattler_conda_types::Version::from_str(v).ok())
+        else {
+            continue;
+        };
+        for (platform, patterns) in package.patterns_for(&version) {
+            if package.platforms_since.contains_key(&platform)
+                || result.get(&platform).is_some_and(|since| *since <= version)
+            {
+                continue;
+            }
+            let filter = asset_filter(package, &platform);
+            if match_platform(&patterns, &filter, &package.asset_preference, &r.assets).is_some() {
+                result.insert(platform, version.clone());
             }
         }
     }
+    result.extend(
+        package
+            .platforms_since
+            .iter()
+            .map(|(platform, since)| (*platform, since.clone())),
+    );
     result
 }
 
-fn match_platform<'a>(
-    patterns: &[regex::Regex],
-    assets: &'a [octocrab::models::repos::Asset],
-) -> Option<&'a octocrab::models::repos::Asset> {
-    let asset_names = assets.iter().map(|a| a.name.as_str()).collect::<Vec<_>>();
-    match_platform_names(patterns, &asset_names).map(|index| &assets[index])
+/// Index of the newest item for each key, the first one wins ties
+///
+/// Items are `(index, (key, timestamp))` in the order of `items`.
+fn newest_indices<K: std::hash::Hash + Eq>(
+    items: impl Iterator<Item = (usize, (K, Option<chrono::DateTime<chrono::Utc>>))>,
+) -> HashMap<K, usize> {
+    let mut newest = HashMap::<K, (usize, Option<chrono::DateTime<chrono::Utc>>)>::new();
+    for (index, (key, timestamp)) in items {
+        match newest.get(&key) {
+            Some((_, current)) if *current >= timestamp => {}
+            _ => {
+                newest.insert(key, (index, timestamp));
+            }
+        }
+    }
+    newest
+        .into_iter()
+        .map(|(key, (index, _))| (key, index))
+        .collect()
 }
 
-fn match_platform_names<'a>(patterns: &[regex::Regex], assets: &'a [&'a str]) -> Option<usize> {
-    for r in patterns {
-        for (index, a) in assets.iter().enumerate() {
-            if r.is_match(&a.to_ascii_lowercase()) {
-                return Some(index);
+/// The `keep` newest of `versions`, ignoring those that are no conda version
+fn newest_versions<'a>(
+    versions: impl Iterator<Item = &'a str>,
+    keep: usize,
+) -> HashSet<rattler_conda_types::Version> {
+    let mut versions = versions
+        .filter_map(|v| rattler_conda_types::Version::from_str(v).ok())
+        .collect::<Vec<_>>();
+    versions.sort_by(|a, b| b.cmp(a));
+    versions.dedup();
+    versions.into_iter().take(keep).collect()
+}
+
+/// Digest algorithms octoconda knows how to put into a recipe
+const DIGEST_ALGORITHMS: &[&str] = &["sha256", "sha512", "md5"];
+
+/// Parse the `algorithm:hex` digest Github reports for `asset`
+///
+/// Unknown algorithms and malformed digests are ignored with a warning.
+fn extract_digest(asset: &octocrab::models::repos::Asset) -> Option<Digest> {
+    let digest = asset.digest.as_deref()?;
+    let parsed = digest.split_once(':').and_then(|(algorithm, value)| {
+        let algorithm = algorithm.trim().to_ascii_lowercase();
+        let value = value.trim().to_ascii_lowercase();
+        (DIGEST_ALGORITHMS.contains(&algorithm.as_str())
+            && !value.is_empty()
+            && value.chars().all(|c| c.is_ascii_hexdigit()))
+        .then_some(Digest { algorithm, value })
+    });
+    if parsed.is_none() {
+        tracing::warn!("Ignoring unsupported digest {digest:?} of {}", asset.name);
+    }
+    parsed
+}
+
+/// Repository metadata for the `about` and `extra` sections of a recipe
+///
+/// Github does not always fill in all fields, so everything that is not
+/// required has a fallback or is optional.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepositoryMetadata {
+    /// `owner/repo`
+    pub full_name: String,
+    pub homepage: Option<String>,
+    /// SPDX license identifier
+    pub license: Option<String>,
+    /// Short description, used as the package summary
+    pub description: Option<String>,
+    pub documentation: Option<String>,
+    /// The repository on GitHub
+    pub dev_url: String,
+}
+
+fn non_empty(value: Option<&str>) -> Option<String> {
+    value
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+/// Does `url` look like it points to documentation?
+fn is_documentation_url(url: &str) -> bool {
+    let Ok(url) = url::Url::parse(url) else {
+        return false;
+    };
+    let host = url.host_str().unwrap_or_default();
+    host.starts_with("docs.")
+        || host.ends_with(".readthedocs.io")
+        || host.ends_with(".readthedocs.org")
+        || url
+            .path_segments()
+            .is_some_and(|mut s| s.any(|s| matches!(s, "doc" | "docs" | "book")))
+}
+
+impl RepositoryMetadata {
+    /// The metadata of `repository` on `github_host`, with the settings of
+    /// `package` taking precedence
+    pub fn new(
+        package: &Package,
+        repository: &octocrab::models::Repository,
+        github_host: &str,
+    ) -> Self {
+        let configured_name = format!("{}/{}", package.repository.owner, package.repository.repo);
+
+        let full_name = repository
+            .html_url
+            .as_ref()
+            .and_then(|u| non_empty(u.path().strip_prefix('/')))
+            .or_else(|| non_empty(repository.full_name.as_deref()))
+            .unwrap_or(configured_name);
+
+        let license = repository
+            .license
+            .as_ref()
+            .map(|l| match l.spdx_id.as_str() {
+                // Fix outdated licenses
+                "GPL-3.0" => "GPL-3.0-only",
+                l => l,
+            })
+            // Github uses this when it can not detect the license
+            .filter(|l| *l != "NOASSERTION")
+            .and_then(|l| non_empty(Some(l)));
+
+        let dev_url = repository
+            .html_url
+            .as_ref()
+            .map(|u| u.as_str().trim_end_matches('/').to_string())
+            .unwrap_or_else(|| format!("https://{github_host}/{full_name}"));
+        // Repositories sometimes have junk like "TBD" as homepage
+        let upstream_homepage = non_empty(repository.homepage.as_deref());
+        let valid_homepage = upstream_homepage
+            .clone()
+            .filter(|h| crate::config_file::http_url(h).is_ok());
+        let homepage = non_empty(package.homepage.as_deref()).or_else(|| {
+            match (&upstream_homepage, &valid_homepage) {
+                (Some(junk), None) => {
+                    tracing::debug!("{full_name}: Ignoring invalid homepage {junk:?}");
+                    Some(dev_url.clone())
+                }
+                _ => valid_homepage.clone(),
+            }
+        });
+        let documentation = non_empty(package.documentation.as_deref()).or_else(|| {
+            valid_homepage
+                .as_ref()
+                .filter(|h| is_documentation_url(h))
+                .cloned()
+        });
+
+        Self {
+            dev_url,
+            full_name,
+            homepage,
+            license,
+            description: non_empty(package.summary.as_deref())
+                .or_else(|| non_empty(repository.description.as_deref())),
+            documentation,
+        }
+    }
+}
+
+/// Appended to the repository description in the `about` section
+const REPACKAGING_NOTE: &str = "... repackaged from github release.
+No files were modified, so all SHAs should match the github release files. \
+Files might have been moved, but no files should have been added or removed \
+(except for obvious junk files).
+Check the extra package data for details on where the github release file was \
+taken from.
+";
+
+/// [`REPACKAGING_NOTE`] for packages that rattler-build may modify
+const RELOCATING_NOTE: &str = "... repackaged from github release.
+Files were relocated into the conda environment, so SHAs might not match the \
+github release files. No files should have been added or removed (except for \
+obvious junk files).
+Check the extra package data for details on where the github release file was \
+taken from.
+";
+
+/// Extensions of assets that are a single script
+const SCRIPT_EXTENSIONS: &[&str] = &[".sh", ".bash", ".py", ".pl", ".rb"];
+
+fn is_script(asset_name: &str) -> bool {
+    let asset_name = asset_name.to_lowercase();
+    SCRIPT_EXTENSIONS.iter().any(|e| asset_name.ends_with(e))
+}
+
+/// Whether rattler-build should relocate binaries and replace the build
+/// prefix in files of `asset`, unless `package` says otherwise
+///
+/// Binaries are left alone: Relocating them changes their digests and breaks
+/// code signatures. Libraries need relocation to find each other in the
+/// environment. Scripts are text, so the usual prefix replacement is fine
+/// for them.
+fn relocation(package: &Package, asset_name: &str) -> (bool, bool) {
+    let script = is_script(asset_name);
+    (
+        package
+            .binary_relocation
+            .unwrap_or(package.kind == PackageKind::Library),
+        package.prefix_detection.unwrap_or(script),
+    )
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Digest {
+    algorithm: String,
+    value: String,
+}
+
+/// Values available to recipe templates
+#[derive(Debug, serde::Serialize)]
+struct RecipeContext<'a> {
+    name: String,
+    version: &'a str,
+    build_number: u32,
+    platform: String,
+    url: String,
+    digest: Option<Digest>,
+    file_name: String,
+    repository: &'a str,
+    homepage: Option<&'a str>,
+    dev_url: &'a str,
+    documentation: Option<&'a str>,
+    license: Option<&'a str>,
+    summary: Option<String>,
+    description: String,
+    release_notes: Option<String>,
+    maintainers: &'a [String],
+    doi: Option<&'a str>,
+    citation: Option<&'a str>,
+    build_string: Option<String>,
+    binary_relocation: bool,
+    prefix_detection: bool,
+    run_exports: &'a [String],
+    run_constraints: &'a [String],
+    kind: PackageKind,
+    install_to: Option<&'a str>,
+    /// Run requirements of python wheel and node packages
+    run_requirements: Vec<String>,
+    /// Top-level modules of python wheel packages, if known
+    python_modules: Vec<String>,
+}
+
+impl RecipeContext<'_> {
+    /// What the package must contain, depending on its kind
+    fn package_contents(&self) -> recipe::PackageContents {
+        let any = || vec!["*".to_string()];
+        let mut contents = recipe::PackageContents {
+            files: recipe::Files {
+                exists: vec![],
+                not_exists: vec![".*".to_string()],
+            },
+            bin: vec![],
+            lib: vec![],
+            include: vec![],
+            site_packages: vec![],
+        };
+        match (self.kind, self.install_to) {
+            // Data packages have nothing in `bin`
+            (PackageKind::Data, Some(dir)) => contents.files.exists = vec![format!("{dir}/**")],
+            (PackageKind::PythonWheel, _) => contents.site_packages = self.python_modules.clone(),
+            (PackageKind::Library, _) => {
+                contents.lib = any();
+                contents.include = any();
+            }
+            _ => contents.bin = any(),
+        }
+        contents
+    }
+
+    fn recipe(&self) -> Recipe {
+        let wheel = self.kind == PackageKind::PythonWheel;
+        let node = self.kind == PackageKind::Node;
+        let digest = |algorithm: &str| {
+            self.digest
+                .as_ref()
+                .filter(|d| d.algorithm == algorithm)
+                .map(|d| d.value.clone())
+        };
+
+        Recipe {
+            package: recipe::PackageSection {
+                name: self.name.clone(),
+                version: self.version.to_string(),
+            },
+            source: recipe::Source {
+                url: self.url.clone(),
+                sha256: digest("sha256"),
+                md5: digest("md5"),
+                file_name: self.file_name.clone(),
+            },
+            build: recipe::Build {
+                number: self.build_number,
+                string: self.build_string.clone(),
+                noarch: match self.kind {
+                    PackageKind::PythonWheel => Some("python".to_string()),
+                    PackageKind::Node => Some("generic".to_string()),
+                    _ => None,
+                },
+                script: wheel.then(|| {
+                    format!(
+                        "python -m pip install --no-deps --no-build-isolation -vv {}",
+                        self.file_name
+                    )
+                }),
+                dynamic_linking: recipe::DynamicLinking {
+                    binary_relocation: self.binary_relocation,
+                },
+                prefix_detection: recipe::PrefixDetection {
+                    ignore: !self.prefix_detection,
+                },
+            },
+            requirements: recipe::Requirements {
+                // `build.sh` reads `package.json` and installs dependencies
+                // with node
+                build: if node {
+                    vec!["nodejs".to_string()]
+                } else {
+                    vec![]
+                },
+                host: if wheel {
+                    vec!["python".to_string(), "pip".to_string()]
+                } else {
+                    vec![]
+                },
+                run: self.run_requirements.clone(),
+                run_exports: self.run_exports.to_vec(),
+                run_constraints: self.run_constraints.to_vec(),
+            },
+            tests: vec![recipe::Test {
+                package_contents: self.package_contents(),
+            }],
+            extra: recipe::Extra {
+                upstream_forge: "github.com".to_string(),
+                upstream_sha256: digest("sha256"),
+                upstream_sha512: digest("sha512"),
+                upstream_md5: digest("md5"),
+                upstream_version: self.version.to_string(),
+                upstream_repository: self.repository.to_string(),
+                release_download_url: self.url.clone(),
+                recipe_maintainers: self.maintainers.to_vec(),
+                doi: self.doi.map(str::to_string),
+                citation: self.citation.map(str::to_string),
+            },
+            about: recipe::About {
+                description: self.description.clone(),
+                homepage: self.homepage.map(str::to_string),
+                repository: self.dev_url.to_string(),
+                documentation: self.documentation.map(str::to_string),
+                license: self.license.map(str::to_string),
+                summary: self.summary.clone(),
+            },
+        }
+    }
+}
+
+fn render_recipe(template: &str, context: &RecipeContext) -> anyhow::Result<String> {
+    let mut env = minijinja::Environment::new();
+    env.set_undefined_behavior(minijinja::UndefinedBehavior::Strict);
+    env.set_keep_trailing_newline(true);
+    env.render_str(template, context)
+        .context("Failed to render recipe template")
+}
+
+fn archive_file_name(
+    package_name: &str,
+    package_version: &str,
+    target_platform: &Platform,
+    asset: &octocrab::models::repos::Asset,
+) -> String {
+    let path = PathBuf::from(asset.browser_download_url.path());
+    let file_name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or_default();
+    // pip only installs wheels with their original file name
+    if file_name.ends_with(".whl") {
+        return file_name.to_string();
+    }
+    let full_ext = if file_name.ends_with(".zip") {
+        ".zip"
+    } else if let Some(pos) = file_name.find(".tar.") {
+        &file_name[pos..]
+    } else if file_name.ends_with(".tgz") {
+        ".tar.gz"
+    } else if file_name.ends_with(".txz") {
+        ".tar.xz"
+    } else if file_name.ends_with(".gz") {
+        ".gz"
+    } else if file_name.ends_with(".xz") {
+        ".xz"
+    } else if file_name.ends_with(".zst") {
+        ".zst"
+    } else if file_name.ends_with(".wasm") {
+        ".wasm"
+    } else {
+        // node tells ES modules from CommonJS by the extension
+        [".js", ".mjs", ".cjs"]
+            .into_iter()
+            .find(|ext| file_name.ends_with(ext))
+            .unwrap_or_default()
+    };
+    format!("{package_name}-{package_version}-{target_platform}{full_ext}")
+}
+
+/// Make a release body fit for a package description
+///
+/// Removes HTML comments (often left over from release templates) and control
+/// characters and truncates notes longer than `limit` at a line break.
+fn sanitize_release_notes(body: &str, limit: usize) -> Option<String> {
+    static COMMENTS: LazyLock<regex::Regex> =
+        LazyLock::new(|| regex::Regex::new(r"(?s)<!--.*?-->").expect("valid regex"));
+    let notes = COMMENTS
+        .replace_all(&body.replace("\r\n", "\n"), "")
+        .chars()
+        .filter(|c| *c == '\n' || *c == '\t' || !c.is_control())
+        .collect::<String>();
+    let notes = notes.trim();
+    if notes.is_empty() {
+        return None;
+    }
+
+    match notes.char_indices().nth(limit) {
+        None => Some(notes.to_string()),
+        Some((end, _)) => {
+            let truncated = &notes[..end];
+            let truncated = truncated
+                .rfind('\n')
+                .map(|pos| &truncated[..pos])
+                .unwrap_or(truncated);
+            Some(format!("{}\n…", truncated.trim_end()))
+        }
+    }
+}
+
+/// Whether `c` is an emoji or joins emoji
+fn is_emoji(c: char) -> bool {
+    matches!(
+        u32::from(c),
+        0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0xFE00..=0xFE0F | 0x200D
+    )
+}
+
+/// Make a repository description fit for a one-line package summary
+///
+/// Drops markdown markup, emoji and line breaks and truncates summaries
+/// longer than `limit` at a word boundary.
+fn sanitize_summary(description: &str, limit: usize) -> Option<String> {
+    static LINKS: LazyLock<regex::Regex> =
+        LazyLock::new(|| regex::Regex::new(r"!?\[([^\]]*)\]\([^)]*\)").expect("valid regex"));
+    static SHORTCODES: LazyLock<regex::Regex> =
+        LazyLock::new(|| regex::Regex::new(r"(^|\s):[a-z0-9_+-]+:").expect("valid regex"));
+    let text = LINKS.replace_all(description, "$1");
+    let text = SHORTCODES
+        .replace_all(&text, "$1")
+        .replace("**", "")
+        .replace("__", "")
+        .replace('`', "");
+    let summary = text
+        .split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| !is_emoji(*c) && !c.is_control())
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let summary = summary.trim_start_matches(['#', ' ']);
+    if summary.is_empty() {
+        return None;
+    }
+
+    match summary.char_indices().nth(limit.saturating_sub(1)) {
+        Some((end, _)) if summary.chars().count() > limit => {
+            let truncated = &summary[..end];
+            let truncated = truncated
+                .rfind(' ')
+                .map(|pos| &truncated[..pos])
+                .unwrap_or(truncated);
+            Some(format!("{}…", truncated.trim_end()))
+        }
+        _ => Some(summary.to_string()),
+    }
+}
+
+/// Fill in the placeholders of a configured build string
+///
+/// `libc` is taken from the asset name and is `none` if it mentions no
+/// libc, `shortsha` is the start of the `commit` the release tag is on.
+/// Without a known commit, build strings with `shortsha` are rejected
+/// instead of leaving the placeholder empty.
+fn expand_build_string(
+    build_string: &str,
+    build_number: u32,
+    asset_name: &str,
+    commit: Option<&str>,
+) -> anyhow::Result<String> {
+    let asset_name = asset_name.to_lowercase();
+    let libc = ["musl", "gnu", "msvc"]
+        .into_iter()
+        .find(|l| asset_name.contains(l))
+        .unwrap_or("none");
+    let shortsha = match commit {
+        Some(commit) => commit.chars().take(7).collect::<String>(),
+        None if build_string.contains("{shortsha}") => {
+            return Err(anyhow::anyhow!(
+                "Build string {build_string:?} needs the commit of the release tag, which is unknown"
+            ));
+        }
+        None => String::new(),
+    };
+
+    Ok(build_string
+        .replace("{build_number}", &build_number.to_string())
+        .replace("{libc}", libc)
+        .replace("{shortsha}", &shortsha))
+}
+
+/// The commit the tag of `release` is on, if known
+///
+/// [`crate::github::Github`] resolves `target_commitish` to the tag commit for
+/// packages that need it, otherwise it usually names a branch.
+fn tag_commit(release: &octocrab::models::repos::Release) -> Option<&str> {
+    let commitish = release.target_commitish.as_str();
+    (commitish.len() == 40 && commitish.chars().all(|c| c.is_ascii_hexdigit())).then_some(commitish)
+}
+
+/// Everything a recipe is generated from
+struct RecipeInput<'a> {
+    package: &'a Package,
+    version: &'a str,
+    build_number: u32,
+    platform: &'a Platform,
+    metadata: &'a RepositoryMetadata,
+    release_notes: Option<&'a str>,
+    asset: &'a octocrab::models::repos::Asset,
+    /// What is in the asset of python wheel packages, if known
+    wheel: Option<&'a crate::wheel::WheelMetadata>,
+    /// The commit the release tag is on, if known
+    commit: Option<&'a str>,
+}
+
+impl RecipeInput<'_> {
+    /// The expanded build string, if one is configured
+    fn build_string(&self) -> anyhow::Result<Option<String>> {
+        self.package
+            .build_string
+            .as_ref()
+            .map(|b| expand_build_string(b, self.build_number, &self.asset.name, self.commit))
+            .transpose()
+    }
+
+    /// Does `repo_packages` contain this package with the same or a newer build?
+    fn is_in_channel(
+        &self,
+        repo_packages: &[crate::conda::ChannelPackage],
+        version: &VersionWithSource,
+    ) -> bool {
+        let subdir = self.platform.to_string();
+        // Invalid build strings are reported before
+        let build_string = self.build_string().ok().flatten();
+        repo_packages.iter().any(|record| {
+            record.subdir == subdir
+                && record.name == self.package.name
+                && record.version == *version
+                && record.build_number >= u64::from(self.build_number)
+                && build_string.as_ref().is_none_or(|b| record.build == *b)
+        })
+    }
+
+    /// The build number following the newest build of this package in
+    /// `repo_packages`
+    fn next_build_number(
+        &self,
+        repo_packages: &[crate::conda::ChannelPackage],
+        version: &VersionWithSource,
+    ) -> u32 {
+        let subdir = self.platform.to_string();
+        let newest = repo_packages
+            .iter()
+            .filter(|record| {
+                record.subdir == subdir
+                    && record.name == self.package.name
+                    && record.version == *version
+            })
+            .map(|record| record.build_number)
+            .max()
+            .unwrap_or_default();
+        u32::try_from(newest + 1)
+            .unwrap_or(u32::MAX)
+            .max(self.build_number + 1)
+    }
+}
+
+impl<'a> RecipeContext<'a> {
+    fn new(input: &RecipeInput<'a>) -> Self {
+        let pn = input.package.name.to_lowercase();
+        let metadata = input.metadata;
+        let limits = &input.package.text_limits;
+        let release_notes = input
+            .release_notes
+            .and_then(|notes| sanitize_release_notes(notes, limits.release_notes));
+        let (binary_relocation, prefix_detection) = relocation(input.package, &input.asset.name);
+
+        let mut description = format!(
+            "{}\n{}",
+            input
+                .package
+                .description
+                .as_deref()
+                .or(metadata.description.as_deref())
+                .unwrap_or_default()
+                .trim_end(),
+            if binary_relocation || prefix_detection {
+                RELOCATING_NOTE
+            } else {
+                REPACKAGING_NOTE
             }
+        );
+        let doi_url = input
+            .package
+            .doi
+            .as_ref()
+            .map(|d| format!("https://doi.org/{d}"));
+        let cite = [input.package.citation.as_deref(), doi_url.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        if !cite.is_empty() {
+            description.push_str(&format!("\nPlease cite:\n{}\n", cite.join("\n")));
+        }
+        if let Some(notes) = &release_notes {
+            description.push_str(&format!("\nRelease notes:\n{notes}\n"));
+        }
+
+        let digest = extract_digest(input.asset);
+        let build_string = input.build_string().ok().flatten();
+
+        RecipeContext {
+            file_name: archive_file_name(&pn, input.version, input.platform, input.asset),
+            name: pn,
+            version: input.version,
+            build_number: input.build_number,
+            platform: input.platform.to_string(),
+            url: input.asset.browser_download_url.to_string(),
+            digest,
+            repository: &metadata.full_name,
+            homepage: metadata.homepage.as_deref(),
+            dev_url: &metadata.dev_url,
+            documentation: metadata.documentation.as_deref(),
+            license: metadata.license.as_deref(),
+            summary: metadata
+                .description
+                .as_deref()
+                .and_then(|d| sanitize_summary(d, limits.summary)),
+            description,
+            release_notes,
+            maintainers: &input.package.maintainers,
+            doi: input.package.doi.as_deref(),
+            citation: input.package.citation.as_deref(),
+            build_string,
+            binary_relocation,
+            prefix_detection,
+            run_exports: &input.package.run_exports,
+            run_constraints: &input.package.run_constraints,
+            kind: input.package.kind,
+            install_to: input.package.install_to.as_deref(),
+            run_requirements: match (input.package.kind, input.wheel) {
+                (PackageKind::PythonWheel, Some(wheel)) => wheel.run_requirements(),
+                (PackageKind::PythonWheel, None) => vec!["python".to_string()],
+                (PackageKind::Node, _) => vec!["nodejs".to_string()],
+                _ => vec![],
+            },
+            python_modules: input
+                .wheel
+                .map(|wheel| wheel.modules.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Render `template` or, if there is none, the default recipe in `format`,
+/// and validate the result
+fn recipe_content(
+    template: Option<&str>,
+    format: RecipeFormat,
+    input: &RecipeInput,
+) -> anyhow::Result<String> {
+    let context = RecipeContext::new(input);
+    let content = match (template, format) {
+        (Some(template), _) => render_recipe(template, &context)?,
+        (None, RecipeFormat::Rattler) => context.recipe().to_yaml()?,
+        (None, RecipeFormat::CondaBuild) => MetaYaml::from(context.recipe()).to_yaml()?,
+    };
+    recipe::validate(&content)?;
+    Ok(content)
+}
+
+/// The file next to `build.sh` listing globs of files to remove from the
+/// package, one per line
+const EXCLUDE_FILES_FILE: &str = "exclude_files.txt";
+/// The file next to `build.sh` listing the executables to install, one per
+/// line, all are installed without it
+const BINARIES_FILE: &str = "binaries.txt";
+/// The file next to `build.sh` listing globs of executables to remove, one
+/// per line, `.exe` suffixes are matched as well
+const EXCLUDE_BINARIES_FILE: &str = "exclude_binaries.txt";
+/// The file next to `build.sh` naming the kind of package, binary packages
+/// have none
+const KIND_FILE: &str = "kind.txt";
+/// The file next to `build.sh` naming the directory data packages are
+/// installed into
+const INSTALL_TO_FILE: &str = "install_to.txt";
+/// The file next to `build.sh` naming quarantined packages, which the upload
+/// script keeps out of the channel
+const QUARANTINE_FILE: &str = "quarantine.txt";
+
+/// Write `entries` into `file` in `recipe_dir`, one per line, or remove the
+/// file if there are no entries
+fn write_list(recipe_dir: &Path, file: &str, entries: &[String]) -> anyhow::Result<()> {
+    let path = recipe_dir.join(file);
+    if entries.is_empty() {
+        if path.exists() {
+            std::fs::remove_file(&path).context(format!("Failed to remove {}", path.display()))?;
+        }
+        return Ok(());
+    }
+    let content = entries.iter().fold(String::new(), |acc, e| acc + e + "\n");
+    std::fs::write(&path, content).context(format!("Failed to write {}", path.display()))
+}
+
+/// Write the `scripts` given as file name and content into `recipe_dir`, and
+/// remove the other `files`
+fn write_scripts(
+    recipe_dir: &Path,
+    files: &[&str],
+    scripts: &[(&str, String)],
+) -> anyhow::Result<()> {
+    for file in files {
+        let path = recipe_dir.join(file);
+        match scripts.iter().find(|(f, _)| f == file) {
+            Some((_, content)) => std::fs::write(&path, content),
+            None if path.exists() => std::fs::remove_file(&path),
+            None => Ok(()),
+        }
+        .context(format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// The files next to `build.sh` the build script installs as link scripts
+const LINK_SCRIPT_FILES: &[&str] = &[
+    "post-link.sh",
+    "post-link.bat",
+    "pre-unlink.sh",
+    "pre-unlink.bat",
+];
+
+/// The post-link and pre-unlink scripts of `package`, as file name and
+/// content
+fn link_scripts(package: &Package) -> Vec<(&'static str, String)> {
+    let scripts = [
+        &package.post_link.sh,
+        &package.post_link.bat,
+        &package.pre_unlink.sh,
+        &package.pre_unlink.bat,
+    ];
+    LINK_SCRIPT_FILES
+        .iter()
+        .zip(scripts)
+        .filter_map(|(file, script)| {
+            let script = script.as_deref()?;
+            let newline = if script.ends_with('\n') { "" } else { "\n" };
+            Some((*file, format!("{script}{newline}")))
+        })
+        .collect()
+}
+
+fn write_recipe(
+    work_dir: &Path,
+    recipe_dir: &Path,
+    format: RecipeFormat,
+    content: &str,
+    package: &Package,
+    existing: ExistingRecipes,
+) -> anyhow::Result<Option<PathBuf>> {
+    let recipe_dir = work_dir.join(recipe_dir);
+    let recipe_file = recipe_dir.join(format.file_name());
+    if existing == ExistingRecipes::Skip && recipe_file.exists() {
+        return Ok(None);
+    }
+    std::fs::create_dir_all(&recipe_dir).context("Failed to create recipe directory")?;
+
+    let build_script_source = work_dir.join("build.sh");
+    let build_script_destination = recipe_dir.join("build.sh");
+    std::fs::copy(&build_script_source, &build_script_destination).context(format!(
+        "Failed to copy build script from {build_script_source:?} to {build_script_destination:?}"
+    ))?;
+    write_list(&recipe_dir, EXCLUDE_FILES_FILE, &package.exclude_files)?;
+    write_list(&recipe_dir, BINARIES_FILE, &package.binaries)?;
+    let updaters = if package.keep_updaters {
+        vec![]
+    } else {
+        crate::inspect::updater_globs()
+    };
+    write_list(&recipe_dir, EXCLUDE_BINARIES_FILE, &updaters)?;
+    let kind = match package.kind {
+        // Wheels are installed by the recipe script, not by `build.sh`
+        PackageKind::Binary | PackageKind::PythonWheel => vec![],
+        PackageKind::Data => vec!["data".to_string()],
+        PackageKind::Library => vec!["library".to_string()],
+        PackageKind::Node => vec!["node".to_string()],
+    };
+    write_list(&recipe_dir, KIND_FILE, &kind)?;
+    let install_to = package.install_to.iter().cloned().collect::<Vec<_>>();
+    write_list(&recipe_dir, INSTALL_TO_FILE, &install_to)?;
+    let quarantine = if package.quarantine {
+        vec![package.name.clone()]
+    } else {
+        vec![]
+    };
+    write_list(&recipe_dir, QUARANTINE_FILE, &quarantine)?;
+    write_scripts(
+        &recipe_dir,
+        crate::activation::SCRIPT_FILES,
+        &crate::activation::scripts(&package.activation_env),
+    )?;
+    write_scripts(&recipe_dir, LINK_SCRIPT_FILES, &link_scripts(package))?;
+
+    let mut file = if existing == ExistingRecipes::Fail {
+        std::fs::File::create_new(&recipe_file)
+    } else {
+        std::fs::File::create(&recipe_file)
+    }
+    .context(format!(
+        "Failed to create recipe file \"{}\"",
+        recipe_file.display()
+    ))?;
+
+    file.write_all(content.as_bytes()).context(format!(
+        "Failed to populate recipe file \"{}\"",
+        recipe_file.display(),
+    ))?;
+
+    Ok(Some(recipe_dir))
+}
+
+fn generate_package(
+    work_dir: &Path,
+    layout: RecipeLayout,
+    format: RecipeFormat,
+    template: Option<&str>,
+    input: &RecipeInput,
+    existing: ExistingRecipes,
+) -> PackagingStatus {
+    let package_name = &input.package.name;
+    let package_version = input.version;
+    let target_platform = input.platform;
+    let recipe_dir = layout.recipe_dir(
+        package_name,
+        package_version,
+        input.build_number,
+        target_platform.as_str(),
+    );
+
+    match recipe_content(template, format, input).and_then(|content| {
+        write_recipe(
+            work_dir,
+            &recipe_dir,
+            format,
+            &content,
+            input.package,
+            existing,
+        )
+    }) {
+        Ok(Some(_)) => {
+            PackagingStatus::success(*target_platform).with_assets(vec![input.asset.into()])
         }
+        Ok(None) => PackagingStatus::existing_recipe(*target_platform),
+        Err(e) => {
+            tracing::error!(
+                "Error in {package_name}@{package_version}-{target_platform} using {}: {e:#}",
+                input.asset.browser_download_url
+            );
+            PackagingStatus::recipe_generation_failed(*target_platform, &format!("{e:#}"))
+        }
+    }
+}
+
+/// Generate one recipe for all `inputs`, which differ in their platform only
+fn generate_multi_platform_package(
+    work_dir: &Path,
+    layout: RecipeLayout,
+    inputs: &[RecipeInput],
+    existing: ExistingRecipes,
+) -> Vec<PackagingStatus> {
+    let Some(first) = inputs.first() else {
+        return vec![];
+    };
+    let recipe_dir = layout.recipe_dir(
+        &first.package.name,
+        first.version,
+        first.build_number,
+        MULTI_PLATFORM_DIR,
+    );
+
+    let content = SelectorRecipe::new(
+        "target_platform",
+        inputs
+            .iter()
+            .map(|i| (i.platform.to_string(), RecipeContext::new(i).recipe()))
+            .collect(),
+    )
+    .and_then(|recipe| recipe.to_yaml())
+    .and_then(|content| recipe::validate(&content).map(|_| content));
+
+    let platforms = inputs.iter().map(|i| *i.platform);
+    match content.and_then(|content| {
+        write_recipe(
+            work_dir,
+            &recipe_dir,
+            RecipeFormat::Rattler,
+            &content,
+            first.package,
+            existing,
+        )
+    }) {
+        Ok(Some(_)) => inputs
+            .iter()
+            .map(|i| PackagingStatus::success(*i.platform).with_assets(vec![i.asset.into()]))
+            .collect(),
+        Ok(None) => platforms.map(PackagingStatus::existing_recipe).collect(),
+        Err(e) => {
+            tracing::error!(
+                "Error in multi-platform recipe of {}@{}: {e:#}",
+                first.package.name,
+                first.version
+            );
+            platforms
+                .map(|p| PackagingStatus::recipe_generation_failed(p, &format!("{e:#}")))
+                .collect()
+        }
+    }
+}
+
+/// Generate a recipe for `input` with a variant for each of the
+/// `(variant, asset)` pairs and the matching `variants.yaml`
+fn generate_variant_package(
+    work_dir: &Path,
+    layout: RecipeLayout,
+    input: &RecipeInput,
+    variant_assets: &[(&str, &octocrab::models::repos::Asset)],
+    existing: ExistingRecipes,
+) -> PackagingStatus {
+    let recipe_dir = layout.recipe_dir(
+        &input.package.name,
+        input.version,
+        input.build_number,
+        input.platform.as_str(),
+    );
+
+    let variants = BTreeMap::from([(
+        recipe::VARIANT_VARIABLE,
+        variant_assets.iter().map(|(v, _)| *v).collect::<Vec<_>>(),
+    )]);
+    let result = SelectorRecipe::new(
+        recipe::VARIANT_VARIABLE,
+        variant_assets
+            .iter()
+            .map(|&(variant, asset)| {
+                let input = RecipeInput { asset, ..*input };
+                (variant.to_string(), RecipeContext::new(&input).recipe())
+            })
+            .collect(),
+    )
+    .and_then(|recipe| recipe.to_yaml())
+    .and_then(|content| recipe::validate(&content).map(|_| content))
+    .and_then(|content| {
+        write_recipe(
+            work_dir,
+            &recipe_dir,
+            RecipeFormat::Rattler,
+            &content,
+            input.package,
+            existing,
+        )
+    })
+    .and_then(|recipe_dir| {
+        let Some(recipe_dir) = recipe_dir else {
+            return Ok(None);
+        };
+        let variants_file = recipe_dir.join("variants.yaml");
+        std::fs::write(&variants_file, serde_yaml::to_string(&variants)?).context(format!(
+            "Failed to write variants file \"{}\"",
+            variants_file.display()
+        ))?;
+        Ok(Some(recipe_dir))
+    });
+
+    match result {
+        Ok(Some(_)) => PackagingStatus::success(*input.platform).with_assets(
+            variant_assets
+                .iter()
+                .map(|(_, asset)| ChosenAsset::from(*asset))
+                .collect(),
+        ),
+        Ok(None) => PackagingStatus::existing_recipe(*input.platform),
+        Err(e) => {
+            tracing::error!(
+                "Error in {}@{}-{} with variants: {e:#}",
+                input.package.name,
+                input.version,
+                input.platform
+            );
+            PackagingStatus::recipe_generation_failed(*input.platform, &format!("{e:#}"))
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    use crate::config_file::{
+        LinkScript,
+        tests::{get_default_patterns, get_optional_patterns},
+    };
+
+    pub fn repository_fixture(json: serde_json::Value) -> octocrab::models::Repository {
+        serde_json::from_value(json).unwrap()
     }
-    None
-}
 
-pub fn generate_packaging_data(
-    package: &Package,
-    repository: &octocrab::models::Repository,
-    releases: &[(octocrab::models::repos::Release, (String, u32))],
-    repo_packages: &[rattler_conda_types::RepoDataRecord],
-    work_dir: &Path,
-    package_count_limit: usize,
-) -> anyhow::Result<(Vec<VersionPackagingStatus>, usize)> {
-    let mut result = vec![];
-    let mut package_generation_count: usize = 0;
+    pub fn release_fixture(tag: &str, assets: &[&str]) -> octocrab::models::repos::Release {
+        serde_json::from_value(serde_json::json!({
+            "url": "https://api.github.com/repos/foo/bar/releases/1",
+            "html_url": format!("https://github.com/foo/bar/releases/tag/{tag}"),
+            "assets_url": "https://api.github.com/repos/foo/bar/releases/1/assets",
+            "upload_url": "https://uploads.github.com/repos/foo/bar/releases/1/assets{?name,label}",
+            "tarball_url": null,
+            "zipball_url": null,
+            "id": 1,
+            "node_id": "RE_1",
+            "tag_name": tag,
+            "target_commitish": "main",
+            "name": tag,
+            "body": null,
+            "draft": false,
+            "prerelease": false,
+            "created_at": "2025-01-01T00:00:00Z",
+            "published_at": "2025-01-01T00:00:00Z",
+            "author": null,
+            "assets": assets.iter().map(|a| asset_fixture(a, None)).collect::<Vec<_>>(),
+        }))
+        .unwrap()
+    }
 
-    for (r, (version_string, build_number)) in releases {
-        let Ok(version) = rattler_conda_types::Version::from_str(version_string) else {
-            result.push(VersionPackagingStatus {
-                version: Some(version_string.clone()),
-                status: vec![PackagingStatus::invalid_version()],
-            });
-            continue;
-        };
-        let version = VersionWithSource::new(version, version_string);
-        let mut version_result = vec![];
-
-        let mut found_platforms = HashSet::new();
-
-        for (platform, pattern) in &package.platforms {
-            if let Some(asset) = match_platform(&pattern[..], &r.assets[..]) {
-                found_platforms.insert(platform);
-
-                if package_generation_count < package_count_limit {
-                    if repo_packages.iter().any(|r| {
-                        r.package_record.subdir == platform.to_string()
-                            && r.package_record.name.as_normalized() == package.name
-                            && r.package_record.version == version
-                    }) {
-                        version_result.push(PackagingStatus::skip_platform(*platform));
-                        continue;
-                    }
+    pub fn asset_fixture(name: &str, digest: Option<&str>) -> octocrab::models::repos::Asset {
+        serde_json::from_value(serde_json::json!({
+            "url": "https://api.github.com/repos/foo/bar/releases/assets/1",
+            "browser_download_url": format!("https://github.com/foo/bar/releases/download/v1.0.0/{name}"),
+            "id": 1,
+            "node_id": "RA_1",
+            "name": name,
+            "label": null,
+            "state": "uploaded",
+            "content_type": "application/octet-stream",
+            "size": 1024,
+            "download_count": 0,
+            "created_at": "2025-01-01T00:00:00Z",
+            "updated_at": "2025-01-01T00:00:00Z",
+            "uploader": null,
+            "digest": digest,
+        }))
+        .unwrap()
+    }
 
-                    version_result.push(generate_package(
-                        work_dir,
-                        package,
-                        version_string,
-                        *build_number,
-                        platform,
-                        repository,
-                        asset,
-                    ));
-                    package_generation_count += 1;
-                }
-            }
+    /// The metadata of a repository that has nothing but its `full_name`
+    fn metadata_fixture(full_name: &str) -> RepositoryMetadata {
+        RepositoryMetadata {
+            full_name: full_name.to_string(),
+            homepage: None,
+            license: None,
+            description: None,
+            documentation: None,
+            dev_url: format!("https://github.com/{full_name}"),
         }
+    }
 
-        for platform in package.platforms.keys() {
-            if !found_platforms.contains(platform) {
-                version_result.push(PackagingStatus::missing_platform(*platform));
-            }
+    /// The input for a 1.0.0 recipe of `package` from `asset`, with the
+    /// metadata of "foo/bar"
+    fn recipe_input<'a>(
+        package: &'a Package,
+        asset: &'a octocrab::models::repos::Asset,
+        platform: &'a Platform,
+    ) -> RecipeInput<'a> {
+        static METADATA: LazyLock<RepositoryMetadata> =
+            LazyLock::new(|| metadata_fixture("foo/bar"));
+        RecipeInput {
+            package,
+            version: "1.0.0",
+            build_number: 0,
+            platform,
+            metadata: &METADATA,
+            release_notes: None,
+            asset,
+            wheel: None,
+            commit: None,
         }
+    }
 
-        result.push(VersionPackagingStatus {
-            version: Some(format!("{version_string}-{build_number}")),
-            status: version_result,
-        });
+    #[test]
+    fn test_metadata_of_minimal_repository() {
+        let package = Package::with_defaults("foo/bar").unwrap();
+        let repository = repository_fixture(serde_json::json!({
+            "id": 1,
+            "name": "bar",
+            "url": "https://api.github.com/repos/foo/bar",
+        }));
+
+        let metadata = RepositoryMetadata::new(&package, &repository, "github.com");
+        assert_eq!(metadata, metadata_fixture("foo/bar"));
+
+        let asset = asset_fixture("bar.tar.gz", None);
+        let input = RecipeInput {
+            metadata: &metadata,
+            ..recipe_input(&package, &asset, &Platform::Linux64)
+        };
+        let recipe = recipe_content(None, RecipeFormat::Rattler, &input).unwrap();
+        let recipe: serde_yaml::Value = serde_yaml::from_str(&recipe).unwrap();
+        assert_eq!(
+            recipe["extra"]["upstream-repository"].as_str(),
+            Some("foo/bar")
+        );
+        assert!(recipe["about"].get("homepage").is_none());
+        assert!(recipe["about"].get("license").is_none());
+        assert!(recipe["source"].get("sha256").is_none());
+        assert!(recipe["extra"].get("doi").is_none());
     }
 
-    Ok((result, package_generation_count))
-}
+    #[test]
+    fn test_citation() {
+        let mut package = Package::with_defaults("foo/bar").unwrap();
+        package.doi = Some("10.5281/zenodo.1234".to_string());
+        package.citation = Some("Bar, A tool. 2025.".to_string());
+        let repository = repository_fixture(serde_json::json!({
+            "id": 1,
+            "name": "bar",
+            "url": "https://api.github.com/repos/foo/bar",
+        }));
+        let metadata = RepositoryMetadata::new(&package, &repository, "github.com");
+        let asset = asset_fixture("bar.tar.gz", None);
+        let input = RecipeInput {
+            metadata: &metadata,
+            ..recipe_input(&package, &asset, &Platform::Linux64)
+        };
 
-fn extract_digest(asset: &octocrab::models::repos::Asset) -> Option<(String, String)> {
-    asset.digest.as_ref().map(|d| {
-        let digest = d.strip_prefix("sha256:").unwrap();
-        ("sha256".to_string(), digest.to_string())
-    })
-}
+        for format in [RecipeFormat::Rattler, RecipeFormat::CondaBuild] {
+            let recipe = recipe_content(None, format, &input).unwrap();
+            let recipe: serde_yaml::Value = serde_yaml::from_str(&recipe).unwrap();
+            assert_eq!(recipe["extra"]["doi"].as_str(), Some("10.5281/zenodo.1234"));
+            assert_eq!(
+                recipe["extra"]["citation"].as_str(),
+                Some("Bar, A tool. 2025.")
+            );
+            assert!(recipe["about"]["description"].as_str().unwrap().contains(
+                "Please cite:\nBar, A tool. 2025.\nhttps://doi.org/10.5281/zenodo.1234\n"
+            ));
+        }
+    }
 
-fn extract_about(
-    package_version: &str,
-    repository: &octocrab::models::Repository,
-    asset: &octocrab::models::repos::Asset,
-) -> String {
-    let extra_section = {
-        let upstream_digest = extract_digest(asset)
-            .map(|(algo, digest)| format!("\n  upstream-{algo}: \"{digest}\""))
-            .unwrap_or_default();
-        let upstream_version = format!("\n  upstream-version: \"{package_version}\"");
-        let upstream_repository = repository
-            .html_url
-            .as_ref()
-            .map(|u| u.path()[1..].to_string()) // strip leading `/`
-            .map(|u| format!("\n  upstream-repository: \"{u}\""))
-            .unwrap_or_default();
-        let download_url = format!(
-            "\n  release-download-url: \"{}\"",
-            asset.browser_download_url
+    #[test]
+    fn test_existing_recipes() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let work_dir = work_dir.path();
+        generate_build_script(work_dir).unwrap();
+        // Running twice in the same work directory is fine
+        generate_build_script(work_dir).unwrap();
+
+        let recipe_dir = RecipeLayout::default().recipe_dir("bar", "1.0.0", 0, "linux-64");
+        let package = Package {
+            exclude_files: vec![],
+            keep_updaters: true,
+            ..Package::with_defaults("foo/bar").unwrap()
+        };
+        let generate = |content, existing| {
+            write_recipe(
+                work_dir,
+                &recipe_dir,
+                RecipeFormat::Rattler,
+                content,
+                &package,
+                existing,
+            )
+        };
+        let recipe_dir = generate("first", ExistingRecipes::Fail).unwrap().unwrap();
+        let read = || std::fs::read_to_string(recipe_dir.join("recipe.yaml")).unwrap();
+        assert!(!recipe_dir.join(EXCLUDE_FILES_FILE).exists());
+        assert!(!recipe_dir.join(EXCLUDE_BINARIES_FILE).exists());
+        assert!(!recipe_dir.join("activate.sh").exists());
+        assert!(!recipe_dir.join(QUARANTINE_FILE).exists());
+
+        assert!(generate("second", ExistingRecipes::Fail).is_err());
+        assert!(generate("second", ExistingRecipes::Skip).unwrap().is_none());
+        assert_eq!(read(), "first");
+        assert!(
+            generate("second", ExistingRecipes::Overwrite)
+                .unwrap()
+                .is_some()
         );
-        format!(
-            "extra:\n  upstream-forge: github.com{upstream_digest}{upstream_version}{upstream_repository}{download_url}\n"
+        assert_eq!(read(), "second");
+
+        write_recipe(
+            work_dir,
+            &recipe_dir,
+            RecipeFormat::Rattler,
+            "third",
+            &Package {
+                binaries: vec!["bar".to_string()],
+                activation_env: BTreeMap::from([("BAR_HOME".to_string(), "{prefix}".to_string())]),
+                post_link: LinkScript {
+                    sh: Some("mkdir -p \"${PREFIX}/etc/bar\"".to_string()),
+                    bat: None,
+                },
+                quarantine: true,
+                ..Package::with_defaults("foo/bar").unwrap()
+            },
+            ExistingRecipes::Overwrite,
         )
-    };
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(recipe_dir.join(EXCLUDE_FILES_FILE)).unwrap(),
+            "*.pdb\n*.debug\n*.dSYM\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(recipe_dir.join(BINARIES_FILE)).unwrap(),
+            "bar\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(recipe_dir.join(EXCLUDE_BINARIES_FILE)).unwrap(),
+            "*-update\n*-updater\n*-upd\nupdater\n"
+        );
+        assert!(recipe_dir.join("activate.sh").exists());
+        assert!(recipe_dir.join("deactivate.bat").exists());
+        assert_eq!(
+            std::fs::read_to_string(recipe_dir.join("post-link.sh")).unwrap(),
+            "mkdir -p \"${PREFIX}/etc/bar\"\n"
+        );
+        assert!(!recipe_dir.join("post-link.bat").exists());
+        assert!(!recipe_dir.join("pre-unlink.sh").exists());
+        assert_eq!(
+            std::fs::read_to_string(recipe_dir.join(QUARANTINE_FILE)).unwrap(),
+            "bar\n"
+        );
+    }
 
-    let about_section = {
-        let homepage = if let Some(homepage) = &repository.homepage
-            && !homepage.is_empty()
-        {
-            format!("  homepage: \"{homepage}\"\n")
-        } else {
-            String::new()
-        };
+    #[test]
+    fn test_recipe_layouts() {
+        let recipe = "package:\n  name: bar\n  version: 1.0.0\nbuild:\n  number: 2\n";
+        let package = Package::with_defaults("foo/bar").unwrap();
+        for layout in [
+            RecipeLayout::PerPlatform,
+            RecipeLayout::PerPackage,
+            RecipeLayout::Flat,
+        ] {
+            let work_dir = tempfile::tempdir().unwrap();
+            let work_dir = work_dir.path();
+            generate_build_script(work_dir).unwrap();
+
+            for platform in [Platform::Linux64, Platform::WinArm64] {
+                let recipe_dir = layout.recipe_dir("bar", "1.0.0", 2, platform.as_str());
+                assert_eq!(recipe_dir.components().count(), layout.depth());
+                assert_eq!(layout.platform_of(&recipe_dir), Some(platform));
+                write_recipe(
+                    work_dir,
+                    &recipe_dir,
+                    RecipeFormat::Rattler,
+                    recipe,
+                    &package,
+                    ExistingRecipes::Fail,
+                )
+                .unwrap();
+            }
 
-        let license = if let Some(license) = &repository.license {
-            // Fix outdated licenses
-            let license_info = match license.spdx_id.as_str() {
-                "GPL-3.0" => "GPL-3.0-only",
-                l => l,
-            };
-            format!("\n  license: \"{}\"", license_info)
-        } else {
-            String::new()
-        };
-        let summary_text = if let Some(description) = &repository.description {
-            description.to_owned()
-        } else {
-            String::new()
+            let recipe_dir = layout.recipe_dir("bar", "1.0.0", 2, MULTI_PLATFORM_DIR);
+            assert_eq!(layout.platform_of(&recipe_dir), None);
+            let multi_platform_recipe = format!(
+                "{recipe}source:\n  - if: target_platform == \"osx-64\"\n    then:\n      url: https://example.com/bar.zip\n"
+            );
+            write_recipe(
+                work_dir,
+                &recipe_dir,
+                RecipeFormat::Rattler,
+                &multi_platform_recipe,
+                &package,
+                ExistingRecipes::Fail,
+            )
+            .unwrap();
+
+            let recipes = crate::clean::find_recipes(work_dir, layout).unwrap();
+            let mut platforms = recipes
+                .iter()
+                .flat_map(|r| r.platforms.iter().copied())
+                .collect::<Vec<_>>();
+            platforms.sort();
+            assert_eq!(
+                platforms,
+                vec![Platform::Linux64, Platform::Osx64, Platform::WinArm64]
+            );
+            assert!(recipes.iter().all(|r| r.build_number == 2));
+        }
+    }
+
+    #[test]
+    fn test_recipe_generation() {
+        let metadata = RepositoryMetadata {
+            license: Some("MIT".to_string()),
+            description: Some("A \"quoted\" tool: fast".to_string()),
+            ..metadata_fixture("foo/bar")
         };
-        let summary = if let Some(description) = &repository.description {
-            format!("\n  summary: \"{}\"", description)
-        } else {
-            String::new()
+        let package = Package::with_defaults("foo/Bar").unwrap();
+        let asset = asset_fixture("bar-x86_64-unknown-linux-musl.tar.gz", Some("sha256:abc"));
+        let input = RecipeInput {
+            build_number: 2,
+            metadata: &metadata,
+            release_notes: Some("<!-- template -->\r\n* Fixed \"bugs\"\r\n"),
+            ..recipe_input(&package, &asset, &Platform::Linux64)
         };
 
-        format!(
-            r#"
-about:
-  description: >
-    {summary_text}
+        let recipe = recipe_content(None, RecipeFormat::Rattler, &input).unwrap();
+        let recipe: serde_yaml::Value = serde_yaml::from_str(&recipe).unwrap();
+        assert_eq!(recipe["package"]["name"].as_str(), Some("bar"));
+        assert_eq!(recipe["package"]["version"].as_str(), Some("1.0.0"));
+        assert_eq!(recipe["source"]["sha256"].as_str(), Some("abc"));
+        assert_eq!(
+            recipe["source"]["file_name"].as_str(),
+            Some("bar-1.0.0-linux-64.tar.gz")
+        );
+        assert_eq!(recipe["build"]["number"].as_u64(), Some(2));
+        assert_eq!(
+            recipe["about"]["summary"].as_str(),
+            Some("A \"quoted\" tool: fast")
+        );
+        assert!(
+            recipe["about"]["description"]
+                .as_str()
+                .unwrap()
+                .starts_with("A \"quoted\" tool: fast\n... repackaged")
+        );
+        assert!(
+            recipe["about"]["description"]
+                .as_str()
+                .unwrap()
+                .ends_with("\nRelease notes:\n* Fixed \"bugs\"\n")
+        );
 
-    ... repackaged from github release.
+        let template = "package:
+  name: {{ name }}-{{ platform }}
+  version: {{ version | tojson }}
+source:
+  url: {{ url }}
+  {{ digest.algorithm }}: {{ digest.value }}
+build:
+  number: {{ build_number }}
+";
+        let recipe = recipe_content(Some(template), RecipeFormat::Rattler, &input).unwrap();
+        assert!(recipe.starts_with("package:\n  name: bar-linux-64\n"));
+        assert!(recipe.contains("  sha256: abc\n"));
+
+        let render = |template: &str| recipe_content(Some(template), RecipeFormat::Rattler, &input);
+
+        let recipe = recipe_content(None, RecipeFormat::CondaBuild, &input).unwrap();
+        let recipe: serde_yaml::Value = serde_yaml::from_str(&recipe).unwrap();
+        assert_eq!(recipe["package"]["name"].as_str(), Some("bar"));
+        assert_eq!(recipe["source"]["sha256"].as_str(), Some("abc"));
+        assert_eq!(
+            recipe["source"]["fn"].as_str(),
+            Some("bar-1.0.0-linux-64.tar.gz")
+        );
+        assert_eq!(recipe["build"]["binary_relocation"].as_bool(), Some(false));
+        assert_eq!(
+            recipe["about"]["dev_url"].as_str(),
+            Some("https://github.com/foo/bar")
+        );
+        assert!(render("{{ no_such_value }}").is_err());
+        // Renders fine, but is not a usable recipe
+        assert!(render(&template.replace("{{ url }}", "{{ file_name }}")).is_err());
+        let quoted_number = template.replace("{{ build_number }}", "\"{{ build_number }}\"");
+        assert!(render(&quoted_number).is_err());
+    }
 
-    No files were modified, so all SHAs should match the github release files.
-    Files might have been moved, but no files should have been added or removed
-    (except for obvious junk files).
+    #[test]
+    fn test_data_recipe() {
+        let metadata = metadata_fixture("foo/bar-fonts");
+        let mut package = Package::with_defaults("foo/bar-fonts").unwrap();
+        let asset = asset_fixture("bar-fonts.zip", None);
+        let recipe = |package: &Package| {
+            let input = RecipeInput {
+                metadata: &metadata,
+                ..recipe_input(package, &asset, &Platform::Linux64)
+            };
+            let recipe = recipe_content(None, RecipeFormat::Rattler, &input).unwrap();
+            serde_yaml::from_str::<serde_yaml::Value>(&recipe).unwrap()["tests"][0]
+                ["package_contents"]
+                .clone()
+        };
 
-    Check the extra package data for details on where the github release file was
-    taken from.
-{homepage}{license}{summary}"#,
-        )
-    };
+        let contents = recipe(&package);
+        assert_eq!(contents["bin"][0].as_str(), Some("*"));
+        assert!(contents["files"].get("exists").is_none());
+
+        package.kind = PackageKind::Data;
+        package.install_to = Some("share/fonts/bar".to_string());
+        let contents = recipe(&package);
+        assert!(contents.get("bin").is_none());
+        assert_eq!(
+            contents["files"]["exists"][0].as_str(),
+            Some("share/fonts/bar/**")
+        );
 
-    format!(
-        r#"{extra_section}
-{about_section}"#
-    )
-}
+        package.kind = PackageKind::Library;
+        package.install_to = None;
+        let contents = recipe(&package);
+        assert!(contents.get("bin").is_none());
+        assert_eq!(contents["lib"][0].as_str(), Some("*"));
+        assert_eq!(contents["include"][0].as_str(), Some("*"));
+    }
 
-fn generate_rattler_build_recipe(
-    work_dir: &Path,
-    package_name: &str,
-    package_version: &str,
-    build_number: u32,
-    target_platform: &Platform,
-    repository: &octocrab::models::Repository,
-    asset: &octocrab::models::repos::Asset,
-) -> anyhow::Result<PathBuf> {
-    let platform_dir = work_dir.join(format!("{target_platform}",));
-    let recipe_dir = platform_dir.join(format!("{package_name}-{package_version}-{build_number}",));
-    std::fs::create_dir_all(&recipe_dir).context("Failed to create recipe directory")?;
+    #[test]
+    fn test_wheel_recipe() {
+        let package = Package {
+            kind: PackageKind::PythonWheel,
+            ..Package::with_defaults("foo/bar").unwrap()
+        };
+        let asset = asset_fixture("bar-1.0.0-py3-none-any.whl", None);
+        let wheel = crate::wheel::WheelMetadata {
+            requires_python: Some(">=3.9".to_string()),
+            requires: vec!["click >=8".to_string()],
+            modules: vec!["bar".to_string()],
+        };
+        let input = RecipeInput {
+            wheel: Some(&wheel),
+            ..recipe_input(&package, &asset, &Platform::NoArch)
+        };
 
-    let build_script_source = work_dir.join("build.sh");
-    let build_script_destination = recipe_dir.join("build.sh");
-    std::fs::copy(&build_script_source, &build_script_destination).context(format!(
-        "Failed to copy build script from {build_script_source:?} to {build_script_destination:?}"
-    ))?;
+        let recipe = recipe_content(None, RecipeFormat::Rattler, &input).unwrap();
+        let recipe: serde_yaml::Value = serde_yaml::from_str(&recipe).unwrap();
+        assert_eq!(recipe["build"]["noarch"].as_str(), Some("python"));
+        assert_eq!(
+            recipe["build"]["script"].as_str(),
+            Some(
+                "python -m pip install --no-deps --no-build-isolation -vv bar-1.0.0-py3-none-any.whl"
+            )
+        );
+        assert_eq!(
+            recipe["source"]["file_name"].as_str(),
+            Some("bar-1.0.0-py3-none-any.whl")
+        );
+        assert_eq!(recipe["requirements"]["host"][1].as_str(), Some("pip"));
+        assert_eq!(
+            recipe["requirements"]["run"][0].as_str(),
+            Some("python >=3.9")
+        );
+        assert_eq!(recipe["requirements"]["run"][1].as_str(), Some("click >=8"));
+        let contents = &recipe["tests"][0]["package_contents"];
+        assert!(contents.get("bin").is_none());
+        assert_eq!(contents["site_packages"][0].as_str(), Some("bar"));
+
+        let recipe = recipe_content(None, RecipeFormat::CondaBuild, &input).unwrap();
+        let recipe: serde_yaml::Value = serde_yaml::from_str(&recipe).unwrap();
+        assert!(
+            recipe["build"]["script"]
+                .as_str()
+                .unwrap()
+                .ends_with(" src/bar-1.0.0-py3-none-any.whl")
+        );
+        assert_eq!(recipe["requirements"]["run"][1].as_str(), Some("click >=8"));
+    }
 
-    let recipe_file = recipe_dir.join("recipe.yaml");
-    let mut file = std::fs::File::create_new(&recipe_file).context(format!(
-        "Failed to create recipe file \"{}\"",
-        recipe_file.display()
-    ))?;
+    #[test]
+    fn test_node_recipe() {
+        let package = Package {
+            kind: PackageKind::Node,
+            ..Package::with_defaults("foo/bar").unwrap()
+        };
+        let asset = asset_fixture("bar.mjs", None);
+        let input = recipe_input(&package, &asset, &Platform::NoArch);
+
+        let recipe = recipe_content(None, RecipeFormat::Rattler, &input).unwrap();
+        let recipe: serde_yaml::Value = serde_yaml::from_str(&recipe).unwrap();
+        assert_eq!(recipe["build"]["noarch"].as_str(), Some("generic"));
+        assert!(recipe["build"].get("script").is_none());
+        assert_eq!(
+            recipe["source"]["file_name"].as_str(),
+            Some("bar-1.0.0-noarch.mjs")
+        );
+        assert_eq!(recipe["requirements"]["build"][0].as_str(), Some("nodejs"));
+        assert_eq!(recipe["requirements"]["run"][0].as_str(), Some("nodejs"));
+        assert_eq!(
+            recipe["tests"][0]["package_contents"]["bin"][0].as_str(),
+            Some("*")
+        );
+    }
 
-    let url = asset.browser_download_url.to_string();
-    let digest = extract_digest(asset)
-        .map(|(algo, value)| format!("\n  {algo}: {value}"))
-        .unwrap_or_default();
+    #[test]
+    fn test_relocation() {
+        let mut package = Package::with_defaults("foo/bar").unwrap();
+        assert_eq!(relocation(&package, "bar-linux.tar.gz"), (false, false));
+        assert_eq!(relocation(&package, "bar-install.SH"), (false, true));
+
+        package.kind = PackageKind::Library;
+        assert_eq!(relocation(&package, "libbar-linux.tar.gz"), (true, false));
+
+        package.kind = PackageKind::Binary;
+        package.binary_relocation = Some(true);
+        package.prefix_detection = Some(false);
+        assert_eq!(relocation(&package, "bar-install.sh"), (true, false));
+
+        let asset = asset_fixture("bar.tar.gz", None);
+        let input = recipe_input(&package, &asset, &Platform::Linux64);
+        let recipe = recipe_content(None, RecipeFormat::Rattler, &input).unwrap();
+        let recipe: serde_yaml::Value = serde_yaml::from_str(&recipe).unwrap();
+        assert_eq!(
+            recipe["build"]["dynamic_linking"]["binary_relocation"].as_bool(),
+            Some(true)
+        );
+        assert_eq!(
+            recipe["build"]["prefix_detection"]["ignore"].as_bool(),
+            Some(true)
+        );
+        assert!(
+            !recipe["about"]["description"]
+                .as_str()
+                .unwrap()
+                .contains("No files were modified")
+        );
+    }
 
-    let about = extract_about(package_version, repository, asset);
-    let pn = package_name.to_lowercase();
+    #[test]
+    fn test_extract_digest() {
+        let digest = |d| extract_digest(&asset_fixture("bar.tar.gz", Some(d)));
+        let parsed = |d| digest(d).map(|d| format!("{}:{}", d.algorithm, d.value));
+
+        assert_eq!(parsed("sha256:ABC123").as_deref(), Some("sha256:abc123"));
+        assert_eq!(parsed("sha512:def456").as_deref(), Some("sha512:def456"));
+        assert_eq!(parsed("MD5:0f").as_deref(), Some("md5:0f"));
+        assert_eq!(parsed("sha1:abc"), None);
+        assert_eq!(parsed("sha256:"), None);
+        assert_eq!(parsed("sha256:xyz"), None);
+        assert_eq!(parsed("abc123"), None);
+        assert!(extract_digest(&asset_fixture("bar.tar.gz", None)).is_none());
+    }
 
-    let archive = {
-        let path = PathBuf::from(asset.browser_download_url.path());
-        let file_name = path
-            .file_name()
-            .unwrap_or_default()
-            .to_str()
-            .unwrap_or_default();
-        let full_ext = if file_name.ends_with(".zip") {
-            ".zip"
-        } else if let Some(pos) = file_name.find(".tar.") {
-            &file_name[pos..]
-        } else if file_name.ends_with(".tgz") {
-            ".tar.gz"
-        } else if file_name.ends_with(".txz") {
-            ".tar.xz"
-        } else if file_name.ends_with(".gz") {
-            ".gz"
-        } else if file_name.ends_with(".xz") {
-            ".xz"
-        } else if file_name.ends_with(".zst") {
-            ".zst"
-        } else {
-            ""
-        };
-        format!("{pn}-{package_version}-{target_platform}{full_ext}")
-    };
+    #[test]
+    fn test_expand_build_string() {
+        assert_eq!(
+            expand_build_string(
+                "gh_{shortsha}_{build_number}",
+                3,
+                "bar-x86_64-unknown-linux-musl.tar.gz",
+                Some("0123456789abcdef0123456789abcdef01234567"),
+            )
+            .unwrap(),
+            "gh_0123456_3"
+        );
+        assert_eq!(
+            expand_build_string(
+                "{libc}_{build_number}",
+                0,
+                "bar-x86_64-unknown-linux-GNU.tar.gz",
+                None
+            )
+            .unwrap(),
+            "gnu_0"
+        );
+        assert_eq!(
+            expand_build_string("{libc}_{build_number}", 0, "bar-macos.tar.gz", None).unwrap(),
+            "none_0"
+        );
+        assert!(
+            expand_build_string("gh_{shortsha}_{build_number}", 0, "bar.tar.gz", None).is_err()
+        );
+    }
 
-    let content = format!(
-        r#"package:
-  name: {pn}
-  version: "{package_version}"
-  
-source:
-  url: "{url}"{digest}
-  file_name: "{archive}"
+    #[test]
+    fn test_tag_commit() {
+        let mut release = release_fixture("v1.0.0", &[]);
+        release.target_commitish = "main".to_string();
+        assert_eq!(tag_commit(&release), None);
+        release.target_commitish = "0123456789abcdef0123456789abcdef01234567".to_string();
+        assert_eq!(
+            tag_commit(&release),
+            Some("0123456789abcdef0123456789abcdef01234567")
+        );
+    }
 
-build:
-  number: {build_number}
-  dynamic_linking:
-    binary_relocation: false
-  prefix_detection:
-    ignore: true
-
-tests:
-  - package_contents:
-      files:
-        not_exists:
-          - .*
-      bin:
-        - "*"
-
-{about}"#,
-    );
+    #[test]
+    fn test_sanitize_release_notes() {
+        let limit = crate::config_file::TextLimits::default().release_notes;
+        assert_eq!(
+            sanitize_release_notes(" \r\n<!--\nnothing\n-->\n", limit),
+            None
+        );
+        assert_eq!(
+            sanitize_release_notes("## Changes\r\n\u{1b}[1m* A\r\n", limit).as_deref(),
+            Some("## Changes\n[1m* A")
+        );
 
-    file.write_all(content.as_bytes()).context(format!(
-        "Failed to populate recipe file \"{}\"",
-        recipe_file.display(),
-    ))?;
+        let long = "* A change\n".repeat(limit);
+        let notes = sanitize_release_notes(&long, limit).unwrap();
+        assert!(notes.chars().count() <= limit + 2);
+        assert!(notes.ends_with("* A change\n…"));
+    }
 
-    Ok(recipe_dir)
-}
+    #[test]
+    fn test_sanitize_summary() {
+        assert_eq!(
+            sanitize_summary(
+                "🚀 A **blazing fast** `grep`\r\nreplacement :sparkles: ([docs](https://example.com))",
+                200
+            )
+            .as_deref(),
+            Some("A blazing fast grep replacement (docs)")
+        );
+        assert_eq!(
+            sanitize_summary("# snake_case \"tool\"", 200).as_deref(),
+            Some("snake_case \"tool\"")
+        );
+        assert_eq!(
+            sanitize_summary("Runs image:tag:latest", 200).as_deref(),
+            Some("Runs image:tag:latest")
+        );
+        assert_eq!(sanitize_summary(" ✨ :tada: ", 200), None);
+        assert_eq!(
+            sanitize_summary("A tool with a long description", 16).as_deref(),
+            Some("A tool with a…")
+        );
+        assert_eq!(
+            sanitize_summary("Supercalifragilistic", 6).as_deref(),
+            Some("Super…")
+        );
+        assert_eq!(sanitize_summary("Short", 5).as_deref(), Some("Short"));
+    }
 
-fn generate_package(
-    work_dir: &Path,
-    package: &Package,
-    package_version: &str,
-    build_number: u32,
-    target_platform: &Platform,
-    repository: &octocrab::models::Repository,
-    asset: &octocrab::models::repos::Asset,
-) -> PackagingStatus {
-    match generate_rattler_build_recipe(
-        work_dir,
-        &package.name,
-        package_version,
-        build_number,
-        target_platform,
-        repository,
-        asset,
-    ) {
-        Ok(_) => PackagingStatus::success(*target_platform),
-        Err(e) => {
-            eprintln!(
-                "Error in {}@{package_version}-{target_platform},\n using {asset:#?}: {e}",
-                package.name
+    #[test]
+    fn test_metadata_fallbacks() {
+        let package = Package::with_defaults("foo/bar").unwrap();
+        let repository = repository_fixture(serde_json::json!({
+            "id": 1,
+            "name": "bar",
+            "url": "https://api.github.com/repos/foo/bar",
+            "html_url": "https://github.com/Foo/Bar",
+            "homepage": "  ",
+            "description": " A tool ",
+            "license": {
+                "key": "other",
+                "name": "Other",
+                "node_id": "L_1",
+                "spdx_id": "NOASSERTION",
+            },
+        }));
+
+        let metadata = RepositoryMetadata::new(&package, &repository, "github.com");
+        assert_eq!(metadata.full_name, "Foo/Bar");
+        assert_eq!(metadata.homepage, None);
+        assert_eq!(metadata.license, None);
+        assert_eq!(metadata.description.as_deref(), Some("A tool"));
+        assert_eq!(metadata.documentation, None);
+        assert_eq!(metadata.dev_url, "https://github.com/Foo/Bar");
+
+        // GitHub Enterprise repositories link to their own host
+        let repository = repository_fixture(serde_json::json!({
+            "id": 1,
+            "name": "bar",
+            "url": "https://github.example.com/api/v3/repos/foo/bar",
+            "html_url": "https://github.example.com/foo/bar",
+            "homepage": "TBD",
+        }));
+        let metadata = RepositoryMetadata::new(&package, &repository, "github.example.com");
+        assert_eq!(metadata.dev_url, "https://github.example.com/foo/bar");
+        assert_eq!(
+            metadata.homepage.as_deref(),
+            Some("https://github.example.com/foo/bar")
+        );
+        let repository = repository_fixture(serde_json::json!({
+            "id": 1,
+            "name": "bar",
+            "url": "https://github.example.com/api/v3/repos/foo/bar",
+        }));
+        let metadata = RepositoryMetadata::new(&package, &repository, "github.example.com");
+        assert_eq!(metadata.dev_url, "https://github.example.com/foo/bar");
+    }
+
+    #[test]
+    fn test_invalid_homepage() {
+        let package = Package::with_defaults("foo/bar").unwrap();
+        for homepage in ["TBD", "bar.example.org", "ftp://example.org", "https://"] {
+            let repository = repository_fixture(serde_json::json!({
+                "id": 1,
+                "name": "bar",
+                "url": "https://api.github.com/repos/foo/bar",
+                "html_url": "https://github.com/foo/bar",
+                "homepage": homepage,
+            }));
+            let metadata = RepositoryMetadata::new(&package, &repository, "github.com");
+            assert_eq!(
+                metadata.homepage.as_deref(),
+                Some("https://github.com/foo/bar"),
+                "{homepage}"
             );
-            PackagingStatus::recipe_generation_failed(*target_platform)
+            assert_eq!(metadata.documentation, None);
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_metadata_overrides() {
+        let repository = repository_fixture(serde_json::json!({
+            "id": 1,
+            "name": "bar",
+            "url": "https://api.github.com/repos/foo/bar",
+            "homepage": "https://bar.readthedocs.io/en/latest",
+            "description": "A tool",
+        }));
+
+        let mut package = Package::with_defaults("foo/bar").unwrap();
+        let metadata = RepositoryMetadata::new(&package, &repository, "github.com");
+        assert_eq!(
+            metadata.documentation.as_deref(),
+            Some("https://bar.readthedocs.io/en/latest")
+        );
+
+        package.summary = Some("A better summary".to_string());
+        package.documentation = Some("https://example.org/manual".to_string());
+        let metadata = RepositoryMetadata::new(&package, &repository, "github.com");
+        assert_eq!(metadata.description.as_deref(), Some("A better summary"));
+        assert_eq!(
+            metadata.documentation.as_deref(),
+            Some("https://example.org/manual")
+        );
+
+        package.homepage = Some("https://bar.example.org".to_string());
+        let metadata = RepositoryMetadata::new(&package, &repository, "github.com");
+        assert_eq!(
+            metadata.homepage.as_deref(),
+            Some("https://bar.example.org")
+        );
 
-    use crate::config_file::tests::get_default_patterns;
+        assert!(is_documentation_url("https://docs.rs/bar"));
+        assert!(is_documentation_url("https://example.org/bar/docs/"));
+        assert!(!is_documentation_url("https://example.org/bar"));
+        assert!(!is_documentation_url("not a url"));
+    }
 
     fn zoxide_names() -> Vec<&'static str> {
         vec![
@@ -715,13 +3218,33 @@ mod tests {
         ]
     }
 
+    fn starship_names() -> Vec<&'static str> {
+        vec![
+            "starship-aarch64-apple-darwin.tar.gz",
+            "starship-aarch64-apple-darwin.tar.gz.sha256",
+            "starship-aarch64-pc-windows-msvc.zip",
+            "starship-aarch64-unknown-linux-musl.tar.gz",
+            "starship-arm-unknown-linux-musleabihf.tar.gz",
+            "starship-arm-unknown-linux-musleabihf.tar.gz.sha256",
+            "starship-i686-pc-windows-msvc.zip",
+            "starship-i686-unknown-linux-musl.tar.gz",
+            "starship-x86_64-apple-darwin.tar.gz",
+            "starship-x86_64-pc-windows-msvc.zip",
+            "starship-x86_64-unknown-freebsd.tar.gz",
+            "starship-x86_64-unknown-linux-gnu.tar.gz",
+            "starship-x86_64-unknown-linux-musl.tar.gz",
+        ]
+    }
+
     #[track_caller]
     fn assert_platform<'a>(
         patterns: &[regex::Regex],
         assets: &'a [&'a str],
         expected: Option<usize>,
     ) {
-        let result = match_platform_names(patterns, assets);
+        let result = matching_assets(patterns, &AssetFilter::default(), assets)
+            .first()
+            .copied();
 
         if let Some(index) = &result {
             eprintln!("    Matched: \"{}\" (index: {index})", assets[*index]);
@@ -763,6 +3286,7 @@ mod tests {
                 (Platform::Linux32, 6),
                 (Platform::Linux64, 9),
                 (Platform::LinuxAarch64, 3),
+                (Platform::LinuxArmV7l, 5),
                 (Platform::Osx64, 7),
                 (Platform::OsxArm64, 0),
                 (Platform::Win64, 8),
@@ -772,6 +3296,38 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_starship_names() {
+        platform_match_test(
+            &[
+                (Platform::Linux32, 7),
+                (Platform::Linux64, 12),
+                (Platform::LinuxAarch64, 3),
+                (Platform::LinuxArmV7l, 4),
+                (Platform::Osx64, 8),
+                (Platform::OsxArm64, 0),
+                (Platform::Win32, 6),
+                (Platform::Win64, 9),
+                (Platform::WinArm64, 2),
+            ],
+            &starship_names(),
+        );
+
+        // ARMv6 glibc builds work as well, soft-float ones do not
+        let patterns = get_default_patterns()
+            .remove(&Platform::LinuxArmV7l)
+            .unwrap();
+        assert_platform(
+            &patterns,
+            &[
+                "tool-arm-unknown-linux-gnueabi.tar.gz",
+                "tool-arm-unknown-linux-gnueabihf.tar.gz",
+            ],
+            Some(1),
+        );
+        assert_platform(&patterns, &["tool-arm-unknown-linux-gnueabi.tar.gz"], None);
+    }
+
     #[test]
     fn test_atuin_names() {
         platform_match_test(
@@ -802,6 +3358,7 @@ mod tests {
         platform_match_test(
             &[
                 (Platform::LinuxAarch64, 14),
+                (Platform::LinuxArmV7l, 22),
                 (Platform::Linux64, 42),
                 (Platform::Osx64, 30),
                 (Platform::OsxArm64, 2),
@@ -817,6 +3374,8 @@ mod tests {
         platform_match_test(
             &[
                 (Platform::LinuxAarch64, 12),
+                (Platform::LinuxArmV7l, 15),
+                (Platform::LinuxRiscv64, 20),
                 (Platform::Linux32, 18),
                 (Platform::Linux64, 29),
                 (Platform::Osx64, 21),
@@ -828,6 +3387,223 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_ppc64le_names() {
+        let patterns = get_optional_patterns(Platform::LinuxPpc64le);
+        assert_platform(&patterns, &bottom_names(), Some(19));
+        assert_platform(&patterns, &zoxide_names(), None);
+        assert_platform(
+            &patterns,
+            &["tool-linux-ppc64le.tar.gz", "tool-linux-amd64.tar.gz"],
+            Some(0),
+        );
+    }
+
+    #[test]
+    fn test_wasi_names() {
+        let patterns = get_optional_patterns(Platform::WasiWasm32);
+        assert_platform(&patterns, &bottom_names(), None);
+        assert_platform(
+            &patterns,
+            &[
+                "tool-x86_64-unknown-linux-musl.tar.gz",
+                "tool-wasm32-wasip1.tar.gz",
+            ],
+            Some(1),
+        );
+        assert_platform(&patterns, &["tool-linux-amd64", "tool.wasm"], Some(1));
+    }
+
+    #[test]
+    fn test_unsupported_os() {
+        assert_eq!(
+            unsupported_os("bottom_x86_64-unknown-freebsd-14.3.tar.gz", None).as_deref(),
+            Some("freebsd")
+        );
+        assert_eq!(
+            unsupported_os("lazygit_0.52.0_FreeBSD_armv6.tar.gz", None).as_deref(),
+            Some("freebsd")
+        );
+        assert_eq!(
+            unsupported_os("tool-armv7-linux-androideabi.tar.gz", None).as_deref(),
+            Some("android")
+        );
+        assert_eq!(
+            unsupported_os("bottom_x86_64-unknown-linux-gnu.tar.gz", None),
+            None
+        );
+
+        let any_x86_64 = [regex::Regex::new("x86_64").unwrap()];
+        assert_platform(
+            &any_x86_64,
+            &[
+                "tool-x86_64-unknown-freebsd.tar.gz",
+                "tool-x86_64-unknown-linux-musl.tar.gz",
+            ],
+            Some(1),
+        );
+
+        // OS names in the package name do not count
+        let dragonfly = Package::with_defaults("dragonflydb/dragonfly").unwrap();
+        assert_eq!(
+            unsupported_os(
+                "dragonfly-x86_64-unknown-linux-gnu.tar.gz",
+                Some(&dragonfly)
+            ),
+            None
+        );
+        assert_eq!(
+            unsupported_os("dragonfly-x86_64-unknown-freebsd.tar.gz", Some(&dragonfly)).as_deref(),
+            Some("freebsd")
+        );
+        let android_tools = Package::with_defaults("foo/android-tools").unwrap();
+        assert_eq!(
+            unsupported_os("android-tools-linux.zip", Some(&android_tools)),
+            None
+        );
+        assert!(unsupported_os("android-tools-linux.zip", None).is_some());
+
+        let mut freebsd = Package::with_defaults("foo/bar").unwrap();
+        freebsd.unsupported_oses = Some(vec![]);
+        assert_eq!(
+            unsupported_os("bar-x86_64-unknown-freebsd.tar.gz", Some(&freebsd)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_exclude_assets() {
+        let patterns = [regex::Regex::new("windows.*\\.zip$").unwrap()];
+        let excludes = [
+            regex::Regex::new("\\.full\\.zip$").unwrap(),
+            regex::Regex::new("-update").unwrap(),
+        ];
+        let assets = [
+            "tool-windows-update.zip",
+            "tool-windows.full.zip",
+            "tool-windows.zip",
+        ];
+        let filter = AssetFilter {
+            excludes: &excludes,
+            ..Default::default()
+        };
+        assert_eq!(
+            matching_assets(&patterns, &AssetFilter::default(), &assets),
+            vec![0, 1, 2]
+        );
+        assert_eq!(matching_assets(&patterns, &filter, &assets), vec![2]);
+        assert!(matching_assets(&patterns, &filter, &assets[..2]).is_empty());
+    }
+
+    #[test]
+    fn test_source_code() {
+        assert!(is_source_code("Source code"));
+        assert!(is_source_code("Source code (zip)"));
+        assert!(is_source_code("Source code (tar.gz)"));
+        assert!(!is_source_code("Source code.tar.gz"));
+        assert!(!is_source_code("tool-x86_64-linux.tar.gz"));
+
+        let patterns = [regex::Regex::new("tar\\.gz").unwrap()];
+        let assets = ["Source code (zip)", "Source code (tar.gz)"];
+        assert!(matching_assets(&patterns, &AssetFilter::default(), &assets).is_empty());
+        let filter = AssetFilter {
+            source_code: true,
+            ..Default::default()
+        };
+        assert_eq!(matching_assets(&patterns, &filter, &assets), vec![1]);
+    }
+
+    #[test]
+    fn test_variant_filter() {
+        let patterns = [regex::Regex::new("linux").unwrap()];
+        let assets = ["tool-linux-MUSL.tar.gz", "tool-linux-gnu.tar.gz"];
+        let variant = |variant| AssetFilter {
+            variant: Some(variant),
+            ..Default::default()
+        };
+        assert_eq!(
+            matching_assets(&patterns, &variant("gnu"), &assets),
+            vec![1]
+        );
+        assert_eq!(
+            matching_assets(&patterns, &variant("musl"), &assets),
+            vec![0]
+        );
+        assert!(matching_assets(&patterns, &variant("msvc"), &assets).is_empty());
+    }
+
+    #[test]
+    fn test_pick_asset() {
+        let patterns = [regex::Regex::new("linux").unwrap()];
+        let names = [
+            "tool-full-linux.tar.gz",
+            "tool-musl-linux.tar.gz",
+            "tool-minimal-linux.tar.gz",
+            "tool-macos.tar.gz",
+        ];
+        let sizes = [300, 200, 100, 50];
+        let candidates = matching_assets(&patterns, &AssetFilter::default(), &names);
+        assert_eq!(candidates, vec![0, 1, 2]);
+
+        let pick = |prefer: &[&str], size| {
+            let preference = AssetPreference {
+                prefer: prefer.iter().map(|p| p.to_string()).collect(),
+                size,
+            };
+            pick_asset(&candidates, &names, &sizes, &preference)
+        };
+        assert_eq!(pick(&[], SizePreference::Ignore), Some(0));
+        assert_eq!(pick(&[], SizePreference::Smallest), Some(2));
+        assert_eq!(pick(&[], SizePreference::Largest), Some(0));
+        assert_eq!(pick(&["MUSL"], SizePreference::Ignore), Some(1));
+        assert_eq!(
+            pick(&["nothing", "minimal"], SizePreference::Largest),
+            Some(2)
+        );
+        assert_eq!(pick(&["nothing"], SizePreference::Largest), Some(0));
+        assert_eq!(
+            pick_asset(&[], &names, &sizes, &AssetPreference::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_newest_indices() {
+        let at = |day| {
+            Some(
+                chrono::DateTime::parse_from_rfc3339(&format!("2024-10-{day:02}T00:00:00Z"))
+                    .unwrap()
+                    .to_utc(),
+            )
+        };
+        let items = [
+            ("1.2.3", at(2)),
+            ("1.2.3", at(3)),
+            ("1.2.2", at(1)),
+            ("1.2.2", at(1)),
+            ("1.2.1", None),
+            ("1.2.1", at(1)),
+        ];
+        let newest = newest_indices(items.into_iter().enumerate());
+        assert_eq!(newest.len(), 3);
+        assert_eq!(newest["1.2.3"], 1);
+        assert_eq!(newest["1.2.2"], 2);
+        assert_eq!(newest["1.2.1"], 5);
+    }
+
+    #[test]
+    fn test_newest_versions() {
+        let versions = ["1.9.0", "1.10.0", "1.2.0", "a!1.0", "1.10.0", "0.9"];
+        let newest = newest_versions(versions.iter().copied(), 2);
+        let expected = ["1.10.0", "1.9.0"]
+            .iter()
+            .map(|v| rattler_conda_types::Version::from_str(v).unwrap())
+            .collect::<HashSet<_>>();
+        assert_eq!(newest, expected);
+
+        assert_eq!(newest_versions(versions.iter().copied(), 10).len(), 4);
+    }
+
     #[test]
     fn test_jjui_names() {
         platform_match_test(
@@ -889,6 +3665,8 @@ mod tests {
         platform_match_test(
             &[
                 (Platform::LinuxAarch64, 2),
+                (Platform::LinuxArmV7l, 3),
+                (Platform::LinuxRiscv64, 4),
                 (Platform::Linux64, 5),
                 (Platform::OsxArm64, 0),
                 (Platform::Osx64, 1),
@@ -917,6 +3695,7 @@ mod tests {
         platform_match_test(
             &[
                 (Platform::LinuxAarch64, 7),
+                (Platform::LinuxArmV7l, 8),
                 (Platform::Linux64, 9),
                 (Platform::OsxArm64, 0),
                 (Platform::Osx64, 1),
@@ -0,0 +1,218 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Check releases and their assets against the policy of a package before
+//! packaging them
+
+use octocrab::models::repos::{Asset, Release};
+
+use crate::{config_file::Policy, types::Repository};
+
+/// Stands for the owner of the repository in `uploaders`
+pub const OWNER_PLACEHOLDER: &str = "{owner}";
+
+/// Why an asset may not be packaged
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// The release is too new, it can be packaged later
+    TooYoung(String),
+    /// The asset must not be packaged at all
+    Forbidden(String),
+}
+
+/// Seconds as the largest whole unit, e.g. `3h`
+fn format_duration(duration: chrono::Duration) -> String {
+    let seconds = duration.num_seconds();
+    match seconds {
+        s if s >= 24 * 60 * 60 => format!("{}d", s / (24 * 60 * 60)),
+        s if s >= 60 * 60 => format!("{}h", s / (60 * 60)),
+        s if s >= 60 => format!("{}m", s / 60),
+        s => format!("{}s", s.max(0)),
+    }
+}
+
+/// The earliest publication time of releases a run looking at releases
+/// published `since` needs to consider
+///
+/// Releases that were too young for `min_release_age` when the run at
+/// `since` saw them were deferred, so they are included again.
+pub fn since(
+    policy: &Policy,
+    since: chrono::DateTime<chrono::Utc>,
+) -> chrono::DateTime<chrono::Utc> {
+    let min_age = policy
+        .min_release_age
+        .and_then(|age| chrono::Duration::from_std(age).ok())
+        .unwrap_or_default();
+    since
+        .checked_sub_signed(min_age)
+        .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC)
+}
+
+/// Check `asset` of `release` of `repository` against `policy` at `now`
+pub fn check(
+    policy: &Policy,
+    repository: &Repository,
+    release: &Release,
+    asset: &Asset,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<(), Violation> {
+    if !policy.uploaders.is_empty() {
+        let Some(uploader) = &asset.uploader else {
+            return Err(Violation::Forbidden(format!(
+                "GitHub does not say who uploaded {}",
+                asset.name
+            )));
+        };
+        let allowed = policy.uploaders.iter().any(|u| {
+            let u = if u == OWNER_PLACEHOLDER {
+                &repository.owner
+            } else {
+                u
+            };
+            u.eq_ignore_ascii_case(&uploader.login)
+        });
+        if !allowed {
+            return Err(Violation::Forbidden(format!(
+                "{} was uploaded by {}, who is not among the allowed uploaders",
+                asset.name, uploader.login
+            )));
+        }
+    }
+
+    if let Some(min_age) = policy.min_release_age {
+        let Some(published) = release.published_at.or(release.created_at) else {
+            return Err(Violation::TooYoung(format!(
+                "{} is not published yet",
+                release.tag_name
+            )));
+        };
+        let age = now - published;
+        let min_age = chrono::Duration::from_std(min_age).unwrap_or(chrono::Duration::MAX);
+        if age < min_age {
+            return Err(Violation::TooYoung(format!(
+                "{} is {} old, the policy asks for {}",
+                release.tag_name,
+                format_duration(age),
+                format_duration(min_age)
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package_generation::tests::{asset_fixture, release_fixture};
+
+    fn uploader(login: &str) -> octocrab::models::repos::Uploader {
+        let url = format!("https://api.github.com/users/{login}");
+        serde_json::from_value(serde_json::json!({
+            "login": login,
+            "id": 1,
+            "node_id": "U_1",
+            "avatar_url": "https://avatars.githubusercontent.com/u/1",
+            "gravatar_id": "",
+            "url": url,
+            "html_url": format!("https://github.com/{login}"),
+            "followers_url": format!("{url}/followers"),
+            "following_url": format!("{url}/following"),
+            "gists_url": format!("{url}/gists"),
+            "starred_url": format!("{url}/starred"),
+            "subscriptions_url": format!("{url}/subscriptions"),
+            "organizations_url": format!("{url}/orgs"),
+            "repos_url": format!("{url}/repos"),
+            "events_url": format!("{url}/events"),
+            "received_events_url": format!("{url}/received_events"),
+            "type": "User",
+            "site_admin": false,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_uploaders() {
+        let repository = Repository::try_from("foo/bar").unwrap();
+        let release = release_fixture("v1.0.0", &[]);
+        let now = "2025-02-01T00:00:00Z".parse().unwrap();
+        let policy = Policy {
+            uploaders: vec![
+                OWNER_PLACEHOLDER.to_string(),
+                "github-actions[bot]".to_string(),
+            ],
+            ..Default::default()
+        };
+        let check_uploader = |login: Option<&str>| {
+            let mut asset = asset_fixture("bar-linux.tar.gz", None);
+            asset.uploader = login.map(uploader);
+            check(&policy, &repository, &release, &asset, now)
+        };
+
+        assert_eq!(check_uploader(Some("Foo")), Ok(()));
+        assert_eq!(check_uploader(Some("github-actions[bot]")), Ok(()));
+        assert_eq!(
+            check_uploader(Some("mallory")),
+            Err(Violation::Forbidden(
+                "bar-linux.tar.gz was uploaded by mallory, who is not among the allowed uploaders"
+                    .to_string()
+            ))
+        );
+        assert!(matches!(check_uploader(None), Err(Violation::Forbidden(_))));
+
+        let anyone = Policy::default();
+        let asset = asset_fixture("bar-linux.tar.gz", None);
+        assert_eq!(check(&anyone, &repository, &release, &asset, now), Ok(()));
+    }
+
+    #[test]
+    fn test_min_release_age() {
+        let repository = Repository::try_from("foo/bar").unwrap();
+        let release = release_fixture("v1.0.0", &[]);
+        let asset = asset_fixture("bar-linux.tar.gz", None);
+        let policy = Policy {
+            min_release_age: Some(std::time::Duration::from_secs(24 * 60 * 60)),
+            ..Default::default()
+        };
+        let check_at =
+            |now: &str| check(&policy, &repository, &release, &asset, now.parse().unwrap());
+
+        assert_eq!(check_at("2025-01-02T00:00:00Z"), Ok(()));
+        assert_eq!(
+            check_at("2025-01-01T03:00:00Z"),
+            Err(Violation::TooYoung(
+                "v1.0.0 is 3h old, the policy asks for 1d".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_since_last_run() {
+        let repository = Repository::try_from("foo/bar").unwrap();
+        // Published 2025-01-01T00:00:00Z
+        let release = release_fixture("v1.0.0", &[]);
+        let asset = asset_fixture("bar-linux.tar.gz", None);
+        let policy = Policy {
+            min_release_age: Some(std::time::Duration::from_secs(24 * 60 * 60)),
+            ..Default::default()
+        };
+        let published = release.published_at.unwrap();
+
+        // The run three hours after the release defers it and counts as
+        // failure-free, so the next run looks at releases since then
+        let last_run = "2025-01-01T03:00:00Z".parse().unwrap();
+        assert!(matches!(
+            check(&policy, &repository, &release, &asset, last_run),
+            Err(Violation::TooYoung(_))
+        ));
+
+        // The next run still sees the deferred release and packages it
+        let now = "2025-01-02T03:00:00Z".parse().unwrap();
+        assert!(published < last_run);
+        assert!(published >= since(&policy, last_run));
+        assert_eq!(check(&policy, &repository, &release, &asset, now), Ok(()));
+
+        // Without a minimum age, releases before the last run are done
+        assert!(published < since(&Policy::default(), last_run));
+    }
+}
@@ -0,0 +1,655 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! The subset of the rattler-build recipe format octoconda generates
+
+use std::collections::BTreeMap;
+
+use anyhow::Context as _;
+use serde::Serialize;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Recipe {
+    pub package: PackageSection,
+    pub source: Source,
+    pub build: Build,
+    #[serde(skip_serializing_if = "Requirements::is_empty")]
+    pub requirements: Requirements,
+    pub tests: Vec<Test>,
+    pub extra: Extra,
+    pub about: About,
+}
+
+impl Recipe {
+    pub fn to_yaml(&self) -> anyhow::Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PackageSection {
+    pub name: String,
+    pub version: String,
+}
+
+/// rattler-build can not check sha512 digests, so those only end up in
+/// [`Extra`]
+#[derive(Clone, Debug, Serialize)]
+pub struct Source {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    pub file_name: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Build {
+    pub number: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub string: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noarch: Option<String>,
+    /// Replaces `build.sh`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    pub dynamic_linking: DynamicLinking,
+    pub prefix_detection: PrefixDetection,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DynamicLinking {
+    pub binary_relocation: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PrefixDetection {
+    pub ignore: bool,
+}
+
+/// Constraints on packages depending on or installed next to this one
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Requirements {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub build: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub host: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub run: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub run_exports: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub run_constraints: Vec<String>,
+}
+
+impl Requirements {
+    pub fn is_empty(&self) -> bool {
+        self.build.is_empty()
+            && self.host.is_empty()
+            && self.run.is_empty()
+            && self.run_exports.is_empty()
+            && self.run_constraints.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Test {
+    pub package_contents: PackageContents,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PackageContents {
+    pub files: Files,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub bin: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub lib: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub site_packages: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Files {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exists: Vec<String>,
+    pub not_exists: Vec<String>,
+}
+
+/// Where the packaged file came from
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Extra {
+    pub upstream_forge: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_sha512: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_md5: Option<String>,
+    pub upstream_version: String,
+    pub upstream_repository: String,
+    pub release_download_url: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recipe_maintainers: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doi: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub citation: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct About {
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+    pub repository: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+}
+
+/// One recipe with several sources, picked by `if`/`then` selectors on a
+/// variable
+///
+/// Multi-platform recipes select on `target_platform`, recipes with
+/// variants on [`VARIANT_VARIABLE`]. Everything but the source is shared.
+#[derive(Clone, Debug, Serialize)]
+pub struct SelectorRecipe {
+    pub package: PackageSection,
+    pub source: Vec<Selected<Source>>,
+    pub build: Build,
+    #[serde(skip_serializing_if = "Requirements::is_empty")]
+    pub requirements: Requirements,
+    pub tests: Vec<Test>,
+    pub extra: SelectorExtra,
+    pub about: About,
+}
+
+/// The variant variable of recipes for packages with `variants`
+pub const VARIANT_VARIABLE: &str = "variant";
+
+impl SelectorRecipe {
+    /// Merge `recipes` of the same package version that differ in the value
+    /// of `variable` only
+    ///
+    /// Everything but the source and its upstream details is taken from the
+    /// first recipe. Recipes that need different relocation, e.g. a script
+    /// and a binary, can not share a build section and are not merged.
+    pub fn new(variable: &str, recipes: Vec<(String, Recipe)>) -> anyhow::Result<Self> {
+        let mut source = Vec::new();
+        let mut sources = BTreeMap::new();
+        let mut first: Option<(String, Recipe)> = None;
+        for (value, recipe) in recipes {
+            if let Some((first_value, first)) = &first
+                && relocation(first) != relocation(&recipe)
+            {
+                return Err(anyhow::anyhow!(
+                    "{variable} {first_value} and {value} need different relocation"
+                ));
+            }
+            source.push(Selected {
+                condition: format!("{variable} == \"{value}\""),
+                then: recipe.source.clone(),
+            });
+            sources.insert(
+                value.clone(),
+                SourceExtra {
+                    upstream_sha256: recipe.extra.upstream_sha256.clone(),
+                    upstream_sha512: recipe.extra.upstream_sha512.clone(),
+                    upstream_md5: recipe.extra.upstream_md5.clone(),
+                    release_download_url: recipe.extra.release_download_url.clone(),
+                },
+            );
+            first.get_or_insert((value, recipe));
+        }
+        let (_, first) = first.ok_or_else(|| anyhow::anyhow!("No recipes to merge"))?;
+
+        Ok(Self {
+            package: first.package,
+            source,
+            build: first.build,
+            requirements: first.requirements,
+            tests: first.tests,
+            extra: SelectorExtra {
+                upstream_forge: first.extra.upstream_forge,
+                upstream_version: first.extra.upstream_version,
+                upstream_repository: first.extra.upstream_repository,
+                recipe_maintainers: first.extra.recipe_maintainers,
+                doi: first.extra.doi,
+                citation: first.extra.citation,
+                sources,
+            },
+            about: first.about,
+        })
+    }
+
+    pub fn to_yaml(&self) -> anyhow::Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+}
+
+/// Binary relocation and prefix detection of `recipe`
+fn relocation(recipe: &Recipe) -> (bool, bool) {
+    (
+        recipe.build.dynamic_linking.binary_relocation,
+        recipe.build.prefix_detection.ignore,
+    )
+}
+
+/// A list item rattler-build only uses when `condition` holds
+#[derive(Clone, Debug, Serialize)]
+pub struct Selected<T> {
+    #[serde(rename = "if")]
+    pub condition: String,
+    pub then: T,
+}
+
+/// [`Extra`] of a [`SelectorRecipe`]
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SelectorExtra {
+    pub upstream_forge: String,
+    pub upstream_version: String,
+    pub upstream_repository: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recipe_maintainers: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doi: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub citation: Option<String>,
+    /// Where the file for each value of the selector variable came from
+    pub sources: BTreeMap<String, SourceExtra>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SourceExtra {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_sha512: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_md5: Option<String>,
+    pub release_download_url: String,
+}
+
+/// The data of a [`Recipe`] as conda-build `meta.yaml`
+///
+/// conda-build has no equivalent of the package content tests, so there are
+/// none.
+#[derive(Clone, Debug, Serialize)]
+pub struct MetaYaml {
+    pub package: PackageSection,
+    pub source: MetaSource,
+    pub build: MetaBuild,
+    #[serde(skip_serializing_if = "MetaRequirements::is_empty")]
+    pub requirements: MetaRequirements,
+    pub extra: Extra,
+    pub about: MetaAbout,
+}
+
+impl MetaYaml {
+    pub fn to_yaml(&self) -> anyhow::Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+}
+
+impl From<Recipe> for MetaYaml {
+    fn from(recipe: Recipe) -> Self {
+        let folder = "src";
+        // The source file is in `folder`
+        let script = recipe.build.script.map(|s| {
+            s.replace(
+                &recipe.source.file_name,
+                &format!("{folder}/{}", recipe.source.file_name),
+            )
+        });
+        Self {
+            package: recipe.package,
+            source: MetaSource {
+                url: recipe.source.url,
+                sha256: recipe.source.sha256,
+                md5: recipe.source.md5,
+                file_name: recipe.source.file_name,
+                folder: folder.to_string(),
+            },
+            build: MetaBuild {
+                number: recipe.build.number,
+                string: recipe.build.string,
+                noarch: recipe.build.noarch,
+                script,
+                binary_relocation: recipe.build.dynamic_linking.binary_relocation,
+                detect_binary_files_with_prefix: !recipe.build.prefix_detection.ignore,
+                run_exports: recipe.requirements.run_exports,
+            },
+            requirements: MetaRequirements {
+                build: recipe.requirements.build,
+                host: recipe.requirements.host,
+                run: recipe.requirements.run,
+                run_constrained: recipe.requirements.run_constraints,
+            },
+            extra: recipe.extra,
+            about: MetaAbout {
+                home: recipe.about.homepage,
+                license: recipe.about.license,
+                summary: recipe.about.summary,
+                description: recipe.about.description,
+                dev_url: recipe.about.repository,
+                doc_url: recipe.about.documentation,
+            },
+        }
+    }
+}
+
+/// conda-build unpacks archives into `folder`, the build script looks there
+#[derive(Clone, Debug, Serialize)]
+pub struct MetaSource {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    #[serde(rename = "fn")]
+    pub file_name: String,
+    pub folder: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MetaBuild {
+    pub number: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub string: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noarch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    pub binary_relocation: bool,
+    pub detect_binary_files_with_prefix: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub run_exports: Vec<String>,
+}
+
+/// conda-build calls run constraints `run_constrained`
+#[derive(Clone, Debug, Serialize)]
+pub struct MetaRequirements {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub build: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub host: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub run: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub run_constrained: Vec<String>,
+}
+
+impl MetaRequirements {
+    pub fn is_empty(&self) -> bool {
+        self.build.is_empty()
+            && self.host.is_empty()
+            && self.run.is_empty()
+            && self.run_constrained.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MetaAbout {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub home: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub description: String,
+    pub dev_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc_url: Option<String>,
+}
+
+fn required_str<'a>(
+    recipe: &'a serde_yaml::Value,
+    section: &str,
+    key: &str,
+) -> anyhow::Result<&'a str> {
+    recipe
+        .get(section)
+        .and_then(|s| s.get(key))
+        .and_then(|v| v.as_str())
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Recipe has no {section}.{key} string"))
+}
+
+fn build_number(recipe: &serde_yaml::Value) -> anyhow::Result<u64> {
+    recipe
+        .get("build")
+        .and_then(|b| b.get("number"))
+        .and_then(|n| n.as_u64())
+        .ok_or_else(|| anyhow::anyhow!("Recipe has no numeric build.number"))
+}
+
+/// The sources of `recipe`, with the platform they are selected for
+///
+/// A plain source is used for all platforms and has no selector.
+fn sources(recipe: &serde_yaml::Value) -> Vec<(Option<&str>, &serde_yaml::Value)> {
+    match recipe.get("source") {
+        Some(serde_yaml::Value::Sequence(sources)) => sources
+            .iter()
+            .map(|s| {
+                let platform = s
+                    .get("if")
+                    .and_then(|c| c.as_str())
+                    .and_then(|c| c.strip_prefix("target_platform == \""))
+                    .and_then(|c| c.strip_suffix('"'));
+                (platform, s.get("then").unwrap_or(s))
+            })
+            .collect(),
+        Some(source) => vec![(None, source)],
+        None => vec![],
+    }
+}
+
+/// Make sure `content` is a YAML document rattler-build can work with
+///
+/// This catches broken custom templates and quoting issues before a recipe
+/// is reported as generated.
+pub fn validate(content: &str) -> anyhow::Result<()> {
+    let recipe: serde_yaml::Value =
+        serde_yaml::from_str(content).context("Recipe is not valid YAML")?;
+
+    required_str(&recipe, "package", "name")?;
+    required_str(&recipe, "package", "version")?;
+    let sources = sources(&recipe);
+    if sources.is_empty() {
+        return Err(anyhow::anyhow!("Recipe has no source"));
+    }
+    for (_, source) in sources {
+        let url = source
+            .get("url")
+            .and_then(|u| u.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Recipe has no source.url string"))?;
+        url::Url::parse(url).context(format!("Recipe has an invalid source.url {url:?}"))?;
+    }
+    build_number(&recipe)?;
+
+    Ok(())
+}
+
+/// The target platforms the sources of a multi-platform recipe are selected
+/// for
+pub fn selected_platforms(content: &str) -> anyhow::Result<Vec<String>> {
+    let recipe: serde_yaml::Value =
+        serde_yaml::from_str(content).context("Recipe is not valid YAML")?;
+
+    Ok(sources(&recipe)
+        .into_iter()
+        .filter_map(|(platform, _)| platform.map(str::to_string))
+        .collect())
+}
+
+/// Package name, version and build number of the recipe in `content`
+pub fn identify(content: &str) -> anyhow::Result<(String, String, u64)> {
+    let recipe: serde_yaml::Value =
+        serde_yaml::from_str(content).context("Recipe is not valid YAML")?;
+
+    Ok((
+        required_str(&recipe, "package", "name")?.to_string(),
+        required_str(&recipe, "package", "version")?.to_string(),
+        build_number(&recipe)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let valid = r#"
+package:
+  name: bar
+  version: "1.0.0"
+source:
+  url: https://github.com/foo/bar/releases/download/v1.0.0/bar.tar.gz
+build:
+  number: 0
+"#;
+        assert!(validate(valid).is_ok());
+
+        assert!(validate("package: [").is_err());
+        assert!(validate(&valid.replace("  name: bar\n", "")).is_err());
+        assert!(validate(&valid.replace("https://", "")).is_err());
+        assert!(validate(&valid.replace("number: 0", "number: zero")).is_err());
+
+        assert_eq!(
+            identify(valid).unwrap(),
+            ("bar".to_string(), "1.0.0".to_string(), 0)
+        );
+        assert!(selected_platforms(valid).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_selector_recipe() {
+        let recipe = |platform: &str| Recipe {
+            package: PackageSection {
+                name: "bar".to_string(),
+                version: "1.0.0".to_string(),
+            },
+            source: Source {
+                url: format!(
+                    "https://github.com/foo/bar/releases/download/v1.0.0/bar-{platform}.zip"
+                ),
+                sha256: Some(format!("{platform}-sha")),
+                md5: None,
+                file_name: format!("bar-1.0.0-{platform}.zip"),
+            },
+            build: Build {
+                number: 1,
+                string: None,
+                noarch: None,
+                script: None,
+                dynamic_linking: DynamicLinking {
+                    binary_relocation: false,
+                },
+                prefix_detection: PrefixDetection { ignore: true },
+            },
+            requirements: Requirements {
+                build: vec![],
+                host: vec![],
+                run: vec![],
+                run_exports: vec![],
+                run_constraints: vec!["bar-plugins >=1".to_string()],
+            },
+            tests: vec![],
+            extra: Extra {
+                upstream_forge: "github.com".to_string(),
+                upstream_sha256: Some(format!("{platform}-sha")),
+                upstream_sha512: None,
+                upstream_md5: None,
+                upstream_version: "1.0.0".to_string(),
+                upstream_repository: "foo/bar".to_string(),
+                release_download_url: format!("https://example.com/{platform}"),
+                recipe_maintainers: vec![],
+                doi: None,
+                citation: None,
+            },
+            about: About {
+                description: "bar".to_string(),
+                homepage: None,
+                repository: "https://github.com/foo/bar".to_string(),
+                documentation: None,
+                license: None,
+                summary: None,
+            },
+        };
+
+        assert!(SelectorRecipe::new("target_platform", vec![]).is_err());
+        let content = SelectorRecipe::new(
+            "target_platform",
+            ["linux-64", "win-64"]
+                .into_iter()
+                .map(|p| (p.to_string(), recipe(p)))
+                .collect(),
+        )
+        .unwrap()
+        .to_yaml()
+        .unwrap();
+
+        validate(&content).unwrap();
+        assert_eq!(
+            identify(&content).unwrap(),
+            ("bar".to_string(), "1.0.0".to_string(), 1)
+        );
+        assert_eq!(
+            selected_platforms(&content).unwrap(),
+            vec!["linux-64".to_string(), "win-64".to_string()]
+        );
+
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&content).unwrap();
+        assert_eq!(
+            yaml["source"][1]["if"].as_str(),
+            Some("target_platform == \"win-64\"")
+        );
+        assert_eq!(
+            yaml["source"][1]["then"]["sha256"].as_str(),
+            Some("win-64-sha")
+        );
+        assert_eq!(
+            yaml["extra"]["sources"]["linux-64"]["upstream-sha256"].as_str(),
+            Some("linux-64-sha")
+        );
+
+        let content = SelectorRecipe::new(
+            VARIANT_VARIABLE,
+            vec![("musl".to_string(), recipe("linux-64"))],
+        )
+        .unwrap()
+        .to_yaml()
+        .unwrap();
+        validate(&content).unwrap();
+        assert!(selected_platforms(&content).unwrap().is_empty());
+        assert!(content.contains("if: variant == \"musl\"\n"));
+        assert!(content.contains("requirements:\n  run_constraints:\n  - bar-plugins >=1\n"));
+
+        let mut script = recipe("win-64");
+        script.build.prefix_detection.ignore = false;
+        assert!(
+            SelectorRecipe::new(
+                "target_platform",
+                vec![
+                    ("linux-64".to_string(), recipe("linux-64")),
+                    ("win-64".to_string(), script),
+                ],
+            )
+            .is_err()
+        );
+
+        let meta = MetaYaml::from(recipe("linux-64")).to_yaml().unwrap();
+        assert!(meta.contains("requirements:\n  run_constrained:\n  - bar-plugins >=1\n"));
+        assert!(!meta.contains("run_exports"));
+    }
+}
@@ -0,0 +1,536 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::collections::HashMap;
+
+use rattler_conda_types::Platform;
+
+use crate::{
+    github::QuotaUsage,
+    package_generation::{ChosenAsset, Status, VersionPackagingStatus},
+};
+
+/// The outcome of a packaging run, keyed by package name
+#[derive(Default)]
+pub struct Report {
+    pub packages: HashMap<String, Vec<VersionPackagingStatus>>,
+    /// Recipes written into the work directory by this run
+    pub recipes: usize,
+    /// Platforms that were packaged before, but have no asset anymore
+    pub regressions: Vec<String>,
+    /// How long the stages of the run took, `None` unless asked for
+    pub timings: Option<Vec<Timing>>,
+    /// How much of the GitHub rate limit the run used, if known
+    pub github_quota: Option<QuotaUsage>,
+    /// Handles to ping when a package fails, keyed by package name
+    pub notify: HashMap<String, Vec<String>>,
+    /// The fallback source releases came from, keyed by package name
+    pub sources: HashMap<String, String>,
+    /// Channels that could not be checked for existing packages
+    pub channel_errors: Vec<String>,
+    /// Release assets that changed after they were packaged
+    pub reuploads: Vec<String>,
+}
+
+/// How long one stage of a run took
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Timing {
+    pub stage: &'static str,
+    /// The package the stage worked on, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    #[serde(rename = "seconds", serialize_with = "serialize_seconds")]
+    pub duration: std::time::Duration,
+}
+
+fn serialize_seconds<S: serde::Serializer>(
+    duration: &std::time::Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// The overall status of a package: Failed if anything failed, Disabled if
+/// the package is disabled, Succeeded if anything succeeded, Skipped
+/// otherwise.
+fn package_status(sub_status: &[VersionPackagingStatus]) -> Status {
+    sub_status
+        .iter()
+        .flat_map(|v| v.status.iter())
+        .fold(Status::Succeeded, |acc, s| match (&s.status, acc) {
+            (&Status::Failed, _) | (_, Status::Failed) => Status::Failed,
+            (&Status::Disabled, _) | (_, Status::Disabled) => Status::Disabled,
+            (&Status::Succeeded, Status::Succeeded) => Status::Succeeded,
+            (&Status::Succeeded, Status::Skipped) => Status::Succeeded,
+            (&Status::Skipped, Status::Succeeded) => Status::Succeeded,
+            (&Status::Skipped, Status::Skipped) => Status::Skipped,
+        })
+}
+
+fn colored(status: Status, text: &str, color: bool) -> String {
+    if !color {
+        return text.to_string();
+    }
+    let code = match status {
+        Status::Failed => "31",
+        Status::Succeeded => "32",
+        Status::Skipped => "33",
+        Status::Disabled => "90",
+    };
+    format!("\x1b[{code}m{text}\x1b[0m")
+}
+
+impl Report {
+    pub fn insert(&mut self, package: String, status: Vec<VersionPackagingStatus>) {
+        self.packages.insert(package, status);
+    }
+
+    pub fn len(&self) -> usize {
+        self.packages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    /// Package name, version and found platforms of the newest considered
+    /// version of each package
+    ///
+    /// Versions that were not considered for any platform, e.g. rejected
+    /// tags or releases held by the policy, do not count. Versions without
+    /// any matching asset do.
+    pub fn latest_coverage(&self) -> impl Iterator<Item = (&str, &str, &[Platform])> {
+        self.packages.iter().filter_map(|(package, sub_status)| {
+            sub_status
+                .iter()
+                .filter(|vs| {
+                    vs.status
+                        .iter()
+                        .any(|s| s.platform != Platform::Unknown && !s.held_by_policy)
+                })
+                .find_map(|vs| {
+                    vs.version
+                        .as_deref()
+                        .map(|v| (package.as_str(), v, &vs.found_platforms[..]))
+                })
+        })
+    }
+
+    /// The release assets of all packages, with the package name
+    pub fn assets(&self) -> impl Iterator<Item = (&str, &ChosenAsset)> {
+        self.packages.iter().flat_map(|(package, sub_status)| {
+            sub_status
+                .iter()
+                .flat_map(|vs| vs.status.iter())
+                .flat_map(|s| s.assets.iter())
+                .map(|a| (package.as_str(), a))
+        })
+    }
+
+    /// Remember how long `stage` took, if timings are recorded
+    pub fn record_timing(
+        &mut self,
+        stage: &'static str,
+        package: Option<&str>,
+        duration: std::time::Duration,
+    ) {
+        if let Some(timings) = &mut self.timings {
+            timings.push(Timing {
+                stage,
+                package: package.map(str::to_string),
+                duration,
+            });
+        }
+    }
+
+    /// The recorded timings as JSON
+    pub fn timings_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self.timings.as_deref().unwrap_or_default())
+    }
+
+    /// Number of packages with the overall `status`
+    pub fn count(&self, status: Status) -> usize {
+        self.packages
+            .values()
+            .filter(|s| package_status(s) == status)
+            .count()
+    }
+
+    /// Render the report grouped by overall package status, failures first.
+    ///
+    /// ANSI colors are used when `color` is set.
+    pub fn render(&self, color: bool) -> String {
+        let mut result = format!(
+            "{} succeeded, {} failed, {} skipped",
+            colored(
+                Status::Succeeded,
+                &self.count(Status::Succeeded).to_string(),
+                color
+            ),
+            colored(
+                Status::Failed,
+                &self.count(Status::Failed).to_string(),
+                color
+            ),
+            colored(
+                Status::Skipped,
+                &self.count(Status::Skipped).to_string(),
+                color
+            ),
+        );
+        let disabled = self.count(Status::Disabled);
+        if disabled > 0 {
+            result.push_str(&format!(
+                ", {} disabled",
+                colored(Status::Disabled, &disabled.to_string(), color)
+            ));
+        }
+        result.push('\n');
+
+        if !self.channel_errors.is_empty() {
+            result.push_str(&colored(
+                Status::Skipped,
+                "\nChannel check unavailable:",
+                color,
+            ));
+            result.push('\n');
+            for error in &self.channel_errors {
+                result.push_str(&format!("    {error}\n"));
+            }
+        }
+
+        if !self.reuploads.is_empty() {
+            result.push_str(&colored(
+                Status::Failed,
+                "\nRe-uploaded release assets (check them before trusting new builds):",
+                color,
+            ));
+            result.push('\n');
+            for reupload in &self.reuploads {
+                result.push_str(&format!("    {reupload}\n"));
+            }
+        }
+
+        if !self.regressions.is_empty() {
+            result.push_str(&colored(
+                Status::Failed,
+                "\nPlatform coverage regressions:",
+                color,
+            ));
+            result.push('\n');
+            for regression in &self.regressions {
+                result.push_str(&format!("    {regression}\n"));
+            }
+        }
+
+        for group in [
+            Status::Failed,
+            Status::Skipped,
+            Status::Succeeded,
+            Status::Disabled,
+        ] {
+            let mut packages = self
+                .packages
+                .iter()
+                .filter(|(_, s)| package_status(s) == group)
+                .collect::<Vec<_>>();
+            if packages.is_empty() {
+                continue;
+            }
+            packages.sort_by_key(|(name, _)| *name);
+
+            result.push('\n');
+
+            for (package, sub_status) in packages {
+                result.push_str(&colored(
+                    group,
+                    &format!("{group}: {} ({} packages)", package, sub_status.len()),
+                    color,
+                ));
+                result.push('\n');
+                if let (Status::Failed, Some(handles)) = (group, self.notify.get(package)) {
+                    result.push_str(&format!("    cc {}\n", handles.join(" ")));
+                }
+                if let Some(source) = self.sources.get(package) {
+                    result.push_str(&format!("    releases from {source}\n"));
+                }
+
+                for vs in sub_status {
+                    let version = vs.version.clone().unwrap_or_default();
+
+                    let skipped = {
+                        let skipped = vs
+                            .status
+                            .iter()
+                            .filter_map(|s| {
+                                (s.status == Status::Skipped && s.platform != Platform::Unknown)
+                                    .then_some(s.platform)
+                            })
+                            .fold(String::new(), |acc, p| {
+                                if acc.is_empty() {
+                                    format!("{p}")
+                                } else {
+                                    format!("{acc}, {p}")
+                                }
+                            });
+                        if skipped.is_empty() {
+                            skipped
+                        } else {
+                            format!(" skipped: {skipped}")
+                        }
+                    };
+
+                    result.push_str(&format!("    {version}{skipped}\n"));
+
+                    for s in &vs.status {
+                        if s.status == Status::Skipped && s.platform != Platform::Unknown {
+                            continue;
+                        }
+                        result.push_str(&colored(
+                            s.status,
+                            &format!("        {}: {} {}", s.status, s.platform, s.message),
+                            color,
+                        ));
+                        result.push('\n');
+                    }
+                }
+            }
+        }
+
+        if let Some(quota) = &self.github_quota {
+            let status = if quota.is_low() || !quota.is_authenticated() {
+                Status::Skipped
+            } else {
+                Status::Succeeded
+            };
+            let authentication = if quota.is_authenticated() {
+                ""
+            } else {
+                ", not authenticated"
+            };
+            result.push('\n');
+            result.push_str(&colored(
+                status,
+                &format!(
+                    "GitHub API: {} requests used, {} of {} left{authentication}",
+                    quota.used(),
+                    quota.after.remaining,
+                    quota.after.limit
+                ),
+                color,
+            ));
+            result.push('\n');
+        }
+
+        if let Some(timings) = &self.timings {
+            result.push_str("\nTimings:\n");
+            for t in timings {
+                let subject = match &t.package {
+                    Some(package) => format!("{} {package}", t.stage),
+                    None => t.stage.to_string(),
+                };
+                result.push_str(&format!("    {subject}: {}ms\n", t.duration.as_millis()));
+            }
+        }
+        result
+    }
+
+    /// The report wrapped up for the markdown status file
+    pub fn to_markdown(&self) -> String {
+        format!(
+            r#"## Status
+
+```
+{self}
+```
+
+"#
+        )
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::package_generation::PackagingStatus;
+
+    #[test]
+    fn test_render_groups_by_status() {
+        let mut report = Report::default();
+        report.insert(
+            "good".to_string(),
+            vec![VersionPackagingStatus {
+                version: Some("1.0.0-0".to_string()),
+                found_platforms: vec![Platform::Linux64],
+                status: vec![PackagingStatus::success(Platform::Linux64)],
+            }],
+        );
+        report.insert(
+            "bad".to_string(),
+            vec![VersionPackagingStatus {
+                version: None,
+                found_platforms: vec![],
+                status: PackagingStatus::github_failed("rate limited"),
+            }],
+        );
+
+        assert_eq!(report.count(Status::Succeeded), 1);
+        assert_eq!(report.count(Status::Failed), 1);
+        assert_eq!(report.count(Status::Skipped), 0);
+
+        let rendered = report.render(false);
+        assert!(rendered.starts_with("1 succeeded, 1 failed, 0 skipped\n"));
+        assert!(rendered.find("bad").unwrap() < rendered.find("good").unwrap());
+        assert!(!rendered.contains('\x1b'));
+
+        assert!(report.render(true).contains("\x1b[31m"));
+        assert!(!rendered.contains("Timings:"));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut report = Report::default();
+        report.insert(
+            "paused".to_string(),
+            vec![VersionPackagingStatus {
+                version: None,
+                found_platforms: vec![],
+                status: vec![PackagingStatus::disabled()],
+            }],
+        );
+        report.insert(
+            "good".to_string(),
+            vec![VersionPackagingStatus {
+                version: Some("1.0.0".to_string()),
+                found_platforms: vec![Platform::Linux64],
+                status: vec![PackagingStatus::success(Platform::Linux64)],
+            }],
+        );
+
+        assert_eq!(report.count(Status::Disabled), 1);
+        assert_eq!(report.count(Status::Skipped), 0);
+        let rendered = report.render(false);
+        assert!(rendered.starts_with("1 succeeded, 0 failed, 0 skipped, 1 disabled\n"));
+        assert!(rendered.find("good").unwrap() < rendered.find("paused").unwrap());
+        assert!(rendered.contains("disabled in the configuration"));
+    }
+
+    #[test]
+    fn test_notify() {
+        let mut report = Report::default();
+        for (package, status) in [
+            ("bad", PackagingStatus::github_failed("rate limited")),
+            ("good", vec![PackagingStatus::success(Platform::Linux64)]),
+        ] {
+            report.insert(
+                package.to_string(),
+                vec![VersionPackagingStatus {
+                    version: None,
+                    found_platforms: vec![],
+                    status,
+                }],
+            );
+            report.notify.insert(
+                package.to_string(),
+                vec!["@alice".to_string(), "@bob".to_string()],
+            );
+        }
+
+        let rendered = report.render(false);
+        assert_eq!(rendered.matches("    cc @alice @bob\n").count(), 1);
+        assert!(rendered.find("cc @alice").unwrap() < rendered.find("good").unwrap());
+    }
+
+    #[test]
+    fn test_sources() {
+        let mut report = Report::default();
+        for package in ["mirrored", "direct"] {
+            report.insert(
+                package.to_string(),
+                vec![VersionPackagingStatus {
+                    version: Some("1.0.0".to_string()),
+                    found_platforms: vec![Platform::Linux64],
+                    status: vec![PackagingStatus::success(Platform::Linux64)],
+                }],
+            );
+        }
+        report
+            .sources
+            .insert("mirrored".to_string(), "mirror/tool".to_string());
+
+        let rendered = report.render(false);
+        assert_eq!(rendered.matches("releases from").count(), 1);
+        assert!(rendered.contains("mirrored (1 packages)\n    releases from mirror/tool\n"));
+    }
+
+    #[test]
+    fn test_github_quota() {
+        use crate::github::Quota;
+
+        let quota = |limit, used| Quota {
+            limit,
+            remaining: limit - used,
+            used,
+        };
+        let mut report = Report {
+            github_quota: Some(QuotaUsage {
+                before: quota(5000, 10),
+                after: quota(5000, 50),
+            }),
+            ..Default::default()
+        };
+        assert!(
+            report
+                .render(false)
+                .contains("\nGitHub API: 40 requests used, 4950 of 5000 left\n")
+        );
+
+        let usage = QuotaUsage {
+            before: quota(60, 55),
+            after: quota(60, 3),
+        };
+        assert_eq!(usage.used(), 3);
+        assert!(!usage.is_authenticated());
+        assert!(!usage.is_low());
+        report.github_quota = Some(usage);
+        assert!(
+            report
+                .render(false)
+                .contains("GitHub API: 3 requests used, 57 of 60 left, not authenticated")
+        );
+        assert!(!Report::default().render(false).contains("GitHub API"));
+    }
+
+    #[test]
+    fn test_timings() {
+        let mut report = Report::default();
+        report.record_timing("github", Some("foo"), std::time::Duration::from_millis(5));
+        assert!(report.timings.is_none());
+        assert_eq!(report.timings_json().unwrap(), "[]");
+
+        report.timings = Some(vec![]);
+        report.record_timing(
+            "conda channel",
+            None,
+            std::time::Duration::from_millis(1500),
+        );
+        report.record_timing("github", Some("foo"), std::time::Duration::from_millis(5));
+
+        let rendered = report.render(false);
+        assert!(rendered.ends_with("\nTimings:\n    conda channel: 1500ms\n    github foo: 5ms\n"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&report.timings_json().unwrap()).unwrap();
+        assert_eq!(json[0]["stage"], "conda channel");
+        assert_eq!(json[0]["seconds"], 1.5);
+        assert!(json[0].get("package").is_none());
+        assert_eq!(json[1]["package"], "foo");
+    }
+}
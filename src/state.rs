@@ -0,0 +1,288 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Report, Result, package_generation::ChosenAsset};
+
+/// The platforms found in the newest release of a package
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Coverage {
+    pub version: String,
+    pub platforms: BTreeSet<String>,
+}
+
+/// A release asset as it was when octoconda packaged it
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct KnownAsset {
+    pub node_id: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl KnownAsset {
+    /// Whether `asset` was deleted and uploaded again or replaced since
+    pub fn is_reupload(&self, asset: &ChosenAsset) -> bool {
+        self.node_id != asset.node_id || self.updated_at != asset.updated_at
+    }
+}
+
+impl From<&ChosenAsset> for KnownAsset {
+    fn from(asset: &ChosenAsset) -> Self {
+        Self {
+            node_id: asset.node_id.clone(),
+            updated_at: asset.updated_at,
+        }
+    }
+}
+
+/// Information kept between runs
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct State {
+    #[serde(default)]
+    pub coverage: BTreeMap<String, Coverage>,
+    /// When the last run without failures started
+    #[serde(default)]
+    pub last_run: Option<chrono::DateTime<chrono::Utc>>,
+    /// The release assets packages were generated from or found in the
+    /// channel for, keyed by download URL
+    #[serde(default)]
+    pub assets: BTreeMap<String, KnownAsset>,
+}
+
+impl State {
+    /// Load the state from `path`, starting out empty if it does not exist
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(path)
+            .context(format!("Failed to read state file {}", path.display()))
+            .map_err(Error::State)?;
+        serde_json::from_str(&contents)
+            .context(format!("Failed to parse state file {}", path.display()))
+            .map_err(Error::State)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = serde_json::to_string_pretty(self)
+            .context("Failed to serialize state")
+            .map_err(Error::State)?;
+        std::fs::write(path, contents)
+            .context(format!("Failed to write state file {}", path.display()))
+            .map_err(Error::State)
+    }
+
+    /// Platforms that had assets in the previous run, but have none now
+    pub fn coverage_regressions(&self, report: &Report) -> Vec<String> {
+        let mut result = Vec::new();
+        for (package, version, platforms) in report.latest_coverage() {
+            let Some(previous) = self.coverage.get(package) else {
+                continue;
+            };
+            for platform in &previous.platforms {
+                if !platforms.iter().any(|p| p.to_string() == *platform) {
+                    result.push(format!(
+                        "{package}: {platform} was packaged for {} but has no asset in {version}",
+                        previous.version
+                    ));
+                }
+            }
+        }
+        result.sort();
+        result
+    }
+
+    /// The release assets in `report` that changed since an earlier run saw
+    /// them
+    ///
+    /// Upstream replacing the asset of a release that is packaged already
+    /// may be a supply-chain attack.
+    pub fn reuploads(&self, report: &Report) -> Vec<String> {
+        let mut result = Vec::new();
+        for (package, asset) in report.assets() {
+            let Some(known) = self.assets.get(&asset.url) else {
+                continue;
+            };
+            if known.is_reupload(asset) {
+                result.push(format!(
+                    "{package}: {} was uploaded again on {} after it was packaged (last seen {})",
+                    asset.url, asset.updated_at, known.updated_at
+                ));
+            }
+        }
+        result.sort();
+        result.dedup();
+        result
+    }
+
+    pub fn update_assets(&mut self, report: &Report) {
+        for (_, asset) in report.assets() {
+            self.assets.insert(asset.url.clone(), asset.into());
+        }
+    }
+
+    pub fn update_coverage(&mut self, report: &Report) {
+        for (package, version, platforms) in report.latest_coverage() {
+            self.coverage.insert(
+                package.to_string(),
+                Coverage {
+                    version: version.to_string(),
+                    platforms: platforms.iter().map(|p| p.to_string()).collect(),
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rattler_conda_types::Platform;
+
+    use crate::package_generation::{PackagingStatus, VersionPackagingStatus};
+
+    fn report(version: &str, platforms: &[Platform]) -> Report {
+        let mut report = Report::default();
+        report.insert(
+            "tool".to_string(),
+            vec![VersionPackagingStatus {
+                version: Some(version.to_string()),
+                found_platforms: platforms.to_vec(),
+                status: platforms
+                    .iter()
+                    .map(|p| PackagingStatus::success(*p))
+                    .collect(),
+            }],
+        );
+        report
+    }
+
+    #[test]
+    fn test_reuploads() {
+        let asset = |node_id: &str, updated_at: &str| {
+            let mut report = Report::default();
+            report.insert(
+                "tool".to_string(),
+                vec![VersionPackagingStatus {
+                    version: Some("1.0.0-0".to_string()),
+                    found_platforms: vec![Platform::Linux64],
+                    status: vec![PackagingStatus::skip_platform(Platform::Linux64).with_assets(
+                        vec![ChosenAsset {
+                            name: "tool-linux.tar.gz".to_string(),
+                            url: "https://github.com/foo/tool/releases/download/v1.0.0/tool-linux.tar.gz".to_string(),
+                            digest: None,
+                            digest_source: None,
+                            node_id: node_id.to_string(),
+                            updated_at: updated_at.parse().unwrap(),
+                        }],
+                    )],
+                }],
+            );
+            report
+        };
+
+        let mut state = State::default();
+        let first = asset("RA_1", "2025-01-01T00:00:00Z");
+        assert!(state.reuploads(&first).is_empty());
+        state.update_assets(&first);
+        assert!(state.reuploads(&first).is_empty());
+
+        assert_eq!(
+            state.reuploads(&asset("RA_1", "2025-03-01T12:00:00Z")),
+            vec![
+                "tool: https://github.com/foo/tool/releases/download/v1.0.0/tool-linux.tar.gz was uploaded again on 2025-03-01 12:00:00 UTC after it was packaged (last seen 2025-01-01 00:00:00 UTC)".to_string()
+            ]
+        );
+        assert_eq!(
+            state
+                .reuploads(&asset("RA_2", "2025-01-01T00:00:00Z"))
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_coverage_regressions() {
+        let mut state = State::default();
+        state.update_coverage(&report(
+            "0.9.5-0",
+            &[Platform::Linux64, Platform::LinuxAarch64],
+        ));
+
+        assert!(
+            state
+                .coverage_regressions(&report(
+                    "0.9.5-0",
+                    &[Platform::Linux64, Platform::LinuxAarch64]
+                ))
+                .is_empty()
+        );
+        assert_eq!(
+            state.coverage_regressions(&report("0.9.6-0", &[Platform::Linux64])),
+            vec![
+                "tool: linux-aarch64 was packaged for 0.9.5-0 but has no asset in 0.9.6-0"
+                    .to_string()
+            ]
+        );
+
+        // Newer versions that were rejected or held by the policy do not count
+        let mut rejected = report("0.9.5-0", &[Platform::Linux64, Platform::LinuxAarch64]);
+        rejected.packages.get_mut("tool").unwrap().splice(
+            0..0,
+            [
+                VersionPackagingStatus {
+                    version: Some("0.9.8-0".to_string()),
+                    found_platforms: vec![],
+                    status: vec![PackagingStatus::rejected_tag("not a version")],
+                },
+                VersionPackagingStatus {
+                    version: Some("0.9.7-0".to_string()),
+                    found_platforms: vec![Platform::Linux64],
+                    status: vec![PackagingStatus::policy_violation(
+                        Platform::Linux64,
+                        crate::policy::Violation::TooYoung("too young".to_string()),
+                    )],
+                },
+            ],
+        );
+        assert!(state.coverage_regressions(&rejected).is_empty());
+    }
+
+    #[test]
+    fn test_coverage_regressions_without_assets() {
+        let mut state = State::default();
+        state.update_coverage(&report(
+            "0.9.5-0",
+            &[Platform::Linux64, Platform::LinuxAarch64],
+        ));
+
+        // Upstream renamed all assets of the newest release
+        let mut renamed = report("0.9.5-0", &[Platform::Linux64, Platform::LinuxAarch64]);
+        renamed.packages.get_mut("tool").unwrap().insert(
+            0,
+            VersionPackagingStatus {
+                version: Some("0.9.6-0".to_string()),
+                found_platforms: vec![],
+                status: vec![
+                    PackagingStatus::missing_platform(Platform::Linux64),
+                    PackagingStatus::missing_platform(Platform::LinuxAarch64),
+                ],
+            },
+        );
+        assert_eq!(
+            state.coverage_regressions(&renamed),
+            vec![
+                "tool: linux-64 was packaged for 0.9.5-0 but has no asset in 0.9.6-0".to_string(),
+                "tool: linux-aarch64 was packaged for 0.9.5-0 but has no asset in 0.9.6-0"
+                    .to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,354 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Download statistics of the packages in a prefix.dev channel
+
+use std::collections::BTreeMap;
+
+use anyhow::Context as _;
+
+const PREFIX_GRAPHQL_URL: &str = "https://prefix.dev/api/graphql";
+
+/// Packages requested from the prefix.dev API per page
+const PAGE_SIZE: u64 = 100;
+
+/// Variants requested per package and page
+const VARIANT_PAGE_SIZE: u64 = 100;
+
+const DOWNLOADS_QUERY: &str = r#"
+query ChannelDownloads(
+  $channel: String!
+  $page: Int!
+  $limit: Int!
+  $variantPage: Int!
+  $variantLimit: Int!
+) {
+  channel(name: $channel) {
+    packages(page: $page, limit: $limit) {
+      pages
+      page {
+        name
+        variants(page: $variantPage, limit: $variantLimit) {
+          pages
+          page {
+            platform
+            downloadCount
+          }
+        }
+      }
+    }
+  }
+}
+"#;
+
+#[derive(serde::Deserialize)]
+struct Response {
+    data: Option<ResponseData>,
+    #[serde(default)]
+    errors: Vec<ResponseError>,
+}
+
+#[derive(serde::Deserialize)]
+struct ResponseError {
+    message: String,
+}
+
+#[derive(serde::Deserialize)]
+struct ResponseData {
+    channel: Option<ChannelData>,
+}
+
+#[derive(serde::Deserialize)]
+struct ChannelData {
+    packages: Paginated<PackageData>,
+}
+
+#[derive(serde::Deserialize)]
+struct Paginated<T> {
+    #[serde(default)]
+    pages: u64,
+    page: Vec<T>,
+}
+
+#[derive(serde::Deserialize)]
+struct PackageData {
+    name: String,
+    variants: Paginated<VariantData>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VariantData {
+    platform: String,
+    download_count: u64,
+}
+
+/// Downloads of one package on one platform
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Downloads {
+    pub package: String,
+    pub platform: String,
+    pub downloads: u64,
+}
+
+/// One page of the downloads of a channel
+struct Page {
+    downloads: Vec<Downloads>,
+    /// Number of package pages
+    pages: u64,
+    /// Number of variant pages of the package with the most variants
+    variant_pages: u64,
+}
+
+fn parse_page(channel: &str, response: &[u8]) -> anyhow::Result<Page> {
+    let response: Response =
+        serde_json::from_slice(response).context("Failed to parse the prefix.dev response")?;
+    if let Some(error) = response.errors.first() {
+        return Err(anyhow::anyhow!("prefix.dev reported: {}", error.message));
+    }
+    let packages = response
+        .data
+        .and_then(|d| d.channel)
+        .context(format!("Channel {channel} not found on prefix.dev"))?
+        .packages;
+
+    let variant_pages = packages
+        .page
+        .iter()
+        .map(|p| p.variants.pages)
+        .max()
+        .unwrap_or_default();
+    let downloads = packages
+        .page
+        .into_iter()
+        .flat_map(|p| {
+            let name = p.name;
+            p.variants.page.into_iter().map(move |v| Downloads {
+                package: name.clone(),
+                platform: v.platform,
+                downloads: v.download_count,
+            })
+        })
+        .collect();
+    Ok(Page {
+        downloads,
+        pages: packages.pages,
+        variant_pages,
+    })
+}
+
+/// Downloads of all packages in `channel` per platform
+///
+/// `token` is needed for private channels only. Each page of packages is
+/// asked for again until all variant pages of its packages are read.
+pub async fn query_downloads(channel: &str, token: Option<&str>) -> anyhow::Result<Vec<Downloads>> {
+    let client = reqwest::Client::new();
+    let mut result = Vec::new();
+    let mut page = 0;
+    let mut variant_page = 0;
+    loop {
+        let body = serde_json::json!({
+            "query": DOWNLOADS_QUERY,
+            "variables": {
+                "channel": channel,
+                "page": page,
+                "limit": PAGE_SIZE,
+                "variantPage": variant_page,
+                "variantLimit": VARIANT_PAGE_SIZE,
+            },
+        });
+        let mut request = client
+            .post(PREFIX_GRAPHQL_URL)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_vec(&body)?);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .context("Failed to query prefix.dev")?
+            .bytes()
+            .await
+            .context("Failed to read the prefix.dev response")?;
+
+        let parsed = parse_page(channel, &response)?;
+        result.extend(parsed.downloads);
+        variant_page += 1;
+        if variant_page < parsed.variant_pages {
+            continue;
+        }
+        variant_page = 0;
+        page += 1;
+        if page >= parsed.pages {
+            return Ok(result);
+        }
+    }
+}
+
+/// The downloads of a package, in total and per platform
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackageDownloads {
+    pub package: String,
+    pub total: u64,
+    pub platforms: BTreeMap<String, u64>,
+}
+
+/// Sum up `downloads` per package, most downloaded packages first
+pub fn rank(downloads: &[Downloads]) -> Vec<PackageDownloads> {
+    let mut packages = BTreeMap::<&str, BTreeMap<String, u64>>::new();
+    for d in downloads {
+        *packages
+            .entry(d.package.as_str())
+            .or_default()
+            .entry(d.platform.clone())
+            .or_default() += d.downloads;
+    }
+
+    let mut result = packages
+        .into_iter()
+        .map(|(package, platforms)| PackageDownloads {
+            package: package.to_string(),
+            total: platforms.values().sum(),
+            platforms,
+        })
+        .collect::<Vec<_>>();
+    result.sort_by(|a, b| {
+        b.total
+            .cmp(&a.total)
+            .then_with(|| a.package.cmp(&b.package))
+    });
+    result
+}
+
+/// A markdown table of `ranked` packages with a column per platform
+pub fn to_markdown(ranked: &[PackageDownloads]) -> String {
+    let mut platforms = ranked
+        .iter()
+        .flat_map(|p| p.platforms.keys())
+        .collect::<Vec<_>>();
+    platforms.sort();
+    platforms.dedup();
+
+    let mut result = String::from("| # | Package | Total |");
+    for platform in &platforms {
+        result.push_str(&format!(" {platform} |"));
+    }
+    result.push_str("\n|---|---|---|");
+    result.push_str(&"---|".repeat(platforms.len()));
+    result.push('\n');
+
+    for (index, package) in ranked.iter().enumerate() {
+        result.push_str(&format!(
+            "| {} | {} | {} |",
+            index + 1,
+            package.package,
+            package.total
+        ));
+        for platform in &platforms {
+            let downloads = package
+                .platforms
+                .get(*platform)
+                .copied()
+                .unwrap_or_default();
+            result.push_str(&format!(" {downloads} |"));
+        }
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_page() {
+        let response = serde_json::json!({
+            "data": { "channel": { "packages": { "pages": 2, "page": [
+                { "name": "foo", "variants": { "pages": 1, "page": [
+                    { "platform": "linux-64", "downloadCount": 10 },
+                    { "platform": "osx-arm64", "downloadCount": 5 },
+                ] } },
+            ] } } }
+        });
+        let page = parse_page("test", &serde_json::to_vec(&response).unwrap()).unwrap();
+        assert_eq!(page.pages, 2);
+        assert_eq!(page.variant_pages, 1);
+        assert_eq!(
+            page.downloads,
+            vec![
+                Downloads {
+                    package: "foo".to_string(),
+                    platform: "linux-64".to_string(),
+                    downloads: 10,
+                },
+                Downloads {
+                    package: "foo".to_string(),
+                    platform: "osx-arm64".to_string(),
+                    downloads: 5,
+                },
+            ]
+        );
+
+        // The package with the most variants decides how often to ask
+        let response = serde_json::json!({
+            "data": { "channel": { "packages": { "pages": 1, "page": [
+                { "name": "foo", "variants": { "pages": 3, "page": [
+                    { "platform": "linux-64", "downloadCount": 10 },
+                ] } },
+                { "name": "bar", "variants": { "pages": 1, "page": [] } },
+            ] } } }
+        });
+        let page = parse_page("test", &serde_json::to_vec(&response).unwrap()).unwrap();
+        assert_eq!(page.pages, 1);
+        assert_eq!(page.variant_pages, 3);
+        assert_eq!(page.downloads.len(), 1);
+
+        assert!(parse_page("test", br#"{"data": {"channel": null}}"#).is_err());
+        assert!(
+            parse_page(
+                "test",
+                br#"{"data": null, "errors": [{"message": "nope"}]}"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_rank() {
+        let downloads = [
+            ("foo", "linux-64", 10),
+            ("bar", "linux-64", 3),
+            ("foo", "linux-64", 2),
+            ("baz", "win-64", 20),
+            ("bar", "osx-arm64", 4),
+            ("unused", "linux-64", 0),
+        ]
+        .map(|(package, platform, downloads)| Downloads {
+            package: package.to_string(),
+            platform: platform.to_string(),
+            downloads,
+        });
+
+        let ranked = rank(&downloads);
+        assert_eq!(
+            ranked
+                .iter()
+                .map(|p| (p.package.as_str(), p.total))
+                .collect::<Vec<_>>(),
+            vec![("baz", 20), ("foo", 12), ("bar", 7), ("unused", 0)]
+        );
+
+        let table = to_markdown(&ranked);
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "| # | Package | Total | linux-64 | osx-arm64 | win-64 |"
+        );
+        assert_eq!(lines[1], "|---|---|---|---|---|---|");
+        assert_eq!(lines[2], "| 1 | baz | 20 | 0 | 0 | 20 |");
+        assert_eq!(lines[4], "| 3 | bar | 7 | 3 | 4 | 0 |");
+    }
+}
@@ -0,0 +1,192 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Copy packages from one conda channel to a prefix.dev channel, and delete
+//! them from there
+
+use anyhow::Context as _;
+use rattler_conda_types::RepoDataRecord;
+use sha2::Digest as _;
+
+/// The packages in `source` that are not in `target` yet
+///
+/// Only packages named in `packages` with one of the `versions` are
+/// selected, an empty list selects everything.
+pub fn select<'a>(
+    source: &'a [RepoDataRecord],
+    target: &[RepoDataRecord],
+    packages: &[String],
+    versions: &[String],
+) -> Vec<&'a RepoDataRecord> {
+    let mut result = source
+        .iter()
+        .filter(|r| {
+            packages.is_empty()
+                || packages
+                    .iter()
+                    .any(|p| p == r.package_record.name.as_normalized())
+        })
+        .filter(|r| {
+            versions.is_empty()
+                || versions
+                    .iter()
+                    .any(|v| *v == r.package_record.version.to_string())
+        })
+        .filter(|r| {
+            !target.iter().any(|t| {
+                t.package_record.subdir == r.package_record.subdir && t.file_name == r.file_name
+            })
+        })
+        .collect::<Vec<_>>();
+    result.sort_by(|a, b| {
+        (&a.package_record.subdir, &a.file_name).cmp(&(&b.package_record.subdir, &b.file_name))
+    });
+    result
+}
+
+/// Make sure `content` is what the repodata of `record` promises
+fn verify(record: &RepoDataRecord, content: &[u8]) -> anyhow::Result<String> {
+    let expected = record.package_record.sha256.as_ref().context(format!(
+        "{} has no SHA256 in the repodata",
+        record.file_name
+    ))?;
+    let actual = sha2::Sha256::digest(content);
+    if actual != *expected {
+        return Err(anyhow::anyhow!(
+            "{} has SHA256 {actual:x}, but the repodata says {expected:x}",
+            record.file_name
+        ));
+    }
+    match record.package_record.size {
+        Some(size) if size != content.len() as u64 => {
+            return Err(anyhow::anyhow!(
+                "{} has {} bytes, but the repodata says {size}",
+                record.file_name,
+                content.len()
+            ));
+        }
+        _ => {}
+    }
+    Ok(format!("{actual:x}"))
+}
+
+/// Download `record`, check its digest and upload it to the prefix.dev
+/// channel `target`
+pub async fn copy(
+    client: &reqwest::Client,
+    record: &RepoDataRecord,
+    target: &str,
+    token: &str,
+) -> anyhow::Result<()> {
+    let content = client
+        .get(record.url.clone())
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .context(format!("Failed to download {}", record.url))?
+        .bytes()
+        .await
+        .context(format!("Failed to download {}", record.url))?;
+    let sha256 = verify(record, &content)?;
+
+    client
+        .post(format!("https://prefix.dev/api/v1/upload/{target}"))
+        .bearer_auth(token)
+        .header("X-File-Name", &record.file_name)
+        .header("X-File-SHA256", sha256)
+        .header("Content-Length", content.len())
+        .header("Content-Type", "application/octet-stream")
+        .body(content)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .context(format!("Failed to upload {} to {target}", record.file_name))?;
+    Ok(())
+}
+
+/// Delete `record` from the prefix.dev channel `channel`
+pub async fn delete(
+    client: &reqwest::Client,
+    record: &RepoDataRecord,
+    channel: &str,
+    token: &str,
+) -> anyhow::Result<()> {
+    client
+        .delete(format!(
+            "https://prefix.dev/api/v1/delete/{channel}/{}/{}",
+            record.package_record.subdir, record.file_name
+        ))
+        .bearer_auth(token)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .context(format!(
+            "Failed to delete {} from {channel}",
+            record.file_name
+        ))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::conda::tests::record_fixture as record;
+
+    #[test]
+    fn test_select() {
+        let source = [
+            record("foo", "1.0.0", "linux-64"),
+            record("foo", "1.1.0", "linux-64"),
+            record("foo", "1.1.0", "osx-arm64"),
+            record("bar", "2.0.0", "linux-64"),
+        ];
+        let target = [record("foo", "1.0.0", "linux-64")];
+        let names = |selected: Vec<&RepoDataRecord>| {
+            selected
+                .iter()
+                .map(|r| format!("{}/{}", r.package_record.subdir, r.file_name))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(select(&source, &target, &[], &[])),
+            vec![
+                "linux-64/bar-2.0.0-h0_0.conda",
+                "linux-64/foo-1.1.0-h0_0.conda",
+                "osx-arm64/foo-1.1.0-h0_0.conda",
+            ]
+        );
+        assert_eq!(
+            names(select(&source, &target, &["foo".to_string()], &[])),
+            vec![
+                "linux-64/foo-1.1.0-h0_0.conda",
+                "osx-arm64/foo-1.1.0-h0_0.conda"
+            ]
+        );
+        assert!(
+            select(
+                &source,
+                &target,
+                &["foo".to_string()],
+                &["1.0.0".to_string()]
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let content = b"not really a package";
+        let mut record = record("foo", "1.0.0", "linux-64");
+        assert!(verify(&record, content).is_err());
+
+        record.package_record.sha256 = Some(sha2::Sha256::digest(content));
+        let sha256 = verify(&record, content).unwrap();
+        assert_eq!(sha256.len(), 64);
+        assert!(verify(&record, b"something else").is_err());
+
+        record.package_record.size = Some(1);
+        assert!(verify(&record, content).is_err());
+    }
+}
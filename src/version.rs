@@ -0,0 +1,286 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Conda versions and build numbers from release tags
+
+use std::str::FromStr as _;
+
+use crate::config_file::{Package, VersionScheme};
+
+/// The version and build number derived from a release tag, or why the tag
+/// was rejected
+pub type TagVersion = Result<(String, u32), String>;
+
+/// Markers of pre-release versions, in lower case
+const PRERELEASE_MARKERS: &[&str] = &["alpha", "beta", "dev", "pre", "preview", "rc"];
+
+/// The version and build number of the release tagged `tag`
+///
+/// The `version_map` of `package` gets to see the tag without `<name>_` and
+/// `v` prefixes first. Its output is used as is, invalid versions are caught
+/// later when parsing them as conda versions. Without a matching rewrite rule
+/// the tag needs to follow the `version_scheme` of `package`.
+///
+/// GitHub's pre-release flag is checked before, this catches pre-releases
+/// upstream did not flag: Versions with a pre-release marker after their
+/// version number are rejected unless `package` asks for pre-releases.
+pub fn version_from_tag(tag: &str, package: &Package) -> TagVersion {
+    let tag = tag
+        .strip_prefix(&format!("{}_", package.name))
+        .unwrap_or(tag);
+    let tag = tag.strip_prefix('v').unwrap_or(tag);
+
+    let mapped = package
+        .version_map
+        .iter()
+        .find(|r| r.regex.is_match(tag))
+        .map(|rewrite| rewrite.regex.replace(tag, &rewrite.replace).into_owned());
+
+    if !package.prereleases {
+        let version = mapped.as_deref().unwrap_or(tag);
+        if let Some(marker) = prerelease_marker(version) {
+            return Err(format!("{version:?} is a pre-release ({marker})"));
+        }
+    }
+
+    if let Some(version) = mapped {
+        return Ok((version, 0));
+    }
+
+    match package.version_scheme {
+        VersionScheme::Standard => standard_version(tag, package.prereleases),
+        VersionScheme::Calver => calver_version(tag),
+    }
+}
+
+/// The pre-release marker in `version`, if any
+///
+/// Only whole words after the leading version number count, so that names
+/// like `devtools` or `src` do not.
+fn prerelease_marker(version: &str) -> Option<&'static str> {
+    let rest = version.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if rest.len() == version.len() {
+        return None;
+    }
+    rest.split(|c: char| !c.is_ascii_alphabetic())
+        .map(|word| word.to_ascii_lowercase())
+        .find_map(|word| PRERELEASE_MARKERS.iter().find(|m| **m == word))
+        .copied()
+}
+
+/// A conda version with an optional `-<build number>`
+///
+/// Semver build metadata (`+...`) is dropped. Semver pre-releases (`-rc.1`)
+/// become conda pre-release versions (`rc1`) if `prereleases` is set and are
+/// rejected otherwise.
+fn standard_version(tag: &str, prereleases: bool) -> TagVersion {
+    let tag = tag.split_once('+').map_or(tag, |(version, _)| version);
+    let (version, suffix) = tag.split_once('-').unwrap_or((tag, ""));
+
+    if !version.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(format!("{tag:?} does not start with a version number"));
+    }
+    if let Err(e) = rattler_conda_types::Version::from_str(version) {
+        return Err(format!("{version:?} is not a valid conda version: {e}"));
+    }
+
+    let build_number = if suffix.is_empty() {
+        0
+    } else if suffix.bytes().all(|b| b.is_ascii_digit()) {
+        suffix
+            .parse()
+            .map_err(|_| format!("build number {suffix:?} is too large"))?
+    } else if let Some(prerelease) = prerelease_suffix(suffix).filter(|_| prereleases) {
+        let version = format!("{version}{prerelease}");
+        if let Err(e) = rattler_conda_types::Version::from_str(&version) {
+            return Err(format!("{version:?} is not a valid conda version: {e}"));
+        }
+        return Ok((version, 0));
+    } else {
+        return Err(format!(
+            "{suffix:?} in {tag:?} is neither a build number nor part of the version, \
+             consider a version_map or version_scheme"
+        ));
+    };
+
+    Ok((version.to_string(), build_number))
+}
+
+/// `rc1` for a semver pre-release suffix like `rc.1`, `RC1` or `rc`
+fn prerelease_suffix(suffix: &str) -> Option<String> {
+    let suffix = suffix.to_ascii_lowercase();
+    let marker = PRERELEASE_MARKERS
+        .iter()
+        .filter(|m| suffix.starts_with(**m))
+        .max_by_key(|m| m.len())?;
+    let rest = &suffix[marker.len()..];
+    let number = rest.strip_prefix(['.', '-', '_']).unwrap_or(rest);
+    if (number.is_empty() && !rest.is_empty()) || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(format!("{marker}{number}"))
+}
+
+fn number(part: &str) -> Result<u64, String> {
+    part.parse()
+        .map_err(|_| format!("{part:?} is too large for a version component"))
+}
+
+/// `2024.10.2`, `2024-10-01`, `20241001` or `24.10`
+///
+/// Components are joined with `.` and lose leading zeros, so the same date
+/// always ends up as the same conda version.
+fn calver_version(tag: &str) -> TagVersion {
+    let parts = tag.split(['.', '-', '_']).collect::<Vec<_>>();
+    if parts
+        .iter()
+        .any(|p| p.is_empty() || !p.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return Err(format!("{tag:?} is not a date-based version"));
+    }
+
+    let mut components = Vec::new();
+    let year = parts[0];
+    if year.len() == 8 {
+        // Compact YYYYMMDD date
+        let month = number(&year[4..6])?;
+        let day = number(&year[6..8])?;
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return Err(format!("{year:?} is not a valid date"));
+        }
+        components.extend([number(&year[..4])?, month, day]);
+    } else if year.len() == 2 || year.len() == 4 {
+        components.push(number(year)?);
+    } else {
+        return Err(format!("{tag:?} does not start with a year"));
+    }
+    for p in &parts[1..] {
+        components.push(number(p)?);
+    }
+
+    let version = components
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(".");
+    Ok((version, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(version_scheme: VersionScheme) -> Package {
+        let mut package = Package::with_defaults("foo/tool").unwrap();
+        package.version_scheme = version_scheme;
+        package
+    }
+
+    #[test]
+    fn test_standard_version() {
+        let package = package(VersionScheme::Standard);
+        let version = |tag| version_from_tag(tag, &package);
+
+        assert_eq!(version("v1.2.3"), Ok(("1.2.3".to_string(), 0)));
+        assert_eq!(version("tool_1.2.3"), Ok(("1.2.3".to_string(), 0)));
+        assert_eq!(version("1.2.3-4"), Ok(("1.2.3".to_string(), 4)));
+        assert_eq!(version("v1.2.3+build.5"), Ok(("1.2.3".to_string(), 0)));
+        assert_eq!(version("1.2.3-1+amd64"), Ok(("1.2.3".to_string(), 1)));
+        assert_eq!(version("0.9.1a"), Ok(("0.9.1a".to_string(), 0)));
+        assert!(version("1.2.3-rc").is_err());
+        assert!(version("1.2.3-rc.1").is_err());
+        assert!(version("2024-10-01").is_err());
+        assert!(version("1.2.3-99999999999").is_err());
+        assert!(version("nightly").is_err());
+        assert!(version("").is_err());
+    }
+
+    #[test]
+    fn test_calver_version() {
+        let package = package(VersionScheme::Calver);
+        let version = |tag| version_from_tag(tag, &package);
+
+        assert_eq!(version("2024.10.2"), Ok(("2024.10.2".to_string(), 0)));
+        assert_eq!(version("v2024.10.02"), Ok(("2024.10.2".to_string(), 0)));
+        assert_eq!(version("2024-10-01"), Ok(("2024.10.1".to_string(), 0)));
+        assert_eq!(version("20241001"), Ok(("2024.10.1".to_string(), 0)));
+        assert_eq!(version("20241001.1"), Ok(("2024.10.1.1".to_string(), 0)));
+        assert_eq!(version("24.10"), Ok(("24.10".to_string(), 0)));
+
+        assert!(version("20241301").is_err());
+        assert!(version("123.1").is_err());
+        assert!(version("2024.10.x").is_err());
+        assert!(version("2024..1").is_err());
+    }
+
+    #[test]
+    fn test_version_map() {
+        let mut package = package(VersionScheme::Standard);
+        for (pattern, replace) in [("^(.*)-patch(\\d+)$", "$1.$2"), ("^(.*)b$", "${1}b0")] {
+            package
+                .version_map
+                .push(crate::config_file::VersionRewrite {
+                    regex: regex::Regex::new(pattern).unwrap(),
+                    replace: replace.to_string(),
+                });
+        }
+        let version = |tag| version_from_tag(tag, &package);
+
+        assert_eq!(version("1.2.3-patch1"), Ok(("1.2.3.1".to_string(), 0)));
+        assert_eq!(version("v1.2.3b"), Ok(("1.2.3b0".to_string(), 0)));
+        assert_eq!(version("1.2.3-4"), Ok(("1.2.3".to_string(), 4)));
+        assert!(version("1.2.3-rc").is_err());
+    }
+
+    #[test]
+    fn test_prereleases() {
+        let mut package = package(VersionScheme::Standard);
+        assert!(version_from_tag("v1.2.3-beta.1", &package).is_err());
+        assert!(version_from_tag("1.0.0rc1", &package).is_err());
+
+        assert!(version_from_tag("2.0.0-Preview", &package).is_err());
+        assert_eq!(prerelease_marker("1.0.0-dev.3"), Some("dev"));
+        assert_eq!(prerelease_marker("1.0.0-devtools"), None);
+        assert_eq!(prerelease_marker("devtools-1.0"), None);
+        assert_eq!(prerelease_marker("src-1.0-rc"), None);
+        assert_eq!(prerelease_marker("0.9.1a"), None);
+
+        // Rewrite rules see the tag before the marker check
+        assert!(version_from_tag("1.2.3-rc-final", &package).is_err());
+        package
+            .version_map
+            .push(crate::config_file::VersionRewrite {
+                regex: regex::Regex::new("^(.*)-rc-final$").unwrap(),
+                replace: "$1".to_string(),
+            });
+        assert_eq!(
+            version_from_tag("1.2.3-rc-final", &package),
+            Ok(("1.2.3".to_string(), 0))
+        );
+
+        package.prereleases = true;
+        assert_eq!(
+            version_from_tag("1.0.0rc1", &package),
+            Ok(("1.0.0rc1".to_string(), 0))
+        );
+        assert_eq!(
+            version_from_tag("v1.2.3-beta.1", &package),
+            Ok(("1.2.3beta1".to_string(), 0))
+        );
+        assert_eq!(
+            version_from_tag("1.2.0-RC1", &package),
+            Ok(("1.2.0rc1".to_string(), 0))
+        );
+        assert_eq!(
+            version_from_tag("2.0.0-preview", &package),
+            Ok(("2.0.0preview".to_string(), 0))
+        );
+        assert!(version_from_tag("1.2.3-rc.", &package).is_err());
+        assert!(version_from_tag("1.2.3-rc.x", &package).is_err());
+        assert!(version_from_tag("1.2.3-nightly", &package).is_err());
+        assert!(
+            rattler_conda_types::Version::from_str("1.2.0rc1").unwrap()
+                < rattler_conda_types::Version::from_str("1.2.0").unwrap()
+        );
+    }
+}
@@ -0,0 +1,322 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Receive GitHub `release` webhooks
+//!
+//! Just enough HTTP/1.1 to accept the POST requests GitHub sends: One request
+//! per connection, with a `Content-Length`.
+
+use std::io::{BufRead, Read as _};
+
+use anyhow::Context as _;
+use hmac::Mac as _;
+
+use crate::{config_file::Package, types::Repository};
+
+/// Largest request line plus headers accepted
+const MAX_HEADER_SIZE: u64 = 16 * 1024;
+
+/// Largest request body accepted
+///
+/// GitHub caps payloads at 25 MB, but release events stay far below this.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// A release that was published upstream
+#[derive(Clone, Debug)]
+pub struct Release {
+    pub repository: Repository,
+    pub tag: String,
+}
+
+impl Release {
+    /// Whether `package` packages the repository of this release
+    pub fn is_for(&self, package: &Package) -> bool {
+        package
+            .repository
+            .owner
+            .eq_ignore_ascii_case(&self.repository.owner)
+            && package
+                .repository
+                .repo
+                .eq_ignore_ascii_case(&self.repository.repo)
+    }
+}
+
+/// The parts of a webhook request octoconda looks at
+#[derive(Debug, Default)]
+pub struct Request {
+    pub method: String,
+    /// `X-GitHub-Event`
+    pub event: Option<String>,
+    /// `X-Hub-Signature-256`
+    pub signature: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// Read one HTTP request from `reader`
+///
+/// Requests that can not be valid webhooks are rejected before their body
+/// is read, so that unauthenticated clients can not make octoconda buffer
+/// large bodies.
+pub fn read_request(reader: &mut impl BufRead) -> anyhow::Result<Request> {
+    let mut headers = reader.by_ref().take(MAX_HEADER_SIZE);
+    let mut line = String::new();
+    read_header_line(&mut headers, &mut line).context("Failed to read the request line")?;
+    let mut request = Request {
+        method: line
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        ..Default::default()
+    };
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        read_header_line(&mut headers, &mut line).context("Failed to read a header")?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(anyhow::anyhow!("Malformed header {line:?}"));
+        };
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" => {
+                content_length = value
+                    .parse()
+                    .context(format!("Invalid Content-Length {value:?}"))?
+            }
+            "x-github-event" => request.event = Some(value.to_string()),
+            "x-hub-signature-256" => request.signature = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    if request.method != "POST" {
+        return Err(anyhow::anyhow!("Unexpected {} request", request.method));
+    }
+    if request.signature.is_none() {
+        return Err(anyhow::anyhow!("Missing signature"));
+    }
+    if content_length > MAX_BODY_SIZE {
+        return Err(anyhow::anyhow!(
+            "Request body of {content_length} bytes is too large"
+        ));
+    }
+    reader
+        .by_ref()
+        .take(content_length as u64)
+        .read_to_end(&mut request.body)
+        .context("Failed to read the request body")?;
+    if request.body.len() < content_length {
+        return Err(anyhow::anyhow!("Connection closed in the request body"));
+    }
+    Ok(request)
+}
+
+/// Read one line of the request line or headers from `reader`
+fn read_header_line(reader: &mut impl BufRead, line: &mut String) -> anyhow::Result<()> {
+    reader.read_line(line)?;
+    if !line.ends_with('\n') {
+        return Err(anyhow::anyhow!(
+            "Connection closed in the headers or headers larger than {MAX_HEADER_SIZE} bytes"
+        ));
+    }
+    Ok(())
+}
+
+/// Reads from a TCP stream that fail once `deadline` has passed
+///
+/// The read timeout of a socket applies to each read, so a client sending
+/// one byte at a time could otherwise keep a connection open forever.
+pub struct DeadlineReader<'a> {
+    stream: &'a std::net::TcpStream,
+    deadline: std::time::Instant,
+}
+
+impl<'a> DeadlineReader<'a> {
+    pub fn new(stream: &'a std::net::TcpStream, timeout: std::time::Duration) -> Self {
+        Self {
+            stream,
+            deadline: std::time::Instant::now() + timeout,
+        }
+    }
+}
+
+impl std::io::Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self
+            .deadline
+            .saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+        let mut stream = self.stream;
+        stream.set_read_timeout(Some(remaining))?;
+        stream.read(buf)
+    }
+}
+
+/// Whether `signature` (`sha256=<hex>`) is the HMAC of `body` with `secret`
+pub fn verify_signature(secret: &[u8], body: &[u8], signature: &str) -> bool {
+    let Some(signature) = signature
+        .strip_prefix("sha256=")
+        .and_then(|s| hex::decode(s).ok())
+    else {
+        return false;
+    };
+    let Ok(mut mac) = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+#[derive(serde::Deserialize)]
+struct ReleasePayload {
+    action: String,
+    release: ReleaseInfo,
+    repository: RepositoryInfo,
+}
+
+#[derive(serde::Deserialize)]
+struct ReleaseInfo {
+    tag_name: String,
+}
+
+#[derive(serde::Deserialize)]
+struct RepositoryInfo {
+    full_name: String,
+}
+
+/// The release published according to `request`, `None` for all other
+/// events
+///
+/// Requests without a valid signature for `secret` are rejected.
+pub fn published_release(request: &Request, secret: &[u8]) -> anyhow::Result<Option<Release>> {
+    if request.method != "POST" {
+        return Err(anyhow::anyhow!("Unexpected {} request", request.method));
+    }
+    if !request
+        .signature
+        .as_deref()
+        .is_some_and(|s| verify_signature(secret, &request.body, s))
+    {
+        return Err(anyhow::anyhow!("Missing or invalid signature"));
+    }
+    if request.event.as_deref() != Some("release") {
+        return Ok(None);
+    }
+
+    let payload: ReleasePayload =
+        serde_json::from_slice(&request.body).context("Failed to parse the release event")?;
+    if payload.action != "published" {
+        return Ok(None);
+    }
+    Ok(Some(Release {
+        repository: Repository::try_from(payload.repository.full_name.as_str())?,
+        tag: payload.release.tag_name,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"It's a Secret to Everybody";
+
+    fn sign(body: &[u8]) -> String {
+        let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(SECRET).unwrap();
+        mac.update(body);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    fn request(event: &str, body: &str) -> Request {
+        let raw = format!(
+            "POST /webhook HTTP/1.1\r\nHost: localhost\r\nX-GitHub-Event: {event}\r\n\
+             X-Hub-Signature-256: {}\r\nContent-Length: {}\r\n\r\n{body}",
+            sign(body.as_bytes()),
+            body.len()
+        );
+        read_request(&mut std::io::Cursor::new(raw)).unwrap()
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        // The example from the GitHub documentation
+        assert!(verify_signature(
+            SECRET,
+            b"Hello, World!",
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+        ));
+        assert!(!verify_signature(
+            SECRET,
+            b"Hello, World?",
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+        ));
+        assert!(!verify_signature(SECRET, b"Hello, World!", "sha256=xyz"));
+        assert!(!verify_signature(SECRET, b"Hello, World!", ""));
+    }
+
+    #[test]
+    fn test_read_request_limits() {
+        let read = |raw: String| read_request(&mut std::io::Cursor::new(raw));
+
+        let huge_header = format!(
+            "POST /webhook HTTP/1.1\r\nX-Padding: {}\r\n\r\n",
+            "x".repeat(MAX_HEADER_SIZE as usize)
+        );
+        assert!(read(huge_header).is_err());
+
+        // Bodies are not read for requests that can not be webhooks
+        let unsigned = "POST /webhook HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}".to_string();
+        assert!(read(unsigned).is_err());
+        let too_large = format!(
+            "POST /webhook HTTP/1.1\r\nX-Hub-Signature-256: sha256=00\r\n\
+             Content-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        assert!(read(too_large).is_err());
+
+        let truncated =
+            "POST /webhook HTTP/1.1\r\nX-Hub-Signature-256: sha256=00\r\nContent-Length: 5\r\n\r\n{}"
+                .to_string();
+        assert!(read(truncated).is_err());
+    }
+
+    #[test]
+    fn test_published_release() {
+        let body = r#"{"action": "published", "release": {"tag_name": "v1.2.3"},
+            "repository": {"full_name": "Foo/Bar"}}"#;
+        let release = published_release(&request("release", body), SECRET)
+            .unwrap()
+            .unwrap();
+        assert_eq!(release.repository.owner, "Foo");
+        assert_eq!(release.repository.repo, "Bar");
+        assert_eq!(release.tag, "v1.2.3");
+        assert!(release.is_for(&Package::with_defaults("foo/bar").unwrap()));
+        assert!(!release.is_for(&Package::with_defaults("foo/baz").unwrap()));
+
+        let edited = body.replace("published", "edited");
+        assert!(
+            published_release(&request("release", &edited), SECRET)
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            published_release(&request("ping", r#"{"zen": "Keep it simple."}"#), SECRET)
+                .unwrap()
+                .is_none()
+        );
+
+        let mut forged = request("release", body);
+        forged.body = body.replace("v1.2.3", "v6.6.6").into_bytes();
+        assert!(published_release(&forged, SECRET).is_err());
+        let mut unsigned = request("release", body);
+        unsigned.signature = None;
+        assert!(published_release(&unsigned, SECRET).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// © Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Pure-python wheels, repackaged as noarch python packages
+
+use std::io::Read as _;
+
+use anyhow::Context as _;
+
+/// What octoconda needs to know about a wheel to write its recipe
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WheelMetadata {
+    /// Version constraint on python, e.g. `>=3.9`
+    pub requires_python: Option<String>,
+    /// Run requirements as conda match specs
+    pub requires: Vec<String>,
+    /// Top-level modules the wheel installs into `site-packages`
+    pub modules: Vec<String>,
+}
+
+impl WheelMetadata {
+    /// The run requirements of the package, python first
+    pub fn run_requirements(&self) -> Vec<String> {
+        let python = match &self.requires_python {
+            Some(spec) => format!("python {spec}"),
+            None => "python".to_string(),
+        };
+        std::iter::once(python)
+            .chain(self.requires.iter().cloned())
+            .collect()
+    }
+}
+
+/// The conda package name of the python distribution `name`
+fn conda_name(name: &str) -> String {
+    name.to_ascii_lowercase().replace(['_', '.'], "-")
+}
+
+/// Environment markers that differ between platforms
+///
+/// A noarch package is the same everywhere, so it can not have requirements
+/// that only apply to some platforms.
+const PLATFORM_MARKERS: &[&str] = &[
+    "sys_platform",
+    "platform_system",
+    "os_name",
+    "platform_machine",
+];
+
+/// Translate the `Requires-Dist` value `requirement` into a conda match spec
+///
+/// Returns `None` for optional requirements, which only apply to extras, for
+/// requirements of some platforms only and for requirements conda can not
+/// express, like direct URLs. Other environment markers are dropped.
+fn conda_spec(requirement: &str) -> Option<String> {
+    let (requirement, marker) = requirement.split_once(';').unwrap_or((requirement, ""));
+    if marker.contains("extra") || PLATFORM_MARKERS.iter().any(|m| marker.contains(m)) {
+        return None;
+    }
+    let requirement = requirement.trim();
+    let end = requirement
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        .unwrap_or(requirement.len());
+    let (name, rest) = requirement.split_at(end);
+    if name.is_empty() {
+        return None;
+    }
+    let mut rest = rest.trim_start();
+    if rest.starts_with('[') {
+        rest = rest.split_once(']').map(|(_, r)| r).unwrap_or_default();
+    }
+    if rest.trim_start().starts_with('@') {
+        return None;
+    }
+    let spec = rest
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '(' | ')'))
+        .collect::<String>()
+        .replace("===", "==");
+
+    let name = conda_name(name);
+    Some(if spec.is_empty() {
+        name
+    } else {
+        format!("{name} {spec}")
+    })
+}
+
+/// Read `Requires-Python` and `Requires-Dist` from the `METADATA` file of a
+/// wheel
+fn parse_metadata(metadata: &str) -> (Option<String>, Vec<String>) {
+    let mut requires_python = None;
+    let mut requires = Vec::new();
+    // The headers end at the first empty line, the description follows
+    for line in metadata.lines().take_while(|l| !l.trim().is_empty()) {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        match key.trim().to_ascii_lowercase().as_str() {
+            "requires-python" => {
+                let spec = value
+                    .chars()
+                    .filter(|c| !c.is_whitespace())
+                    .collect::<String>();
+                requires_python = Some(spec).filter(|s| !s.is_empty());
+            }
+            "requires-dist" => requires.extend(conda_spec(value)),
+            _ => {}
+        }
+    }
+    (requires_python, requires)
+}
+
+/// The top-level modules among the files of a wheel
+fn modules(files: &[String]) -> Vec<String> {
+    let mut result = files
+        .iter()
+        .filter_map(|f| {
+            let (first, rest) = f.split_once('/').unwrap_or((f, ""));
+            if first.ends_with(".dist-info") || first.ends_with(".data") {
+                return None;
+            }
+            match (rest.is_empty(), first.strip_suffix(".py")) {
+                (true, Some(module)) => Some(module.to_string()),
+                (true, None) => None,
+                (false, _) => Some(first.to_string()),
+            }
+        })
+        .collect::<Vec<_>>();
+    result.sort();
+    result.dedup();
+    result
+}
+
+/// Look into the wheel with `content`
+pub fn metadata(content: &[u8]) -> anyhow::Result<WheelMetadata> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(content))?;
+    let files = archive.file_names().map(str::to_string).collect::<Vec<_>>();
+    let metadata_file = files
+        .iter()
+        .find(|f| {
+            f.split_once('/')
+                .is_some_and(|(dir, file)| dir.ends_with(".dist-info") && file == "METADATA")
+        })
+        .context("The wheel has no METADATA file")?;
+
+    let mut metadata = String::new();
+    archive
+        .by_name(metadata_file)?
+        .read_to_string(&mut metadata)?;
+    let (requires_python, requires) = parse_metadata(&metadata);
+
+    Ok(WheelMetadata {
+        requires_python,
+        requires,
+        modules: modules(&files),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write as _;
+
+    use super::*;
+
+    #[test]
+    fn test_conda_spec() {
+        assert_eq!(conda_spec("requests").as_deref(), Some("requests"));
+        assert_eq!(
+            conda_spec("Typing_Extensions (>=4.0, <5)").as_deref(),
+            Some("typing-extensions >=4.0,<5")
+        );
+        assert_eq!(
+            conda_spec("rich[jupyter]~=13.0 ; python_version >= \"3.8\"").as_deref(),
+            Some("rich ~=13.0")
+        );
+        assert_eq!(conda_spec("pytest ; extra == \"test\""), None);
+        assert_eq!(conda_spec("pywin32; sys_platform == \"win32\""), None);
+        assert_eq!(
+            conda_spec("colorama ; platform_system == 'Windows' and python_version < '3.12'"),
+            None
+        );
+        assert_eq!(conda_spec("tool @ https://example.com/tool.whl"), None);
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        for (path, content) in [
+            ("bar/__init__.py", ""),
+            ("bar/cli.py", ""),
+            ("bar_helpers.py", ""),
+            (
+                "bar-1.0.0.dist-info/METADATA",
+                "Metadata-Version: 2.1\nName: bar\nRequires-Python: >= 3.9\nRequires-Dist: click>=8\nRequires-Dist: pytest; extra == 'test'\n\nRequires-Dist: not-a-header\n",
+            ),
+            ("bar-1.0.0.dist-info/RECORD", ""),
+        ] {
+            writer.start_file(path, options).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        let content = writer.finish().unwrap().into_inner();
+
+        let wheel = metadata(&content).unwrap();
+        assert_eq!(
+            wheel,
+            WheelMetadata {
+                requires_python: Some(">=3.9".to_string()),
+                requires: vec!["click >=8".to_string()],
+                modules: vec!["bar".to_string(), "bar_helpers".to_string()],
+            }
+        );
+        assert_eq!(
+            wheel.run_requirements(),
+            vec!["python >=3.9".to_string(), "click >=8".to_string()]
+        );
+
+        assert!(metadata(b"not a wheel").is_err());
+    }
+}